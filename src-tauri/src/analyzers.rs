@@ -0,0 +1,539 @@
+use crate::checksums::ChecksumDb;
+use crate::dataless::NoMaterialize;
+use crate::filesystem;
+use crate::hashing::HashThrottle;
+use crate::media_tags::{self, MediaInfo};
+use crate::messages::Message;
+use crate::scanner::FileEntry;
+use crate::snapshots;
+use crate::suggestions::{Candidate, KeptCopy, Suggestion};
+use crate::watcher;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fs::{self, Metadata};
+use std::path::{Component, Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+const JUNK_PATTERNS: &[&str] = &[".DS_Store", "Thumbs.db", "ehthumbs.db"];
+const STALE_DOWNLOAD_PATTERNS: &[&str] = &["*.crdownload", "*.part", "*.download", "*.tmp"];
+const STALE_DOWNLOAD_AGE: Duration = Duration::from_secs(7 * 24 * 60 * 60);
+const OLD_FILE_AGE: Duration = Duration::from_secs(365 * 24 * 60 * 60);
+const OLD_FILE_MIN_BYTES: u64 = 100 * 1024 * 1024;
+const MEDIA_EXTENSIONS: &[&str] = &["mp3", "m4a", "flac", "wav", "mp4", "m4v", "mov"];
+const LOSSLESS_EXTENSIONS: &[&str] = &["flac", "wav"];
+// Versions of a track whose lengths differ by more than this are different edits or takes.
+const MEDIA_DURATION_TOLERANCE_SECS: f64 = 2.0;
+
+pub struct TreeFile {
+  pub path: PathBuf,
+  pub metadata: Metadata,
+}
+
+// What an analyzer gets to look at: the files under the scan root it declared itself applicable
+// to, plus the scan's largest files. Hashes come from the root's checksum database and are
+// computed through the shared throttle.
+pub struct AnalysisTree<'a> {
+  pub files: Vec<TreeFile>,
+  pub top_files: &'a [FileEntry],
+  pub hashing: &'a HashThrottle,
+  pub checksums: &'a ChecksumDb,
+}
+
+pub trait Analyzer: Send + Sync {
+  fn name(&self) -> &'static str;
+  // Called for every regular file during the shared walk; keep it cheap.
+  fn applicable(&self, path: &Path) -> bool;
+  fn analyze(&self, tree: &AnalysisTree) -> Vec<Suggestion>;
+}
+
+pub struct Registry {
+  analyzers: Vec<Box<dyn Analyzer>>,
+}
+
+impl Default for Registry {
+  fn default() -> Self {
+    let mut registry = Registry {
+      analyzers: Vec::new(),
+    };
+    registry.register(Box::new(JunkAnalyzer::new()));
+    registry.register(Box::new(CacheAnalyzer));
+    registry.register(Box::new(DuplicateAnalyzer));
+    registry.register(Box::new(OldLargeAnalyzer));
+    registry.register(Box::new(MediaDuplicateAnalyzer));
+    registry
+  }
+}
+
+impl Registry {
+  pub fn register(&mut self, analyzer: Box<dyn Analyzer>) {
+    self.analyzers.push(analyzer);
+  }
+
+  pub fn names(&self) -> Vec<&'static str> {
+    self
+      .analyzers
+      .iter()
+      .map(|analyzer| analyzer.name())
+      .collect()
+  }
+
+  pub fn disable(&mut self, names: &[String]) {
+    self
+      .analyzers
+      .retain(|analyzer| !names.iter().any(|name| name == analyzer.name()));
+  }
+
+  // Walks the root once, handing each analyzer only the files it asked for.
+  // Evicted cloud files are skipped rather than downloaded, e.g. when hashing duplicates.
+  pub fn run(
+    &self,
+    root: &Path,
+    top_files: &[FileEntry],
+    hashing: &HashThrottle,
+    checksums: &ChecksumDb,
+  ) -> Vec<Suggestion> {
+    let _no_materialize = NoMaterialize::enter();
+    let mut buckets: Vec<Vec<TreeFile>> = self.analyzers.iter().map(|_| Vec::new()).collect();
+    let mut dirs = vec![root.to_path_buf()];
+
+    while let Some(dir) = dirs.pop() {
+      let entries = match fs::read_dir(&dir) {
+        Ok(entries) => entries,
+        Err(_) => continue,
+      };
+      for entry in entries.flatten() {
+        let file_type = match entry.file_type() {
+          Ok(file_type) => file_type,
+          Err(_) => continue,
+        };
+        if file_type.is_symlink() {
+          continue;
+        }
+        let path = entry.path();
+        if file_type.is_dir() {
+          if !snapshots::is_snapshot_dir(&entry) {
+            dirs.push(path);
+          }
+          continue;
+        }
+        if !file_type.is_file() {
+          continue;
+        }
+
+        let wanted: Vec<usize> = self
+          .analyzers
+          .iter()
+          .enumerate()
+          .filter(|(_, analyzer)| analyzer.applicable(&path))
+          .map(|(index, _)| index)
+          .collect();
+        if wanted.is_empty() {
+          continue;
+        }
+        let metadata = match entry.metadata() {
+          Ok(metadata) => metadata,
+          Err(_) => continue,
+        };
+        for index in wanted {
+          buckets[index].push(TreeFile {
+            path: path.clone(),
+            metadata: metadata.clone(),
+          });
+        }
+      }
+    }
+
+    self
+      .analyzers
+      .iter()
+      .zip(buckets)
+      .flat_map(|(analyzer, files)| {
+        let mut suggestions = analyzer.analyze(&AnalysisTree {
+          files,
+          top_files,
+          hashing,
+          checksums,
+        });
+        for suggestion in &mut suggestions {
+          suggestion.analyzer = analyzer.name().to_string();
+        }
+        suggestions
+      })
+      .collect()
+  }
+}
+
+fn older_than(metadata: &Metadata, age: Duration) -> bool {
+  metadata
+    .modified()
+    .ok()
+    .and_then(|modified| SystemTime::now().duration_since(modified).ok())
+    .map(|elapsed| elapsed >= age)
+    .unwrap_or(false)
+}
+
+// The per-app folder inside a well-known cache location, e.g. ~/Library/Caches/com.foo.Bar.
+fn cache_folder(path: &Path) -> Option<PathBuf> {
+  let components: Vec<Component> = path.components().collect();
+  for index in 0..components.len().saturating_sub(2) {
+    let name = components[index].as_os_str();
+    let parent = index
+      .checked_sub(1)
+      .map(|parent| components[parent].as_os_str());
+    let is_cache = name == ".cache"
+      || (name == "Caches" && parent.map(|parent| parent == "Library").unwrap_or(false))
+      || (name == "Temp" && parent.map(|parent| parent == "Local").unwrap_or(false));
+    if is_cache {
+      return Some(components[..index + 2].iter().collect());
+    }
+  }
+  None
+}
+
+struct JunkAnalyzer {
+  junk_patterns: Vec<String>,
+  download_patterns: Vec<String>,
+}
+
+impl JunkAnalyzer {
+  fn new() -> Self {
+    let patterns = |list: &[&str]| list.iter().map(|pattern| pattern.to_string()).collect();
+    Self {
+      junk_patterns: patterns(JUNK_PATTERNS),
+      download_patterns: patterns(STALE_DOWNLOAD_PATTERNS),
+    }
+  }
+}
+
+impl Analyzer for JunkAnalyzer {
+  fn name(&self) -> &'static str {
+    "junk"
+  }
+
+  // Anything inside a cache folder is left to the cache analyzer.
+  fn applicable(&self, path: &Path) -> bool {
+    (watcher::is_ignored(path, &self.junk_patterns)
+      || watcher::is_ignored(path, &self.download_patterns))
+      && cache_folder(path).is_none()
+  }
+
+  fn analyze(&self, tree: &AnalysisTree) -> Vec<Suggestion> {
+    let mut junk = Candidate::default();
+    let mut downloads = Candidate::default();
+    for file in &tree.files {
+      if watcher::is_ignored(&file.path, &self.junk_patterns) {
+        junk.add(&file.path, file.metadata.len());
+      } else if older_than(&file.metadata, STALE_DOWNLOAD_AGE) {
+        downloads.add(&file.path, file.metadata.len());
+      }
+    }
+
+    let mut suggestions = Vec::new();
+    if !junk.paths.is_empty() {
+      suggestions.push(junk.into_suggestion(
+        "junk:system".to_string(),
+        "junk",
+        Message::new("suggestion.junk_metadata"),
+      ));
+    }
+    if !downloads.paths.is_empty() {
+      suggestions.push(downloads.into_suggestion(
+        "junk:downloads".to_string(),
+        "junk",
+        Message::new("suggestion.junk_downloads"),
+      ));
+    }
+    suggestions
+  }
+}
+
+struct CacheAnalyzer;
+
+impl Analyzer for CacheAnalyzer {
+  fn name(&self) -> &'static str {
+    "caches"
+  }
+
+  fn applicable(&self, path: &Path) -> bool {
+    cache_folder(path).is_some()
+  }
+
+  fn analyze(&self, tree: &AnalysisTree) -> Vec<Suggestion> {
+    let mut caches: BTreeMap<PathBuf, Candidate> = BTreeMap::new();
+    for file in &tree.files {
+      if let Some(folder) = cache_folder(&file.path) {
+        caches
+          .entry(folder)
+          .or_default()
+          .add(&file.path, file.metadata.len());
+      }
+    }
+
+    caches
+      .into_iter()
+      .map(|(folder, candidate)| {
+        let name = folder
+          .file_name()
+          .map(|name| name.to_string_lossy().to_string())
+          .unwrap_or_default();
+        candidate.into_suggestion(
+          format!("caches:{}", folder.to_string_lossy()),
+          "caches",
+          Message::new("suggestion.caches").with("name", name),
+        )
+      })
+      .collect()
+  }
+}
+
+struct DuplicateAnalyzer;
+
+impl Analyzer for DuplicateAnalyzer {
+  fn name(&self) -> &'static str {
+    "duplicates"
+  }
+
+  fn applicable(&self, _path: &Path) -> bool {
+    false
+  }
+
+  fn analyze(&self, tree: &AnalysisTree) -> Vec<Suggestion> {
+    let mut by_size: HashMap<u64, Vec<&FileEntry>> = HashMap::new();
+    for file in tree.top_files {
+      by_size.entry(file.size).or_default().push(file);
+    }
+
+    let mut suggestions = Vec::new();
+    for (size, files) in by_size {
+      if files.len() < 2 {
+        continue;
+      }
+      let mut by_hash: BTreeMap<String, Vec<&str>> = BTreeMap::new();
+      for file in files {
+        if let Ok(hash) = tree.checksums.sha256(Path::new(&file.path), tree.hashing) {
+          by_hash.entry(hash).or_default().push(&file.path);
+        }
+      }
+      for (hash, mut paths) in by_hash {
+        if paths.len() < 2 {
+          continue;
+        }
+        // The first path (alphabetically) is kept; the other copies are suggested.
+        paths.sort_unstable();
+        let (candidate, clones) = reclaimable_copies(&paths, size);
+        let name = Path::new(paths[0])
+          .file_name()
+          .map(|name| name.to_string_lossy().to_string())
+          .unwrap_or_default();
+        let title = if clones == paths.len() - 1 {
+          Message::new("suggestion.duplicate_clones").with("count", clones)
+        } else {
+          Message::new("suggestion.duplicates").with("count", paths.len() - 1)
+        };
+        let kept = KeptCopy {
+          path: paths[0].to_string(),
+          size,
+          sha256: hash.clone(),
+        };
+        suggestions.push(
+          candidate
+            .into_suggestion(
+              format!("duplicates:{}", hash),
+              "duplicates",
+              title.with("name", name),
+            )
+            .keeping(kept),
+        );
+      }
+    }
+    suggestions
+  }
+}
+
+// The copies after the first, with what deleting each would free. A copy that is a clone of
+// the kept file, or of a copy already counted, shares its storage and only frees the blocks it
+// has of its own. Also returns how many copies were such clones.
+fn reclaimable_copies(paths: &[&str], size: u64) -> (Candidate, usize) {
+  let mut families: HashSet<(u64, u64)> = HashSet::new();
+  families.extend(filesystem::clone_family(Path::new(paths[0])));
+  let mut candidate = Candidate::default();
+  let mut clones = 0;
+  for path in &paths[1..] {
+    let path = Path::new(path);
+    let bytes = match filesystem::clone_family(path) {
+      Some(family) if !families.insert(family) => {
+        clones += 1;
+        filesystem::private_size(path).unwrap_or(0).min(size)
+      }
+      _ => size,
+    };
+    candidate.add(path, bytes);
+  }
+  (candidate, clones)
+}
+
+struct OldLargeAnalyzer;
+
+impl Analyzer for OldLargeAnalyzer {
+  fn name(&self) -> &'static str {
+    "old_large"
+  }
+
+  fn applicable(&self, _path: &Path) -> bool {
+    false
+  }
+
+  fn analyze(&self, tree: &AnalysisTree) -> Vec<Suggestion> {
+    let mut candidate = Candidate::default();
+    for file in tree.top_files {
+      if file.size < OLD_FILE_MIN_BYTES {
+        continue;
+      }
+      let path = Path::new(&file.path);
+      if let Ok(metadata) = fs::symlink_metadata(path) {
+        if metadata.is_file() && older_than(&metadata, OLD_FILE_AGE) {
+          candidate.add(path, file.size);
+        }
+      }
+    }
+    if candidate.paths.is_empty() {
+      return Vec::new();
+    }
+    vec![candidate.into_suggestion(
+      "old_large:all".to_string(),
+      "old_large",
+      Message::new("suggestion.old_large"),
+    )]
+  }
+}
+
+struct MediaDuplicateAnalyzer;
+
+struct MediaFile<'a> {
+  file: &'a TreeFile,
+  duration_secs: Option<f64>,
+  lossless: bool,
+}
+
+fn lowercase_extension(path: &Path) -> String {
+  path
+    .extension()
+    .map(|extension| extension.to_string_lossy().to_lowercase())
+    .unwrap_or_default()
+}
+
+fn normalize_name(name: &str) -> String {
+  name
+    .chars()
+    .filter(|c| c.is_alphanumeric())
+    .flat_map(char::to_lowercase)
+    .collect()
+}
+
+// Tagged files match by artist and title; untagged ones by file name, but only when both
+// lengths are known.
+fn media_key(path: &Path, info: &MediaInfo) -> Option<String> {
+  match &info.title {
+    Some(title) => Some(format!(
+      "tag:{}:{}",
+      normalize_name(info.artist.as_deref().unwrap_or_default()),
+      normalize_name(title)
+    )),
+    None => {
+      info.duration_secs?;
+      let stem = normalize_name(&path.file_stem()?.to_string_lossy());
+      (!stem.is_empty()).then(|| format!("name:{}", stem))
+    }
+  }
+}
+
+// The version kept: the smallest lossless copy if there is one, otherwise the largest (highest
+// bitrate) lossy one.
+fn keep_index(versions: &[MediaFile]) -> usize {
+  let lossless = versions
+    .iter()
+    .enumerate()
+    .filter(|(_, version)| version.lossless)
+    .min_by_key(|(_, version)| version.file.metadata.len());
+  match lossless {
+    Some((index, _)) => index,
+    None => versions
+      .iter()
+      .enumerate()
+      .max_by_key(|(_, version)| version.file.metadata.len())
+      .map(|(index, _)| index)
+      .unwrap_or(0),
+  }
+}
+
+impl Analyzer for MediaDuplicateAnalyzer {
+  fn name(&self) -> &'static str {
+    "media_duplicates"
+  }
+
+  fn applicable(&self, path: &Path) -> bool {
+    MEDIA_EXTENSIONS.contains(&lowercase_extension(path).as_str())
+  }
+
+  // Only container headers are read: ID3, FLAC metadata blocks, the MP4 `moov` box, or WAV chunks.
+  fn analyze(&self, tree: &AnalysisTree) -> Vec<Suggestion> {
+    let mut groups: BTreeMap<String, Vec<MediaFile>> = BTreeMap::new();
+    for file in &tree.files {
+      let info = match media_tags::read(&file.path) {
+        Some(info) => info,
+        None => continue,
+      };
+      if let Some(key) = media_key(&file.path, &info) {
+        groups.entry(key).or_default().push(MediaFile {
+          file,
+          duration_secs: info.duration_secs,
+          lossless: LOSSLESS_EXTENSIONS.contains(&lowercase_extension(&file.path).as_str()),
+        });
+      }
+    }
+
+    let mut suggestions = Vec::new();
+    for mut versions in groups.into_values() {
+      let reference = versions.iter().find_map(|version| version.duration_secs);
+      versions.retain(|version| match (version.duration_secs, reference) {
+        (Some(duration), Some(reference)) => {
+          (duration - reference).abs() <= MEDIA_DURATION_TOLERANCE_SECS
+        }
+        _ => true,
+      });
+      // The same format at the same size is an exact copy, left to the duplicates analyzer.
+      let distinct: HashSet<(String, u64)> = versions
+        .iter()
+        .map(|version| {
+          (
+            lowercase_extension(&version.file.path),
+            version.file.metadata.len(),
+          )
+        })
+        .collect();
+      if distinct.len() < 2 {
+        continue;
+      }
+      let kept = versions.swap_remove(keep_index(&versions));
+      let mut candidate = Candidate::default();
+      for version in &versions {
+        candidate.add(&version.file.path, version.file.metadata.len());
+      }
+      let name = kept
+        .file
+        .path
+        .file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_default();
+      suggestions.push(
+        candidate.into_suggestion(
+          format!("media_duplicates:{}", kept.file.path.to_string_lossy()),
+          "media_duplicates",
+          Message::new("suggestion.media_duplicates")
+            .with("count", versions.len())
+            .with("name", name),
+        ),
+      );
+    }
+    suggestions
+  }
+}
@@ -0,0 +1,39 @@
+use crate::cache;
+use crate::messages::Message;
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+const ANNOTATIONS_FILE: &str = "annotations.json";
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Annotation {
+  pub path: String,
+  pub note: String,
+  #[serde(rename = "updatedAt")]
+  pub updated_at: u64,
+}
+
+pub fn load(cache_dir: &Path) -> Vec<Annotation> {
+  cache::read_json(&cache_dir.join(ANNOTATIONS_FILE))
+}
+
+// An empty note clears the annotation.
+pub fn annotate(cache_dir: &Path, path: &str, note: &str) -> Result<(), Message> {
+  let mut annotations = load(cache_dir);
+  annotations.retain(|annotation| annotation.path != path);
+
+  let note = note.trim();
+  if !note.is_empty() {
+    annotations.push(Annotation {
+      path: path.to_string(),
+      note: note.to_string(),
+      updated_at: SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or(0),
+    });
+  }
+
+  cache::write_json(&cache_dir.join(ANNOTATIONS_FILE), &annotations)
+}
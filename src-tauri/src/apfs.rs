@@ -0,0 +1,130 @@
+use crate::volumes;
+use serde::Serialize;
+use std::cmp::Reverse;
+use std::path::Path;
+use std::process::Command;
+
+#[derive(Clone, Serialize)]
+pub struct ContainerVolume {
+  pub device: String,
+  pub name: String,
+  // e.g. "System", "Data", "VM", "Preboot"; None for ordinary volumes.
+  pub role: Option<String>,
+  #[serde(rename = "mountPoint")]
+  pub mount_point: Option<String>,
+  #[serde(rename = "consumedBytes")]
+  pub consumed_bytes: u64,
+  // Of the container's capacity.
+  #[serde(rename = "sharePercent")]
+  pub share_percent: f64,
+}
+
+// An APFS container and what each of its volumes takes from the space they share, as Disk
+// Utility shows it.
+#[derive(Clone, Serialize)]
+pub struct ApfsContainer {
+  pub reference: String,
+  #[serde(rename = "capacityBytes")]
+  pub capacity_bytes: u64,
+  #[serde(rename = "usedBytes")]
+  pub used_bytes: u64,
+  #[serde(rename = "freeBytes")]
+  pub free_bytes: u64,
+  pub volumes: Vec<ContainerVolume>,
+}
+
+// `diskutil` prints sizes as "494384795648 B (494.4 GB)".
+fn bytes(value: &str) -> Option<u64> {
+  value.split_whitespace().next()?.parse().ok()
+}
+
+// The "Name: value" lines of `diskutil apfs list`, without the tree drawn to their left.
+fn fields(text: &str) -> impl Iterator<Item = (&str, &str)> {
+  text.lines().filter_map(|line| {
+    let (key, value) = line.trim_start_matches([' ', '|']).split_once(':')?;
+    Some((key.trim(), value.trim()))
+  })
+}
+
+fn parse_container(text: &str) -> Option<ApfsContainer> {
+  let mut container = ApfsContainer {
+    reference: String::new(),
+    capacity_bytes: 0,
+    used_bytes: 0,
+    free_bytes: 0,
+    volumes: Vec::new(),
+  };
+  for (key, value) in fields(text) {
+    match key {
+      "APFS Container Reference" => container.reference = value.to_string(),
+      "Size (Capacity Ceiling)" => container.capacity_bytes = bytes(value).unwrap_or(0),
+      "Capacity In Use By Volumes" => container.used_bytes = bytes(value).unwrap_or(0),
+      "Capacity Not Allocated" => container.free_bytes = bytes(value).unwrap_or(0),
+      // e.g. "disk3s1 (System)", or just "disk3s5" without a role.
+      "APFS Volume Disk (Role)" => {
+        let (device, role) = match value.split_once(' ') {
+          Some((device, role)) => (device, role.trim_matches(['(', ')'])),
+          None => (value, "No specific role"),
+        };
+        container.volumes.push(ContainerVolume {
+          device: device.to_string(),
+          name: String::new(),
+          role: (role != "No specific role").then(|| role.to_string()),
+          mount_point: None,
+          consumed_bytes: 0,
+          share_percent: 0.0,
+        });
+      }
+      _ => {
+        let volume = match container.volumes.last_mut() {
+          Some(volume) => volume,
+          None => continue,
+        };
+        match key {
+          // e.g. "Macintosh HD (Case-insensitive)".
+          "Name" => {
+            volume.name = match value.rsplit_once(" (") {
+              Some((name, _)) => name.to_string(),
+              None => value.to_string(),
+            }
+          }
+          "Mount Point" if value != "Not Mounted" => volume.mount_point = Some(value.to_string()),
+          // The sealed system volume is mounted through its snapshot.
+          "Snapshot Mount Point" if volume.mount_point.is_none() => {
+            volume.mount_point = Some(value.to_string())
+          }
+          "Capacity Consumed" => volume.consumed_bytes = bytes(value).unwrap_or(0),
+          _ => {}
+        }
+      }
+    }
+  }
+  if container.reference.is_empty() || container.capacity_bytes == 0 {
+    return None;
+  }
+  for volume in &mut container.volumes {
+    volume.share_percent = volume.consumed_bytes as f64 * 100.0 / container.capacity_bytes as f64;
+  }
+  container
+    .volumes
+    .sort_by_key(|volume| Reverse(volume.consumed_bytes));
+  Some(container)
+}
+
+fn diskutil(args: &[&str]) -> Option<String> {
+  let output = Command::new("diskutil").args(args).output().ok()?;
+  if !output.status.success() {
+    return None;
+  }
+  Some(String::from_utf8_lossy(&output.stdout).to_string())
+}
+
+// The container holding the APFS volume `path` is on; None on other filesystems.
+pub fn container_for(path: &Path) -> Option<ApfsContainer> {
+  let volume = volumes::volume_for(path).filter(|volume| volume.fs_type == "apfs")?;
+  let info = diskutil(&["info", &volume.mount_point.to_string_lossy()])?;
+  let reference = fields(&info)
+    .find(|(key, _)| *key == "APFS Container")
+    .map(|(_, value)| value.to_string())?;
+  parse_container(&diskutil(&["apfs", "list", &reference])?)
+}
@@ -0,0 +1,282 @@
+use crate::messages::{self, Message};
+use crate::SharedScanState;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::fs;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::os::unix::fs::{DirBuilderExt, PermissionsExt};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::{Path, PathBuf};
+use std::sync::{
+  atomic::{AtomicBool, Ordering},
+  Arc,
+};
+use std::thread;
+use tauri::Manager;
+
+pub const SOCKET_FILE: &str = "api.sock";
+// The socket's folder, only accessible to the current user, so the socket is never reachable by
+// others between being bound and having its permissions set.
+const SOCKET_DIR: &str = "api";
+// Longest request line read, newline included. The connection is closed after a longer one.
+const MAX_REQUEST_BYTES: u64 = 16 * 1024 * 1024;
+
+const PARSE_ERROR: i64 = -32700;
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+const APP_ERROR: i64 = -32000;
+
+#[derive(Deserialize)]
+struct Request {
+  #[serde(default)]
+  id: Value,
+  method: String,
+  #[serde(default)]
+  params: Value,
+}
+
+// `message` is the English text; `data` carries the message identifier and its parameters.
+#[derive(Serialize)]
+struct RpcError {
+  code: i64,
+  message: String,
+  data: Message,
+}
+
+impl RpcError {
+  fn new(code: i64, data: Message) -> Self {
+    Self {
+      code,
+      message: data.text(),
+      data,
+    }
+  }
+}
+
+#[derive(Serialize)]
+struct Response {
+  jsonrpc: &'static str,
+  id: Value,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  result: Option<Value>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  error: Option<RpcError>,
+}
+
+fn param<T: DeserializeOwned>(params: &Value, key: &str) -> Result<T, RpcError> {
+  serde_json::from_value(params.get(key).cloned().unwrap_or(Value::Null)).map_err(|_| {
+    RpcError::new(
+      INVALID_PARAMS,
+      Message::new("api.invalid_parameter").with("key", key),
+    )
+  })
+}
+
+fn reply<T: Serialize, E: Into<Message>>(result: Result<T, E>) -> Result<Value, RpcError> {
+  result
+    .map_err(Into::into)
+    .and_then(|value| {
+      serde_json::to_value(value).map_err(|_| Message::new("app.encode_result_failed"))
+    })
+    .map_err(|message| RpcError::new(APP_ERROR, message))
+}
+
+// Same behaviour as the Tauri commands of the same name; progress events are not
+// forwarded, so clients poll `get_scan_result` until the scan has finished.
+fn dispatch(app: &tauri::AppHandle, method: &str, params: &Value) -> Result<Value, RpcError> {
+  match method {
+    "start_scan" => reply(crate::start_scan(
+      param(params, "rootPath")?,
+      param(params, "options")?,
+      param(params, "label")?,
+      app.clone(),
+      app.state(),
+    )),
+    "cancel_scan" => reply(crate::cancel_scan(param(params, "scanId")?, app.state())),
+    "get_scan_result" => {
+      let scan_id: u64 = param(params, "scanId")?;
+      let state = app.state::<SharedScanState>();
+      let state = state.lock();
+      reply(
+        state
+          .scan_summaries
+          .get(&scan_id)
+          .cloned()
+          .ok_or_else(|| Message::new("scan.not_finished")),
+      )
+    }
+    "get_top_files" => reply(crate::get_top_files(
+      param(params, "scanId")?,
+      param(params, "n")?,
+      app.state(),
+    )),
+    "get_recent_removals" => reply(crate::get_recent_removals(
+      param(params, "scanId")?,
+      app.state(),
+    )),
+    "list_scan_history" => reply(crate::list_scan_history(app.clone())),
+    "get_memory_stats" => reply(crate::get_memory_stats(app.state())),
+    "get_cache_stats" => reply(crate::get_cache_stats(app.clone())),
+    "get_watch_status" => reply(crate::get_watch_status(app.state())),
+    "get_budget_status" => reply(crate::get_budget_status(app.state())),
+    "get_suggestions" => reply(crate::get_suggestions(
+      param(params, "scanId")?,
+      app.clone(),
+      app.state(),
+    )),
+    "simulate_savings" => reply(crate::simulate_savings(
+      param(params, "scanId")?,
+      param(params, "selections")?,
+      app.state(),
+    )),
+    "list_volumes" => reply(crate::list_volumes()),
+    "disk_overview" => reply(crate::disk_overview(
+      param(params, "rootPath")?,
+      app.clone(),
+    )),
+    _ => Err(RpcError::new(
+      METHOD_NOT_FOUND,
+      Message::new("api.unknown_method").with("method", method),
+    )),
+  }
+}
+
+// One JSON-RPC 2.0 request per line in, one response per line out.
+fn handle_line(app: &tauri::AppHandle, line: &str) -> Response {
+  let (id, outcome) = match serde_json::from_str::<Request>(line) {
+    Ok(request) => (request.id, dispatch(app, &request.method, &request.params)),
+    Err(_) => (
+      Value::Null,
+      Err(RpcError::new(
+        PARSE_ERROR,
+        Message::new("api.invalid_request"),
+      )),
+    ),
+  };
+  response(id, outcome)
+}
+
+fn response(id: Value, outcome: Result<Value, RpcError>) -> Response {
+  let (result, error) = match outcome {
+    Ok(result) => (Some(result), None),
+    Err(error) => (None, Some(error)),
+  };
+  Response {
+    jsonrpc: "2.0",
+    id,
+    result,
+    error,
+  }
+}
+
+pub fn socket_path(app: &tauri::AppHandle) -> Result<PathBuf, Message> {
+  Ok(
+    crate::cache::app_dir(app)?
+      .join(SOCKET_DIR)
+      .join(SOCKET_FILE),
+  )
+}
+
+pub struct ApiServer {
+  path: PathBuf,
+  stop: Arc<AtomicBool>,
+}
+
+impl ApiServer {
+  pub fn path(&self) -> &Path {
+    &self.path
+  }
+}
+
+impl Drop for ApiServer {
+  fn drop(&mut self) {
+    self.stop.store(true, Ordering::Relaxed);
+    // Wakes the blocking accept so the listener thread can exit.
+    let _ = UnixStream::connect(&self.path);
+    let _ = fs::remove_file(&self.path);
+  }
+}
+
+fn send(writer: &mut UnixStream, response: &Response) -> bool {
+  serde_json::to_string(response)
+    .ok()
+    .is_some_and(|encoded| writeln!(writer, "{}", encoded).is_ok())
+}
+
+fn serve(app: tauri::AppHandle, stream: UnixStream) {
+  let mut writer = match stream.try_clone() {
+    Ok(writer) => writer,
+    Err(_) => return,
+  };
+  let mut reader = BufReader::new(stream);
+  let mut buffer = Vec::new();
+  loop {
+    buffer.clear();
+    match (&mut reader)
+      .take(MAX_REQUEST_BYTES)
+      .read_until(b'\n', &mut buffer)
+    {
+      Ok(0) | Err(_) => return,
+      Ok(_) => {}
+    }
+    // Cut off before its end, so what follows can't be told apart from the next request.
+    if buffer.len() as u64 == MAX_REQUEST_BYTES && !buffer.ends_with(b"\n") {
+      let error = RpcError::new(
+        PARSE_ERROR,
+        Message::new("api.request_too_long").with("limit", messages::size(MAX_REQUEST_BYTES)),
+      );
+      send(&mut writer, &response(Value::Null, Err(error)));
+      return;
+    }
+    let line = match std::str::from_utf8(&buffer) {
+      Ok(line) => line,
+      Err(_) => return,
+    };
+    if line.trim().is_empty() {
+      continue;
+    }
+    if !send(&mut writer, &handle_line(&app, line)) {
+      return;
+    }
+  }
+}
+
+// The socket and its folder are only accessible to the current user.
+pub fn start(app: tauri::AppHandle, path: PathBuf) -> Result<ApiServer, Message> {
+  if UnixStream::connect(&path).is_ok() {
+    return Err(Message::new("api.socket_in_use"));
+  }
+  let dir = path
+    .parent()
+    .ok_or_else(|| Message::new("api.socket_create_failed"))?;
+  fs::DirBuilder::new()
+    .recursive(true)
+    .mode(0o700)
+    .create(dir)
+    .map_err(|_| Message::new("api.socket_create_failed"))?;
+  fs::set_permissions(dir, fs::Permissions::from_mode(0o700))
+    .map_err(|_| Message::new("api.socket_secure_failed"))?;
+  let _ = fs::remove_file(&path);
+  let listener = UnixListener::bind(&path).map_err(|_| Message::new("api.socket_create_failed"))?;
+  fs::set_permissions(&path, fs::Permissions::from_mode(0o600))
+    .map_err(|_| Message::new("api.socket_secure_failed"))?;
+
+  let stop = Arc::new(AtomicBool::new(false));
+  let server = ApiServer {
+    path,
+    stop: stop.clone(),
+  };
+  thread::spawn(move || {
+    for stream in listener.incoming() {
+      if stop.load(Ordering::Relaxed) {
+        return;
+      }
+      if let Ok(stream) = stream {
+        let app = app.clone();
+        thread::spawn(move || serve(app, stream));
+      }
+    }
+  });
+  Ok(server)
+}
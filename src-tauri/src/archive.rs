@@ -0,0 +1,238 @@
+use crate::hashing;
+use crate::messages::Message;
+use crate::plan::{self, PlanFailure};
+use crate::watcher::OwnActivity;
+use crate::{audit, cache, history};
+use serde::{Deserialize, Serialize};
+use std::fs::{self, File, Metadata};
+#[cfg(target_family = "unix")]
+use std::os::unix::fs::MetadataExt;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::time::UNIX_EPOCH;
+
+const MANIFEST_VERSION: u32 = 1;
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct ManifestItem {
+  pub path: String,
+  pub size: u64,
+  pub sha256: String,
+  #[serde(rename = "modifiedAt")]
+  pub modified_at: Option<u64>,
+  // Relative to the archive folder, e.g. `objects/ab/ab12….gz`.
+  pub object: String,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct ArchiveManifest {
+  pub version: u32,
+  #[serde(rename = "createdAt")]
+  pub created_at: u64,
+  pub items: Vec<ManifestItem>,
+}
+
+#[derive(Serialize)]
+pub struct ArchiveExecution {
+  #[serde(rename = "archivedFiles")]
+  pub archived_files: u64,
+  #[serde(rename = "archivedBytes")]
+  pub archived_bytes: u64,
+  // Compressed size of objects that were new to the archive.
+  #[serde(rename = "storedBytes")]
+  pub stored_bytes: u64,
+  #[serde(rename = "deletedFiles")]
+  pub deleted_files: u64,
+  pub manifest: Option<String>,
+  pub failures: Vec<PlanFailure>,
+}
+
+#[cfg(target_family = "unix")]
+fn device(metadata: &Metadata) -> Option<u64> {
+  Some(metadata.dev())
+}
+
+#[cfg(not(target_family = "unix"))]
+fn device(_metadata: &Metadata) -> Option<u64> {
+  None
+}
+
+fn modified_secs(metadata: &Metadata) -> Option<u64> {
+  metadata
+    .modified()
+    .ok()?
+    .duration_since(UNIX_EPOCH)
+    .ok()
+    .map(|duration| duration.as_secs())
+}
+
+fn object_path(sha256: &str) -> String {
+  format!("objects/{}/{}.gz", &sha256[..2], sha256)
+}
+
+// Compresses `source` into `target` with gzip, then reads it back and checks the hash before
+// the object is moved into place.
+fn store_object(source: &Path, target: &Path, sha256: &str) -> Result<u64, Message> {
+  let parent = target
+    .parent()
+    .ok_or_else(|| Message::new("archive.invalid_path"))?;
+  fs::create_dir_all(parent).map_err(|_| Message::new("archive.write_failed"))?;
+  let temp = target.with_extension("tmp");
+
+  let input = File::open(source).map_err(|_| Message::new("file.open_failed"))?;
+  let output = File::create(&temp).map_err(|_| Message::new("archive.write_failed"))?;
+  let status = Command::new("gzip")
+    .arg("-c")
+    .arg("-n")
+    .stdin(input)
+    .stdout(output)
+    .stderr(Stdio::null())
+    .status()
+    .map_err(|_| Message::new("file.gzip_failed"))?;
+  if !status.success() {
+    let _ = fs::remove_file(&temp);
+    return Err(Message::new("file.compress_failed"));
+  }
+
+  let verified = Command::new("gzip")
+    .arg("-dc")
+    .arg(&temp)
+    .stdout(Stdio::piped())
+    .stderr(Stdio::null())
+    .spawn()
+    .ok()
+    .and_then(|mut child| {
+      let hash = child.stdout.take().map(hashing::sha256_reader);
+      let status = child.wait().ok()?;
+      hash?.ok().filter(|hash| status.success() && hash == sha256)
+    })
+    .is_some();
+  if !verified {
+    let _ = fs::remove_file(&temp);
+    return Err(Message::new("archive.verification_failed"));
+  }
+
+  let stored = fs::metadata(&temp)
+    .map(|metadata| metadata.len())
+    .unwrap_or(0);
+  fs::rename(&temp, target).map_err(|_| Message::new("archive.write_failed"))?;
+  Ok(stored)
+}
+
+fn write_manifest(archive_dir: &Path, manifest: &ArchiveManifest) -> Result<PathBuf, Message> {
+  let dir = archive_dir.join("manifests");
+  fs::create_dir_all(&dir).map_err(|_| Message::new("archive.write_failed"))?;
+  let mut path = dir.join(format!("{}.json", manifest.created_at));
+  let mut suffix = 1;
+  while path.exists() {
+    path = dir.join(format!("{}-{}.json", manifest.created_at, suffix));
+    suffix += 1;
+  }
+  cache::write_json(&path, manifest)?;
+  Ok(path)
+}
+
+// Copies each file into a content-addressed archive on another volume (identical files are
+// stored once), writes a manifest, and only then deletes the originals. Files that changed
+// while they were being archived are kept. Each deletion goes into the audit log in `cache_dir`.
+pub fn archive_then_delete(
+  paths: &[String],
+  archive_dir: &Path,
+  own_activity: &OwnActivity,
+  cache_dir: &Path,
+) -> Result<ArchiveExecution, Message> {
+  if paths.is_empty() {
+    return Err(Message::new("archive.nothing"));
+  }
+  let archive_metadata =
+    fs::metadata(archive_dir).map_err(|_| Message::new("archive.folder_not_found"))?;
+  if !archive_metadata.is_dir() {
+    return Err(Message::new("archive.destination_not_folder"));
+  }
+  let archive_device = device(&archive_metadata);
+
+  let mut execution = ArchiveExecution {
+    archived_files: 0,
+    archived_bytes: 0,
+    stored_bytes: 0,
+    deleted_files: 0,
+    manifest: None,
+    failures: Vec::new(),
+  };
+  let mut archived = Vec::new();
+  for path in paths {
+    let outcome = plan::regular_file_size(Path::new(path)).and_then(|_| {
+      let metadata = fs::symlink_metadata(path).map_err(|_| Message::new("file.not_found"))?;
+      if archive_device.is_some() && device(&metadata) == archive_device {
+        return Err(Message::new("archive.same_volume"));
+      }
+      let sha256 = hashing::sha256_file(Path::new(path))?;
+      let object = object_path(&sha256);
+      let target = archive_dir.join(&object);
+      if !target.exists() {
+        execution.stored_bytes += store_object(Path::new(path), &target, &sha256)?;
+      }
+      Ok(ManifestItem {
+        path: path.clone(),
+        size: metadata.len(),
+        sha256,
+        modified_at: modified_secs(&metadata),
+        object,
+      })
+    });
+    match outcome {
+      Ok(item) => {
+        execution.archived_files += 1;
+        execution.archived_bytes += item.size;
+        archived.push(item);
+      }
+      Err(error) => execution.failures.push(PlanFailure {
+        path: path.clone(),
+        error,
+      }),
+    }
+  }
+  if archived.is_empty() {
+    return Ok(execution);
+  }
+
+  let manifest = ArchiveManifest {
+    version: MANIFEST_VERSION,
+    created_at: history::now_secs(),
+    items: archived,
+  };
+  let manifest_path = write_manifest(archive_dir, &manifest)?;
+  execution.manifest = Some(manifest_path.to_string_lossy().to_string());
+
+  for item in &manifest.items {
+    let unchanged = fs::symlink_metadata(&item.path)
+      .ok()
+      .filter(|metadata| metadata.len() == item.size && modified_secs(metadata) == item.modified_at)
+      .is_some();
+    if !unchanged {
+      execution.failures.push(PlanFailure {
+        path: item.path.clone(),
+        error: Message::new("archive.file_changed"),
+      });
+      continue;
+    }
+    own_activity.record(Path::new(&item.path));
+    let deleted = fs::remove_file(&item.path).map_err(|_| Message::new("archive.delete_failed"));
+    audit::record(
+      cache_dir,
+      "delete",
+      Path::new(&item.path),
+      item.size,
+      Some(&archive_dir.join(&item.object)),
+      deleted.as_ref().map(|_| ()),
+    );
+    match deleted {
+      Ok(()) => execution.deleted_files += 1,
+      Err(error) => execution.failures.push(PlanFailure {
+        path: item.path.clone(),
+        error,
+      }),
+    }
+  }
+  Ok(execution)
+}
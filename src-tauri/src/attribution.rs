@@ -0,0 +1,152 @@
+use crate::open_files::{self, OpenProcess};
+use serde::Serialize;
+use std::cmp::Reverse;
+use std::collections::HashMap;
+use std::fs;
+#[cfg(target_family = "unix")]
+use std::os::unix::fs::MetadataExt;
+use std::path::{Component, Path, PathBuf};
+
+// Folders whose children are named after the app (or its bundle id) that owns them.
+const APP_DATA_PARENTS: &[&str] = &[
+  "Application Support",
+  "Caches",
+  "Containers",
+  "Group Containers",
+  "Logs",
+  "Saved Application State",
+  ".cache",
+  ".config",
+];
+
+#[derive(Serialize)]
+pub struct AttributedPath {
+  pub path: String,
+  pub size: u64,
+  pub reason: String,
+}
+
+#[derive(Serialize)]
+pub struct ProcessUsage {
+  pub name: String,
+  pub pids: Vec<u32>,
+  #[serde(rename = "totalBytes")]
+  pub total_bytes: u64,
+  pub paths: Vec<AttributedPath>,
+}
+
+fn normalize(name: &str) -> String {
+  name
+    .chars()
+    .filter(|c| c.is_alphanumeric())
+    .flat_map(|c| c.to_lowercase())
+    .collect()
+}
+
+fn app_dir_name(path: &Path) -> Option<String> {
+  let components: Vec<Component> = path.components().collect();
+  components.windows(2).find_map(|pair| match pair {
+    [Component::Normal(parent), Component::Normal(child)]
+      if APP_DATA_PARENTS
+        .iter()
+        .any(|name| parent.to_str() == Some(*name)) =>
+    {
+      Some(normalize(&child.to_string_lossy()))
+    }
+    _ => None,
+  })
+}
+
+fn matches_app(dir: &str, process: &str) -> bool {
+  !process.is_empty() && (dir == process || (process.len() >= 4 && dir.contains(process)))
+}
+
+#[cfg(target_family = "unix")]
+fn file_id(metadata: &fs::Metadata) -> (u64, u64) {
+  (metadata.dev(), metadata.ino())
+}
+
+#[cfg(not(target_family = "unix"))]
+fn file_id(_metadata: &fs::Metadata) -> (u64, u64) {
+  (0, 0)
+}
+
+fn add(
+  usage: &mut HashMap<String, ProcessUsage>,
+  process: &OpenProcess,
+  path: &Path,
+  size: u64,
+  reason: &str,
+) {
+  let entry = usage
+    .entry(process.name.clone())
+    .or_insert_with(|| ProcessUsage {
+      name: process.name.clone(),
+      pids: Vec::new(),
+      total_bytes: 0,
+      paths: Vec::new(),
+    });
+  if !entry.pids.contains(&process.pid) {
+    entry.pids.push(process.pid);
+  }
+  let path = path.to_string_lossy().to_string();
+  if !entry.paths.iter().any(|attributed| attributed.path == path) {
+    entry.total_bytes += size;
+    entry.paths.push(AttributedPath {
+      path,
+      size,
+      reason: reason.to_string(),
+    });
+  }
+}
+
+// Groups by process name so an app's helper processes count its files once.
+pub fn attribute_paths(paths: &[PathBuf]) -> Vec<ProcessUsage> {
+  let mut holders: HashMap<(u64, u64), Vec<u32>> = HashMap::new();
+  for open in open_files::open_files() {
+    holders
+      .entry((open.dev, open.ino))
+      .or_default()
+      .push(open.pid);
+  }
+  let own_pid = std::process::id();
+  let processes: Vec<(String, OpenProcess)> = open_files::running_processes()
+    .into_iter()
+    .filter(|process| process.pid != own_pid)
+    .map(|process| (normalize(&process.name), process))
+    .collect();
+
+  let mut usage: HashMap<String, ProcessUsage> = HashMap::new();
+  for path in paths {
+    let metadata = match fs::symlink_metadata(path) {
+      Ok(metadata) if !metadata.file_type().is_symlink() => metadata,
+      _ => continue,
+    };
+    let size = if metadata.is_file() {
+      metadata.len()
+    } else {
+      0
+    };
+
+    if metadata.is_file() {
+      if let Some(pids) = holders.get(&file_id(&metadata)) {
+        for pid in pids.iter().filter(|pid| **pid != own_pid) {
+          add(&mut usage, &open_files::process(*pid), path, size, "open");
+        }
+        continue;
+      }
+    }
+
+    if let Some(dir) = app_dir_name(path) {
+      for (name, process) in &processes {
+        if matches_app(&dir, name) {
+          add(&mut usage, process, path, size, "app_data");
+        }
+      }
+    }
+  }
+
+  let mut usage: Vec<ProcessUsage> = usage.into_values().collect();
+  usage.sort_by_key(|process| Reverse(process.total_bytes));
+  usage
+}
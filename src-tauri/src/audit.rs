@@ -0,0 +1,140 @@
+use crate::history;
+use crate::messages::Message;
+use serde::{Deserialize, Serialize};
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::path::Path;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+
+const AUDIT_LOG_FILE: &str = "audit.jsonl";
+
+static WRITE_LOCK: Mutex<()> = Mutex::new(());
+static NEXT_SEQUENCE: AtomicU64 = AtomicU64::new(0);
+
+// One file the app deleted, trashed, moved, or truncated, or tried to.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct AuditEntry {
+  pub id: u64,
+  // Seconds since the epoch.
+  pub at: u64,
+  // "delete", "trash", "move", "truncate", "restore" (out of the trash), or "confirm" (a
+  // confirmation token spent, with an empty `path`).
+  pub action: String,
+  pub path: String,
+  pub size: u64,
+  // Where the file went: its place in the trash, or the target of a move.
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub destination: Option<String>,
+  // "ok" or "failed".
+  pub outcome: String,
+  // Why it failed, in English.
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub error: Option<String>,
+  // For "confirm": the command the token was spent on, what the user confirmed, and the paths
+  // (or hooks and policies) it covers.
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub command: Option<String>,
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub summary: Option<String>,
+  #[serde(default, skip_serializing_if = "Vec::is_empty")]
+  pub targets: Vec<String>,
+}
+
+// Unique across runs: milliseconds since the epoch, times a thousand, plus a sequence number.
+fn next_id() -> u64 {
+  let millis = std::time::SystemTime::now()
+    .duration_since(std::time::UNIX_EPOCH)
+    .map(|duration| duration.as_millis() as u64)
+    .unwrap_or(0);
+  millis * 1000 + NEXT_SEQUENCE.fetch_add(1, Ordering::Relaxed) % 1000
+}
+
+// Appends an entry to `audit.jsonl` in `app_dir`, one JSON object per line. Entries are never
+// rewritten or pruned. Failing to write the log doesn't fail the operation it records.
+pub fn record(
+  app_dir: &Path,
+  action: &str,
+  path: &Path,
+  size: u64,
+  destination: Option<&Path>,
+  result: Result<(), &Message>,
+) -> u64 {
+  append(
+    app_dir,
+    AuditEntry {
+      id: next_id(),
+      at: history::now_secs(),
+      action: action.to_string(),
+      path: path.to_string_lossy().to_string(),
+      size,
+      destination: destination.map(|destination| destination.to_string_lossy().to_string()),
+      outcome: if result.is_ok() { "ok" } else { "failed" }.to_string(),
+      error: result.err().map(Message::text),
+      command: None,
+      summary: None,
+      targets: Vec::new(),
+    },
+  )
+}
+
+// Records a confirmation token spent on `command`, or refused, with the summary the user
+// confirmed when there was one.
+pub fn record_confirmation(
+  app_dir: &Path,
+  command: &str,
+  targets: &[String],
+  result: Result<&str, &Message>,
+) -> u64 {
+  append(
+    app_dir,
+    AuditEntry {
+      id: next_id(),
+      at: history::now_secs(),
+      action: "confirm".to_string(),
+      path: String::new(),
+      size: 0,
+      destination: None,
+      outcome: if result.is_ok() { "ok" } else { "failed" }.to_string(),
+      error: result.err().map(Message::text),
+      command: Some(command.to_string()),
+      summary: result.ok().map(str::to_string),
+      targets: targets.to_vec(),
+    },
+  )
+}
+
+fn append(app_dir: &Path, entry: AuditEntry) -> u64 {
+  if let Ok(mut line) = serde_json::to_string(&entry) {
+    line.push('\n');
+    let _guard = WRITE_LOCK.lock();
+    let _ = fs::create_dir_all(app_dir);
+    let _ = OpenOptions::new()
+      .create(true)
+      .append(true)
+      .open(app_dir.join(AUDIT_LOG_FILE))
+      .and_then(|mut file| file.write_all(line.as_bytes()));
+  }
+  entry.id
+}
+
+// The entry with `id`, read a line at a time rather than loading the whole log.
+pub fn find(app_dir: &Path, id: u64) -> Option<AuditEntry> {
+  let file = File::open(app_dir.join(AUDIT_LOG_FILE)).ok()?;
+  BufReader::new(file)
+    .lines()
+    .map_while(Result::ok)
+    .filter_map(|line| serde_json::from_str::<AuditEntry>(&line).ok())
+    .find(|entry| entry.id == id)
+}
+
+// The entries recorded at or after `since` (seconds since the epoch), oldest first. Lines that
+// don't parse, e.g. one cut short by a crash, are skipped.
+pub fn load(app_dir: &Path, since: u64) -> Vec<AuditEntry> {
+  fs::read_to_string(app_dir.join(AUDIT_LOG_FILE))
+    .unwrap_or_default()
+    .lines()
+    .filter_map(|line| serde_json::from_str::<AuditEntry>(line).ok())
+    .filter(|entry| entry.at >= since)
+    .collect()
+}
@@ -0,0 +1,146 @@
+use serde::Serialize;
+#[cfg(target_os = "macos")]
+use std::fs;
+#[cfg(target_os = "macos")]
+use std::path::{Path, PathBuf};
+#[cfg(target_os = "macos")]
+use std::process::Command;
+#[cfg(target_os = "macos")]
+use std::time::UNIX_EPOCH;
+
+#[derive(Serialize)]
+pub struct BackupCopy {
+  pub path: String,
+  // "backed_up" (the same size, last modified before the backup), "changed" (in the backup, but
+  // modified since), "missing", or "unknown" when no backup can be read.
+  pub status: &'static str,
+  #[serde(rename = "backupPath")]
+  pub backup_path: Option<String>,
+}
+
+#[derive(Serialize)]
+pub struct BackupReport {
+  // Whether Time Machine has a backup destination.
+  pub configured: bool,
+  // The latest backup, when its disk is connected.
+  #[serde(rename = "latestBackup")]
+  pub latest_backup: Option<String>,
+  #[serde(rename = "backedUpAt")]
+  pub backed_up_at: Option<u64>,
+  pub files: Vec<BackupCopy>,
+}
+
+#[cfg(target_os = "macos")]
+fn tmutil(args: &[&str]) -> Option<String> {
+  let output = Command::new("tmutil").args(args).output().ok()?;
+  if !output.status.success() {
+    return None;
+  }
+  Some(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+#[cfg(target_os = "macos")]
+fn modified_secs(metadata: &fs::Metadata) -> Option<u64> {
+  metadata
+    .modified()
+    .ok()?
+    .duration_since(UNIX_EPOCH)
+    .ok()
+    .map(|duration| duration.as_secs())
+}
+
+// Where `path` would be in a backup: the backup holds a folder per backed-up volume ("Macintosh
+// HD - Data"), with the volume's contents under it.
+#[cfg(target_os = "macos")]
+fn find_in_backup(backup: &Path, path: &Path) -> Option<PathBuf> {
+  let resolved = path.canonicalize().unwrap_or_else(|_| path.to_path_buf());
+  let mount_point = crate::volumes::volume_for(&resolved)
+    .map(|volume| volume.mount_point)
+    .unwrap_or_else(|| PathBuf::from("/"));
+  let relative = resolved.strip_prefix(&mount_point).ok()?;
+  fs::read_dir(backup)
+    .ok()?
+    .flatten()
+    .map(|volume| volume.path().join(relative))
+    .find(|candidate| fs::symlink_metadata(candidate).is_ok())
+}
+
+// Whether each of `paths` has a copy in the latest Time Machine backup, for a warning before
+// deleting permanently. The backup has to be reachable (`tmutil latestbackup`), so a backup disk
+// that isn't connected leaves every file "unknown".
+#[cfg(target_os = "macos")]
+pub fn backup_status(paths: &[String]) -> BackupReport {
+  let configured = tmutil(&["destinationinfo"])
+    .is_some_and(|info| !info.is_empty() && !info.contains("No destinations configured"));
+  let latest = configured
+    .then(|| tmutil(&["latestbackup"]))
+    .flatten()
+    .map(PathBuf::from)
+    .filter(|backup| backup.is_dir());
+  let backed_up_at = latest
+    .as_ref()
+    .and_then(|backup| fs::metadata(backup).ok())
+    .and_then(|metadata| modified_secs(&metadata));
+
+  let files = paths
+    .iter()
+    .map(|path| {
+      let backup = match &latest {
+        Some(backup) => backup,
+        None => {
+          return BackupCopy {
+            path: path.clone(),
+            status: "unknown",
+            backup_path: None,
+          }
+        }
+      };
+      let copy = find_in_backup(backup, Path::new(path));
+      let status = match (&copy, fs::metadata(path)) {
+        (None, _) => "missing",
+        (Some(copy), Ok(current)) => {
+          let same_size = fs::metadata(copy).is_ok_and(|copy| copy.len() == current.len());
+          let unchanged = match (modified_secs(&current), backed_up_at) {
+            (Some(modified), Some(backed_up_at)) => modified <= backed_up_at,
+            _ => true,
+          };
+          if same_size && unchanged {
+            "backed_up"
+          } else {
+            "changed"
+          }
+        }
+        (Some(_), Err(_)) => "backed_up",
+      };
+      BackupCopy {
+        path: path.clone(),
+        status,
+        backup_path: copy.map(|copy| copy.to_string_lossy().to_string()),
+      }
+    })
+    .collect();
+
+  BackupReport {
+    configured,
+    latest_backup: latest.map(|backup| backup.to_string_lossy().to_string()),
+    backed_up_at,
+    files,
+  }
+}
+
+#[cfg(not(target_os = "macos"))]
+pub fn backup_status(paths: &[String]) -> BackupReport {
+  BackupReport {
+    configured: false,
+    latest_backup: None,
+    backed_up_at: None,
+    files: paths
+      .iter()
+      .map(|path| BackupCopy {
+        path: path.clone(),
+        status: "unknown",
+        backup_path: None,
+      })
+      .collect(),
+  }
+}
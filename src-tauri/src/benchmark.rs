@@ -0,0 +1,117 @@
+use crate::messages::Message;
+use crate::scanner;
+use crate::synthetic::{self, TreeSpec};
+use serde::Serialize;
+use std::sync::atomic::AtomicBool;
+use std::thread;
+use std::time::Instant;
+
+const THREAD_COUNTS: [usize; 5] = [1, 2, 4, 8, 16];
+const RUNS_PER_CONFIG: usize = 3;
+pub const PROFILES: [&str; 3] = ["quick", "wide", "deep"];
+
+#[derive(Serialize)]
+pub struct BenchmarkRun {
+  pub threads: usize,
+  // "unordered" reads entries as the filesystem returns them; "sorted" is the deterministic mode.
+  pub backend: &'static str,
+  // Fastest of the runs, with the cache already warm.
+  #[serde(rename = "elapsedMs")]
+  pub elapsed_ms: f64,
+  #[serde(rename = "filesPerSecond")]
+  pub files_per_second: f64,
+}
+
+#[derive(Serialize)]
+pub struct BenchmarkReport {
+  pub profile: String,
+  pub files: u64,
+  pub directories: u64,
+  pub cpus: usize,
+  pub runs: Vec<BenchmarkRun>,
+  // The fastest unordered run, i.e. what to set `threads` to on this machine.
+  #[serde(rename = "bestThreads")]
+  pub best_threads: usize,
+}
+
+fn profile_spec(profile: &str) -> Option<TreeSpec> {
+  let (depth, dirs_per_dir, files_per_dir) = match profile {
+    "quick" => (3, 4, 50),
+    "wide" => (2, 30, 100),
+    "deep" => (10, 2, 10),
+    _ => return None,
+  };
+  Some(TreeSpec {
+    depth,
+    dirs_per_dir,
+    files_per_dir,
+    max_file_bytes: 1 << 20,
+    ..TreeSpec::default()
+  })
+}
+
+// Folders in a tree of the given shape, root included.
+fn directory_count(spec: &TreeSpec) -> u64 {
+  (0..=spec.depth as u32)
+    .map(|level| (spec.dirs_per_dir as u64).pow(level))
+    .sum()
+}
+
+// Scans a synthetic tree in the temp folder with each thread count and traversal order, and
+// reports throughput. Every scan must find exactly the files the tree was built with.
+pub fn run(profile: &str) -> Result<BenchmarkReport, Message> {
+  let spec = profile_spec(profile).ok_or_else(|| {
+    Message::new("benchmark.unknown_profile").with("profiles", PROFILES.join(", "))
+  })?;
+  let tree = synthetic::build(&std::env::temp_dir(), "chonky-disk-benchmark", &spec)?;
+  let cpus = thread::available_parallelism()
+    .map(|cpus| cpus.get())
+    .unwrap_or(1);
+  let running = AtomicBool::new(false);
+  let scan = |threads: usize, sorted: bool| -> Result<f64, Message> {
+    let started = Instant::now();
+    let summary = scanner::scan_subtree_with(&tree.root, &running, 10, threads, sorted, None)
+      .ok_or_else(|| Message::new("scan.cancelled"))?;
+    let elapsed = started.elapsed().as_secs_f64();
+    if !tree.mismatches(&summary).is_empty() {
+      return Err(Message::new("benchmark.mismatch"));
+    }
+    Ok(elapsed)
+  };
+
+  // Warms the cache so the first configuration isn't penalised.
+  scan(1, false)?;
+  let mut runs = Vec::new();
+  for threads in THREAD_COUNTS
+    .into_iter()
+    .filter(|threads| *threads == 1 || *threads <= cpus * 2)
+  {
+    for (backend, sorted) in [("unordered", false), ("sorted", true)] {
+      let mut fastest = f64::MAX;
+      for _ in 0..RUNS_PER_CONFIG {
+        fastest = fastest.min(scan(threads, sorted)?);
+      }
+      runs.push(BenchmarkRun {
+        threads,
+        backend,
+        elapsed_ms: fastest * 1000.0,
+        files_per_second: tree.files as f64 / fastest.max(f64::EPSILON),
+      });
+    }
+  }
+
+  let best_threads = runs
+    .iter()
+    .filter(|run| run.backend == "unordered")
+    .max_by(|a, b| a.files_per_second.total_cmp(&b.files_per_second))
+    .map(|run| run.threads)
+    .unwrap_or(1);
+  Ok(BenchmarkReport {
+    profile: profile.to_string(),
+    files: tree.files,
+    directories: directory_count(&spec),
+    cpus,
+    runs,
+    best_threads,
+  })
+}
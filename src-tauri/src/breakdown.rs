@@ -0,0 +1,187 @@
+use crate::history;
+use crate::rules::expand_home;
+use crate::system_usage;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+// One of the folders a category is measured from, with the size its last completed scan found.
+#[derive(Serialize)]
+pub struct CategoryRoot {
+  pub path: String,
+  // None until the folder has been scanned on its own.
+  pub bytes: Option<u64>,
+  #[serde(rename = "scannedAt")]
+  pub scanned_at: Option<u64>,
+}
+
+#[derive(Serialize)]
+pub struct SpaceCategory {
+  // "system", "apps", "user", "purgeable" or "other".
+  pub id: &'static str,
+  // The scanned part of the category; None when none of its roots has been scanned.
+  pub bytes: Option<u64>,
+  // False while some of its roots are unscanned, so `bytes` is a lower bound.
+  pub complete: bool,
+  pub roots: Vec<CategoryRoot>,
+}
+
+// Used space in the categories of the macOS storage pane, from the scans of well-known folders.
+#[derive(Serialize)]
+pub struct SpaceBreakdown {
+  pub categories: Vec<SpaceCategory>,
+}
+
+#[cfg(target_os = "macos")]
+fn known_roots(category: &str) -> &'static [&'static str] {
+  match category {
+    "system" => &[
+      "/System",
+      "/Library",
+      "/usr",
+      "/bin",
+      "/sbin",
+      "/private/var",
+    ],
+    "apps" => &["/Applications", "~/Applications"],
+    "user" => &["/Users"],
+    "purgeable" => &["~/Library/Caches", "~/.Trash"],
+    _ => &[],
+  }
+}
+
+#[cfg(not(any(target_os = "macos", target_os = "windows")))]
+fn known_roots(category: &str) -> &'static [&'static str] {
+  match category {
+    "system" => &[
+      "/usr", "/boot", "/etc", "/var", "/lib", "/lib64", "/bin", "/sbin",
+    ],
+    "apps" => &[
+      "/opt",
+      "/snap",
+      "/var/lib/flatpak",
+      "~/.local/share/flatpak",
+    ],
+    "user" => &["/home", "/root"],
+    "purgeable" => &["/var/cache", "~/.cache", "~/.local/share/Trash"],
+    _ => &[],
+  }
+}
+
+#[cfg(target_os = "windows")]
+fn known_roots(category: &str) -> &'static [&'static str] {
+  match category {
+    "system" => &["C:\\Windows"],
+    "apps" => &[
+      "C:\\Program Files",
+      "C:\\Program Files (x86)",
+      "C:\\ProgramData",
+    ],
+    "user" => &["C:\\Users"],
+    "purgeable" => &["C:\\$Recycle.Bin", "C:\\Windows\\Temp"],
+    _ => &[],
+  }
+}
+
+const CATEGORIES: [&str; 4] = ["system", "apps", "user", "purgeable"];
+
+fn normalize(root: &str) -> PathBuf {
+  PathBuf::from(expand_home(root)).components().collect()
+}
+
+// Splits `used_bytes` of the volume holding `root` into categories. A folder nested in another
+// category's root (caches in a home folder, apps in /opt under /var) counts only once, in the
+// innermost; `system_bytes` (swap, hibernation) adds to the system category, and "other" is
+// whatever is left once every category is complete.
+pub fn breakdown(
+  app_dir: &Path,
+  root: &Path,
+  used_bytes: u64,
+  system_bytes: u64,
+) -> SpaceBreakdown {
+  // Newest first, so the first entry for a root is its latest full scan.
+  let mut scanned: HashMap<PathBuf, (u64, u64)> = HashMap::new();
+  for entry in history::load(app_dir) {
+    if !entry.cancelled && entry.include.is_empty() {
+      scanned
+        .entry(normalize(&entry.root))
+        .or_insert((entry.scanned_bytes, entry.finished_at));
+    }
+  }
+  let roots: Vec<(usize, PathBuf)> = CATEGORIES
+    .iter()
+    .enumerate()
+    .flat_map(|(index, category)| {
+      known_roots(category)
+        .iter()
+        .map(move |path| (index, normalize(path)))
+    })
+    // Not through symlinks: /lib and /bin are links into /usr on most distributions.
+    .filter(|(_, path)| {
+      fs::symlink_metadata(path).is_ok_and(|metadata| metadata.is_dir())
+        && system_usage::same_volume(path, root)
+    })
+    .collect();
+  let scanned_inside = |path: &Path| -> Vec<&Path> {
+    roots
+      .iter()
+      .map(|(_, other)| other.as_path())
+      .filter(|other| *other != path && other.starts_with(path) && scanned.contains_key(*other))
+      .collect()
+  };
+
+  let mut categories: Vec<SpaceCategory> = CATEGORIES
+    .iter()
+    .map(|id| SpaceCategory {
+      id,
+      bytes: None,
+      complete: true,
+      roots: Vec::new(),
+    })
+    .collect();
+  for (category, path) in &roots {
+    let scan = scanned.get(path).copied();
+    // Nested roots are measured separately, so their share comes out of this one; only the
+    // outermost of them, since those already hold the rest.
+    let inside = scanned_inside(path);
+    let nested: u64 = inside
+      .iter()
+      .filter(|other| {
+        !inside
+          .iter()
+          .any(|outer| outer != *other && other.starts_with(outer))
+      })
+      .filter_map(|other| scanned.get(*other).map(|(bytes, _)| *bytes))
+      .sum();
+    let bytes = scan.map(|(bytes, _)| bytes.saturating_sub(nested));
+    let slot = &mut categories[*category];
+    if let Some(bytes) = bytes {
+      slot.bytes = Some(slot.bytes.unwrap_or(0) + bytes);
+    } else {
+      slot.complete = false;
+    }
+    slot.roots.push(CategoryRoot {
+      path: path.to_string_lossy().to_string(),
+      bytes,
+      scanned_at: scan.map(|(_, finished_at)| finished_at),
+    });
+  }
+  if system_bytes > 0 {
+    let system = &mut categories[0];
+    system.bytes = Some(system.bytes.unwrap_or(0) + system_bytes);
+  }
+
+  let complete = categories.iter().all(|category| category.complete);
+  let known: u64 = categories
+    .iter()
+    .filter_map(|category| category.bytes)
+    .sum();
+  categories.push(SpaceCategory {
+    id: "other",
+    bytes: complete.then(|| used_bytes.saturating_sub(known)),
+    complete,
+    roots: Vec::new(),
+  });
+  SpaceBreakdown { categories }
+}
@@ -0,0 +1,265 @@
+use crate::dataless::NoMaterialize;
+use crate::messages::Message;
+use crate::rules;
+use crate::scanner;
+use crate::watcher::{self, OwnActivity};
+use notify::{RecommendedWatcher, RecursiveMode, Watcher};
+use serde::{Deserialize, Serialize};
+use std::cmp::Reverse;
+use std::path::PathBuf;
+use std::sync::{
+  atomic::{AtomicBool, Ordering},
+  mpsc, Arc, Mutex,
+};
+use std::thread;
+use std::time::{Duration, Instant};
+use tauri::Manager;
+
+const BUDGET_POLL_INTERVAL: Duration = Duration::from_millis(250);
+const REMEASURE_QUIET_PERIOD: Duration = Duration::from_secs(5);
+// Catches changes the watcher missed, e.g. while events were dropped.
+const REMEASURE_INTERVAL: Duration = Duration::from_secs(15 * 60);
+const RECENT_ADDITION_WINDOW: Duration = Duration::from_secs(24 * 60 * 60);
+const MAX_RECENT_ADDITIONS: usize = 10;
+
+// e.g. `{ "path": "~/Downloads", "maxBytes": 21474836480 }`.
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+pub struct Budget {
+  pub path: String,
+  #[serde(rename = "maxBytes")]
+  pub max_bytes: u64,
+}
+
+#[derive(Clone, Serialize)]
+pub struct RecentAddition {
+  path: String,
+  size: u64,
+  #[serde(rename = "secondsAgo")]
+  seconds_ago: u64,
+}
+
+#[derive(Clone, Serialize)]
+pub struct BudgetStatus {
+  path: String,
+  #[serde(rename = "maxBytes")]
+  max_bytes: u64,
+  // None until the folder has been measured, or if it does not exist.
+  #[serde(rename = "usedBytes")]
+  used_bytes: Option<u64>,
+  exceeded: bool,
+}
+
+#[derive(Clone, Serialize)]
+struct BudgetExceededPayload {
+  path: String,
+  #[serde(rename = "maxBytes")]
+  max_bytes: u64,
+  #[serde(rename = "usedBytes")]
+  used_bytes: u64,
+  #[serde(rename = "overageBytes")]
+  overage_bytes: u64,
+  // Largest files added or grown within the last day, biggest first.
+  #[serde(rename = "recentAdditions")]
+  recent_additions: Vec<RecentAddition>,
+}
+
+struct TrackedBudget {
+  budget: Budget,
+  root: PathBuf,
+  dirty_since: Option<Instant>,
+  measured_at: Option<Instant>,
+  additions: Vec<(PathBuf, u64, Instant)>,
+}
+
+impl TrackedBudget {
+  fn record_addition(&mut self, path: PathBuf, size: u64) {
+    self
+      .additions
+      .retain(|(existing, _, at)| existing != &path && at.elapsed() < RECENT_ADDITION_WINDOW);
+    self.additions.push((path, size, Instant::now()));
+    self.additions.sort_by_key(|(_, size, _)| Reverse(*size));
+    self.additions.truncate(MAX_RECENT_ADDITIONS);
+  }
+
+  fn recent_additions(&self) -> Vec<RecentAddition> {
+    self
+      .additions
+      .iter()
+      .filter(|(_, _, at)| at.elapsed() < RECENT_ADDITION_WINDOW)
+      .map(|(path, size, at)| RecentAddition {
+        path: path.to_string_lossy().to_string(),
+        size: *size,
+        seconds_ago: at.elapsed().as_secs(),
+      })
+      .collect()
+  }
+}
+
+// Watches every budgeted folder and re-measures it once changes settle; dropping the monitor
+// stops it.
+pub struct BudgetMonitor {
+  budgets: Vec<Budget>,
+  statuses: Arc<Mutex<Vec<BudgetStatus>>>,
+  stop: Arc<AtomicBool>,
+}
+
+impl BudgetMonitor {
+  pub fn budgets(&self) -> &[Budget] {
+    &self.budgets
+  }
+
+  pub fn statuses(&self) -> Vec<BudgetStatus> {
+    self
+      .statuses
+      .lock()
+      .map(|statuses| statuses.clone())
+      .unwrap_or_default()
+  }
+}
+
+impl Drop for BudgetMonitor {
+  fn drop(&mut self) {
+    self.stop.store(true, Ordering::Relaxed);
+  }
+}
+
+pub fn start(app: tauri::AppHandle, budgets: Vec<Budget>) -> Result<BudgetMonitor, Message> {
+  let (tx, rx) = mpsc::sync_channel(1024);
+  let mut watcher: RecommendedWatcher = notify::recommended_watcher(move |res| {
+    let _ = tx.try_send(res);
+  })
+  .map_err(|_| Message::new("watch.start_failed"))?;
+
+  let mut tracked: Vec<TrackedBudget> = budgets
+    .iter()
+    .map(|budget| TrackedBudget {
+      budget: budget.clone(),
+      root: PathBuf::from(rules::expand_home(&budget.path)),
+      dirty_since: None,
+      measured_at: None,
+      additions: Vec::new(),
+    })
+    .collect();
+  // Folders that do not exist yet are reported as unmeasured rather than failing the monitor.
+  for budget in &tracked {
+    if budget.root.is_dir() {
+      let _ = watcher.watch(&budget.root, RecursiveMode::Recursive);
+    }
+  }
+
+  let statuses = Arc::new(Mutex::new(
+    budgets
+      .iter()
+      .map(|budget| BudgetStatus {
+        path: budget.path.clone(),
+        max_bytes: budget.max_bytes,
+        used_bytes: None,
+        exceeded: false,
+      })
+      .collect::<Vec<_>>(),
+  ));
+  let stop = Arc::new(AtomicBool::new(false));
+  let monitor = BudgetMonitor {
+    budgets,
+    statuses: statuses.clone(),
+    stop: stop.clone(),
+  };
+
+  thread::spawn(move || {
+    let _watcher = watcher;
+    let _no_materialize = NoMaterialize::enter();
+    while !stop.load(Ordering::Relaxed) {
+      for (index, budget) in tracked.iter_mut().enumerate() {
+        let settled = budget
+          .dirty_since
+          .map(|since| since.elapsed() >= REMEASURE_QUIET_PERIOD)
+          .unwrap_or(false);
+        let stale = budget
+          .measured_at
+          .map(|at| at.elapsed() >= REMEASURE_INTERVAL)
+          .unwrap_or(true);
+        if settled || stale {
+          measure(&app, budget, &statuses, index, &stop);
+        }
+      }
+
+      let event = match rx.recv_timeout(BUDGET_POLL_INTERVAL) {
+        Ok(Ok(event)) => event,
+        Ok(Err(_)) | Err(mpsc::RecvTimeoutError::Timeout) => continue,
+        Err(mpsc::RecvTimeoutError::Disconnected) => return,
+      };
+      let (kind, dir_kind) = match watcher::event_kinds(&event.kind) {
+        Some(kinds) => kinds,
+        None => continue,
+      };
+      let own_activity = app.state::<OwnActivity>();
+      for path in event.paths {
+        let addition = match watcher::classify_change(&path, kind, dir_kind) {
+          Some(("create" | "modify", Some(size))) if !own_activity.contains(&path) => Some(size),
+          _ => None,
+        };
+        // Nested budgets (e.g. ~/Downloads inside ~) all see the change.
+        for budget in tracked
+          .iter_mut()
+          .filter(|budget| path.starts_with(&budget.root))
+        {
+          budget.dirty_since = Some(Instant::now());
+          if let Some(size) = addition {
+            budget.record_addition(path.clone(), size);
+          }
+        }
+      }
+    }
+  });
+
+  Ok(monitor)
+}
+
+// Emits `budget_exceeded` when a folder goes over its budget; it fires again only after the
+// folder has dropped back under.
+fn measure(
+  app: &tauri::AppHandle,
+  budget: &mut TrackedBudget,
+  statuses: &Mutex<Vec<BudgetStatus>>,
+  index: usize,
+  stop: &AtomicBool,
+) {
+  budget.dirty_since = None;
+  budget.measured_at = Some(Instant::now());
+  let used_bytes = if budget.root.is_dir() {
+    match scanner::scan_subtree(&budget.root, stop, 0) {
+      Some(summary) => Some(summary.scanned_bytes),
+      None => return,
+    }
+  } else {
+    None
+  };
+
+  let exceeded = used_bytes
+    .filter(|used| *used > budget.budget.max_bytes)
+    .is_some();
+  let was_exceeded = match statuses.lock() {
+    Ok(mut statuses) => {
+      let status = &mut statuses[index];
+      let was_exceeded = status.exceeded;
+      status.used_bytes = used_bytes;
+      status.exceeded = exceeded;
+      was_exceeded
+    }
+    Err(_) => return,
+  };
+
+  if let (true, false, Some(used_bytes)) = (exceeded, was_exceeded, used_bytes) {
+    let _ = app.emit_to(
+      "main",
+      "budget_exceeded",
+      BudgetExceededPayload {
+        path: budget.budget.path.clone(),
+        max_bytes: budget.budget.max_bytes,
+        used_bytes,
+        overage_bytes: used_bytes - budget.budget.max_bytes,
+        recent_additions: budget.recent_additions(),
+      },
+    );
+  }
+}
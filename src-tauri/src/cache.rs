@@ -0,0 +1,170 @@
+use crate::messages::Message;
+use crate::volumes;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::fs;
+use std::io::{BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+
+const FNV_OFFSET: u64 = 0xcbf2_9ce4_8422_2325;
+const FNV_PRIME: u64 = 0x0000_0100_0000_01b3;
+const ZSTD_LEVEL: i32 = 3;
+// A corrupt or crafted cache file is treated as missing rather than read without bound.
+const MAX_CACHE_BYTES: u64 = 512 * 1024 * 1024;
+
+// Stable across runs and Rust versions, unlike DefaultHasher.
+pub fn fnv1a(text: &str) -> u64 {
+  text.bytes().fold(FNV_OFFSET, |hash, byte| {
+    (hash ^ byte as u64).wrapping_mul(FNV_PRIME)
+  })
+}
+
+fn root_key(root: &str) -> String {
+  format!("{:016x}", fnv1a(root))
+}
+
+pub fn app_dir(app: &tauri::AppHandle) -> Result<PathBuf, Message> {
+  let dir = app
+    .path_resolver()
+    .app_data_dir()
+    .ok_or_else(|| Message::new("app.data_dir_unavailable"))?;
+  fs::create_dir_all(&dir).map_err(|_| Message::new("cache.create_dir_failed"))?;
+  Ok(dir)
+}
+
+// Everything cached for one scan root lives in its own folder under the app data dir, keyed by
+// volume UUID and the path on that volume when the volume has a UUID, so it survives remounts.
+pub fn root_dir(app: &tauri::AppHandle, root: &Path) -> Result<PathBuf, Message> {
+  root_dir_in(&app_dir(app)?, root)
+}
+
+pub fn root_dir_in(app_dir: &Path, root: &Path) -> Result<PathBuf, Message> {
+  let scans = app_dir.join("scans");
+  let by_path = scans.join(root_key(&root.to_string_lossy()));
+  let dir = match volumes::locate(root) {
+    Some(location) => {
+      let dir = scans.join(root_key(&format!(
+        "{}:{}",
+        location.uuid, location.relative_path
+      )));
+      // Folders created before caches were keyed by volume are adopted once.
+      if !dir.exists() && by_path.is_dir() {
+        let _ = fs::rename(&by_path, &dir);
+      }
+      dir
+    }
+    None => by_path,
+  };
+  fs::create_dir_all(&dir).map_err(|_| Message::new("cache.create_dir_failed"))?;
+  Ok(dir)
+}
+
+pub fn read_json<T: DeserializeOwned + Default>(path: &Path) -> T {
+  fs::metadata(path)
+    .ok()
+    .filter(|metadata| metadata.len() <= MAX_CACHE_BYTES)
+    .and_then(|_| fs::read(path).ok())
+    .and_then(|bytes| decode_json(&bytes))
+    .unwrap_or_default()
+}
+
+pub fn decode_json<T: DeserializeOwned>(bytes: &[u8]) -> Option<T> {
+  serde_json::from_slice(bytes).ok()
+}
+
+pub fn write_json<T: Serialize>(path: &Path, value: &T) -> Result<(), Message> {
+  let bytes = serde_json::to_vec(value).map_err(|_| Message::new("cache.encode_failed"))?;
+  let temp = path.with_extension("tmp");
+  fs::write(&temp, bytes)
+    .and_then(|_| fs::rename(&temp, path))
+    .map_err(|_| Message::new("cache.write_failed"))
+}
+
+// A read-only mapping of a whole file, so large caches are decoded straight from the page cache
+// instead of being copied into memory first.
+#[cfg(target_family = "unix")]
+struct MappedFile {
+  ptr: *mut libc::c_void,
+  len: usize,
+}
+
+#[cfg(target_family = "unix")]
+impl MappedFile {
+  fn open(path: &Path) -> Option<Self> {
+    use std::os::unix::io::AsRawFd;
+    let file = fs::File::open(path).ok()?;
+    let len = usize::try_from(file.metadata().ok()?.len()).ok()?;
+    if len == 0 {
+      return None;
+    }
+    let ptr = unsafe {
+      libc::mmap(
+        std::ptr::null_mut(),
+        len,
+        libc::PROT_READ,
+        libc::MAP_PRIVATE,
+        file.as_raw_fd(),
+        0,
+      )
+    };
+    if ptr == libc::MAP_FAILED {
+      return None;
+    }
+    Some(Self { ptr, len })
+  }
+
+  fn bytes(&self) -> &[u8] {
+    unsafe { std::slice::from_raw_parts(self.ptr as *const u8, self.len) }
+  }
+}
+
+#[cfg(target_family = "unix")]
+impl Drop for MappedFile {
+  fn drop(&mut self) {
+    unsafe { libc::munmap(self.ptr, self.len) };
+  }
+}
+
+#[cfg(target_family = "unix")]
+fn decode_zstd<T: DeserializeOwned>(path: &Path) -> Option<T> {
+  decode_compressed(MappedFile::open(path)?.bytes())
+}
+
+pub fn decode_compressed<T: DeserializeOwned>(bytes: &[u8]) -> Option<T> {
+  let decoder = zstd::stream::Decoder::new(bytes).ok()?;
+  serde_json::from_reader(decoder.take(MAX_CACHE_BYTES)).ok()
+}
+
+#[cfg(not(target_family = "unix"))]
+fn decode_zstd<T: DeserializeOwned>(path: &Path) -> Option<T> {
+  let file = fs::File::open(path).ok()?;
+  let decoder = zstd::stream::Decoder::new(file).ok()?;
+  serde_json::from_reader(decoder.take(MAX_CACHE_BYTES)).ok()
+}
+
+// For caches that grow with the number of files, e.g. `checksums.json.zst`. Data written as plain
+// JSON by older versions (the same name without `.zst`) is still read.
+pub fn read_compressed<T: DeserializeOwned + Default>(path: &Path) -> T {
+  if path.exists() {
+    return decode_zstd(path).unwrap_or_default();
+  }
+  read_json(&path.with_extension(""))
+}
+
+pub fn write_compressed<T: Serialize>(path: &Path, value: &T) -> Result<(), Message> {
+  let temp = path.with_extension("tmp");
+  let encoded = fs::File::create(&temp)
+    .and_then(|file| zstd::stream::Encoder::new(BufWriter::new(file), ZSTD_LEVEL))
+    .and_then(|mut encoder| {
+      serde_json::to_writer(&mut encoder, value)?;
+      encoder.finish()?.flush()
+    });
+  if encoded.is_err() {
+    let _ = fs::remove_file(&temp);
+    return Err(Message::new("cache.write_failed"));
+  }
+  fs::rename(&temp, path).map_err(|_| Message::new("cache.write_failed"))?;
+  // The uncompressed copy from older versions is now stale.
+  let _ = fs::remove_file(path.with_extension(""));
+  Ok(())
+}
@@ -0,0 +1,129 @@
+use crate::hashing::{self, HashThrottle};
+use crate::messages::Message;
+use crate::{cache, filesystem};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs::{self, Metadata};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::UNIX_EPOCH;
+
+const CHECKSUMS_FILE: &str = "checksums.json.zst";
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct ChecksumEntry {
+  size: u64,
+  // Nanoseconds since the epoch.
+  mtime: u64,
+  sha256: String,
+}
+
+// Truncated to what the filesystem stores (e.g. 2 seconds on FAT32), so a time that was
+// rounded on write, or reported more precisely by another OS, still matches.
+fn mtime_nanos(metadata: &Metadata, resolution: u64) -> Option<u64> {
+  let nanos = metadata
+    .modified()
+    .ok()?
+    .duration_since(UNIX_EPOCH)
+    .ok()
+    .and_then(|duration| u64::try_from(duration.as_nanos()).ok())?;
+  Some(nanos - nanos % resolution.max(1))
+}
+
+// File hashes for one scan root keyed by path, valid while size and mtime are unchanged, so
+// repeat duplicate searches only hash new or modified files.
+pub struct ChecksumDb {
+  file: Option<PathBuf>,
+  entries: Mutex<HashMap<String, ChecksumEntry>>,
+  // Nanoseconds.
+  mtime_resolution: u64,
+}
+
+impl ChecksumDb {
+  // Without a cache folder the database still works, but only for this run.
+  pub fn open(root_dir: Option<&Path>, root: &Path) -> Self {
+    let file = root_dir.map(|dir| dir.join(CHECKSUMS_FILE));
+    let entries = file
+      .as_deref()
+      .map(cache::read_compressed)
+      .unwrap_or_default();
+    let resolution_ms = filesystem::filesystem_info(root)
+      .map(|info| info.mtime_resolution_ms)
+      .unwrap_or(0);
+    Self {
+      file,
+      entries: Mutex::new(entries),
+      mtime_resolution: resolution_ms * 1_000_000,
+    }
+  }
+
+  pub fn sha256(&self, path: &Path, throttle: &HashThrottle) -> Result<String, Message> {
+    let metadata = fs::symlink_metadata(path).map_err(|_| Message::new("file.read_failed"))?;
+    let key = path.to_string_lossy().to_string();
+    let size = metadata.len();
+    let mtime = mtime_nanos(&metadata, self.mtime_resolution);
+
+    if let (Some(mtime), Ok(entries)) = (mtime, self.entries.lock()) {
+      if let Some(entry) = entries
+        .get(&key)
+        .filter(|entry| entry.size == size && entry.mtime == mtime)
+      {
+        return Ok(entry.sha256.clone());
+      }
+    }
+
+    let sha256 = hashing::sha256_file_throttled(path, throttle)?;
+    if let (Some(mtime), Ok(mut entries)) = (mtime, self.entries.lock()) {
+      entries.insert(
+        key,
+        ChecksumEntry {
+          size,
+          mtime,
+          sha256: sha256.clone(),
+        },
+      );
+    }
+    Ok(sha256)
+  }
+
+  // Entries for files that were deleted or changed since they were hashed are dropped.
+  pub fn save(&self) -> Result<(), Message> {
+    let file = match &self.file {
+      Some(file) => file,
+      None => return Ok(()),
+    };
+    let resolution = self.mtime_resolution;
+    let mut entries = self
+      .entries
+      .lock()
+      .map_err(|_| Message::new("state.checksum_lock_poisoned"))?;
+    entries.retain(|path, entry| {
+      fs::symlink_metadata(path)
+        .ok()
+        .filter(|metadata| {
+          metadata.len() == entry.size && mtime_nanos(metadata, resolution) == Some(entry.mtime)
+        })
+        .is_some()
+    });
+    cache::write_compressed(file, &*entries)
+  }
+}
+
+// Drops entries for files that no longer exist, for roots that are not being searched right now.
+// Returns the bytes saved on disk.
+pub fn compact(root_dir: &Path) -> u64 {
+  let file = root_dir.join(CHECKSUMS_FILE);
+  let before = match fs::metadata(&file) {
+    Ok(metadata) => metadata.len(),
+    Err(_) => return 0,
+  };
+  let mut entries: HashMap<String, ChecksumEntry> = cache::read_compressed(&file);
+  let count = entries.len();
+  entries.retain(|path, _| fs::symlink_metadata(path).is_ok());
+  if entries.len() == count || cache::write_compressed(&file, &entries).is_err() {
+    return 0;
+  }
+  fs::metadata(&file)
+    .map(|metadata| before.saturating_sub(metadata.len()))
+    .unwrap_or(0)
+}
@@ -0,0 +1,263 @@
+use crate::messages::Message;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::fs;
+#[cfg(target_family = "unix")]
+use std::os::unix::fs::MetadataExt;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Condvar, Mutex};
+use std::time::{Duration, Instant};
+use tauri::Manager;
+
+// An unanswered prompt skips the file, so an unattended run doesn't hang.
+const ASK_TIMEOUT: Duration = Duration::from_secs(10 * 60);
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Resolution {
+  Skip,
+  Overwrite,
+  Rename,
+}
+
+impl Resolution {
+  pub fn parse(value: &str) -> Result<Self, Message> {
+    match value {
+      "skip" => Ok(Self::Skip),
+      "overwrite" => Ok(Self::Overwrite),
+      "rename" => Ok(Self::Rename),
+      _ => Err(Message::new("conflict.invalid_resolution")),
+    }
+  }
+}
+
+// What to do when a move, copy or restore finds its target name taken: "skip", "overwrite",
+// "rename" (`name 2.ext`, `name 3.ext`, ...) or "ask", which sends `file_conflict` and waits for
+// `resolve_conflict`.
+#[derive(Clone, Copy, PartialEq)]
+pub enum ConflictPolicy {
+  Always(Resolution),
+  Ask,
+}
+
+impl ConflictPolicy {
+  pub fn parse(value: Option<&str>) -> Result<Self, Message> {
+    match value.unwrap_or("skip") {
+      "ask" => Ok(Self::Ask),
+      value => Resolution::parse(value)
+        .map(Self::Always)
+        .map_err(|_| Message::new("conflict.invalid_policy")),
+    }
+  }
+  // Whether files already in the way may be replaced: always, or when the user is asked.
+  pub fn may_overwrite(self) -> bool {
+    matches!(self, Self::Always(Resolution::Overwrite) | Self::Ask)
+  }
+}
+
+#[derive(Clone, Serialize)]
+pub struct ConflictPrompt {
+  pub id: u64,
+  // e.g. "export" or "archive_policy".
+  pub operation: &'static str,
+  pub source: String,
+  pub target: String,
+}
+
+// Prompts sent to the window and not yet answered, with the answer once it arrives.
+#[derive(Default)]
+pub struct ConflictPrompts {
+  next_id: AtomicU64,
+  pending: Mutex<HashMap<u64, Option<(Resolution, bool)>>>,
+  answered: Condvar,
+}
+
+impl ConflictPrompts {
+  // Emits `file_conflict` and waits for its answer: the resolution, and whether it applies to
+  // the rest of the operation. None if nobody answers in time.
+  fn ask(&self, app: &tauri::AppHandle, prompt: ConflictPrompt) -> Option<(Resolution, bool)> {
+    let id = prompt.id;
+    self.pending.lock().ok()?.insert(id, None);
+    let _ = app.emit_to("main", "file_conflict", prompt);
+    let deadline = Instant::now() + ASK_TIMEOUT;
+    let mut pending = self.pending.lock().ok()?;
+    loop {
+      if let Some(answer) = pending.get(&id).copied().flatten() {
+        pending.remove(&id);
+        return Some(answer);
+      }
+      let left = deadline.saturating_duration_since(Instant::now());
+      if left.is_zero() {
+        pending.remove(&id);
+        return None;
+      }
+      pending = self.answered.wait_timeout(pending, left).ok()?.0;
+    }
+  }
+
+  pub fn answer(&self, id: u64, resolution: Resolution, apply_to_all: bool) -> Result<(), Message> {
+    let mut pending = self
+      .pending
+      .lock()
+      .map_err(|_| Message::new("state.conflict_lock_poisoned"))?;
+    match pending.get_mut(&id) {
+      Some(answer @ None) => *answer = Some((resolution, apply_to_all)),
+      _ => return Err(Message::new("conflict.none_waiting")),
+    }
+    self.answered.notify_all();
+    Ok(())
+  }
+}
+
+// Where to put a file: a free name, or the taken one when the file there is to be replaced.
+pub struct Placement {
+  pub path: PathBuf,
+  pub replace: bool,
+}
+
+// Settles the name clashes of one operation. An "ask" answer applied to all is reused for the
+// rest of it; without a window to ask, asking skips.
+pub struct Conflicts<'a> {
+  policy: ConflictPolicy,
+  operation: &'static str,
+  app: Option<&'a tauri::AppHandle>,
+  remembered: Option<Resolution>,
+}
+
+impl<'a> Conflicts<'a> {
+  pub fn new(
+    policy: ConflictPolicy,
+    operation: &'static str,
+    app: Option<&'a tauri::AppHandle>,
+  ) -> Self {
+    Self {
+      policy,
+      operation,
+      app,
+      remembered: None,
+    }
+  }
+
+  // Where `source` should go instead of `target`, or None to leave it out.
+  pub fn resolve(&mut self, source: &Path, target: &Path) -> Result<Option<Placement>, Message> {
+    let existing = match fs::symlink_metadata(target) {
+      Ok(existing) => existing,
+      Err(_) => {
+        return Ok(Some(Placement {
+          path: target.to_path_buf(),
+          replace: false,
+        }))
+      }
+    };
+    let resolution = match (self.policy, self.remembered) {
+      (ConflictPolicy::Always(resolution), _) | (ConflictPolicy::Ask, Some(resolution)) => {
+        resolution
+      }
+      (ConflictPolicy::Ask, None) => self.ask(source, target),
+    };
+    match resolution {
+      Resolution::Skip => Ok(None),
+      Resolution::Overwrite => {
+        if existing.is_dir() {
+          return Err(Message::new("conflict.folder_in_way"));
+        }
+        if same_file(source, target) {
+          return Err(Message::new("conflict.same_file"));
+        }
+        Ok(Some(Placement {
+          path: target.to_path_buf(),
+          replace: true,
+        }))
+      }
+      Resolution::Rename => {
+        let dir = target.parent().unwrap_or(Path::new(""));
+        let name = unique_name(target, |name| fs::symlink_metadata(dir.join(name)).is_ok())?;
+        Ok(Some(Placement {
+          path: dir.join(name),
+          replace: false,
+        }))
+      }
+    }
+  }
+
+  fn ask(&mut self, source: &Path, target: &Path) -> Resolution {
+    let app = match self.app {
+      Some(app) => app,
+      None => return Resolution::Skip,
+    };
+    let prompts = app.state::<ConflictPrompts>();
+    let prompt = ConflictPrompt {
+      id: prompts.next_id.fetch_add(1, Ordering::Relaxed),
+      operation: self.operation,
+      source: source.to_string_lossy().to_string(),
+      target: target.to_string_lossy().to_string(),
+    };
+    match prompts.ask(app, prompt) {
+      Some((resolution, apply_to_all)) => {
+        if apply_to_all {
+          self.remembered = Some(resolution);
+        }
+        resolution
+      }
+      None => Resolution::Skip,
+    }
+  }
+}
+
+#[cfg(target_family = "unix")]
+fn same_file(a: &Path, b: &Path) -> bool {
+  match (fs::metadata(a), fs::metadata(b)) {
+    (Ok(a), Ok(b)) => a.dev() == b.dev() && a.ino() == b.ino(),
+    _ => false,
+  }
+}
+
+#[cfg(not(target_family = "unix"))]
+fn same_file(a: &Path, b: &Path) -> bool {
+  match (fs::canonicalize(a), fs::canonicalize(b)) {
+    (Ok(a), Ok(b)) => a == b,
+    _ => false,
+  }
+}
+
+// Writes the file with `write`, straight to its place or, when replacing, next to it first so
+// the old file is only swapped out once the new one is complete.
+pub fn write_then_place(
+  placement: &Placement,
+  write: impl FnOnce(&Path) -> Result<(), Message>,
+) -> Result<(), Message> {
+  if !placement.replace {
+    return write(&placement.path);
+  }
+  let name = placement
+    .path
+    .file_name()
+    .map(|name| name.to_string_lossy().to_string())
+    .ok_or_else(|| Message::new("path.invalid_file_name"))?;
+  let staging = placement.path.with_file_name(format!(".{}.partial", name));
+  let _ = fs::remove_file(&staging);
+  write(&staging)?;
+  fs::rename(&staging, &placement.path).map_err(|_| {
+    let _ = fs::remove_file(&staging);
+    Message::new("conflict.replace_failed")
+  })
+}
+
+// `name`, then `name 2`, `name 3`, ... until `taken` says the name is free.
+pub fn unique_name(path: &Path, taken: impl Fn(&str) -> bool) -> Result<String, Message> {
+  let name = path
+    .file_name()
+    .map(|name| name.to_string_lossy().to_string())
+    .ok_or_else(|| Message::new("path.invalid_file_name"))?;
+  if !taken(&name) {
+    return Ok(name);
+  }
+  let (stem, extension) = match name.rsplit_once('.') {
+    Some((stem, extension)) if !stem.is_empty() => (stem.to_string(), format!(".{}", extension)),
+    _ => (name.clone(), String::new()),
+  };
+  (2..10_000)
+    .map(|counter| format!("{} {}{}", stem, counter, extension))
+    .find(|candidate| !taken(candidate))
+    .ok_or_else(|| Message::new("conflict.no_free_name"))
+}
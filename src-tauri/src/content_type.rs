@@ -0,0 +1,286 @@
+use crate::messages::Message;
+use serde::Serialize;
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom};
+use std::path::Path;
+
+const HEADER_BYTES: usize = 512;
+const ISO_MAGIC_OFFSET: u64 = 0x8001;
+
+struct Signature {
+  offset: usize,
+  magic: &'static [u8],
+  mime: &'static str,
+  category: &'static str,
+  extensions: &'static [&'static str],
+}
+
+const SIGNATURES: &[Signature] = &[
+  Signature {
+    offset: 0,
+    magic: b"\x1A\x45\xDF\xA3",
+    mime: "video/x-matroska",
+    category: "video",
+    extensions: &["mkv", "webm", "mka"],
+  },
+  Signature {
+    offset: 8,
+    magic: b"AVI ",
+    mime: "video/x-msvideo",
+    category: "video",
+    extensions: &["avi"],
+  },
+  Signature {
+    offset: 0,
+    magic: b"\x00\x00\x01\xBA",
+    mime: "video/mpeg",
+    category: "video",
+    extensions: &["mpg", "mpeg", "vob"],
+  },
+  Signature {
+    offset: 8,
+    magic: b"WAVE",
+    mime: "audio/wav",
+    category: "audio",
+    extensions: &["wav"],
+  },
+  Signature {
+    offset: 0,
+    magic: b"ID3",
+    mime: "audio/mpeg",
+    category: "audio",
+    extensions: &["mp3"],
+  },
+  Signature {
+    offset: 0,
+    magic: b"fLaC",
+    mime: "audio/flac",
+    category: "audio",
+    extensions: &["flac"],
+  },
+  Signature {
+    offset: 0,
+    magic: b"OggS",
+    mime: "audio/ogg",
+    category: "audio",
+    extensions: &["ogg", "oga", "ogv", "opus"],
+  },
+  Signature {
+    offset: 0,
+    magic: b"\xFF\xD8\xFF",
+    mime: "image/jpeg",
+    category: "image",
+    extensions: &["jpg", "jpeg"],
+  },
+  Signature {
+    offset: 0,
+    magic: b"\x89PNG\r\n\x1A\n",
+    mime: "image/png",
+    category: "image",
+    extensions: &["png"],
+  },
+  Signature {
+    offset: 0,
+    magic: b"GIF8",
+    mime: "image/gif",
+    category: "image",
+    extensions: &["gif"],
+  },
+  Signature {
+    offset: 8,
+    magic: b"WEBP",
+    mime: "image/webp",
+    category: "image",
+    extensions: &["webp"],
+  },
+  Signature {
+    offset: 0,
+    magic: b"%PDF",
+    mime: "application/pdf",
+    category: "document",
+    extensions: &["pdf"],
+  },
+  Signature {
+    offset: 0,
+    magic: b"SQLite format 3\x00",
+    mime: "application/vnd.sqlite3",
+    category: "database",
+    extensions: &["sqlite", "sqlite3", "db"],
+  },
+  Signature {
+    offset: 0,
+    magic: b"PK\x03\x04",
+    mime: "application/zip",
+    category: "archive",
+    extensions: &["zip", "jar", "apk", "ipa", "docx", "xlsx", "pptx", "epub"],
+  },
+  Signature {
+    offset: 0,
+    magic: b"\x1F\x8B",
+    mime: "application/gzip",
+    category: "archive",
+    extensions: &["gz", "tgz"],
+  },
+  Signature {
+    offset: 0,
+    magic: b"7z\xBC\xAF\x27\x1C",
+    mime: "application/x-7z-compressed",
+    category: "archive",
+    extensions: &["7z"],
+  },
+  Signature {
+    offset: 0,
+    magic: b"Rar!\x1A\x07",
+    mime: "application/vnd.rar",
+    category: "archive",
+    extensions: &["rar"],
+  },
+  Signature {
+    offset: 0,
+    magic: b"BZh",
+    mime: "application/x-bzip2",
+    category: "archive",
+    extensions: &["bz2", "tbz2"],
+  },
+  Signature {
+    offset: 0,
+    magic: b"\xFD7zXZ\x00",
+    mime: "application/x-xz",
+    category: "archive",
+    extensions: &["xz", "txz"],
+  },
+  Signature {
+    offset: 0,
+    magic: b"\x28\xB5\x2F\xFD",
+    mime: "application/zstd",
+    category: "archive",
+    extensions: &["zst"],
+  },
+  Signature {
+    offset: 257,
+    magic: b"ustar",
+    mime: "application/x-tar",
+    category: "archive",
+    extensions: &["tar"],
+  },
+  Signature {
+    offset: 0,
+    magic: b"\x7FELF",
+    mime: "application/x-elf",
+    category: "executable",
+    extensions: &["so", "o", "elf", "bin"],
+  },
+  Signature {
+    offset: 0,
+    magic: b"\xCF\xFA\xED\xFE",
+    mime: "application/x-mach-binary",
+    category: "executable",
+    extensions: &["dylib", "bundle", "o"],
+  },
+  Signature {
+    offset: 0,
+    magic: b"\xCA\xFE\xBA\xBE",
+    mime: "application/x-mach-binary",
+    category: "executable",
+    extensions: &["dylib", "bundle", "class"],
+  },
+  Signature {
+    offset: 0,
+    magic: b"MZ",
+    mime: "application/vnd.microsoft.portable-executable",
+    category: "executable",
+    extensions: &["exe", "dll", "sys"],
+  },
+];
+
+#[derive(Serialize)]
+pub struct ContentType {
+  pub path: String,
+  pub mime: String,
+  pub category: String,
+  pub extension: Option<String>,
+  // True when the extension does not match the detected content, e.g. a video saved as ".dat".
+  pub mislabeled: bool,
+}
+
+// ISO base media files (mp4, mov, m4a, heic) share the "ftyp" box and differ by brand.
+fn iso_media_type(header: &[u8]) -> Option<(&'static str, &'static str, &'static [&'static str])> {
+  if header.get(4..8)? != b"ftyp" {
+    return None;
+  }
+  Some(match header.get(8..12)? {
+    b"qt  " => ("video/quicktime", "video", &["mov", "qt"]),
+    b"M4A " | b"M4B " => ("audio/mp4", "audio", &["m4a", "m4b"]),
+    b"heic" | b"heix" | b"mif1" => ("image/heic", "image", &["heic", "heif"]),
+    _ => ("video/mp4", "video", &["mp4", "m4v", "3gp"]),
+  })
+}
+
+fn is_text(header: &[u8]) -> bool {
+  if header.is_empty() || header.contains(&0) {
+    return false;
+  }
+  match std::str::from_utf8(header) {
+    Ok(_) => true,
+    // The sample may end in the middle of a multi-byte character.
+    Err(error) => error.error_len().is_none(),
+  }
+}
+
+fn read_iso_magic(file: &mut File) -> bool {
+  let mut magic = [0u8; 5];
+  file.seek(SeekFrom::Start(ISO_MAGIC_OFFSET)).is_ok()
+    && file.read_exact(&mut magic).is_ok()
+    && &magic == b"CD001"
+}
+
+pub fn sniff_type(path: &Path) -> Result<ContentType, Message> {
+  let mut file = File::open(path).map_err(|_| Message::new("file.open_failed"))?;
+  let mut header = Vec::with_capacity(HEADER_BYTES);
+  file
+    .by_ref()
+    .take(HEADER_BYTES as u64)
+    .read_to_end(&mut header)
+    .map_err(|_| Message::new("file.read_failed"))?;
+
+  let detected = iso_media_type(&header).or_else(|| {
+    SIGNATURES
+      .iter()
+      .find(|signature| {
+        header.get(signature.offset..signature.offset + signature.magic.len())
+          == Some(signature.magic)
+      })
+      .map(|signature| (signature.mime, signature.category, signature.extensions))
+  });
+  let detected = detected.or_else(|| {
+    read_iso_magic(&mut file).then_some((
+      "application/x-iso9660-image",
+      "disk_image",
+      &["iso", "cdr", "img"][..],
+    ))
+  });
+
+  let extension = path
+    .extension()
+    .map(|extension| extension.to_string_lossy().to_lowercase());
+
+  let (mime, category, mislabeled) = match detected {
+    Some((mime, category, extensions)) => {
+      let mislabeled = match &extension {
+        Some(extension) => !extensions.contains(&extension.as_str()),
+        None => false,
+      };
+      (mime, category, mislabeled)
+    }
+    None if is_text(&header) => ("text/plain", "text", false),
+    None => ("application/octet-stream", "unknown", false),
+  };
+
+  Ok(ContentType {
+    path: path.to_string_lossy().to_string(),
+    mime: mime.to_string(),
+    category: category.to_string(),
+    extension,
+    mislabeled,
+  })
+}
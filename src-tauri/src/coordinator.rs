@@ -0,0 +1,454 @@
+use crate::messages::Message;
+use crate::scanner::{MemoryCounters, ResyncRequests};
+use crate::watcher::{self, WatchCommand, WatchHandle, WatchStatus, WatchWorker};
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{mpsc, Arc};
+use std::thread;
+
+// What a scan that was just made the active one runs with.
+pub struct ScanTicket {
+  pub scan_id: u64,
+  pub cancel_flag: Arc<AtomicBool>,
+  // The walk waits while this is set.
+  pub paused: Arc<AtomicBool>,
+  pub memory: Arc<MemoryCounters>,
+  pub resync: Arc<ResyncRequests>,
+  // Watching starts only if no other scan began in the meantime.
+  pub watch_generation: u64,
+}
+
+// Where to watch once a scan has finished.
+pub struct WatchRequest {
+  pub app: tauri::AppHandle,
+  pub root: PathBuf,
+  pub scan_id: u64,
+  pub watch_generation: u64,
+  pub cancel_flag: Arc<AtomicBool>,
+  pub ignore_patterns: Vec<String>,
+}
+
+pub struct Snapshot {
+  pub active_id: Option<u64>,
+  pub paused: bool,
+  pub memory: Arc<MemoryCounters>,
+  pub resync: Arc<ResyncRequests>,
+  pub watch: Option<WatchStatus>,
+}
+
+enum Command {
+  Start(mpsc::Sender<ScanTicket>),
+  StartVolumes(mpsc::Sender<(u64, Arc<AtomicBool>)>),
+  Cancel(u64, mpsc::Sender<bool>),
+  Finish(u64),
+  FinishVolumes(u64),
+  Pause(u64, bool, mpsc::Sender<bool>),
+  Watch(Box<WatchRequest>, mpsc::Sender<Option<WatchWorker>>),
+  SendToWatch(WatchCommand, mpsc::Sender<bool>),
+  Query(mpsc::Sender<Snapshot>),
+  SkipIds(u64),
+}
+
+// The scan lifecycle: which scan is active, its cancel and pause flags, the watcher that follows
+// it, and the volume scans. Only the coordinator thread touches it, one command at a time.
+struct Lifecycle {
+  next_id: u64,
+  active_id: Option<u64>,
+  cancel_flag: Arc<AtomicBool>,
+  paused: Arc<AtomicBool>,
+  watch_generation: u64,
+  watch: Option<WatchHandle>,
+  memory: Arc<MemoryCounters>,
+  resync: Arc<ResyncRequests>,
+  volume_scans: HashMap<u64, Arc<AtomicBool>>,
+}
+
+impl Lifecycle {
+  fn new() -> Self {
+    Self {
+      next_id: 1,
+      active_id: None,
+      cancel_flag: Arc::new(AtomicBool::new(false)),
+      paused: Arc::new(AtomicBool::new(false)),
+      watch_generation: 0,
+      watch: None,
+      memory: Arc::new(MemoryCounters::default()),
+      resync: Arc::new(ResyncRequests::default()),
+      volume_scans: HashMap::new(),
+    }
+  }
+
+  fn take_id(&mut self) -> u64 {
+    let scan_id = self.next_id;
+    self.next_id = self.next_id.wrapping_add(1);
+    scan_id
+  }
+
+  // Cancels the running scan and watcher, and makes a new scan the active one.
+  fn start(&mut self) -> ScanTicket {
+    if self.active_id.is_some() {
+      self.cancel_flag.store(true, Ordering::Relaxed);
+    }
+    let scan_id = self.take_id();
+    self.watch_generation = self.watch_generation.wrapping_add(1);
+    self.cancel_flag = Arc::new(AtomicBool::new(false));
+    self.paused = Arc::new(AtomicBool::new(false));
+    self.active_id = Some(scan_id);
+    self.watch = None;
+    self.memory = Arc::new(MemoryCounters::default());
+    self.resync = Arc::new(ResyncRequests::default());
+    ScanTicket {
+      scan_id,
+      cancel_flag: self.cancel_flag.clone(),
+      paused: self.paused.clone(),
+      memory: self.memory.clone(),
+      resync: self.resync.clone(),
+      watch_generation: self.watch_generation,
+    }
+  }
+
+  // Volume scans run alongside the active scan, each with its own cancel flag.
+  fn start_volumes(&mut self) -> (u64, Arc<AtomicBool>) {
+    let scan_id = self.take_id();
+    let cancel = Arc::new(AtomicBool::new(false));
+    self.volume_scans.insert(scan_id, cancel.clone());
+    (scan_id, cancel)
+  }
+
+  // Cancels `scan_id`, whether it is scanning, watching, or scanning all volumes, so it is no
+  // longer the active scan. False when it is none of those.
+  fn cancel(&mut self, scan_id: u64) -> bool {
+    if let Some(cancel) = self.volume_scans.remove(&scan_id) {
+      cancel.store(true, Ordering::Relaxed);
+      return true;
+    }
+    let watching = self.watch.as_ref().map(|watch| watch.scan_id) == Some(scan_id);
+    if self.active_id == Some(scan_id) || watching {
+      self.cancel_flag.store(true, Ordering::Relaxed);
+      self.paused.store(false, Ordering::Relaxed);
+      self.active_id = None;
+      self.watch = None;
+      true
+    } else {
+      false
+    }
+  }
+
+  // A scan that ran to its end (or noticed its cancellation) stops being the active one, unless
+  // a newer scan has taken over.
+  fn finish(&mut self, scan_id: u64) {
+    if self.active_id == Some(scan_id) {
+      self.active_id = None;
+      self.paused.store(false, Ordering::Relaxed);
+    }
+  }
+
+  fn pause(&mut self, scan_id: u64, paused: bool) -> bool {
+    if self.active_id != Some(scan_id) {
+      return false;
+    }
+    self.paused.store(paused, Ordering::Relaxed);
+    true
+  }
+
+  // The worker is returned rather than started, so the filesystem is only touched off this thread.
+  fn watch(&mut self, request: WatchRequest) -> Option<WatchWorker> {
+    let current = !request.cancel_flag.load(Ordering::Relaxed)
+      && request.watch_generation == self.watch_generation;
+    if !current {
+      return None;
+    }
+    let (handle, worker) = watcher::prepare_fs_watcher(
+      request.app,
+      request.root,
+      request.scan_id,
+      request.cancel_flag,
+      request.ignore_patterns,
+    );
+    self.watch = Some(handle);
+    Some(worker)
+  }
+
+  fn snapshot(&self) -> Snapshot {
+    Snapshot {
+      active_id: self.active_id,
+      paused: self.active_id.is_some() && self.paused.load(Ordering::Relaxed),
+      memory: self.memory.clone(),
+      resync: self.resync.clone(),
+      watch: self.watch.as_ref().map(|watch| watch.status()),
+    }
+  }
+
+  // A closed reply channel means the caller gave up waiting; there is nobody to tell.
+  fn handle(&mut self, command: Command) {
+    match command {
+      Command::Start(reply) => {
+        let _ = reply.send(self.start());
+      }
+      Command::StartVolumes(reply) => {
+        let _ = reply.send(self.start_volumes());
+      }
+      Command::Cancel(scan_id, reply) => {
+        let _ = reply.send(self.cancel(scan_id));
+      }
+      Command::Finish(scan_id) => self.finish(scan_id),
+      Command::FinishVolumes(scan_id) => {
+        self.volume_scans.remove(&scan_id);
+      }
+      Command::Pause(scan_id, paused, reply) => {
+        let _ = reply.send(self.pause(scan_id, paused));
+      }
+      Command::Watch(request, reply) => {
+        let _ = reply.send(self.watch(*request));
+      }
+      Command::SendToWatch(command, reply) => {
+        let sent = self.watch.as_ref().is_some_and(|watch| watch.send(command));
+        let _ = reply.send(sent);
+      }
+      Command::Query(reply) => {
+        let _ = reply.send(self.snapshot());
+      }
+      Command::SkipIds(next_id) => self.next_id = self.next_id.max(next_id),
+    }
+  }
+}
+
+// Runs the scan lifecycle on its own thread and takes commands over a channel, so starts,
+// cancels, pauses and finishes from commands and worker threads are applied one at a time, in
+// the order they were sent, without a lock to hold or to poison.
+pub struct ScanCoordinator {
+  commands: mpsc::Sender<Command>,
+}
+
+impl ScanCoordinator {
+  pub fn start() -> Self {
+    let (commands, receiver) = mpsc::channel();
+    thread::spawn(move || {
+      let mut lifecycle = Lifecycle::new();
+      for command in receiver {
+        lifecycle.handle(command);
+      }
+    });
+    Self { commands }
+  }
+
+  fn request<T>(&self, command: impl FnOnce(mpsc::Sender<T>) -> Command) -> Result<T, Message> {
+    let (reply, response) = mpsc::channel();
+    self
+      .commands
+      .send(command(reply))
+      .map_err(|_| Message::new("scan.coordinator_stopped"))?;
+    response
+      .recv()
+      .map_err(|_| Message::new("scan.coordinator_stopped"))
+  }
+
+  pub fn begin_scan(&self) -> Result<ScanTicket, Message> {
+    self.request(Command::Start)
+  }
+
+  pub fn begin_volume_scan(&self) -> Result<(u64, Arc<AtomicBool>), Message> {
+    self.request(Command::StartVolumes)
+  }
+
+  pub fn cancel(&self, scan_id: u64) -> Result<bool, Message> {
+    self.request(|reply| Command::Cancel(scan_id, reply))
+  }
+
+  pub fn finish(&self, scan_id: u64) {
+    let _ = self.commands.send(Command::Finish(scan_id));
+  }
+
+  pub fn finish_volume_scan(&self, scan_id: u64) {
+    let _ = self.commands.send(Command::FinishVolumes(scan_id));
+  }
+
+  // False when `scan_id` isn't the active scan.
+  pub fn set_paused(&self, scan_id: u64, paused: bool) -> Result<bool, Message> {
+    self.request(|reply| Command::Pause(scan_id, paused, reply))
+  }
+
+  // Starts watching after a scan, unless it was cancelled or another scan began since. The
+  // watcher thread is spawned here, on the caller's thread.
+  pub fn watch(&self, request: WatchRequest) -> bool {
+    match self.request(|reply| Command::Watch(Box::new(request), reply)) {
+      Ok(Some(worker)) => {
+        worker.spawn();
+        true
+      }
+      _ => false,
+    }
+  }
+
+  // False when nothing is being watched.
+  pub fn send_to_watch(&self, command: WatchCommand) -> bool {
+    self
+      .request(|reply| Command::SendToWatch(command, reply))
+      .unwrap_or(false)
+  }
+
+  pub fn snapshot(&self) -> Result<Snapshot, Message> {
+    self.request(Command::Query)
+  }
+
+  // Scan IDs carry on from the history, so each names one scan across launches.
+  pub fn skip_ids_below(&self, next_id: u64) {
+    let _ = self.commands.send(Command::SkipIds(next_id));
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  const THREADS: usize = 8;
+  const ROUNDS: usize = 200;
+
+  // Every scan but the active one has been told to stop.
+  fn assert_only_active_runs(coordinator: &ScanCoordinator, tickets: &[ScanTicket]) {
+    let active_id = coordinator.snapshot().unwrap().active_id;
+    for ticket in tickets {
+      let active = active_id == Some(ticket.scan_id);
+      assert_eq!(
+        ticket.cancel_flag.load(Ordering::Relaxed),
+        !active,
+        "scan {} active: {}",
+        ticket.scan_id,
+        active
+      );
+    }
+  }
+
+  #[test]
+  fn concurrent_starts_leave_one_active_scan() {
+    let coordinator = Arc::new(ScanCoordinator::start());
+    let workers: Vec<_> = (0..THREADS)
+      .map(|_| {
+        let coordinator = coordinator.clone();
+        thread::spawn(move || {
+          (0..ROUNDS)
+            .map(|_| coordinator.begin_scan().unwrap())
+            .collect::<Vec<_>>()
+        })
+      })
+      .collect();
+    let tickets: Vec<_> = workers
+      .into_iter()
+      .flat_map(|worker| worker.join().unwrap())
+      .collect();
+
+    let mut ids: Vec<u64> = tickets.iter().map(|ticket| ticket.scan_id).collect();
+    ids.sort_unstable();
+    ids.dedup();
+    assert_eq!(ids.len(), THREADS * ROUNDS);
+    // Each scan gets its own generation, and the latest belongs to the active scan.
+    let mut generations: Vec<u64> = tickets
+      .iter()
+      .map(|ticket| ticket.watch_generation)
+      .collect();
+    generations.sort_unstable();
+    generations.dedup();
+    assert_eq!(generations.len(), THREADS * ROUNDS);
+    let latest = tickets
+      .iter()
+      .max_by_key(|ticket| ticket.watch_generation)
+      .unwrap();
+    let snapshot = coordinator.snapshot().unwrap();
+    assert_eq!(snapshot.active_id, Some(latest.scan_id));
+    assert_only_active_runs(&coordinator, &tickets);
+  }
+
+  #[test]
+  fn cancels_racing_starts_never_stop_a_newer_scan() {
+    let coordinator = Arc::new(ScanCoordinator::start());
+    let workers: Vec<_> = (0..THREADS)
+      .map(|worker| {
+        let coordinator = coordinator.clone();
+        thread::spawn(move || {
+          let mut tickets: Vec<ScanTicket> = Vec::new();
+          for round in 0..ROUNDS {
+            let ticket = coordinator.begin_scan().unwrap();
+            let scan_id = ticket.scan_id;
+            tickets.push(ticket);
+            // Half the scans are cancelled right away, the rest some rounds later, by which time
+            // other threads have usually started newer ones.
+            if (worker + round) % 2 == 0 {
+              coordinator.cancel(scan_id).unwrap();
+            } else if round >= 3 {
+              coordinator.cancel(tickets[round - 3].scan_id).unwrap();
+            }
+          }
+          tickets
+        })
+      })
+      .collect();
+    let tickets: Vec<_> = workers
+      .into_iter()
+      .flat_map(|worker| worker.join().unwrap())
+      .collect();
+
+    assert_only_active_runs(&coordinator, &tickets);
+  }
+
+  #[test]
+  fn stale_cancel_and_finish_leave_the_new_scan_active() {
+    let coordinator = ScanCoordinator::start();
+    let old = coordinator.begin_scan().unwrap();
+    let new = coordinator.begin_scan().unwrap();
+
+    assert!(old.cancel_flag.load(Ordering::Relaxed));
+    assert!(!coordinator.cancel(old.scan_id).unwrap());
+    coordinator.finish(old.scan_id);
+    assert_eq!(coordinator.snapshot().unwrap().active_id, Some(new.scan_id));
+    assert!(!new.cancel_flag.load(Ordering::Relaxed));
+
+    assert!(coordinator.cancel(new.scan_id).unwrap());
+    assert!(new.cancel_flag.load(Ordering::Relaxed));
+    assert_eq!(coordinator.snapshot().unwrap().active_id, None);
+    assert!(!coordinator.cancel(new.scan_id).unwrap());
+  }
+
+  #[test]
+  fn stopping_a_volume_scan_cancels_only_it() {
+    let coordinator = ScanCoordinator::start();
+    let scan = coordinator.begin_scan().unwrap();
+    let (volume_id, volume_cancel) = coordinator.begin_volume_scan().unwrap();
+    assert_ne!(volume_id, scan.scan_id);
+
+    assert!(coordinator.cancel(volume_id).unwrap());
+    assert!(volume_cancel.load(Ordering::Relaxed));
+    assert!(!coordinator.cancel(volume_id).unwrap());
+    assert!(!scan.cancel_flag.load(Ordering::Relaxed));
+    assert_eq!(
+      coordinator.snapshot().unwrap().active_id,
+      Some(scan.scan_id)
+    );
+  }
+
+  #[test]
+  fn only_the_active_scan_pauses() {
+    let coordinator = ScanCoordinator::start();
+    let old = coordinator.begin_scan().unwrap();
+    let new = coordinator.begin_scan().unwrap();
+
+    assert!(!coordinator.set_paused(old.scan_id, true).unwrap());
+    assert!(coordinator.set_paused(new.scan_id, true).unwrap());
+    assert!(new.paused.load(Ordering::Relaxed));
+    assert!(coordinator.snapshot().unwrap().paused);
+
+    // Cancelling lets the paused walk see the cancellation and stop.
+    assert!(coordinator.cancel(new.scan_id).unwrap());
+    assert!(!new.paused.load(Ordering::Relaxed));
+    assert!(!coordinator.snapshot().unwrap().paused);
+  }
+
+  #[test]
+  fn ids_continue_after_skipped_ones() {
+    let coordinator = ScanCoordinator::start();
+    coordinator.skip_ids_below(40);
+    assert_eq!(coordinator.begin_scan().unwrap().scan_id, 40);
+    coordinator.skip_ids_below(10);
+    assert_eq!(coordinator.begin_volume_scan().unwrap().0, 41);
+  }
+}
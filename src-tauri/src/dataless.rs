@@ -0,0 +1,97 @@
+// Cloud-backed files (iCloud Drive, File Provider apps) can be "dataless": their contents are
+// evicted and opening or reading them downloads everything again. Scanning must never do that,
+// so every thread that walks user folders turns materialization off for itself: stat and
+// readdir keep working, while anything that would trigger a download fails with EDEADLK.
+use std::fs::Metadata;
+#[cfg(target_os = "macos")]
+use std::os::macos::fs::MetadataExt;
+
+#[cfg(target_os = "macos")]
+const SF_DATALESS: u32 = 0x40000000;
+#[cfg(target_os = "macos")]
+const IOPOL_TYPE_VFS_MATERIALIZE_DATALESS_FILES: libc::c_int = 3;
+#[cfg(target_os = "macos")]
+const IOPOL_SCOPE_THREAD: libc::c_int = 1;
+#[cfg(target_os = "macos")]
+const IOPOL_MATERIALIZE_DATALESS_FILES_OFF: libc::c_int = 1;
+
+#[cfg(target_os = "macos")]
+extern "C" {
+  fn getiopolicy_np(iotype: libc::c_int, scope: libc::c_int) -> libc::c_int;
+  fn setiopolicy_np(iotype: libc::c_int, scope: libc::c_int, policy: libc::c_int) -> libc::c_int;
+}
+
+#[cfg(target_os = "macos")]
+pub fn is_dataless(metadata: &Metadata) -> bool {
+  metadata.st_flags() & SF_DATALESS != 0
+}
+
+#[cfg(not(target_os = "macos"))]
+pub fn is_dataless(_metadata: &Metadata) -> bool {
+  false
+}
+
+// Keeps materialization off on the current thread until dropped, then restores the previous
+// policy. Must be dropped on the thread that created it.
+pub struct NoMaterialize {
+  #[cfg(target_os = "macos")]
+  previous: libc::c_int,
+}
+
+impl NoMaterialize {
+  #[cfg(target_os = "macos")]
+  pub fn enter() -> Self {
+    let previous = unsafe {
+      getiopolicy_np(
+        IOPOL_TYPE_VFS_MATERIALIZE_DATALESS_FILES,
+        IOPOL_SCOPE_THREAD,
+      )
+    };
+    unsafe {
+      setiopolicy_np(
+        IOPOL_TYPE_VFS_MATERIALIZE_DATALESS_FILES,
+        IOPOL_SCOPE_THREAD,
+        IOPOL_MATERIALIZE_DATALESS_FILES_OFF,
+      );
+    }
+    Self { previous }
+  }
+
+  #[cfg(not(target_os = "macos"))]
+  pub fn enter() -> Self {
+    Self {}
+  }
+}
+
+#[cfg(target_os = "macos")]
+impl Drop for NoMaterialize {
+  fn drop(&mut self) {
+    if self.previous >= 0 {
+      unsafe {
+        setiopolicy_np(
+          IOPOL_TYPE_VFS_MATERIALIZE_DATALESS_FILES,
+          IOPOL_SCOPE_THREAD,
+          self.previous,
+        );
+      }
+    }
+  }
+}
+
+// Whether the current thread is guaranteed not to download dataless files.
+#[cfg(target_os = "macos")]
+pub fn materialization_disabled() -> bool {
+  let policy = unsafe {
+    getiopolicy_np(
+      IOPOL_TYPE_VFS_MATERIALIZE_DATALESS_FILES,
+      IOPOL_SCOPE_THREAD,
+    )
+  };
+  policy == IOPOL_MATERIALIZE_DATALESS_FILES_OFF
+}
+
+// Other platforms have no dataless files.
+#[cfg(not(target_os = "macos"))]
+pub fn materialization_disabled() -> bool {
+  true
+}
@@ -0,0 +1,222 @@
+use crate::cache;
+use crate::history::{self, HistoryEntry};
+use crate::messages::Message;
+use crate::scanner::{DirTotal, DirTotals};
+use serde::Serialize;
+use std::cmp::Reverse;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+const TOTALS_DIR: &str = "totals";
+// Folder totals are kept for this many scans of each root.
+const KEEP_TOTALS: usize = 20;
+
+#[derive(Serialize)]
+pub struct DirectoryDelta {
+  pub path: String,
+  #[serde(rename = "oldBytes")]
+  pub old_bytes: u64,
+  #[serde(rename = "newBytes")]
+  pub new_bytes: u64,
+  #[serde(rename = "deltaBytes")]
+  pub delta_bytes: i64,
+  #[serde(rename = "oldFiles")]
+  pub old_files: u64,
+  #[serde(rename = "newFiles")]
+  pub new_files: u64,
+  #[serde(rename = "deltaFiles")]
+  pub delta_files: i64,
+}
+
+// What changed between two completed scans of the same folder, per folder down to a few levels
+// below it, largest change first.
+#[derive(Serialize)]
+pub struct ScanDiff {
+  pub root: String,
+  #[serde(rename = "oldScanId")]
+  pub old_scan_id: u64,
+  #[serde(rename = "newScanId")]
+  pub new_scan_id: u64,
+  #[serde(rename = "oldFinishedAt")]
+  pub old_finished_at: u64,
+  #[serde(rename = "newFinishedAt")]
+  pub new_finished_at: u64,
+  #[serde(rename = "oldBytes")]
+  pub old_bytes: u64,
+  #[serde(rename = "newBytes")]
+  pub new_bytes: u64,
+  #[serde(rename = "deltaBytes")]
+  pub delta_bytes: i64,
+  pub directories: Vec<DirectoryDelta>,
+}
+
+fn totals_path(root_dir: &Path, scan_id: u64) -> PathBuf {
+  root_dir
+    .join(TOTALS_DIR)
+    .join(format!("{}.json.zst", scan_id))
+}
+
+// Keeps the folder totals of a completed scan, dropping those of the oldest scans of the root.
+pub fn record_totals(root_dir: &Path, scan_id: u64, totals: &DirTotals) -> Result<(), Message> {
+  let dir = root_dir.join(TOTALS_DIR);
+  fs::create_dir_all(&dir).map_err(|_| Message::new("cache.create_dir_failed"))?;
+  cache::write_compressed(&totals_path(root_dir, scan_id), totals)?;
+  let mut kept: Vec<(u64, PathBuf)> = fs::read_dir(&dir)
+    .map_err(|_| Message::new("folder.read_failed"))?
+    .flatten()
+    .filter_map(|entry| {
+      let name = entry.file_name().to_string_lossy().to_string();
+      let id = name.strip_suffix(".json.zst")?.parse().ok()?;
+      Some((id, entry.path()))
+    })
+    .collect();
+  kept.sort_by_key(|(id, _)| Reverse(*id));
+  for (_, path) in kept.into_iter().skip(KEEP_TOTALS) {
+    let _ = fs::remove_file(path);
+  }
+  Ok(())
+}
+
+fn find_scan(history: &[HistoryEntry], scan_id: u64) -> Result<&HistoryEntry, Message> {
+  history
+    .iter()
+    .find(|entry| entry.scan_id == scan_id && !entry.cancelled)
+    .ok_or_else(|| Message::new("scan.unknown"))
+}
+
+fn delta(old: u64, new: u64) -> i64 {
+  new as i64 - old as i64
+}
+
+// A completed scan's history entry with the folder totals kept for it.
+pub fn scan_totals(app_dir: &Path, scan_id: u64) -> Result<(HistoryEntry, DirTotals), Message> {
+  let history = history::load(app_dir);
+  let scan = find_scan(&history, scan_id)?;
+  let root_dir = cache::root_dir_in(app_dir, Path::new(&scan.root))?;
+  let path = totals_path(&root_dir, scan_id);
+  if !path.is_file() {
+    return Err(Message::new("diff.no_totals"));
+  }
+  Ok((scan.clone(), cache::read_compressed(&path)))
+}
+
+pub fn diff_scans(app_dir: &Path, old_scan_id: u64, new_scan_id: u64) -> Result<ScanDiff, Message> {
+  let (old_scan, old_totals) = scan_totals(app_dir, old_scan_id)?;
+  let (new_scan, mut new_totals) = scan_totals(app_dir, new_scan_id)?;
+  if !old_scan.same_root(&new_scan.root, new_scan.location.as_ref()) {
+    return Err(Message::new("diff.different_roots"));
+  }
+  if old_scan.include != new_scan.include {
+    return Err(Message::new("diff.different_filters"));
+  }
+  let root = Path::new(&new_scan.root);
+
+  let mut pairs: Vec<(String, DirTotal, DirTotal)> = old_totals
+    .into_iter()
+    .map(|(relative, old)| {
+      let new = new_totals.remove(&relative).unwrap_or_default();
+      (relative, old, new)
+    })
+    .collect();
+  pairs.extend(
+    new_totals
+      .into_iter()
+      .map(|(relative, new)| (relative, DirTotal::default(), new)),
+  );
+  let mut directories: Vec<DirectoryDelta> = pairs
+    .into_iter()
+    .filter(|(_, old, new)| old.bytes != new.bytes || old.files != new.files)
+    .map(|(relative, old, new)| DirectoryDelta {
+      path: if relative.is_empty() {
+        new_scan.root.clone()
+      } else {
+        root.join(relative).to_string_lossy().to_string()
+      },
+      old_bytes: old.bytes,
+      new_bytes: new.bytes,
+      delta_bytes: delta(old.bytes, new.bytes),
+      old_files: old.files,
+      new_files: new.files,
+      delta_files: delta(old.files, new.files),
+    })
+    .collect();
+  directories.sort_by(|a, b| {
+    b.delta_bytes
+      .unsigned_abs()
+      .cmp(&a.delta_bytes.unsigned_abs())
+      .then_with(|| a.path.cmp(&b.path))
+  });
+
+  Ok(ScanDiff {
+    root: new_scan.root.clone(),
+    old_scan_id,
+    new_scan_id,
+    old_finished_at: old_scan.finished_at,
+    new_finished_at: new_scan.finished_at,
+    old_bytes: old_scan.scanned_bytes,
+    new_bytes: new_scan.scanned_bytes,
+    delta_bytes: delta(old_scan.scanned_bytes, new_scan.scanned_bytes),
+    directories,
+  })
+}
+
+// The two most recent completed scans of `root` that kept folder totals, oldest first. The older
+// one has the same include filter as the newer.
+pub fn latest_pair(app_dir: &Path, root: &Path) -> Result<(u64, u64), Message> {
+  let root_dir = cache::root_dir_in(app_dir, root)?;
+  let root = root.to_string_lossy();
+  let scans: Vec<HistoryEntry> = history::load(app_dir)
+    .into_iter()
+    .filter(|entry| entry.root == root && !entry.cancelled)
+    .filter(|entry| totals_path(&root_dir, entry.scan_id).is_file())
+    .collect();
+  let newest = scans.first();
+  let previous = newest.and_then(|newest| {
+    scans[1..]
+      .iter()
+      .find(|entry| entry.include == newest.include)
+  });
+  match (newest, previous) {
+    (Some(new), Some(old)) => Ok((old.scan_id, new.scan_id)),
+    _ => Err(Message::new("diff.needs_two_scans")),
+  }
+}
+
+fn csv_field(value: &str) -> String {
+  if value.contains([',', '"', '\n', '\r']) {
+    format!("\"{}\"", value.replace('"', "\"\""))
+  } else {
+    value.to_string()
+  }
+}
+
+// The diff as "json" or "csv", one row per changed folder.
+pub fn render(diff: &ScanDiff, format: &str) -> Result<String, Message> {
+  match format {
+    "json" => serde_json::to_string_pretty(diff)
+      .map(|json| json + "\n")
+      .map_err(|_| Message::new("app.encode_report_failed")),
+    "csv" => {
+      let mut csv =
+        String::from("path,old_bytes,new_bytes,delta_bytes,old_files,new_files,delta_files\n");
+      for directory in &diff.directories {
+        csv.push_str(&format!(
+          "{},{},{},{},{},{},{}\n",
+          csv_field(&directory.path),
+          directory.old_bytes,
+          directory.new_bytes,
+          directory.delta_bytes,
+          directory.old_files,
+          directory.new_files,
+          directory.delta_files
+        ));
+      }
+      Ok(csv)
+    }
+    _ => Err(Message::new("diff.invalid_format")),
+  }
+}
+
+pub fn write_report(diff: &ScanDiff, path: &Path, format: &str) -> Result<(), Message> {
+  fs::write(path, render(diff, format)?).map_err(|_| Message::new("diff.write_failed"))
+}
@@ -0,0 +1,180 @@
+use crate::cache;
+use crate::history;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+#[cfg(target_family = "unix")]
+use std::os::unix::fs::MetadataExt;
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+const DIR_CACHE_FILE: &str = "dirs.json.zst";
+const DEFAULT_FULL_VERIFICATION_DAYS: u64 = 7;
+const SECONDS_PER_DAY: u64 = 24 * 60 * 60;
+
+// e.g. `{ "enabled": true, "fullVerificationDays": 14 }`.
+#[derive(Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct IncrementalSettings {
+  // Makes every scan incremental unless its options say otherwise.
+  pub enabled: bool,
+  // How often an incremental scan reads every folder anyway; 7 days when unset.
+  #[serde(rename = "fullVerificationDays")]
+  pub full_verification_days: Option<u64>,
+}
+
+// A folder's modification time changes when entries are added, removed or renamed, and its link
+// count follows its children on APFS and its subfolders on most Linux filesystems.
+#[derive(Clone, Copy, PartialEq, Serialize, Deserialize)]
+struct Stamp {
+  #[serde(rename = "mtimeNs")]
+  mtime_ns: u64,
+  links: u64,
+}
+
+impl Stamp {
+  fn of(dir: &Path) -> Option<Self> {
+    let metadata = fs::metadata(dir).ok()?;
+    let mtime_ns = metadata
+      .modified()
+      .ok()?
+      .duration_since(UNIX_EPOCH)
+      .ok()?
+      .as_nanos() as u64;
+    Some(Self {
+      mtime_ns,
+      links: links(&metadata),
+    })
+  }
+}
+
+#[cfg(target_family = "unix")]
+fn links(metadata: &fs::Metadata) -> u64 {
+  metadata.nlink()
+}
+
+#[cfg(not(target_family = "unix"))]
+fn links(_metadata: &fs::Metadata) -> u64 {
+  0
+}
+
+// What the scanner took from one folder: its files as (name, size, logical size) and the
+// subfolders it queued.
+#[derive(Serialize, Deserialize)]
+pub struct CachedDir {
+  #[serde(flatten)]
+  stamp: Stamp,
+  pub files: Vec<(String, u64, Option<u64>)>,
+  pub dirs: Vec<String>,
+}
+
+#[derive(Default, Serialize, Deserialize)]
+pub struct DirCacheFile {
+  #[serde(rename = "verifiedAt")]
+  verified_at: u64,
+  dirs: HashMap<String, CachedDir>,
+}
+
+#[derive(Clone, Copy, Default, Serialize)]
+pub struct IncrementalStats {
+  #[serde(rename = "reusedDirs")]
+  pub reused_dirs: u64,
+  #[serde(rename = "readDirs")]
+  pub read_dirs: u64,
+  // Every folder was read to catch files that changed size in place.
+  #[serde(rename = "fullVerification")]
+  pub full_verification: bool,
+}
+
+// Lets an incremental scan reuse what the last one found in folders that haven't changed,
+// instead of listing them and reading every file's metadata again. Subfolders are still checked
+// one by one, since a change deep down doesn't touch its parents. A file that grows in place
+// doesn't change its folder either, so every few days all folders are read in full.
+pub struct DirCache {
+  file: PathBuf,
+  previous: HashMap<String, CachedDir>,
+  current: HashMap<String, CachedDir>,
+  verified_at: u64,
+  stats: IncrementalStats,
+}
+
+impl DirCache {
+  pub fn open(root_dir: &Path, settings: &IncrementalSettings) -> Self {
+    let file = root_dir.join(DIR_CACHE_FILE);
+    let cached: DirCacheFile = cache::read_compressed(&file);
+    let interval = settings
+      .full_verification_days
+      .unwrap_or(DEFAULT_FULL_VERIFICATION_DAYS)
+      * SECONDS_PER_DAY;
+    let full_verification =
+      cached.dirs.is_empty() || history::now_secs().saturating_sub(cached.verified_at) >= interval;
+    Self {
+      file,
+      previous: if full_verification {
+        HashMap::new()
+      } else {
+        cached.dirs
+      },
+      current: HashMap::new(),
+      verified_at: cached.verified_at,
+      stats: IncrementalStats {
+        full_verification,
+        ..IncrementalStats::default()
+      },
+    }
+  }
+
+  // Whether `dir` is unchanged since the last scan, without using its entry.
+  pub fn is_fresh(&self, dir: &Path) -> bool {
+    let cached = match self.previous.get(dir.to_string_lossy().as_ref()) {
+      Some(cached) => cached,
+      None => return false,
+    };
+    Stamp::of(dir) == Some(cached.stamp)
+  }
+
+  // The cached entry for `dir` when it is unchanged, or a fresh one to fill in while reading
+  // it. None when the folder can't be stat'ed.
+  pub fn lookup(&mut self, dir: &Path) -> Option<Result<&CachedDir, CachedDir>> {
+    let stamp = Stamp::of(dir)?;
+    let key = dir.to_string_lossy().to_string();
+    match self.previous.remove(&key) {
+      Some(cached) if cached.stamp == stamp => {
+        self.stats.reused_dirs += 1;
+        Some(Ok(self.current.entry(key).or_insert(cached)))
+      }
+      _ => Some(Err(CachedDir {
+        stamp,
+        files: Vec::new(),
+        dirs: Vec::new(),
+      })),
+    }
+  }
+
+  pub fn store(&mut self, dir: &Path, entry: CachedDir) {
+    self.stats.read_dirs += 1;
+    self
+      .current
+      .insert(dir.to_string_lossy().to_string(), entry);
+  }
+
+  pub fn stats(&self) -> IncrementalStats {
+    self.stats
+  }
+
+  // Only called for completed scans, so the cache always describes a whole tree.
+  pub fn save(self) {
+    let verified_at = if self.stats.full_verification {
+      history::now_secs()
+    } else {
+      self.verified_at
+    };
+    let _ = cache::write_compressed(
+      &self.file,
+      &DirCacheFile {
+        verified_at,
+        dirs: self.current,
+      },
+    );
+  }
+}
@@ -0,0 +1,218 @@
+use crate::messages::Message;
+use serde::Serialize;
+use std::fs;
+#[cfg(target_family = "unix")]
+use std::os::unix::fs::MetadataExt;
+use std::path::Path;
+use std::process::Command;
+
+const IMAGE_EXTENSIONS: &[&str] = &["dmg", "iso", "img", "sparsebundle", "sparseimage"];
+
+#[derive(Serialize)]
+pub struct MountedImage {
+  #[serde(rename = "imagePath")]
+  pub image_path: String,
+  pub device: String,
+  #[serde(rename = "mountPoint")]
+  pub mount_point: String,
+}
+
+pub fn is_disk_image(path: &Path) -> bool {
+  path
+    .extension()
+    .map(|extension| extension.to_string_lossy().to_lowercase())
+    .map(|extension| IMAGE_EXTENSIONS.contains(&extension.as_str()))
+    .unwrap_or(false)
+}
+
+pub struct ImageSize {
+  pub allocated: u64,
+  pub logical: u64,
+}
+
+fn has_extension(path: &Path, wanted: &str) -> bool {
+  path
+    .extension()
+    .map(|extension| extension.eq_ignore_ascii_case(wanted))
+    .unwrap_or(false)
+}
+
+pub fn is_sparse_bundle(path: &Path) -> bool {
+  has_extension(path, "sparsebundle")
+}
+
+pub fn is_sparse_image(path: &Path) -> bool {
+  has_extension(path, "sparseimage")
+}
+
+#[cfg(target_family = "unix")]
+pub fn allocated_size(metadata: &fs::Metadata) -> u64 {
+  metadata.blocks() * 512
+}
+
+#[cfg(not(target_family = "unix"))]
+pub fn allocated_size(metadata: &fs::Metadata) -> u64 {
+  metadata.len()
+}
+
+fn plist_integer(plist: &str, key: &str) -> Option<u64> {
+  let key_tag = format!("<key>{}</key>", key);
+  let rest = &plist[plist.find(&key_tag)? + key_tag.len()..];
+  let value = rest.trim_start().strip_prefix("<integer>")?;
+  let end = value.find("</integer>")?;
+  value[..end].trim().parse().ok()
+}
+
+pub fn sparse_bundle_size(bundle: &Path) -> Option<ImageSize> {
+  let plist = fs::read_to_string(bundle.join("Info.plist")).ok()?;
+  let logical = plist_integer(&plist, "size")?;
+
+  let mut allocated = 0u64;
+  for entry in fs::read_dir(bundle.join("bands")).ok()?.flatten() {
+    if let Ok(metadata) = entry.metadata() {
+      if metadata.is_file() {
+        allocated += allocated_size(&metadata);
+      }
+    }
+  }
+
+  Some(ImageSize { allocated, logical })
+}
+
+fn run(command: &mut Command) -> Result<String, Message> {
+  let output = command
+    .output()
+    .map_err(|_| Message::new("image.tools_missing"))?;
+  if !output.status.success() {
+    let stderr = String::from_utf8_lossy(&output.stderr).trim().to_string();
+    return Err(if stderr.is_empty() {
+      Message::new("image.command_failed")
+    } else {
+      Message::unexpected(stderr)
+    });
+  }
+  Ok(String::from_utf8_lossy(&output.stdout).to_string())
+}
+
+#[cfg(target_os = "macos")]
+pub fn mount(image: &Path) -> Result<MountedImage, Message> {
+  let output = run(
+    Command::new("hdiutil")
+      .arg("attach")
+      .arg("-nobrowse")
+      .arg("-readonly")
+      .arg("-noverify")
+      .arg(image),
+  )?;
+
+  // Each line is "<device>\t<content hint>\t<mount point>"; only mounted slices have the last column.
+  output
+    .lines()
+    .filter_map(|line| {
+      let columns: Vec<&str> = line.split('\t').map(|column| column.trim()).collect();
+      match columns.as_slice() {
+        [device, .., mount_point] if mount_point.starts_with('/') => Some(MountedImage {
+          image_path: image.to_string_lossy().to_string(),
+          device: device.to_string(),
+          mount_point: mount_point.to_string(),
+        }),
+        _ => None,
+      }
+    })
+    .last()
+    .ok_or_else(|| Message::new("image.no_volume"))
+}
+
+#[cfg(target_os = "macos")]
+pub fn unmount(device: &str) -> Result<(), Message> {
+  run(Command::new("hdiutil").arg("detach").arg(device)).map(|_| ())
+}
+
+#[cfg(target_os = "linux")]
+pub fn mount(image: &Path) -> Result<MountedImage, Message> {
+  let output = run(
+    Command::new("udisksctl")
+      .arg("loop-setup")
+      .arg("--read-only")
+      .arg("--no-user-interaction")
+      .arg("--file")
+      .arg(image),
+  )?;
+
+  // "Mapped file <image> as /dev/loop0."
+  let loop_device = output
+    .split_whitespace()
+    .last()
+    .map(|device| device.trim_end_matches('.').to_string())
+    .filter(|device| device.starts_with("/dev/"))
+    .ok_or_else(|| Message::new("image.loop_device_failed"))?;
+
+  let mut last_error = Message::new("image.no_volume");
+  for device in [loop_device.clone(), format!("{}p1", loop_device)] {
+    match run(
+      Command::new("udisksctl")
+        .arg("mount")
+        .arg("--no-user-interaction")
+        .arg("--block-device")
+        .arg(&device),
+    ) {
+      // "Mounted /dev/loop0 at /media/user/NAME"
+      Ok(output) => {
+        if let Some((_, mount_point)) = output.trim().split_once(" at ") {
+          return Ok(MountedImage {
+            image_path: image.to_string_lossy().to_string(),
+            device,
+            mount_point: mount_point.trim_end_matches('.').to_string(),
+          });
+        }
+      }
+      Err(error) => last_error = error,
+    }
+  }
+
+  let _ = run(
+    Command::new("udisksctl")
+      .arg("loop-delete")
+      .arg("--no-user-interaction")
+      .arg("--block-device")
+      .arg(&loop_device),
+  );
+  Err(last_error)
+}
+
+#[cfg(target_os = "linux")]
+pub fn unmount(device: &str) -> Result<(), Message> {
+  run(
+    Command::new("udisksctl")
+      .arg("unmount")
+      .arg("--no-user-interaction")
+      .arg("--block-device")
+      .arg(device),
+  )?;
+
+  let loop_device = match device.strip_prefix("/dev/loop") {
+    Some(rest) => {
+      let number: String = rest.chars().take_while(|c| c.is_ascii_digit()).collect();
+      format!("/dev/loop{}", number)
+    }
+    None => device.to_string(),
+  };
+  run(
+    Command::new("udisksctl")
+      .arg("loop-delete")
+      .arg("--no-user-interaction")
+      .arg("--block-device")
+      .arg(&loop_device),
+  )
+  .map(|_| ())
+}
+
+#[cfg(not(any(target_os = "macos", target_os = "linux")))]
+pub fn mount(_image: &Path) -> Result<MountedImage, Message> {
+  Err(Message::new("image.unsupported_platform"))
+}
+
+#[cfg(not(any(target_os = "macos", target_os = "linux")))]
+pub fn unmount(_device: &str) -> Result<(), Message> {
+  Err(Message::new("image.unsupported_platform"))
+}
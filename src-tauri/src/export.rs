@@ -0,0 +1,199 @@
+use crate::audit;
+use crate::conflicts::{self, Conflicts};
+use crate::messages::Message;
+use crate::plan::{self, PlanFailure};
+use serde::Serialize;
+use std::fs::{self, File};
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+const COPY_BUFFER_BYTES: usize = 1024 * 1024;
+const PROGRESS_INTERVAL: Duration = Duration::from_millis(200);
+
+#[derive(Clone, Serialize)]
+pub struct ExportProgress {
+  #[serde(rename = "doneFiles")]
+  pub done_files: u64,
+  #[serde(rename = "totalFiles")]
+  pub total_files: u64,
+  #[serde(rename = "doneBytes")]
+  pub done_bytes: u64,
+  #[serde(rename = "totalBytes")]
+  pub total_bytes: u64,
+  pub current: String,
+}
+
+#[derive(Serialize)]
+pub struct ExportedItem {
+  pub path: String,
+  pub target: String,
+}
+
+#[derive(Serialize)]
+pub struct ExportExecution {
+  #[serde(rename = "exportedFiles")]
+  pub exported_files: u64,
+  #[serde(rename = "exportedBytes")]
+  pub exported_bytes: u64,
+  pub exported: Vec<ExportedItem>,
+  // Left out because their name was taken and the conflict policy said to skip them.
+  pub skipped: Vec<String>,
+  pub failures: Vec<PlanFailure>,
+}
+
+struct Reporter<'a> {
+  progress: ExportProgress,
+  last_emit: Instant,
+  emit: &'a mut dyn FnMut(&ExportProgress),
+}
+
+impl Reporter<'_> {
+  fn advance(&mut self, bytes: u64) {
+    self.progress.done_bytes += bytes;
+    if self.last_emit.elapsed() >= PROGRESS_INTERVAL {
+      (self.emit)(&self.progress);
+      self.last_emit = Instant::now();
+    }
+  }
+}
+
+// Copies in chunks so progress moves within large files. The target is created fresh, and
+// removed again if the copy fails part way.
+fn copy_file(source: &Path, target: &Path, reporter: &mut Reporter) -> Result<(), Message> {
+  let mut input = File::open(source).map_err(|_| Message::new("file.read_failed"))?;
+  let mut output = File::options()
+    .write(true)
+    .create_new(true)
+    .open(target)
+    .map_err(|_| Message::new("copy.create_file_failed"))?;
+  let mut buffer = vec![0; COPY_BUFFER_BYTES];
+  let copied = loop {
+    let read = match input.read(&mut buffer) {
+      Ok(0) => break Ok(()),
+      Ok(read) => read,
+      Err(_) => break Err(Message::new("file.read_failed")),
+    };
+    if output.write_all(&buffer[..read]).is_err() {
+      break Err(Message::new("copy.write_failed"));
+    }
+    reporter.advance(read as u64);
+  };
+  if let Err(error) = copied {
+    drop(output);
+    let _ = fs::remove_file(target);
+    return Err(error);
+  }
+  if let Ok(modified) = input.metadata().and_then(|metadata| metadata.modified()) {
+    let _ = output.set_modified(modified);
+  }
+  Ok(())
+}
+
+// Copies, or with `hard_link` links, the selected files side by side into `destination`, e.g. to
+// drag them onto a NAS afterwards. Names already taken there are settled by `conflicts`; each
+// file replaced that way goes to the audit log in `app_dir`.
+pub fn export_selection(
+  paths: &[String],
+  destination: &Path,
+  hard_link: bool,
+  conflicts: &mut Conflicts,
+  app_dir: &Path,
+  emit: &mut dyn FnMut(&ExportProgress),
+) -> Result<ExportExecution, Message> {
+  if paths.is_empty() {
+    return Err(Message::new("export.nothing"));
+  }
+  let destination_metadata =
+    fs::metadata(destination).map_err(|_| Message::new("archive.destination_not_found"))?;
+  if !destination_metadata.is_dir() {
+    return Err(Message::new("export.destination_not_folder"));
+  }
+
+  let mut execution = ExportExecution {
+    exported_files: 0,
+    exported_bytes: 0,
+    exported: Vec::new(),
+    skipped: Vec::new(),
+    failures: Vec::new(),
+  };
+  let mut selected = Vec::new();
+  for path in paths {
+    match plan::regular_file_size(Path::new(path)) {
+      Ok(size) => selected.push((PathBuf::from(path), size)),
+      Err(error) => execution.failures.push(PlanFailure {
+        path: path.clone(),
+        error,
+      }),
+    }
+  }
+  let mut reporter = Reporter {
+    progress: ExportProgress {
+      done_files: 0,
+      total_files: selected.len() as u64,
+      done_bytes: 0,
+      total_bytes: selected.iter().map(|(_, size)| size).sum(),
+      current: String::new(),
+    },
+    last_emit: Instant::now() - PROGRESS_INTERVAL,
+    emit,
+  };
+
+  for (source, size) in selected {
+    let path = source.to_string_lossy().to_string();
+    reporter.progress.current = path.clone();
+    let target = match source.file_name() {
+      Some(name) => destination.join(name),
+      None => continue,
+    };
+    let outcome = conflicts.resolve(&source, &target).and_then(|placement| {
+      let placement = match placement {
+        Some(placement) => placement,
+        None => return Ok(None),
+      };
+      let before = reporter.progress.done_bytes;
+      let replaced = fs::symlink_metadata(&placement.path)
+        .ok()
+        .filter(|_| placement.replace)
+        .map(|metadata| metadata.len());
+      let written = conflicts::write_then_place(&placement, |written| {
+        if hard_link {
+          fs::hard_link(&source, written).map_err(|_| Message::new("export.hardlink_other_volume"))
+        } else {
+          copy_file(&source, written, &mut reporter)
+        }
+      });
+      if let Some(replaced) = replaced {
+        audit::record(
+          app_dir,
+          "overwrite",
+          &placement.path,
+          replaced,
+          Some(&source),
+          written.as_ref().map(|_| ()),
+        );
+      }
+      reporter.progress.done_bytes = before + size;
+      written.map(|()| Some(placement.path))
+    });
+    match outcome {
+      Ok(Some(target)) => {
+        execution.exported_files += 1;
+        execution.exported_bytes += size;
+        execution.exported.push(ExportedItem {
+          path,
+          target: target.to_string_lossy().to_string(),
+        });
+      }
+      Ok(None) => {
+        reporter.advance(size);
+        execution.skipped.push(path);
+      }
+      Err(error) => execution.failures.push(PlanFailure { path, error }),
+    }
+    reporter.progress.done_files += 1;
+  }
+  reporter.progress.current = String::new();
+  (reporter.emit)(&reporter.progress);
+  Ok(execution)
+}
@@ -0,0 +1,475 @@
+use serde::Serialize;
+#[cfg(target_os = "macos")]
+use std::ffi::CStr;
+#[cfg(target_family = "unix")]
+use std::ffi::CString;
+#[cfg(target_family = "unix")]
+use std::os::unix::ffi::OsStrExt;
+#[cfg(target_os = "windows")]
+use std::os::windows::ffi::OsStrExt;
+use std::path::Path;
+
+#[derive(Clone, Serialize)]
+pub struct FilesystemInfo {
+  #[serde(rename = "type")]
+  pub fs_type: String,
+  #[serde(rename = "caseSensitive")]
+  pub case_sensitive: Option<bool>,
+  #[serde(rename = "supportsClones")]
+  pub supports_clones: bool,
+  #[serde(rename = "supportsSparseFiles")]
+  pub supports_sparse_files: bool,
+  // FAT and exFAT have no hard links, and their inode numbers are synthesized.
+  #[serde(rename = "supportsHardLinks")]
+  pub supports_hard_links: bool,
+  // 4 GB minus one byte on FAT32.
+  #[serde(rename = "maxFileBytes")]
+  pub max_file_bytes: Option<u64>,
+  // How coarse stored modification times are; 0 when finer than a millisecond.
+  #[serde(rename = "mtimeResolutionMs")]
+  pub mtime_resolution_ms: u64,
+}
+
+fn is_fat_family(fs_type: &str) -> bool {
+  matches!(fs_type, "msdos" | "vfat" | "exfat")
+}
+
+impl FilesystemInfo {
+  fn from_type(fs_type: &str, case_sensitive: Option<bool>) -> Self {
+    Self {
+      fs_type: fs_type.to_string(),
+      case_sensitive,
+      supports_clones: matches!(fs_type, "apfs" | "btrfs" | "xfs" | "zfs"),
+      supports_sparse_files: !is_fat_family(fs_type) && fs_type != "hfs",
+      supports_hard_links: !is_fat_family(fs_type),
+      max_file_bytes: matches!(fs_type, "msdos" | "vfat").then_some(u32::MAX as u64),
+      mtime_resolution_ms: match fs_type {
+        "msdos" | "vfat" => 2000,
+        "hfs" => 1000,
+        "exfat" => 10,
+        _ => 0,
+      },
+    }
+  }
+
+  pub fn is_fat(&self) -> bool {
+    is_fat_family(&self.fs_type)
+  }
+}
+
+pub fn is_fat(path: &Path) -> bool {
+  filesystem_info(path).filter(|info| info.is_fat()).is_some()
+}
+
+#[cfg(target_os = "macos")]
+pub fn filesystem_info(path: &Path) -> Option<FilesystemInfo> {
+  let c_path = CString::new(path.as_os_str().as_bytes()).ok()?;
+  let mut stats: libc::statfs = unsafe { std::mem::zeroed() };
+  if unsafe { libc::statfs(c_path.as_ptr(), &mut stats) } != 0 {
+    return None;
+  }
+  let fs_type = unsafe { CStr::from_ptr(stats.f_fstypename.as_ptr()) }
+    .to_string_lossy()
+    .to_lowercase();
+
+  let case_sensitive = match unsafe { libc::pathconf(c_path.as_ptr(), libc::_PC_CASE_SENSITIVE) } {
+    -1 => None,
+    value => Some(value == 1),
+  };
+
+  Some(FilesystemInfo::from_type(&fs_type, case_sensitive))
+}
+
+#[cfg(target_os = "linux")]
+pub fn filesystem_info(path: &Path) -> Option<FilesystemInfo> {
+  let c_path = CString::new(path.as_os_str().as_bytes()).ok()?;
+  let mut stats: libc::statfs = unsafe { std::mem::zeroed() };
+  if unsafe { libc::statfs(c_path.as_ptr(), &mut stats) } != 0 {
+    return None;
+  }
+
+  let fs_type = match stats.f_type as u32 {
+    0xEF53 => "ext4",
+    0x9123_683E => "btrfs",
+    0x5846_5342 => "xfs",
+    0x2FC1_2FC1 => "zfs",
+    0xF2F5_2010 => "f2fs",
+    0x0102_1994 => "tmpfs",
+    0x794C_7630 => "overlay",
+    0x5346_544E => "ntfs",
+    0x2011_BAB0 => "exfat",
+    0x4D44 => "vfat",
+    0x6969 => "nfs",
+    0xFF53_4D42 | 0xFE53_4D42 => "smb",
+    0x6573_5546 => "fuse",
+    _ => "unknown",
+  };
+
+  let case_sensitive = match fs_type {
+    "vfat" | "exfat" => Some(false),
+    "unknown" | "fuse" | "smb" | "nfs" => None,
+    _ => Some(true),
+  };
+
+  Some(FilesystemInfo::from_type(fs_type, case_sensitive))
+}
+
+#[cfg(not(any(target_os = "macos", target_os = "linux")))]
+pub fn filesystem_info(_path: &Path) -> Option<FilesystemInfo> {
+  None
+}
+
+#[derive(Clone, Copy, PartialEq, Serialize)]
+pub enum StorageKind {
+  #[serde(rename = "ssd")]
+  Ssd,
+  #[serde(rename = "hdd")]
+  Hdd,
+  #[serde(rename = "network")]
+  Network,
+  #[serde(rename = "unknown")]
+  Unknown,
+}
+
+#[derive(Clone, Serialize)]
+pub struct StorageInfo {
+  pub kind: StorageKind,
+  // What a scan of this volume uses unless `threads` is set.
+  #[serde(rename = "defaultThreads")]
+  pub default_threads: usize,
+}
+
+impl StorageKind {
+  // Flash handles many reads in flight, a spinning disk slows down when its head has to seek
+  // between them, and network filesystems are bound by round trips rather than the disk.
+  pub fn default_threads(self) -> usize {
+    let cpus = std::thread::available_parallelism()
+      .map(|cpus| cpus.get())
+      .unwrap_or(1);
+    match self {
+      StorageKind::Ssd => cpus.clamp(4, 16),
+      StorageKind::Hdd => 1,
+      StorageKind::Network => 8,
+      StorageKind::Unknown => 2,
+    }
+  }
+}
+
+fn is_network_type(fs_type: &str) -> bool {
+  matches!(
+    fs_type,
+    "nfs" | "smb" | "smbfs" | "afpfs" | "webdav" | "cifs"
+  )
+}
+
+pub fn storage_info(path: &Path) -> StorageInfo {
+  let kind = storage_kind(path);
+  StorageInfo {
+    kind,
+    default_threads: kind.default_threads(),
+  }
+}
+
+// Reads the block device's `queue/rotational` flag; partitions take it from their disk.
+#[cfg(target_os = "linux")]
+pub fn storage_kind(path: &Path) -> StorageKind {
+  use std::os::unix::fs::MetadataExt;
+
+  let fs_type = filesystem_info(path).map(|info| info.fs_type);
+  match fs_type.as_deref() {
+    Some(fs_type) if is_network_type(fs_type) => return StorageKind::Network,
+    Some("tmpfs") => return StorageKind::Ssd,
+    _ => {}
+  }
+  let device = match std::fs::metadata(path) {
+    Ok(metadata) => metadata.dev(),
+    Err(_) => return StorageKind::Unknown,
+  };
+  let sys_dir =
+    Path::new("/sys/dev/block").join(format!("{}:{}", libc::major(device), libc::minor(device)));
+  let sys_dir = match sys_dir.canonicalize() {
+    Ok(sys_dir) => sys_dir,
+    Err(_) => return StorageKind::Unknown,
+  };
+  let rotational = [sys_dir.join("queue"), sys_dir.join("../queue")]
+    .iter()
+    .find_map(|queue| std::fs::read_to_string(queue.join("rotational")).ok());
+  match rotational.as_deref().map(str::trim) {
+    Some("0") => StorageKind::Ssd,
+    Some("1") => StorageKind::Hdd,
+    _ => StorageKind::Unknown,
+  }
+}
+
+// Asks diskutil whether the volume's device is solid state.
+#[cfg(target_os = "macos")]
+pub fn storage_kind(path: &Path) -> StorageKind {
+  let c_path = match CString::new(path.as_os_str().as_bytes()) {
+    Ok(c_path) => c_path,
+    Err(_) => return StorageKind::Unknown,
+  };
+  let mut stats: libc::statfs = unsafe { std::mem::zeroed() };
+  if unsafe { libc::statfs(c_path.as_ptr(), &mut stats) } != 0 {
+    return StorageKind::Unknown;
+  }
+  let text = |chars: &[libc::c_char]| {
+    unsafe { CStr::from_ptr(chars.as_ptr()) }
+      .to_string_lossy()
+      .to_string()
+  };
+  if is_network_type(&text(&stats.f_fstypename).to_lowercase()) {
+    return StorageKind::Network;
+  }
+  let device = text(&stats.f_mntfromname);
+  if !device.starts_with("/dev/") {
+    return StorageKind::Unknown;
+  }
+  let info = match std::process::Command::new("diskutil")
+    .arg("info")
+    .arg(&device)
+    .output()
+  {
+    Ok(info) => String::from_utf8_lossy(&info.stdout).to_string(),
+    Err(_) => return StorageKind::Unknown,
+  };
+  match info
+    .lines()
+    .find_map(|line| line.trim().strip_prefix("Solid State:"))
+    .map(str::trim)
+  {
+    Some("Yes") => StorageKind::Ssd,
+    Some("No") => StorageKind::Hdd,
+    _ => StorageKind::Unknown,
+  }
+}
+
+#[cfg(not(any(target_os = "macos", target_os = "linux")))]
+pub fn storage_kind(_path: &Path) -> StorageKind {
+  StorageKind::Unknown
+}
+
+// Total and available bytes of the volume holding `path`.
+#[cfg(target_family = "unix")]
+pub fn volume_space(path: &Path) -> Option<(u64, u64)> {
+  let c_path = CString::new(path.as_os_str().as_bytes()).ok()?;
+  let mut stats: libc::statvfs = unsafe { std::mem::zeroed() };
+  if unsafe { libc::statvfs(c_path.as_ptr(), &mut stats) } != 0 {
+    return None;
+  }
+  let block_size = if stats.f_frsize > 0 {
+    stats.f_frsize as u64
+  } else {
+    stats.f_bsize as u64
+  };
+  Some((
+    stats.f_blocks as u64 * block_size,
+    stats.f_bavail as u64 * block_size,
+  ))
+}
+
+#[cfg(target_os = "windows")]
+pub fn volume_space(path: &Path) -> Option<(u64, u64)> {
+  #[link(name = "kernel32")]
+  extern "system" {
+    fn GetDiskFreeSpaceExW(
+      directory: *const u16,
+      free_to_caller: *mut u64,
+      total: *mut u64,
+      total_free: *mut u64,
+    ) -> i32;
+  }
+
+  let wide: Vec<u16> = path.as_os_str().encode_wide().chain(Some(0)).collect();
+  let (mut available, mut total, mut free) = (0u64, 0u64, 0u64);
+  if unsafe { GetDiskFreeSpaceExW(wide.as_ptr(), &mut available, &mut total, &mut free) } == 0 {
+    return None;
+  }
+  Some((total, available))
+}
+
+#[cfg(not(any(target_family = "unix", target_os = "windows")))]
+pub fn volume_space(_path: &Path) -> Option<(u64, u64)> {
+  None
+}
+
+pub fn available_bytes(path: &Path) -> Option<u64> {
+  volume_space(path).map(|(_, available)| available)
+}
+
+pub fn used_percent(path: &Path) -> Option<f64> {
+  let (total, available) = volume_space(path)?;
+  if total == 0 {
+    return None;
+  }
+  Some(total.saturating_sub(available) as f64 / total as f64 * 100.0)
+}
+
+// Bytes that belong to this file alone on APFS, i.e. not shared with clones.
+#[cfg(target_os = "macos")]
+pub fn private_size(path: &Path) -> Option<u64> {
+  const ATTR_BIT_MAP_COUNT: u16 = 5;
+  const ATTR_CMNEXT_PRIVATESIZE: u32 = 0x00000008;
+  const FSOPT_NOFOLLOW: u32 = 0x00000001;
+  const FSOPT_ATTR_CMN_EXTENDED: u32 = 0x00000020;
+
+  let c_path = CString::new(path.as_os_str().as_bytes()).ok()?;
+  let mut attrlist: libc::attrlist = unsafe { std::mem::zeroed() };
+  attrlist.bitmapcount = ATTR_BIT_MAP_COUNT;
+  attrlist.forkattr = ATTR_CMNEXT_PRIVATESIZE;
+
+  let mut buffer = [0u8; 16];
+  let result = unsafe {
+    libc::getattrlist(
+      c_path.as_ptr(),
+      &mut attrlist as *mut _ as *mut libc::c_void,
+      buffer.as_mut_ptr() as *mut _,
+      buffer.len(),
+      FSOPT_NOFOLLOW | FSOPT_ATTR_CMN_EXTENDED,
+    )
+  };
+  if result != 0 {
+    return None;
+  }
+  let size = unsafe { std::ptr::read_unaligned(buffer.as_ptr().add(4) as *const i64) };
+  u64::try_from(size).ok()
+}
+
+#[cfg(not(target_os = "macos"))]
+pub fn private_size(_path: &Path) -> Option<u64> {
+  None
+}
+
+// Files sharing storage through APFS clones return the same (device, clone ID).
+#[cfg(target_os = "macos")]
+pub fn clone_family(path: &Path) -> Option<(u64, u64)> {
+  use std::os::macos::fs::MetadataExt;
+  const ATTR_BIT_MAP_COUNT: u16 = 5;
+  const ATTR_CMNEXT_CLONEID: u32 = 0x00000100;
+  const FSOPT_NOFOLLOW: u32 = 0x00000001;
+  const FSOPT_ATTR_CMN_EXTENDED: u32 = 0x00000020;
+
+  let device = std::fs::symlink_metadata(path).ok()?.st_dev();
+  let c_path = CString::new(path.as_os_str().as_bytes()).ok()?;
+  let mut attrlist: libc::attrlist = unsafe { std::mem::zeroed() };
+  attrlist.bitmapcount = ATTR_BIT_MAP_COUNT;
+  attrlist.forkattr = ATTR_CMNEXT_CLONEID;
+
+  let mut buffer = [0u8; 16];
+  let result = unsafe {
+    libc::getattrlist(
+      c_path.as_ptr(),
+      &mut attrlist as *mut _ as *mut libc::c_void,
+      buffer.as_mut_ptr() as *mut _,
+      buffer.len(),
+      FSOPT_NOFOLLOW | FSOPT_ATTR_CMN_EXTENDED,
+    )
+  };
+  if result != 0 {
+    return None;
+  }
+  let clone_id = unsafe { std::ptr::read_unaligned(buffer.as_ptr().add(4) as *const u64) };
+  Some((device as u64, clone_id))
+}
+
+#[cfg(target_os = "linux")]
+const FIEMAP_BATCH: usize = 32;
+
+// One run of a file's data on disk, as reported by FIEMAP.
+#[cfg(target_os = "linux")]
+pub struct Extent {
+  logical: u64,
+  pub physical: u64,
+  pub length: u64,
+  // Also referenced by another file through a reflink or snapshot.
+  pub shared: bool,
+  last: bool,
+}
+
+// Up to FIEMAP_BATCH extents of `file`, starting at byte `start`.
+#[cfg(target_os = "linux")]
+fn fiemap(file: &std::fs::File, start: u64) -> Option<Vec<Extent>> {
+  use std::os::unix::io::AsRawFd;
+  const FS_IOC_FIEMAP: libc::c_ulong = 0xC020_660B;
+  const FIEMAP_EXTENT_LAST: u32 = 0x0000_0001;
+  const FIEMAP_EXTENT_SHARED: u32 = 0x0000_2000;
+
+  #[repr(C)]
+  #[derive(Clone, Copy)]
+  struct FiemapExtent {
+    logical: u64,
+    physical: u64,
+    length: u64,
+    reserved64: [u64; 2],
+    flags: u32,
+    reserved: [u32; 3],
+  }
+
+  #[repr(C)]
+  struct Fiemap {
+    start: u64,
+    length: u64,
+    flags: u32,
+    mapped_extents: u32,
+    extent_count: u32,
+    reserved: u32,
+    extents: [FiemapExtent; FIEMAP_BATCH],
+  }
+
+  let mut map: Fiemap = unsafe { std::mem::zeroed() };
+  map.start = start;
+  map.length = u64::MAX - start;
+  map.extent_count = FIEMAP_BATCH as u32;
+  if unsafe { libc::ioctl(file.as_raw_fd(), FS_IOC_FIEMAP as _, &mut map) } != 0 {
+    return None;
+  }
+  let mapped = (map.mapped_extents as usize).min(FIEMAP_BATCH);
+  Some(
+    map.extents[..mapped]
+      .iter()
+      .map(|extent| Extent {
+        logical: extent.logical,
+        physical: extent.physical,
+        length: extent.length,
+        shared: extent.flags & FIEMAP_EXTENT_SHARED != 0,
+        last: extent.flags & FIEMAP_EXTENT_LAST != 0,
+      })
+      .collect(),
+  )
+}
+
+// Every extent of the file at `path`; None where FIEMAP isn't supported.
+#[cfg(target_os = "linux")]
+pub fn extents(path: &Path) -> Option<Vec<Extent>> {
+  let file = std::fs::File::open(path).ok()?;
+  let mut extents = Vec::new();
+  let mut start = 0;
+  loop {
+    let batch = fiemap(&file, start)?;
+    let next = match batch.last() {
+      Some(last) if !last.last => last.logical + last.length,
+      _ => {
+        extents.extend(batch);
+        return Some(extents);
+      }
+    };
+    extents.extend(batch);
+    start = next;
+  }
+}
+
+// btrfs and XFS reflinks: files whose first extent is shared and starts at the same physical
+// offset return the same (device, offset).
+#[cfg(target_os = "linux")]
+pub fn clone_family(path: &Path) -> Option<(u64, u64)> {
+  use std::os::unix::fs::MetadataExt;
+
+  let file = std::fs::File::open(path).ok()?;
+  let device = file.metadata().ok()?.dev();
+  let first = fiemap(&file, 0)?.into_iter().next()?;
+  first.shared.then_some((device, first.physical))
+}
+
+#[cfg(not(any(target_os = "macos", target_os = "linux")))]
+pub fn clone_family(_path: &Path) -> Option<(u64, u64)> {
+  None
+}
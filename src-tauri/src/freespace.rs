@@ -0,0 +1,185 @@
+use crate::messages::Message;
+use crate::volumes;
+use serde::Serialize;
+use std::path::Path;
+use std::process::{Command, Output};
+
+// Free space in extents smaller than this can't take a large file in one piece.
+const FRAGMENTED_BELOW_BYTES: u64 = 1024 * 1024;
+
+#[derive(Serialize)]
+pub struct FreeExtentBucket {
+  #[serde(rename = "minBytes")]
+  pub min_bytes: u64,
+  #[serde(rename = "maxBytes")]
+  pub max_bytes: u64,
+  pub extents: u64,
+  #[serde(rename = "freeBytes")]
+  pub free_bytes: u64,
+}
+
+// How a volume's free space is laid out, read from the device by the filesystem's own tools.
+#[derive(Serialize)]
+pub struct FreeSpaceMap {
+  pub device: String,
+  #[serde(rename = "fsType")]
+  pub fs_type: String,
+  #[serde(rename = "blockSize")]
+  pub block_size: u64,
+  #[serde(rename = "freeBytes")]
+  pub free_bytes: u64,
+  #[serde(rename = "freeExtents")]
+  pub free_extents: u64,
+  // Exact on ext4; the top of the largest bucket elsewhere.
+  #[serde(rename = "largestExtentBytes")]
+  pub largest_extent_bytes: u64,
+  // Share of the free space in extents under 1 MB.
+  #[serde(rename = "fragmentedPercent")]
+  pub fragmented_percent: f64,
+  pub histogram: Vec<FreeExtentBucket>,
+}
+
+// "4K", "64M", "2G" as printed by e2freefrag.
+fn parse_size(token: &str) -> Option<u64> {
+  let token = token.trim().trim_end_matches('-');
+  let (number, unit) = token.split_at(token.find(|c: char| !c.is_ascii_digit())?);
+  let multiplier: u64 = match unit {
+    "K" => 1 << 10,
+    "M" => 1 << 20,
+    "G" => 1 << 30,
+    "T" => 1 << 40,
+    _ => return None,
+  };
+  Some(number.parse::<u64>().ok()? * multiplier)
+}
+
+fn field(output: &str, label: &str) -> Option<u64> {
+  output.lines().find_map(|line| {
+    line
+      .strip_prefix(label)?
+      .split_whitespace()
+      .next()?
+      .parse()
+      .ok()
+  })
+}
+
+fn run(command: &mut Command) -> Result<String, Message> {
+  let Output {
+    status,
+    stdout,
+    stderr,
+  } = command
+    .output()
+    .map_err(|_| Message::new("freespace.tools_missing"))?;
+  let stderr = String::from_utf8_lossy(&stderr);
+  if stderr.contains("Permission denied") || stderr.contains("Operation not permitted") {
+    return Err(Message::new("freespace.needs_admin"));
+  }
+  if !status.success() {
+    return Err(Message::new("freespace.read_failed"));
+  }
+  Ok(String::from_utf8_lossy(&stdout).to_string())
+}
+
+// e2freefrag prints a histogram of power-of-two ranges, e.g.
+// `    4M...    8M-  :             3         19963   35.63%`.
+fn ext_map(device: &str) -> Result<(u64, u64, Vec<FreeExtentBucket>), Message> {
+  let output = run(Command::new("e2freefrag").arg(device))?;
+  let block_size =
+    field(&output, "Blocksize:").ok_or_else(|| Message::new("freespace.unexpected_e2freefrag"))?;
+  let largest = field(&output, "Max. free extent:").unwrap_or(0) * 1024;
+  let histogram = output
+    .lines()
+    .skip_while(|line| !line.starts_with("Extent Size Range"))
+    .skip(1)
+    .filter_map(|line| {
+      let (range, counts) = line.split_once(':')?;
+      let (min, max) = range.trim().split_once("...")?;
+      let mut counts = counts.split_whitespace();
+      let extents = counts.next()?.parse().ok()?;
+      let blocks: u64 = counts.next()?.parse().ok()?;
+      Some(FreeExtentBucket {
+        min_bytes: parse_size(min)?,
+        max_bytes: parse_size(max)?,
+        extents,
+        free_bytes: blocks * block_size,
+      })
+    })
+    .collect();
+  Ok((block_size, largest, histogram))
+}
+
+// xfs_db counts in filesystem blocks: `from to extents blocks pct`.
+fn xfs_map(device: &str) -> Result<(u64, u64, Vec<FreeExtentBucket>), Message> {
+  let output = run(
+    Command::new("xfs_db")
+      .arg("-r")
+      .arg("-c")
+      .arg("sb 0")
+      .arg("-c")
+      .arg("p blocksize")
+      .arg("-c")
+      .arg("freesp")
+      .arg(device),
+  )?;
+  let block_size =
+    field(&output, "blocksize =").ok_or_else(|| Message::new("freespace.unexpected_xfs_db"))?;
+  let histogram: Vec<FreeExtentBucket> = output
+    .lines()
+    .filter_map(|line| {
+      let columns: Vec<u64> = line
+        .split_whitespace()
+        .take(4)
+        .map(|column| column.parse().ok())
+        .collect::<Option<_>>()?;
+      match columns.as_slice() {
+        [from, to, extents, blocks] => Some(FreeExtentBucket {
+          min_bytes: from * block_size,
+          max_bytes: (to + 1) * block_size,
+          extents: *extents,
+          free_bytes: blocks * block_size,
+        }),
+        _ => None,
+      }
+    })
+    .collect();
+  let largest = histogram
+    .iter()
+    .filter(|bucket| bucket.extents > 0)
+    .map(|bucket| bucket.max_bytes)
+    .max()
+    .unwrap_or(0);
+  Ok((block_size, largest, histogram))
+}
+
+// Reads the free extents of the volume holding `path`. Supported on ext2/3/4 and XFS, and
+// usually needs administrator access since it reads the raw device.
+pub fn free_space_map(path: &Path) -> Result<FreeSpaceMap, Message> {
+  let volume = volumes::volume_for(path).ok_or_else(|| Message::new("volume.not_found"))?;
+  let (block_size, largest_extent_bytes, histogram) = match volume.fs_type.as_str() {
+    "ext2" | "ext3" | "ext4" => ext_map(&volume.device)?,
+    "xfs" => xfs_map(&volume.device)?,
+    _ => return Err(Message::new("freespace.unsupported").with("fsType", &volume.fs_type)),
+  };
+  let free_bytes: u64 = histogram.iter().map(|bucket| bucket.free_bytes).sum();
+  let fragmented: u64 = histogram
+    .iter()
+    .filter(|bucket| bucket.max_bytes <= FRAGMENTED_BELOW_BYTES)
+    .map(|bucket| bucket.free_bytes)
+    .sum();
+  Ok(FreeSpaceMap {
+    device: volume.device,
+    fs_type: volume.fs_type,
+    block_size,
+    free_bytes,
+    free_extents: histogram.iter().map(|bucket| bucket.extents).sum(),
+    largest_extent_bytes,
+    fragmented_percent: if free_bytes == 0 {
+      0.0
+    } else {
+      fragmented as f64 * 100.0 / free_bytes as f64
+    },
+    histogram,
+  })
+}
@@ -0,0 +1,36 @@
+use crate::cache;
+use crate::checksums::ChecksumEntry;
+use crate::dircache::DirCacheFile;
+use crate::history::HistoryEntry;
+use crate::journal::Checkpoint;
+use crate::plan::PlanFile;
+use crate::remote::NcduReader;
+use crate::settings::Settings;
+use std::collections::HashMap;
+
+// Entry points for the targets in `fuzz/`. Each runs untrusted bytes through the same code the
+// app uses on input it didn't write itself; a panic or a hang is a bug, an error is not.
+
+// An ncdu export, as streamed back from `ssh host ncdu -o -`.
+pub fn ncdu_export(data: &[u8]) {
+  let mut reader = NcduReader::default();
+  for &byte in data {
+    if reader.feed(byte, |_, _| {}).is_err() {
+      return;
+    }
+  }
+}
+
+// A cleanup plan exported from another account.
+pub fn plan_file(data: &[u8]) {
+  let _ = PlanFile::parse(data);
+}
+
+// A cache file left corrupt or crafted in the app data folder, as each kind of cache.
+pub fn cache_file(data: &[u8]) {
+  let _: Option<Checkpoint> = cache::decode_compressed(data);
+  let _: Option<DirCacheFile> = cache::decode_compressed(data);
+  let _: Option<HashMap<String, ChecksumEntry>> = cache::decode_compressed(data);
+  let _: Option<Settings> = cache::decode_json(data);
+  let _: Option<Vec<HistoryEntry>> = cache::decode_json(data);
+}
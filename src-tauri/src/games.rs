@@ -0,0 +1,282 @@
+use crate::disk_image;
+use crate::messages::Message;
+use crate::rules::expand_home;
+use serde::Serialize;
+use serde_json::Value;
+use std::cmp::Reverse;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+#[derive(Serialize)]
+pub struct Game {
+  // The launcher's own ID: a Steam app ID, an Epic app name, or the Battle.net folder name.
+  pub id: String,
+  pub name: String,
+  pub path: String,
+  pub bytes: u64,
+  // Seconds since the epoch; only Steam records it.
+  #[serde(rename = "lastPlayed")]
+  pub last_played: Option<u64>,
+  // A link that opens the launcher's uninstall prompt, where it has one.
+  #[serde(rename = "uninstallUrl")]
+  pub uninstall_url: Option<String>,
+  pub uninstall: Message,
+}
+
+#[derive(Serialize)]
+pub struct GameLibrary {
+  // "steam", "epic" or "battlenet".
+  pub launcher: &'static str,
+  pub path: String,
+  pub bytes: u64,
+  // Largest first.
+  pub games: Vec<Game>,
+}
+
+#[cfg(target_os = "macos")]
+const STEAM_ROOTS: &[&str] = &["~/Library/Application Support/Steam"];
+#[cfg(target_os = "windows")]
+const STEAM_ROOTS: &[&str] = &["C:\\Program Files (x86)\\Steam", "C:\\Program Files\\Steam"];
+#[cfg(not(any(target_os = "macos", target_os = "windows")))]
+const STEAM_ROOTS: &[&str] = &[
+  "~/.local/share/Steam",
+  "~/.steam/steam",
+  "~/.var/app/com.valvesoftware.Steam/.local/share/Steam",
+];
+
+#[cfg(target_os = "macos")]
+const EPIC_MANIFESTS: &[&str] =
+  &["~/Library/Application Support/Epic/EpicGamesLauncher/Data/Manifests"];
+#[cfg(target_os = "windows")]
+const EPIC_MANIFESTS: &[&str] = &["C:\\ProgramData\\Epic\\EpicGamesLauncher\\Data\\Manifests"];
+#[cfg(not(any(target_os = "macos", target_os = "windows")))]
+const EPIC_MANIFESTS: &[&str] = &[];
+
+// Battle.net installs each game in its own folder, marked by a `.build.info` file.
+#[cfg(target_os = "macos")]
+const BATTLENET_FOLDERS: &[&str] = &["/Applications"];
+#[cfg(target_os = "windows")]
+const BATTLENET_FOLDERS: &[&str] = &["C:\\Program Files (x86)", "C:\\Program Files"];
+#[cfg(not(any(target_os = "macos", target_os = "windows")))]
+const BATTLENET_FOLDERS: &[&str] = &[];
+
+// The `"key" "value"` lines of a Valve KeyValues file (.vdf, .acf), in order, ignoring nesting.
+fn vdf_pairs(text: &str) -> Vec<(String, String)> {
+  text
+    .lines()
+    .filter_map(|line| {
+      let mut quoted = line.split('"').skip(1).step_by(2);
+      let key = quoted.next()?;
+      let value = quoted.next()?;
+      Some((key.to_string(), value.replace("\\\\", "\\")))
+    })
+    .collect()
+}
+
+fn vdf_value<'a>(pairs: &'a [(String, String)], key: &str) -> Option<&'a str> {
+  pairs
+    .iter()
+    .find(|(name, _)| name.eq_ignore_ascii_case(key))
+    .map(|(_, value)| value.as_str())
+}
+
+fn folder_size(path: &Path) -> u64 {
+  let mut bytes = 0;
+  let mut dirs = vec![path.to_path_buf()];
+  while let Some(dir) = dirs.pop() {
+    let entries = match fs::read_dir(&dir) {
+      Ok(entries) => entries,
+      Err(_) => continue,
+    };
+    for entry in entries.flatten() {
+      let metadata = match entry.metadata() {
+        Ok(metadata) => metadata,
+        Err(_) => continue,
+      };
+      if metadata.is_dir() {
+        dirs.push(entry.path());
+      } else if metadata.is_file() {
+        bytes += disk_image::allocated_size(&metadata);
+      }
+    }
+  }
+  bytes
+}
+
+fn library(launcher: &'static str, path: &Path, mut games: Vec<Game>) -> GameLibrary {
+  games.sort_by_key(|game| Reverse(game.bytes));
+  GameLibrary {
+    launcher,
+    path: path.to_string_lossy().to_string(),
+    bytes: games.iter().map(|game| game.bytes).sum(),
+    games,
+  }
+}
+
+// Every Steam library: the install folder plus those listed in its `libraryfolders.vdf`, in both
+// the current format (`"path"` keys) and the old one (numbered keys).
+fn steam_library_paths() -> Vec<PathBuf> {
+  let mut paths: Vec<PathBuf> = Vec::new();
+  for root in STEAM_ROOTS {
+    let root = PathBuf::from(expand_home(root));
+    if !root.join("steamapps").is_dir() {
+      continue;
+    }
+    let listed = fs::read_to_string(root.join("steamapps").join("libraryfolders.vdf"))
+      .map(|text| vdf_pairs(&text))
+      .unwrap_or_default();
+    let extra = listed
+      .into_iter()
+      .filter(|(key, _)| key == "path" || key.parse::<u32>().is_ok())
+      .map(|(_, value)| PathBuf::from(value));
+    for path in std::iter::once(root.clone()).chain(extra) {
+      let resolved = path.canonicalize().unwrap_or(path);
+      if resolved.join("steamapps").is_dir() && !paths.contains(&resolved) {
+        paths.push(resolved);
+      }
+    }
+  }
+  paths
+}
+
+fn steam_games(library: &Path) -> Vec<Game> {
+  let steamapps = library.join("steamapps");
+  let entries = match fs::read_dir(&steamapps) {
+    Ok(entries) => entries,
+    Err(_) => return Vec::new(),
+  };
+  entries
+    .flatten()
+    .filter(|entry| {
+      let name = entry.file_name().to_string_lossy().to_string();
+      name.starts_with("appmanifest_") && name.ends_with(".acf")
+    })
+    .filter_map(|entry| {
+      let pairs = vdf_pairs(&fs::read_to_string(entry.path()).ok()?);
+      let id = vdf_value(&pairs, "appid")?.to_string();
+      let name = vdf_value(&pairs, "name").unwrap_or(&id).to_string();
+      let path = steamapps
+        .join("common")
+        .join(vdf_value(&pairs, "installdir")?);
+      let bytes = vdf_value(&pairs, "SizeOnDisk")
+        .and_then(|size| size.parse().ok())
+        .filter(|size| *size > 0)
+        .unwrap_or_else(|| folder_size(&path));
+      let last_played = vdf_value(&pairs, "LastPlayed")
+        .and_then(|time| time.parse().ok())
+        .filter(|time| *time > 0);
+      Some(Game {
+        uninstall_url: Some(format!("steam://uninstall/{}", id)),
+        uninstall: Message::new("games.uninstall_steam").with("name", name.clone()),
+        id,
+        name,
+        path: path.to_string_lossy().to_string(),
+        bytes,
+        last_played,
+      })
+    })
+    .collect()
+}
+
+// Epic keeps one JSON `.item` manifest per installed game; libraries are the folders games were
+// installed into.
+fn epic_libraries() -> Vec<GameLibrary> {
+  let mut libraries: Vec<(PathBuf, Vec<Game>)> = Vec::new();
+  for dir in EPIC_MANIFESTS {
+    let entries = match fs::read_dir(expand_home(dir)) {
+      Ok(entries) => entries,
+      Err(_) => continue,
+    };
+    for entry in entries.flatten() {
+      if entry
+        .path()
+        .extension()
+        .is_none_or(|extension| extension != "item")
+      {
+        continue;
+      }
+      let manifest: Value = match fs::read(entry.path())
+        .ok()
+        .and_then(|bytes| serde_json::from_slice(&bytes).ok())
+      {
+        Some(manifest) => manifest,
+        None => continue,
+      };
+      let path = match manifest["InstallLocation"].as_str() {
+        Some(path) if Path::new(path).is_dir() => PathBuf::from(path),
+        _ => continue,
+      };
+      let id = manifest["AppName"].as_str().unwrap_or_default().to_string();
+      let name = manifest["DisplayName"].as_str().unwrap_or(&id).to_string();
+      let game = Game {
+        bytes: manifest["InstallSize"]
+          .as_u64()
+          .filter(|size| *size > 0)
+          .unwrap_or_else(|| folder_size(&path)),
+        uninstall_url: None,
+        uninstall: Message::new("games.uninstall_epic").with("name", name.clone()),
+        id,
+        name,
+        path: path.to_string_lossy().to_string(),
+        last_played: None,
+      };
+      let parent = path.parent().unwrap_or(&path).to_path_buf();
+      match libraries.iter_mut().find(|(library, _)| *library == parent) {
+        Some((_, games)) => games.push(game),
+        None => libraries.push((parent, vec![game])),
+      }
+    }
+  }
+  libraries
+    .into_iter()
+    .map(|(path, games)| library("epic", &path, games))
+    .collect()
+}
+
+fn battlenet_libraries() -> Vec<GameLibrary> {
+  let mut libraries = Vec::new();
+  for dir in BATTLENET_FOLDERS {
+    let entries = match fs::read_dir(dir) {
+      Ok(entries) => entries,
+      Err(_) => continue,
+    };
+    let games: Vec<Game> = entries
+      .flatten()
+      .map(|entry| entry.path())
+      // The Battle.net app marks its own folder the same way.
+      .filter(|path| path.join(".build.info").is_file() && !path.ends_with("Battle.net"))
+      .map(|path| {
+        let name = path
+          .file_name()
+          .map(|name| name.to_string_lossy().to_string())
+          .unwrap_or_default();
+        Game {
+          id: name.clone(),
+          uninstall: Message::new("games.uninstall_battlenet").with("name", name.clone()),
+          name,
+          bytes: folder_size(&path),
+          path: path.to_string_lossy().to_string(),
+          last_played: None,
+          uninstall_url: None,
+        }
+      })
+      .collect();
+    if !games.is_empty() {
+      libraries.push(library("battlenet", Path::new(dir), games));
+    }
+  }
+  libraries
+}
+
+// Installed games per launcher library, largest library first. Sizes come from the launchers'
+// manifests where they record them, and are measured otherwise.
+pub fn list_games() -> Vec<GameLibrary> {
+  let mut libraries: Vec<GameLibrary> = steam_library_paths()
+    .iter()
+    .map(|path| library("steam", path, steam_games(path)))
+    .collect();
+  libraries.extend(epic_libraries());
+  libraries.extend(battlenet_libraries());
+  libraries.sort_by_key(|library| Reverse(library.bytes));
+  libraries
+}
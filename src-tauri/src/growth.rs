@@ -0,0 +1,185 @@
+use crate::open_files::{self, OpenProcess};
+use serde::Serialize;
+use std::cmp::Reverse;
+use std::collections::{HashMap, VecDeque};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+// Growth is kept per minute for this long; the ticker can look back over any part of it.
+pub const MAX_WINDOW_MINUTES: u64 = 30;
+const TICKER_ENTRIES: usize = 20;
+// Known file sizes beyond this are forgotten, so a busy tree can't grow the map without bound.
+const MAX_TRACKED_FILES: usize = 200_000;
+
+#[derive(Serialize)]
+pub struct GrowthEntry {
+  // The folder the bytes were written to.
+  pub path: String,
+  #[serde(rename = "addedBytes")]
+  pub added_bytes: u64,
+  #[serde(rename = "bytesPerMinute")]
+  pub bytes_per_minute: u64,
+  // The file in it that grew the most.
+  #[serde(rename = "topFile")]
+  pub top_file: String,
+  // Processes with a file in the folder open, when sampled.
+  pub processes: Vec<OpenProcess>,
+}
+
+#[derive(Serialize)]
+pub struct GrowthTicker {
+  #[serde(rename = "windowMinutes")]
+  pub window_minutes: u64,
+  pub entries: Vec<GrowthEntry>,
+}
+
+#[derive(Default)]
+struct Minute {
+  minute: u64,
+  // Per folder, bytes added and per file within it.
+  dirs: HashMap<PathBuf, HashMap<PathBuf, u64>>,
+}
+
+// Bytes added per folder over the last minutes, from the size changes a watch reports. Shrinking
+// files don't count against growth, and a file seen for the first time by a modification only
+// sets its baseline.
+#[derive(Default)]
+pub struct GrowthTracker {
+  sizes: HashMap<PathBuf, u64>,
+  minutes: VecDeque<Minute>,
+}
+
+fn current_minute() -> u64 {
+  SystemTime::now()
+    .duration_since(UNIX_EPOCH)
+    .map(|duration| duration.as_secs() / 60)
+    .unwrap_or(0)
+}
+
+impl GrowthTracker {
+  // `kind` and `size` as classified by the watcher.
+  pub fn record(&mut self, path: &Path, kind: &str, size: Option<u64>) {
+    let size = match (kind, size) {
+      ("remove", _) | ("dir_removed", _) => {
+        self.sizes.remove(path);
+        return;
+      }
+      (_, Some(size)) => size,
+      _ => return,
+    };
+    if self.sizes.len() >= MAX_TRACKED_FILES && !self.sizes.contains_key(path) {
+      self.sizes.clear();
+    }
+    let previous = self.sizes.insert(path.to_path_buf(), size);
+    let added = match (previous, kind) {
+      (Some(previous), _) => size.saturating_sub(previous),
+      (None, "create") => size,
+      (None, _) => 0,
+    };
+    let dir = match path.parent() {
+      Some(dir) if added > 0 => dir,
+      _ => return,
+    };
+
+    let minute = current_minute();
+    if self.minutes.back().map(|bucket| bucket.minute) != Some(minute) {
+      self.minutes.push_back(Minute {
+        minute,
+        ..Default::default()
+      });
+    }
+    while self
+      .minutes
+      .front()
+      .is_some_and(|bucket| bucket.minute + MAX_WINDOW_MINUTES <= minute)
+    {
+      self.minutes.pop_front();
+    }
+    if let Some(bucket) = self.minutes.back_mut() {
+      *bucket
+        .dirs
+        .entry(dir.to_path_buf())
+        .or_default()
+        .entry(path.to_path_buf())
+        .or_default() += added;
+    }
+  }
+
+  // Adds the growth of the last `window_minutes` into `totals`, per folder and file.
+  pub fn collect(&self, window_minutes: u64, totals: &mut HashMap<PathBuf, HashMap<PathBuf, u64>>) {
+    let since = current_minute().saturating_sub(window_minutes.saturating_sub(1));
+    for bucket in self.minutes.iter().filter(|bucket| bucket.minute >= since) {
+      for (dir, files) in &bucket.dirs {
+        let dir_totals = totals.entry(dir.clone()).or_default();
+        for (file, added) in files {
+          *dir_totals.entry(file.clone()).or_default() += added;
+        }
+      }
+    }
+  }
+}
+
+// The folders that grew most over the window, from what `collect` gathered.
+pub fn ticker(
+  window_minutes: u64,
+  totals: HashMap<PathBuf, HashMap<PathBuf, u64>>,
+) -> GrowthTicker {
+  let mut entries: Vec<GrowthEntry> = totals
+    .into_iter()
+    .filter_map(|(dir, files)| {
+      let added_bytes = files.values().sum();
+      let (top_file, _) = files.into_iter().max_by_key(|(_, added)| *added)?;
+      Some(GrowthEntry {
+        path: dir.to_string_lossy().to_string(),
+        added_bytes,
+        bytes_per_minute: added_bytes / window_minutes.max(1),
+        top_file: top_file.to_string_lossy().to_string(),
+        processes: Vec::new(),
+      })
+    })
+    .collect();
+  entries.sort_by_key(|entry| Reverse(entry.added_bytes));
+  entries.truncate(TICKER_ENTRIES);
+  GrowthTicker {
+    window_minutes,
+    entries,
+  }
+}
+
+// Fills in the processes holding files open in each folder of the ticker; one pass over every
+// open file, so only when asked for. Empty where open files can't be listed (Windows).
+pub fn attribute_processes(ticker: &mut GrowthTicker) {
+  if ticker.entries.is_empty() {
+    return;
+  }
+  let own_pid = std::process::id();
+  let mut holders: HashMap<&Path, Vec<u32>> = ticker
+    .entries
+    .iter()
+    .map(|entry| (Path::new(&entry.path), Vec::new()))
+    .collect();
+  for file in open_files::open_files() {
+    if file.pid == own_pid {
+      continue;
+    }
+    let pids = match Path::new(&file.path)
+      .parent()
+      .and_then(|dir| holders.get_mut(dir))
+    {
+      Some(pids) => pids,
+      None => continue,
+    };
+    if !pids.contains(&file.pid) {
+      pids.push(file.pid);
+    }
+  }
+  let holders: HashMap<String, Vec<u32>> = holders
+    .into_iter()
+    .map(|(dir, pids)| (dir.to_string_lossy().to_string(), pids))
+    .collect();
+  for entry in &mut ticker.entries {
+    if let Some(pids) = holders.get(&entry.path) {
+      entry.processes = pids.iter().map(|pid| open_files::process(*pid)).collect();
+    }
+  }
+}
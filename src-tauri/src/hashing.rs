@@ -0,0 +1,153 @@
+use crate::messages::Message;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant};
+
+const READ_CHUNK_BYTES: usize = 1024 * 1024;
+const IDLE_CHECK_BYTES: u64 = 256 * 1024 * 1024;
+const IDLE_POLL_INTERVAL: Duration = Duration::from_secs(5);
+const IDLE_WAIT_LIMIT: Duration = Duration::from_secs(5 * 60);
+// Load average per CPU below which the system counts as idle, on top of the hashing thread itself.
+const IDLE_LOAD_PER_CPU: f64 = 0.25;
+
+// e.g. `{ "maxBytesPerSecond": 52428800, "idleOnly": true }`.
+#[derive(Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct HashingSettings {
+  #[serde(rename = "maxBytesPerSecond")]
+  pub max_bytes_per_second: Option<u64>,
+  #[serde(rename = "idleOnly")]
+  pub idle_only: bool,
+}
+
+#[derive(Default)]
+struct ThrottleState {
+  settings: HashingSettings,
+  next_read: Option<Instant>,
+}
+
+// Shared by every background hashing job, so the cap holds for all of them together.
+#[derive(Default)]
+pub struct HashThrottle {
+  state: Mutex<ThrottleState>,
+}
+
+impl HashThrottle {
+  pub fn configure(&self, settings: &HashingSettings) {
+    if let Ok(mut state) = self.state.lock() {
+      state.settings = settings.clone();
+      state.next_read = None;
+    }
+  }
+
+  fn idle_only(&self) -> bool {
+    self
+      .state
+      .lock()
+      .map(|state| state.settings.idle_only)
+      .unwrap_or(false)
+  }
+
+  // Reserves a slot for `bytes` and sleeps until it comes up.
+  fn pace(&self, bytes: usize) {
+    let wait = match self.state.lock() {
+      Ok(mut state) => match state.settings.max_bytes_per_second.filter(|rate| *rate > 0) {
+        Some(rate) => {
+          let now = Instant::now();
+          let start = state.next_read.filter(|next| *next > now).unwrap_or(now);
+          state.next_read = Some(start + Duration::from_secs_f64(bytes as f64 / rate as f64));
+          start - now
+        }
+        None => Duration::ZERO,
+      },
+      Err(_) => Duration::ZERO,
+    };
+    if !wait.is_zero() {
+      thread::sleep(wait);
+    }
+  }
+
+  fn wait_for_idle(&self) -> Result<(), Message> {
+    let started = Instant::now();
+    while self.idle_only() && !system_idle() {
+      if started.elapsed() >= IDLE_WAIT_LIMIT {
+        return Err(Message::new("system.busy"));
+      }
+      thread::sleep(IDLE_POLL_INTERVAL);
+    }
+    Ok(())
+  }
+}
+
+#[cfg(target_family = "unix")]
+fn system_idle() -> bool {
+  let mut load = [0f64; 3];
+  if unsafe { libc::getloadavg(load.as_mut_ptr(), 1) } != 1 {
+    return true;
+  }
+  let cpus = thread::available_parallelism()
+    .map(|cpus| cpus.get())
+    .unwrap_or(1) as f64;
+  load[0] < cpus * IDLE_LOAD_PER_CPU + 1.0
+}
+
+// Without a load average there is nothing to wait for.
+#[cfg(not(target_family = "unix"))]
+fn system_idle() -> bool {
+  true
+}
+
+fn to_hex(digest: &[u8]) -> String {
+  digest.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+pub fn sha256_file(path: &Path) -> Result<String, Message> {
+  let file = File::open(path).map_err(|_| Message::new("file.open_failed"))?;
+  sha256_reader(file)
+}
+
+pub fn sha256_reader(mut reader: impl Read) -> Result<String, Message> {
+  let mut hasher = Sha256::new();
+  let mut buffer = vec![0u8; READ_CHUNK_BYTES];
+  loop {
+    let read = reader
+      .read(&mut buffer)
+      .map_err(|_| Message::new("file.read_failed"))?;
+    if read == 0 {
+      break;
+    }
+    hasher.update(&buffer[..read]);
+  }
+  Ok(to_hex(&hasher.finalize()))
+}
+
+// For background work such as duplicate detection: reads are paced to the configured cap, and in
+// idle-only mode hashing pauses while the system is busy (giving up after a few minutes).
+pub fn sha256_file_throttled(path: &Path, throttle: &HashThrottle) -> Result<String, Message> {
+  throttle.wait_for_idle()?;
+  let mut file = File::open(path).map_err(|_| Message::new("file.open_failed"))?;
+  let mut hasher = Sha256::new();
+  let mut buffer = vec![0u8; READ_CHUNK_BYTES];
+  let mut since_idle_check = 0u64;
+  loop {
+    throttle.pace(buffer.len());
+    let read = file
+      .read(&mut buffer)
+      .map_err(|_| Message::new("file.read_failed"))?;
+    if read == 0 {
+      break;
+    }
+    hasher.update(&buffer[..read]);
+    since_idle_check += read as u64;
+    if since_idle_check >= IDLE_CHECK_BYTES {
+      throttle.wait_for_idle()?;
+      since_idle_check = 0;
+    }
+  }
+  Ok(to_hex(&hasher.finalize()))
+}
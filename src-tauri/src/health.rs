@@ -0,0 +1,133 @@
+use crate::messages::Message;
+use serde::Serialize;
+use serde_json::Value;
+#[cfg(target_os = "macos")]
+use std::ffi::{CStr, CString};
+#[cfg(target_os = "macos")]
+use std::os::unix::ffi::OsStrExt;
+use std::path::Path;
+use std::process::Command;
+
+const REALLOCATED_SECTOR_ATTRIBUTE: u64 = 5;
+
+#[derive(Serialize)]
+pub struct DriveHealth {
+  pub device: String,
+  pub model: Option<String>,
+  pub passed: Option<bool>,
+  #[serde(rename = "temperatureCelsius")]
+  pub temperature_celsius: Option<i64>,
+  #[serde(rename = "reallocatedSectors")]
+  pub reallocated_sectors: Option<u64>,
+  #[serde(rename = "percentageUsed")]
+  pub percentage_used: Option<u64>,
+}
+
+#[cfg(target_os = "macos")]
+fn device_for_mount(mount_point: &Path) -> Option<String> {
+  let c_path = CString::new(mount_point.as_os_str().as_bytes()).ok()?;
+  let mut stats: libc::statfs = unsafe { std::mem::zeroed() };
+  if unsafe { libc::statfs(c_path.as_ptr(), &mut stats) } != 0 {
+    return None;
+  }
+  let device = unsafe { CStr::from_ptr(stats.f_mntfromname.as_ptr()) }
+    .to_string_lossy()
+    .to_string();
+
+  // APFS volumes live on a synthesized disk; SMART data belongs to its physical store.
+  let info = Command::new("diskutil")
+    .arg("info")
+    .arg(&device)
+    .output()
+    .ok()?;
+  let info = String::from_utf8_lossy(&info.stdout);
+  let store = info
+    .lines()
+    .find_map(|line| line.trim().strip_prefix("APFS Physical Store:"))
+    .map(|store| format!("/dev/{}", store.trim()))
+    .unwrap_or(device);
+
+  let disk = store.trim_start_matches("/dev/");
+  let whole = match disk.strip_prefix("disk") {
+    Some(rest) => format!(
+      "/dev/disk{}",
+      rest
+        .chars()
+        .take_while(|c| c.is_ascii_digit())
+        .collect::<String>()
+    ),
+    None => store.clone(),
+  };
+  Some(whole)
+}
+
+#[cfg(target_os = "linux")]
+fn device_for_mount(mount_point: &Path) -> Option<String> {
+  let mounts = std::fs::read_to_string("/proc/self/mounts").ok()?;
+  let device = mounts
+    .lines()
+    .filter_map(|line| {
+      let mut columns = line.split_whitespace();
+      Some((columns.next()?, columns.next()?))
+    })
+    .filter(|(device, _)| device.starts_with("/dev/"))
+    .filter(|(_, mount)| Path::new(mount) == mount_point)
+    .map(|(device, _)| device.to_string())
+    .next_back()?;
+
+  // Strip the partition: /dev/sda2 -> /dev/sda, /dev/nvme0n1p2 -> /dev/nvme0n1.
+  let whole = if device.contains("nvme") || device.contains("mmcblk") {
+    match device.rfind('p') {
+      Some(index) if device[index + 1..].chars().all(|c| c.is_ascii_digit()) => {
+        device[..index].to_string()
+      }
+      _ => device,
+    }
+  } else {
+    device
+      .trim_end_matches(|c: char| c.is_ascii_digit())
+      .to_string()
+  };
+  Some(whole)
+}
+
+#[cfg(not(any(target_os = "macos", target_os = "linux")))]
+fn device_for_mount(_mount_point: &Path) -> Option<String> {
+  None
+}
+
+fn reallocated_sectors(report: &Value) -> Option<u64> {
+  report["ata_smart_attributes"]["table"]
+    .as_array()?
+    .iter()
+    .find(|attribute| attribute["id"].as_u64() == Some(REALLOCATED_SECTOR_ATTRIBUTE))
+    .and_then(|attribute| attribute["raw"]["value"].as_u64())
+}
+
+pub fn drive_health(mount_point: &Path) -> Result<DriveHealth, Message> {
+  let device =
+    device_for_mount(mount_point).ok_or_else(|| Message::new("health.no_physical_drive"))?;
+
+  let output = Command::new("smartctl")
+    .arg("--json")
+    .arg("--all")
+    .arg(&device)
+    .output()
+    .map_err(|_| Message::new("health.needs_smartctl"))?;
+
+  // smartctl uses its exit status as a bitmask and still prints a report for non-fatal bits.
+  let report: Value =
+    serde_json::from_slice(&output.stdout).map_err(|_| Message::new("health.unavailable"))?;
+  if report["smart_status"].is_null() && report["temperature"].is_null() {
+    return Err(Message::new("health.no_smart"));
+  }
+
+  Ok(DriveHealth {
+    device,
+    model: report["model_name"].as_str().map(|model| model.to_string()),
+    passed: report["smart_status"]["passed"].as_bool(),
+    temperature_celsius: report["temperature"]["current"].as_i64(),
+    reallocated_sectors: reallocated_sectors(&report),
+    percentage_used: report["nvme_smart_health_information_log"]["percentage_used"].as_u64(),
+  })
+}
@@ -0,0 +1,133 @@
+use crate::cache;
+use crate::messages::Message;
+use crate::scanner::ScanSummary;
+use crate::volumes::VolumeLocation;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+const HISTORY_FILE: &str = "history.json";
+const RESULT_FILE: &str = "last_result.json.zst";
+const MAX_HISTORY_ENTRIES: usize = 500;
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct HistoryEntry {
+  #[serde(rename = "scanId")]
+  pub scan_id: u64,
+  pub root: String,
+  pub label: Option<String>,
+  #[serde(default)]
+  pub metadata: HashMap<String, String>,
+  #[serde(rename = "startedAt")]
+  pub started_at: u64,
+  #[serde(rename = "finishedAt")]
+  pub finished_at: u64,
+  pub cancelled: bool,
+  #[serde(rename = "scannedFiles")]
+  pub scanned_files: u64,
+  #[serde(rename = "scannedBytes")]
+  pub scanned_bytes: u64,
+  #[serde(rename = "usedPercent", default)]
+  pub used_percent: Option<f64>,
+  // Set when the root is on a volume with a UUID; matches the root across mount point changes.
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub location: Option<VolumeLocation>,
+  // The include filter of a scan that only counted some files.
+  #[serde(default, skip_serializing_if = "Vec::is_empty")]
+  pub include: Vec<String>,
+}
+
+impl HistoryEntry {
+  pub fn same_root(&self, root: &str, location: Option<&VolumeLocation>) -> bool {
+    match (&self.location, location) {
+      (Some(known), Some(location)) => known == location,
+      _ => self.root == root,
+    }
+  }
+}
+
+// The last completed scan of a root, kept next to its other cached data.
+#[derive(Serialize)]
+struct RetainedResult<'a> {
+  label: &'a Option<String>,
+  metadata: &'a HashMap<String, String>,
+  #[serde(rename = "finishedAt")]
+  finished_at: u64,
+  summary: &'a ScanSummary,
+}
+
+pub fn now_secs() -> u64 {
+  SystemTime::now()
+    .duration_since(UNIX_EPOCH)
+    .map(|duration| duration.as_secs())
+    .unwrap_or(0)
+}
+
+// The volume usage recorded by the last completed scan of `root`.
+pub fn previous_used_percent(
+  app_dir: &Path,
+  root: &str,
+  location: Option<&VolumeLocation>,
+) -> Option<f64> {
+  load(app_dir)
+    .into_iter()
+    .find(|entry| entry.same_root(root, location) && !entry.cancelled)
+    .and_then(|entry| entry.used_percent)
+}
+
+// What the last completed scan of `root` with the same include filter found, as an estimate for
+// the next one.
+pub fn previous_scanned_bytes(
+  app_dir: &Path,
+  root: &str,
+  location: Option<&VolumeLocation>,
+  include: &[String],
+) -> Option<u64> {
+  load(app_dir)
+    .into_iter()
+    .find(|entry| entry.same_root(root, location) && !entry.cancelled && entry.include == include)
+    .map(|entry| entry.scanned_bytes)
+}
+
+// Newest first.
+pub fn load(app_dir: &Path) -> Vec<HistoryEntry> {
+  cache::read_json(&app_dir.join(HISTORY_FILE))
+}
+
+// Keeps at most `max_entries`, none finished before `cutoff`. Returns how many were removed.
+pub fn prune(app_dir: &Path, max_entries: usize, cutoff: u64) -> Result<u64, Message> {
+  let mut history = load(app_dir);
+  let count = history.len();
+  history.retain(|entry| entry.finished_at >= cutoff);
+  history.truncate(max_entries);
+  if history.len() == count {
+    return Ok(0);
+  }
+  cache::write_json(&app_dir.join(HISTORY_FILE), &history)?;
+  Ok((count - history.len()) as u64)
+}
+
+pub fn record(
+  app_dir: &Path,
+  root_dir: &Path,
+  entry: HistoryEntry,
+  summary: &ScanSummary,
+) -> Result<(), Message> {
+  if !entry.cancelled {
+    cache::write_compressed(
+      &root_dir.join(RESULT_FILE),
+      &RetainedResult {
+        label: &entry.label,
+        metadata: &entry.metadata,
+        finished_at: entry.finished_at,
+        summary,
+      },
+    )?;
+  }
+
+  let mut history = load(app_dir);
+  history.insert(0, entry);
+  history.truncate(MAX_HISTORY_ENTRIES);
+  cache::write_json(&app_dir.join(HISTORY_FILE), &history)
+}
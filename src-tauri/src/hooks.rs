@@ -0,0 +1,133 @@
+use crate::history::HistoryEntry;
+use crate::messages::Message;
+use crate::scanner::FileEntry;
+use crate::settings::Hook;
+use serde::Serialize;
+use std::io::Write;
+use std::process::{Command, Stdio};
+use std::thread;
+use tauri::Manager;
+
+#[derive(Serialize)]
+struct HookPayload<'a> {
+  event: &'static str,
+  #[serde(flatten)]
+  entry: &'a HistoryEntry,
+  #[serde(rename = "topFiles")]
+  top_files: &'a [FileEntry],
+}
+
+#[derive(Clone, Serialize)]
+struct HookFailedPayload {
+  target: String,
+  error: Message,
+}
+
+#[cfg(target_family = "unix")]
+fn shell(command: &str) -> Command {
+  let mut shell = Command::new("sh");
+  shell.arg("-c").arg(command);
+  shell
+}
+
+#[cfg(not(target_family = "unix"))]
+fn shell(command: &str) -> Command {
+  let mut shell = Command::new("cmd");
+  shell.arg("/C").arg(command);
+  shell
+}
+
+fn curl_post(url: &str) -> Command {
+  let mut curl = Command::new("curl");
+  curl
+    .arg("-fsS")
+    .arg("-X")
+    .arg("POST")
+    .arg("-H")
+    .arg("Content-Type: application/json")
+    .arg("--data-binary")
+    .arg("@-")
+    .arg(url);
+  curl
+}
+
+fn run_with_stdin(mut command: Command, input: &[u8]) -> Result<(), Message> {
+  let mut child = command
+    .stdin(Stdio::piped())
+    .stdout(Stdio::null())
+    .stderr(Stdio::piped())
+    .spawn()
+    .map_err(|_| Message::new("hooks.start_failed"))?;
+  if let Some(mut stdin) = child.stdin.take() {
+    let _ = stdin.write_all(input);
+  }
+  let output = child
+    .wait_with_output()
+    .map_err(|_| Message::new("hooks.run_failed"))?;
+  if output.status.success() {
+    return Ok(());
+  }
+  let stderr = String::from_utf8_lossy(&output.stderr);
+  Err(
+    stderr
+      .lines()
+      .find(|line| !line.trim().is_empty())
+      .map_or_else(
+        || Message::new("hooks.exited_with_error"),
+        |line| Message::unexpected(line.trim()),
+      ),
+  )
+}
+
+fn event_for(hook: &Hook, used: Option<f64>, previous_used: Option<f64>) -> Option<&'static str> {
+  match hook.threshold_percent {
+    None => Some("scan_complete"),
+    Some(threshold) => {
+      let above = |percent: Option<f64>| percent.filter(|percent| *percent >= threshold).is_some();
+      let crossed = above(used) && !above(previous_used);
+      crossed.then_some("threshold_crossed")
+    }
+  }
+}
+
+// Runs on its own thread so slow hooks never hold up the scan; failures are emitted as `hook_failed`.
+pub fn fire(
+  app: &tauri::AppHandle,
+  hooks: Vec<Hook>,
+  entry: HistoryEntry,
+  top_files: Vec<FileEntry>,
+  previous_used: Option<f64>,
+) {
+  if hooks.is_empty() {
+    return;
+  }
+  let app = app.clone();
+  thread::spawn(move || {
+    for hook in hooks {
+      let event = match event_for(&hook, entry.used_percent, previous_used) {
+        Some(event) => event,
+        None => continue,
+      };
+      let payload = HookPayload {
+        event,
+        entry: &entry,
+        top_files: &top_files,
+      };
+      let body = match serde_json::to_vec(&payload) {
+        Ok(body) => body,
+        Err(_) => continue,
+      };
+
+      let targets = hook
+        .command
+        .iter()
+        .map(|command| (command.clone(), shell(command)))
+        .chain(hook.url.iter().map(|url| (url.clone(), curl_post(url))));
+      for (target, command) in targets {
+        if let Err(error) = run_with_stdin(command, &body) {
+          let _ = app.emit_to("main", "hook_failed", HookFailedPayload { target, error });
+        }
+      }
+    }
+  });
+}
@@ -0,0 +1,131 @@
+use crate::cache;
+use crate::history;
+use crate::scanner::WalkState;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+const CHECKPOINT_FILE: &str = "checkpoint.json.zst";
+const CHECKPOINT_INTERVAL: Duration = Duration::from_secs(30);
+
+// Everything needed to pick a scan up again: how it was started, plus the directory queue and
+// counters at the last directory boundary.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Checkpoint {
+  #[serde(rename = "scanId", default)]
+  pub scan_id: u64,
+  pub root: String,
+  pub label: Option<String>,
+  #[serde(default)]
+  pub metadata: HashMap<String, String>,
+  #[serde(rename = "topN")]
+  pub top_n: usize,
+  #[serde(default)]
+  pub deterministic: bool,
+  #[serde(rename = "startedAt")]
+  pub started_at: u64,
+  #[serde(rename = "savedAt")]
+  pub saved_at: u64,
+  // Written when the scan was cancelled rather than interrupted by a crash.
+  #[serde(default)]
+  pub cancelled: bool,
+  #[serde(default, skip_serializing_if = "Vec::is_empty")]
+  pub include: Vec<String>,
+  #[serde(
+    rename = "minFileSize",
+    default,
+    skip_serializing_if = "Option::is_none"
+  )]
+  pub min_file_size: Option<u64>,
+  #[serde(flatten)]
+  pub progress: WalkState,
+}
+
+#[derive(Serialize)]
+pub struct InterruptedScan {
+  #[serde(rename = "rootPath")]
+  pub root_path: String,
+  pub label: Option<String>,
+  #[serde(rename = "startedAt")]
+  pub started_at: u64,
+  #[serde(rename = "savedAt")]
+  pub saved_at: u64,
+  #[serde(rename = "scannedFiles")]
+  pub scanned_files: u64,
+  #[serde(rename = "scannedBytes")]
+  pub scanned_bytes: u64,
+  #[serde(rename = "queuedDirs")]
+  pub queued_dirs: usize,
+  pub cancelled: bool,
+}
+
+// Writes a checkpoint of the running scan into the root's cache folder every 30 seconds, and a
+// final one when it is cancelled. The file outlives a crash or force quit; `clear` removes it
+// once the scan has completed.
+pub struct ScanJournal {
+  file: PathBuf,
+  checkpoint: Checkpoint,
+  last_saved: Instant,
+}
+
+impl ScanJournal {
+  pub fn new(root_dir: &Path, mut checkpoint: Checkpoint) -> Self {
+    checkpoint.progress = WalkState::default();
+    Self {
+      file: root_dir.join(CHECKPOINT_FILE),
+      checkpoint,
+      last_saved: Instant::now(),
+    }
+  }
+
+  pub fn due(&self) -> bool {
+    self.last_saved.elapsed() >= CHECKPOINT_INTERVAL
+  }
+
+  pub fn save(&mut self, progress: WalkState) {
+    self.checkpoint.saved_at = history::now_secs();
+    self.checkpoint.progress = progress;
+    let _ = cache::write_compressed(&self.file, &self.checkpoint);
+    self.checkpoint.progress = WalkState::default();
+    self.last_saved = Instant::now();
+  }
+
+  pub fn save_cancelled(&mut self, progress: WalkState) {
+    self.checkpoint.cancelled = true;
+    self.save(progress);
+  }
+}
+
+pub fn load(root_dir: &Path) -> Option<Checkpoint> {
+  cache::read_compressed(&root_dir.join(CHECKPOINT_FILE))
+}
+
+pub fn clear(root_dir: &Path) {
+  let _ = fs::remove_file(root_dir.join(CHECKPOINT_FILE));
+}
+
+// Scans that were cancelled or still running when the app last quit, newest first.
+pub fn interrupted(app_dir: &Path) -> Vec<InterruptedScan> {
+  let entries = match fs::read_dir(app_dir.join("scans")) {
+    Ok(entries) => entries,
+    Err(_) => return Vec::new(),
+  };
+  let mut scans: Vec<InterruptedScan> = entries
+    .flatten()
+    .filter_map(|entry| load(&entry.path()))
+    .map(|checkpoint| InterruptedScan {
+      root_path: checkpoint.root,
+      label: checkpoint.label,
+      started_at: checkpoint.started_at,
+      saved_at: checkpoint.saved_at,
+      scanned_files: checkpoint.progress.scanned_files,
+      scanned_bytes: checkpoint.progress.scanned_bytes,
+      queued_dirs: checkpoint.progress.queue.len(),
+      cancelled: checkpoint.cancelled,
+    })
+    .collect();
+  scans.sort_by_key(|scan| std::cmp::Reverse(scan.saved_at));
+  scans
+}
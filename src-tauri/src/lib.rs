@@ -0,0 +1,2249 @@
+mod analyzers;
+mod annotations;
+mod apfs;
+#[cfg(target_family = "unix")]
+mod api;
+mod archive;
+mod attribution;
+mod audit;
+mod backups;
+mod benchmark;
+mod breakdown;
+mod budgets;
+mod cache;
+mod checksums;
+mod conflicts;
+mod content_type;
+mod coordinator;
+mod dataless;
+mod diff;
+mod dircache;
+mod disk_image;
+mod export;
+mod filesystem;
+mod freespace;
+#[doc(hidden)]
+pub mod fuzzing;
+mod games;
+mod growth;
+mod hashing;
+mod health;
+mod history;
+mod hooks;
+mod journal;
+mod listing;
+mod logs;
+mod maintenance;
+mod media_tags;
+mod messages;
+mod metrics;
+mod offload;
+mod open_files;
+mod path_info;
+mod plan;
+mod policies;
+mod profiler;
+mod remote;
+mod rules;
+mod savings;
+mod scanner;
+mod security;
+mod settings;
+mod shadow_copies;
+mod sharing;
+mod snapshots;
+mod suggestions;
+mod synthetic;
+mod system_usage;
+mod terminal;
+mod tokens;
+mod trash;
+mod tree;
+mod vm_images;
+mod volumes;
+mod watch_session;
+mod watcher;
+
+use annotations::Annotation;
+use apfs::ApfsContainer;
+use archive::ArchiveExecution;
+use attribution::ProcessUsage;
+use audit::AuditEntry;
+use backups::BackupReport;
+use benchmark::BenchmarkReport;
+use breakdown::SpaceBreakdown;
+use budgets::BudgetStatus;
+use checksums::ChecksumDb;
+use conflicts::{ConflictPolicy, ConflictPrompts, Conflicts, Resolution};
+use content_type::ContentType;
+use coordinator::{ScanCoordinator, ScanTicket, WatchRequest};
+use diff::ScanDiff;
+use dircache::DirCache;
+use disk_image::MountedImage;
+use export::ExportExecution;
+use filesystem::{FilesystemInfo, StorageInfo};
+use freespace::FreeSpaceMap;
+use games::GameLibrary;
+use growth::GrowthTicker;
+use hashing::HashThrottle;
+use health::DriveHealth;
+use history::HistoryEntry;
+use journal::{Checkpoint, InterruptedScan, ScanJournal};
+use logs::{FileTail, TruncateResult};
+use maintenance::{CacheStats, MaintenanceReport};
+use messages::Message;
+use offload::{Offload, OffloadReport, OffloadStatus};
+use open_files::{DeleteCheck, UnreleasedSpace};
+use path_info::PathInfo;
+use plan::{CleanupPlan, PlanExecution, PlanSummary};
+use policies::{PolicyPreview, PolicyRun};
+use profiler::ScanProfiler;
+use rules::{NameFilter, RuleSet};
+use savings::SavingsSimulation;
+use scanner::{
+  FileEntry, MemoryStats, ScanConfig, ScanOptions, ScanResync, ScanSummary, StreamedScan,
+  DEFAULT_TOP_N,
+};
+use security::SecurityReport;
+use serde::Serialize;
+use settings::Settings;
+use shadow_copies::ShadowStorage;
+use sharing::SharingReport;
+use snapshots::SnapshotEntry;
+use std::any::Any;
+use std::collections::HashMap;
+#[cfg(target_os = "macos")]
+use std::ffi::CStr;
+#[cfg(target_os = "macos")]
+use std::ffi::CString;
+use std::fs;
+#[cfg(target_os = "macos")]
+use std::os::unix::ffi::OsStrExt;
+use std::panic::{self, AssertUnwindSafe};
+use std::path::{Path, PathBuf};
+use std::ptr;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use suggestions::{Suggestion, SuggestionReport};
+use system_usage::SystemUsage;
+use tauri::Manager;
+use tokens::{DestructiveToken, DestructiveTokens, Role};
+use trash::{Removal, Restored, TrashUsage};
+use tree::TreeStream;
+use vm_images::{Compaction, VmImage};
+use volumes::{VolumeInfo, VolumeScanOptions};
+use watch_session::{WatchSessionHandle, WatchSessionInfo};
+use watcher::{OwnActivity, WatchCommand, WatchStatus};
+
+// Some filesystems release freed blocks asynchronously; wait before measuring the volume.
+const CLEANUP_SETTLE_DELAY: Duration = Duration::from_secs(1);
+
+// The scan state is shared by every command and worker thread. Its lock doesn't poison, so one
+// thread panicking while holding it can't lock everything else out. Which scan is running or
+// watched is up to the `ScanCoordinator`.
+type SharedScanState = parking_lot::Mutex<ScanState>;
+
+struct ScanState {
+  watch_ignore_patterns: Vec<String>,
+  scan_roots: HashMap<u64, PathBuf>,
+  scan_summaries: HashMap<u64, ScanSummary>,
+  // The last suggestions computed for each scan, for `execute_suggestion`.
+  suggestions: HashMap<u64, Vec<Suggestion>>,
+  next_session_id: u64,
+  watch_sessions: HashMap<u64, WatchSessionHandle>,
+  #[cfg(target_family = "unix")]
+  api_server: Option<api::ApiServer>,
+  metrics_server: Option<metrics::MetricsServer>,
+  budget_monitor: Option<budgets::BudgetMonitor>,
+  policy_scheduler: Option<policies::PolicyScheduler>,
+}
+
+#[derive(Serialize)]
+struct DiskOverview {
+  #[serde(rename = "rootPath")]
+  root_path: String,
+  #[serde(rename = "mountPoint")]
+  mount_point: String,
+  #[serde(rename = "volumeName")]
+  volume_name: String,
+  #[serde(rename = "volumeUuid")]
+  volume_uuid: Option<String>,
+  #[serde(rename = "totalBytes")]
+  total_bytes: u64,
+  #[serde(rename = "availableBytes")]
+  available_bytes: u64,
+  #[serde(rename = "usedBytes")]
+  used_bytes: u64,
+  #[serde(rename = "usedPercent")]
+  used_percent: f64,
+  filesystem: Option<FilesystemInfo>,
+  storage: StorageInfo,
+  // The APFS container the volume shares with its siblings, e.g. Macintosh HD, Data, VM.
+  container: Option<ApfsContainer>,
+  #[serde(rename = "systemUsage")]
+  system_usage: SystemUsage,
+  // System, apps, user data, purgeable and other, as far as the scan history covers them.
+  breakdown: SpaceBreakdown,
+  // Volume Shadow Copies stored on the volume; Windows only.
+  #[serde(rename = "shadowCopies")]
+  shadow_copies: Option<ShadowStorage>,
+}
+
+#[derive(Clone, Serialize)]
+struct ProfilePayload {
+  #[serde(rename = "scanId")]
+  scan_id: u64,
+  #[serde(rename = "tracePath")]
+  trace_path: String,
+  directories: usize,
+  error: Option<Message>,
+}
+
+impl Default for ScanState {
+  fn default() -> Self {
+    Self {
+      watch_ignore_patterns: watcher::default_ignore_patterns(),
+      scan_roots: HashMap::new(),
+      scan_summaries: HashMap::new(),
+      suggestions: HashMap::new(),
+      next_session_id: 1,
+      watch_sessions: HashMap::new(),
+      #[cfg(target_family = "unix")]
+      api_server: None,
+      metrics_server: None,
+      budget_monitor: None,
+      policy_scheduler: None,
+    }
+  }
+}
+
+#[derive(Clone, Serialize)]
+struct BackfillPayload {
+  #[serde(rename = "scanId")]
+  scan_id: u64,
+  added: Vec<FileEntry>,
+}
+
+// Tombstones the entries at or under `path` in the retained results (of `scan_id`, or all of
+// them), and refills their top files from the candidates, emitting what was added.
+fn retire_path(app: &tauri::AppHandle, scan_id: Option<u64>, path: &Path) {
+  let state = app.state::<SharedScanState>();
+  let mut state = state.lock();
+  for (id, summary) in state.scan_summaries.iter_mut() {
+    if scan_id.is_some_and(|scan_id| scan_id != *id) {
+      continue;
+    }
+    let removed = summary.mark_removed(path, history::now_secs());
+    let added = summary.backfill(removed);
+    if !added.is_empty() {
+      let _ = app.emit_to(
+        "main",
+        "scan_top_files_backfilled",
+        BackfillPayload {
+          scan_id: *id,
+          added,
+        },
+      );
+    }
+  }
+}
+
+// Every command that changes files goes through this first, so audit mode holds whatever the
+// frontend shows.
+fn ensure_writable(app: &tauri::AppHandle, command: &str) -> Result<(), Message> {
+  if settings::load(&cache::app_dir(app)?).audit_mode {
+    return Err(Message::new("audit.read_only").with("command", command));
+  }
+  Ok(())
+}
+
+// The one way into a destructive command: refused in audit mode, and otherwise only with a
+// token from `request_destructive_token` minted for this command and exactly these targets,
+// spent here. The confirmed summary, or the refusal, goes to the audit log.
+fn authorize_destructive(
+  app: &tauri::AppHandle,
+  token: &str,
+  command: &str,
+  targets: &[String],
+) -> Result<(), Message> {
+  ensure_writable(app, command)?;
+  let redeemed = app
+    .state::<DestructiveTokens>()
+    .redeem(token, command, targets);
+  audit::record_confirmation(&cache::app_dir(app)?, command, targets, redeemed.as_deref());
+  redeemed.map(|_| ())
+}
+
+// Targets listed in the confirmation dialog; the rest are only counted.
+const CONFIRM_TARGETS_SHOWN: usize = 10;
+
+// Mints a single-use token, valid for a minute, that only works for `command` acting on exactly
+// `targets`. The backend asks the user itself, in a native dialog with `summary` and the
+// targets, so the frontend can't confirm for them. Async, so the dialog runs off the main thread.
+#[tauri::command]
+async fn request_destructive_token(
+  summary: String,
+  command: String,
+  targets: Vec<String>,
+  app: tauri::AppHandle,
+  window: tauri::Window,
+) -> Result<DestructiveToken, Message> {
+  ensure_writable(&app, &command)?;
+  let settings = settings::load(&cache::app_dir(&app)?);
+  if !Role::parse(settings.role.as_deref())?.may_confirm(&command) {
+    return Err(
+      Message::new("role.not_allowed")
+        .with("role", settings.role.as_deref().unwrap_or("owner"))
+        .with("command", &command),
+    );
+  }
+  let mut details = vec![summary.clone(), String::new()];
+  details.extend(targets.iter().take(CONFIRM_TARGETS_SHOWN).cloned());
+  if targets.len() > CONFIRM_TARGETS_SHOWN {
+    details.push(
+      Message::new("token.more_targets")
+        .with("count", targets.len() - CONFIRM_TARGETS_SHOWN)
+        .text(),
+    );
+  }
+  let title = Message::new("token.confirm_title")
+    .with("command", &command)
+    .text();
+  if !tauri::api::dialog::blocking::ask(Some(&window), title, details.join("\n")) {
+    return Err(Message::new("token.declined"));
+  }
+  app
+    .state::<DestructiveTokens>()
+    .mint(summary, command, targets)
+}
+
+#[derive(Clone, Serialize)]
+struct ScanFailedPayload {
+  #[serde(rename = "scanId")]
+  scan_id: u64,
+  error: Message,
+}
+
+fn panic_message(payload: &(dyn Any + Send)) -> String {
+  payload
+    .downcast_ref::<&str>()
+    .map(|message| message.to_string())
+    .or_else(|| payload.downcast_ref::<String>().cloned())
+    .unwrap_or_else(|| "unknown error".to_string())
+}
+
+// Runs a scan's or watcher's work on its own thread. A panic would otherwise end the thread
+// silently and leave the scan active for good; instead the scan is cancelled, dropped from the
+// state along with its checkpoint, and reported as `scan_failed`.
+fn spawn_worker(app: tauri::AppHandle, scan_id: u64, body: impl FnOnce() + Send + 'static) {
+  std::thread::spawn(move || {
+    let payload = match panic::catch_unwind(AssertUnwindSafe(body)) {
+      Ok(()) => return,
+      Err(payload) => payload,
+    };
+    let _ = app.state::<ScanCoordinator>().cancel(scan_id);
+    let root = app
+      .state::<SharedScanState>()
+      .lock()
+      .scan_roots
+      .remove(&scan_id);
+    // Resuming from the crashed scan's checkpoint would only walk back into the same crash.
+    if let Some(root_dir) = root.and_then(|root| cache::root_dir(&app, &root).ok()) {
+      if journal::load(&root_dir).is_some_and(|checkpoint| checkpoint.scan_id == scan_id) {
+        journal::clear(&root_dir);
+      }
+    }
+    let payload = ScanFailedPayload {
+      scan_id,
+      error: Message::new("scan.panicked").with("reason", panic_message(&*payload)),
+    };
+    let _ = app.emit_to("main", "scan_failed", payload);
+  });
+}
+
+#[cfg(target_os = "macos")]
+fn mount_point_for_path(path: &PathBuf) -> Option<String> {
+  let c_path = CString::new(path.as_os_str().as_bytes()).ok()?;
+  let mut stats: libc::statfs = unsafe { std::mem::zeroed() };
+  let result = unsafe { libc::statfs(c_path.as_ptr(), &mut stats) };
+  if result != 0 {
+    return None;
+  }
+  let mount = unsafe { CStr::from_ptr(stats.f_mntonname.as_ptr()) };
+  Some(mount.to_string_lossy().to_string())
+}
+
+#[cfg(target_os = "macos")]
+fn volume_name_for_path(path: &PathBuf) -> Option<String> {
+  #[repr(C)]
+  #[derive(Copy, Clone)]
+  struct AttrReference {
+    attr_dataoffset: i32,
+    attr_length: u32,
+  }
+
+  const ATTR_BIT_MAP_COUNT: u16 = 5;
+  const ATTR_VOL_NAME: u32 = 0x00000001;
+
+  let c_path = CString::new(path.as_os_str().as_bytes()).ok()?;
+  let mut attrlist: libc::attrlist = unsafe { std::mem::zeroed() };
+  attrlist.bitmapcount = ATTR_BIT_MAP_COUNT;
+  attrlist.volattr = ATTR_VOL_NAME;
+
+  let mut buffer = vec![0u8; 1024];
+  let result = unsafe {
+    libc::getattrlist(
+      c_path.as_ptr(),
+      &mut attrlist as *mut _ as *mut libc::c_void,
+      buffer.as_mut_ptr() as *mut _,
+      buffer.len(),
+      0,
+    )
+  };
+
+  if result != 0 || buffer.len() < 4 + std::mem::size_of::<AttrReference>() {
+    return None;
+  }
+
+  let attr_ref = unsafe { ptr::read_unaligned(buffer.as_ptr().add(4) as *const AttrReference) };
+  if attr_ref.attr_dataoffset < 0 {
+    return None;
+  }
+  let offset = usize::try_from(attr_ref.attr_dataoffset).ok()?;
+  let start = 4usize.checked_add(offset)?;
+  let end = start.checked_add(attr_ref.attr_length as usize)?;
+  if end > buffer.len() {
+    return None;
+  }
+  let bytes = &buffer[start..end];
+  let trimmed = if bytes.last() == Some(&0) {
+    &bytes[..bytes.len().saturating_sub(1)]
+  } else {
+    bytes
+  };
+  Some(String::from_utf8_lossy(trimmed).to_string())
+}
+
+#[tauri::command]
+fn start_scan(
+  root_path: String,
+  options: Option<ScanOptions>,
+  label: Option<String>,
+  app: tauri::AppHandle,
+  state: tauri::State<SharedScanState>,
+) -> Result<u64, Message> {
+  let label = label
+    .map(|label| label.trim().to_string())
+    .filter(|label| !label.is_empty());
+  spawn_scan(
+    root_path,
+    options.unwrap_or_default(),
+    label,
+    app,
+    state,
+    None,
+    None,
+  )
+}
+
+#[tauri::command]
+fn start_scan_profiling(
+  root_path: String,
+  trace_path: String,
+  app: tauri::AppHandle,
+  state: tauri::State<SharedScanState>,
+) -> Result<u64, Message> {
+  let trace_path = PathBuf::from(trace_path);
+  match trace_path.parent() {
+    Some(parent) if parent.as_os_str().is_empty() || parent.is_dir() => {}
+    _ => return Err(Message::new("scan.trace_dir_missing")),
+  }
+  spawn_scan(
+    root_path,
+    ScanOptions::default(),
+    None,
+    app,
+    state,
+    Some(trace_path),
+    None,
+  )
+}
+
+#[tauri::command(async)]
+fn run_benchmark(profile: Option<String>) -> Result<BenchmarkReport, Message> {
+  benchmark::run(profile.as_deref().unwrap_or("quick"))
+}
+
+// `chonky-disk --diff <root>` or `--diff <old scan id> <new scan id>`, with `--csv` and
+// `--output <file>`, prints or writes a diff report instead of opening the app.
+struct DiffArgs {
+  scans: Vec<String>,
+  format: &'static str,
+  output: Option<PathBuf>,
+}
+
+fn diff_args() -> Option<DiffArgs> {
+  let args: Vec<String> = std::env::args().collect();
+  let start = args.iter().position(|arg| arg == "--diff")? + 1;
+  Some(DiffArgs {
+    scans: args[start..]
+      .iter()
+      .take_while(|arg| !arg.starts_with("--"))
+      .take(2)
+      .cloned()
+      .collect(),
+    format: if args.iter().any(|arg| arg == "--csv") {
+      "csv"
+    } else {
+      "json"
+    },
+    output: args
+      .iter()
+      .position(|arg| arg == "--output")
+      .and_then(|index| args.get(index + 1))
+      .map(PathBuf::from),
+  })
+}
+
+fn run_diff(app_dir: &Path, args: &DiffArgs) -> Result<(), Message> {
+  let (old, new) = match args.scans.as_slice() {
+    [root] => diff::latest_pair(app_dir, Path::new(root))?,
+    [old, new] => match (old.parse(), new.parse()) {
+      (Ok(old), Ok(new)) => (old, new),
+      _ => return Err(Message::new("diff.invalid_scan_ids")),
+    },
+    _ => return Err(Message::new("diff.usage")),
+  };
+  let diff = diff::diff_scans(app_dir, old, new)?;
+  match &args.output {
+    Some(path) => diff::write_report(&diff, path, args.format),
+    None => {
+      print!("{}", diff::render(&diff, args.format)?);
+      Ok(())
+    }
+  }
+}
+
+// `chonky-disk --benchmark [profile]` runs the benchmark and prints its report instead of
+// opening the app.
+fn benchmark_profile_arg() -> Option<String> {
+  let mut args = std::env::args().skip_while(|arg| arg != "--benchmark");
+  args.next()?;
+  Some(
+    args
+      .next()
+      .filter(|arg| !arg.starts_with("--"))
+      .unwrap_or_else(|| "quick".to_string()),
+  )
+}
+
+#[tauri::command(async)]
+fn list_interrupted_scans(app: tauri::AppHandle) -> Result<Vec<InterruptedScan>, Message> {
+  Ok(journal::interrupted(&cache::app_dir(&app)?))
+}
+
+#[tauri::command]
+fn resume_interrupted_scan(
+  root_path: String,
+  app: tauri::AppHandle,
+  state: tauri::State<SharedScanState>,
+) -> Result<u64, Message> {
+  let checkpoint = journal::load(&cache::root_dir(&app, &PathBuf::from(&root_path))?)
+    .ok_or_else(|| Message::new("scan.no_interrupted"))?;
+  let options = ScanOptions {
+    top_n: Some(checkpoint.top_n),
+    metadata: checkpoint.metadata.clone(),
+    deterministic: checkpoint.deterministic,
+    threads: None,
+    incremental: None,
+    include: checkpoint.include.clone(),
+    min_file_size: checkpoint.min_file_size,
+  };
+  spawn_scan(
+    root_path,
+    options,
+    checkpoint.label.clone(),
+    app,
+    state,
+    None,
+    Some(checkpoint),
+  )
+}
+
+// Continues a scan cancelled in this session from where it stopped, as a new scan.
+#[tauri::command]
+fn resume_scan(
+  scan_id: u64,
+  app: tauri::AppHandle,
+  state: tauri::State<SharedScanState>,
+) -> Result<u64, Message> {
+  let root = scan_root(&state, scan_id)?;
+  let checkpoint = journal::load(&cache::root_dir(&app, &root)?)
+    .filter(|checkpoint| checkpoint.cancelled && checkpoint.scan_id == scan_id)
+    .ok_or_else(|| Message::new("scan.no_checkpoint"))?;
+  let options = ScanOptions {
+    top_n: Some(checkpoint.top_n),
+    metadata: checkpoint.metadata.clone(),
+    deterministic: checkpoint.deterministic,
+    threads: None,
+    incremental: None,
+    include: checkpoint.include.clone(),
+    min_file_size: checkpoint.min_file_size,
+  };
+  spawn_scan(
+    root.to_string_lossy().to_string(),
+    options,
+    checkpoint.label.clone(),
+    app,
+    state,
+    None,
+    Some(checkpoint),
+  )
+}
+
+#[tauri::command]
+fn discard_interrupted_scan(root_path: String, app: tauri::AppHandle) -> Result<bool, Message> {
+  journal::clear(&cache::root_dir(&app, &PathBuf::from(&root_path))?);
+  Ok(true)
+}
+
+// Launching the app with `--deterministic` makes every scan traverse in sorted order, e.g. for
+// audits driven through the API socket.
+fn deterministic_by_default() -> bool {
+  std::env::args().any(|arg| arg == "--deterministic")
+}
+
+fn spawn_scan(
+  root_path: String,
+  options: ScanOptions,
+  label: Option<String>,
+  app: tauri::AppHandle,
+  state: tauri::State<SharedScanState>,
+  trace_path: Option<PathBuf>,
+  resume: Option<Checkpoint>,
+) -> Result<u64, Message> {
+  let root = PathBuf::from(root_path);
+  if !root.exists() {
+    return Err(Message::new("path.missing"));
+  }
+
+  let ScanTicket {
+    scan_id,
+    cancel_flag,
+    paused,
+    memory,
+    resync,
+    watch_generation,
+  } = app.state::<ScanCoordinator>().begin_scan()?;
+  state.lock().scan_roots.insert(scan_id, root.clone());
+  let watch_root = root.clone();
+
+  spawn_worker(app.clone(), scan_id, move || {
+    let started_at = resume
+      .as_ref()
+      .map(|checkpoint| checkpoint.started_at)
+      .unwrap_or_else(history::now_secs);
+    let settings = cache::app_dir(&app)
+      .map(|app_dir| settings::load(&app_dir))
+      .unwrap_or_default();
+    let rules = RuleSet::new(&settings.rules);
+    let top_n = options.top_n.unwrap_or(DEFAULT_TOP_N);
+    let deterministic = options.deterministic || deterministic_by_default();
+    let threads = options
+      .threads
+      .unwrap_or_else(|| filesystem::storage_kind(&root).default_threads())
+      .clamp(1, scanner::MAX_THREADS);
+    let root_dir = cache::root_dir(&app, &root).ok();
+    let include = Some(NameFilter::new(&options.include))
+      .filter(|include| !include.is_empty())
+      .map(Arc::new);
+    // A resumed or filtered scan only sees part of the tree, so it neither uses nor replaces the
+    // cache.
+    let incremental = options
+      .incremental
+      .unwrap_or(settings.incremental_scans.enabled)
+      && include.is_none();
+    let dir_cache = root_dir
+      .as_ref()
+      .filter(|_| incremental && resume.is_none())
+      .map(|root_dir| DirCache::open(root_dir, &settings.incremental_scans));
+    let journal = root_dir.as_ref().map(|root_dir| {
+      ScanJournal::new(
+        root_dir,
+        Checkpoint {
+          scan_id,
+          root: root.to_string_lossy().to_string(),
+          label: label.clone(),
+          metadata: options.metadata.clone(),
+          top_n,
+          deterministic,
+          started_at,
+          saved_at: 0,
+          cancelled: false,
+          include: options.include.clone(),
+          min_file_size: options.min_file_size,
+          progress: Default::default(),
+        },
+      )
+    });
+    // The last scan of the root, or the volume's used space when scanning all of it unfiltered.
+    let expected_bytes = cache::app_dir(&app)
+      .ok()
+      .and_then(|app_dir| {
+        history::previous_scanned_bytes(
+          &app_dir,
+          &root.to_string_lossy(),
+          volumes::locate(&root).as_ref(),
+          &options.include,
+        )
+      })
+      .or_else(|| {
+        volumes::volume_for(&root)
+          .filter(|volume| volume.mount_point == root && options.include.is_empty())
+          .and_then(|_| filesystem::volume_space(&root))
+          .map(|(total, available)| total.saturating_sub(available))
+      });
+    let mut profiler = trace_path.as_ref().map(|_| ScanProfiler::default());
+    let (cancelled, summary, dir_totals) = scanner::scan_directory(
+      app.clone(),
+      root.clone(),
+      cancel_flag.clone(),
+      ScanConfig {
+        top_n,
+        rules: Arc::new(rules),
+        deterministic,
+        threads,
+        dir_cache,
+        journal,
+        resume: resume.map(|checkpoint| checkpoint.progress),
+        expected_bytes,
+        include,
+        min_file_size: options.min_file_size.unwrap_or(0),
+        paused,
+        resync,
+      },
+      scan_id,
+      memory,
+      profiler.as_mut(),
+    );
+
+    let entry = HistoryEntry {
+      scan_id,
+      root: summary.path.clone(),
+      label,
+      metadata: options.metadata,
+      started_at,
+      finished_at: history::now_secs(),
+      cancelled,
+      scanned_files: summary.scanned_files,
+      scanned_bytes: summary.scanned_bytes,
+      used_percent: filesystem::used_percent(&root),
+      location: volumes::locate(&root),
+      include: options.include,
+    };
+    // A cancelled scan keeps its checkpoint for `resume_scan`.
+    if let (Some(root_dir), false) = (&root_dir, cancelled) {
+      journal::clear(root_dir);
+    }
+    if let (Ok(app_dir), Some(root_dir)) = (cache::app_dir(&app), &root_dir) {
+      let previous_used =
+        history::previous_used_percent(&app_dir, &entry.root, entry.location.as_ref());
+      let _ = history::record(&app_dir, root_dir, entry.clone(), &summary);
+      if let Some(dir_totals) = &dir_totals {
+        let _ = diff::record_totals(root_dir, scan_id, dir_totals);
+      }
+      // Hooks run shell commands, so audit mode holds them back too.
+      if !cancelled && !settings.audit_mode {
+        hooks::fire(
+          &app,
+          settings.hooks.clone(),
+          entry,
+          summary.top_files.clone(),
+          previous_used,
+        );
+      }
+    }
+
+    if !cancelled {
+      volumes::check_low_space(&app, &root, &settings);
+    }
+
+    if let (Some(profiler), Some(trace_path)) = (profiler, trace_path) {
+      let payload = ProfilePayload {
+        scan_id,
+        trace_path: trace_path.to_string_lossy().to_string(),
+        directories: profiler.directories(),
+        error: profiler
+          .write_chrome_trace(&trace_path)
+          .err()
+          .map(|_| Message::new("scan.trace_write_failed")),
+      };
+      let _ = app.emit_to("main", "scan_profile_written", payload);
+    }
+
+    let ignore_patterns = {
+      let state = app.state::<SharedScanState>();
+      let mut state = state.lock();
+      if !cancelled {
+        state.scan_summaries.insert(scan_id, summary);
+      }
+      state.watch_ignore_patterns.clone()
+    };
+    let coordinator = app.state::<ScanCoordinator>();
+    coordinator.finish(scan_id);
+    if !cancelled && !settings.for_path(&watch_root).never_watch {
+      coordinator.watch(WatchRequest {
+        app: app.clone(),
+        root: watch_root,
+        scan_id,
+        watch_generation,
+        cancel_flag,
+        ignore_patterns,
+      });
+    }
+  });
+
+  Ok(scan_id)
+}
+
+#[derive(Clone, Serialize)]
+struct RemoteScanFailedPayload {
+  #[serde(rename = "scanId")]
+  scan_id: u64,
+  error: Message,
+}
+
+#[tauri::command]
+fn start_remote_scan(
+  host: String,
+  remote_path: String,
+  app: tauri::AppHandle,
+  coordinator: tauri::State<ScanCoordinator>,
+) -> Result<u64, Message> {
+  remote::validate_host(&host)?;
+  if !remote_path.starts_with('/') {
+    return Err(Message::new("remote.path_not_absolute"));
+  }
+
+  let ScanTicket {
+    scan_id,
+    cancel_flag,
+    memory,
+    resync,
+    ..
+  } = coordinator.begin_scan()?;
+
+  spawn_worker(app.clone(), scan_id, move || {
+    let mut scan = StreamedScan::new(app.clone(), scan_id, memory, resync, DEFAULT_TOP_N);
+    let result = remote::scan_over_ssh(&host, &remote_path, &mut scan, &cancel_flag);
+    if let Err(error) = &result {
+      let payload = RemoteScanFailedPayload {
+        scan_id,
+        error: error.clone(),
+      };
+      let _ = app.emit_to("main", "remote_scan_failed", payload);
+    } else {
+      scan.finish(&format!("{}:{}", host, remote_path));
+    }
+
+    app.state::<ScanCoordinator>().finish(scan_id);
+  });
+
+  Ok(scan_id)
+}
+
+#[tauri::command(async)]
+fn list_volumes() -> Result<Vec<VolumeInfo>, Message> {
+  Ok(volumes::list_volumes())
+}
+
+#[tauri::command]
+fn scan_all_volumes(
+  options: Option<VolumeScanOptions>,
+  app: tauri::AppHandle,
+  coordinator: tauri::State<ScanCoordinator>,
+) -> Result<u64, Message> {
+  let settings = settings::load(&cache::app_dir(&app)?);
+  let volumes: Vec<_> = volumes::mounted_volumes()
+    .into_iter()
+    .filter(|volume| !settings.for_volume(Some(volume)).skip_full_scans)
+    .collect();
+  if volumes.is_empty() {
+    return Err(Message::new("volume.none_mounted"));
+  }
+
+  let (scan_id, cancel) = coordinator.begin_volume_scan()?;
+
+  spawn_worker(app.clone(), scan_id, move || {
+    volumes::scan_all(
+      &app,
+      scan_id,
+      volumes,
+      &cancel,
+      &options.unwrap_or_default(),
+    );
+    app.state::<ScanCoordinator>().finish_volume_scan(scan_id);
+  });
+
+  Ok(scan_id)
+}
+
+#[tauri::command]
+fn get_settings(app: tauri::AppHandle) -> Result<Settings, Message> {
+  Ok(settings::load(&cache::app_dir(&app)?))
+}
+
+// Starts, moves, or stops the metrics server to match the settings.
+fn apply_metrics_settings(
+  app: &tauri::AppHandle,
+  state: &mut ScanState,
+  settings: &Settings,
+) -> Result<(), Message> {
+  let address = settings.metrics_socket()?;
+  let current = state.metrics_server.as_ref().map(|server| server.address());
+  if address != current {
+    state.metrics_server = None;
+    if let Some(address) = address {
+      state.metrics_server = Some(metrics::start(app.clone(), address)?);
+    }
+  }
+  Ok(())
+}
+
+// Restarts the budget monitor whenever the budgets change.
+fn apply_budget_settings(
+  app: &tauri::AppHandle,
+  state: &mut ScanState,
+  settings: &Settings,
+) -> Result<(), Message> {
+  let current = state
+    .budget_monitor
+    .as_ref()
+    .map(|monitor| monitor.budgets())
+    .unwrap_or_default();
+  if current != settings.budgets.as_slice() {
+    state.budget_monitor = None;
+    if !settings.budgets.is_empty() {
+      state.budget_monitor = Some(budgets::start(app.clone(), settings.budgets.clone())?);
+    }
+  }
+  Ok(())
+}
+
+// Restarts the archive policy scheduler whenever the policies change.
+fn apply_policy_settings(app: &tauri::AppHandle, state: &mut ScanState, settings: &Settings) {
+  let current = state
+    .policy_scheduler
+    .as_ref()
+    .map(|scheduler| scheduler.policies())
+    .unwrap_or_default();
+  if current != settings.archive_policies.as_slice() {
+    state.policy_scheduler = None;
+    if settings
+      .archive_policies
+      .iter()
+      .any(|policy| policy.every_hours.is_some())
+    {
+      state.policy_scheduler = Some(policies::start_scheduler(
+        app.clone(),
+        settings.archive_policies.clone(),
+      ));
+    }
+  }
+}
+
+#[tauri::command(async)]
+fn preview_archive_policy(name: String, app: tauri::AppHandle) -> Result<PolicyPreview, Message> {
+  let settings = settings::load(&cache::app_dir(&app)?);
+  policies::preview(policies::find(&settings.archive_policies, &name)?)
+}
+
+#[tauri::command(async)]
+fn run_archive_policy(
+  name: String,
+  token: String,
+  app: tauri::AppHandle,
+  own_activity: tauri::State<OwnActivity>,
+) -> Result<PolicyRun, Message> {
+  let settings = settings::load(&cache::app_dir(&app)?);
+  let policy = policies::find(&settings.archive_policies, &name)?;
+  authorize_destructive(
+    &app,
+    &token,
+    "run_archive_policy",
+    &[policy.source.clone(), policy.destination.clone()],
+  )?;
+  Ok(policies::run(policy, &own_activity, false, Some(&app)))
+}
+
+#[tauri::command]
+fn resolve_conflict(
+  id: u64,
+  resolution: String,
+  apply_to_all: Option<bool>,
+  prompts: tauri::State<ConflictPrompts>,
+) -> Result<(), Message> {
+  prompts.answer(
+    id,
+    Resolution::parse(&resolution)?,
+    apply_to_all.unwrap_or(false),
+  )
+}
+
+#[tauri::command]
+fn get_budget_status(state: tauri::State<SharedScanState>) -> Result<Vec<BudgetStatus>, Message> {
+  let state = state.lock();
+  Ok(
+    state
+      .budget_monitor
+      .as_ref()
+      .map(|monitor| monitor.statuses())
+      .unwrap_or_default(),
+  )
+}
+
+// Hooks (by command or URL) and archive policies (by name) that `new` adds or changes. They run
+// without anyone at the keyboard, so setting them up takes a confirmation token.
+fn added_automation(current: &Settings, new: &Settings) -> Vec<String> {
+  let hooks = new
+    .hooks
+    .iter()
+    .filter(|hook| !current.hooks.contains(hook))
+    .filter_map(|hook| hook.command.clone().or_else(|| hook.url.clone()));
+  let policies = new
+    .archive_policies
+    .iter()
+    .filter(|policy| !current.archive_policies.contains(policy))
+    .map(|policy| policy.name.clone());
+  hooks.chain(policies).collect()
+}
+
+#[tauri::command]
+fn set_settings(
+  settings: Settings,
+  token: Option<String>,
+  app: tauri::AppHandle,
+  state: tauri::State<SharedScanState>,
+) -> Result<Settings, Message> {
+  let app_dir = cache::app_dir(&app)?;
+  let current = settings::load(&app_dir);
+  if Role::parse(settings.role.as_deref())? > Role::parse(current.role.as_deref())? {
+    return Err(Message::new("role.locked"));
+  }
+  if current.audit_mode {
+    if !settings.audit_mode {
+      return Err(Message::new("audit.locked"));
+    }
+    // Hooks run shell commands and scheduled policies move files, so neither may change.
+    if settings.hooks != current.hooks || settings.archive_policies != current.archive_policies {
+      return Err(Message::new("audit.automation_locked"));
+    }
+  }
+  let added = added_automation(&current, &settings);
+  if !added.is_empty() {
+    authorize_destructive(&app, token.as_deref().unwrap_or(""), "set_settings", &added)?;
+  }
+  settings::save(&app_dir, &settings)?;
+  let mut state = state.lock();
+  apply_metrics_settings(&app, &mut state, &settings)?;
+  apply_budget_settings(&app, &mut state, &settings)?;
+  apply_policy_settings(&app, &mut state, &settings);
+  app.state::<HashThrottle>().configure(&settings.hashing);
+  Ok(settings)
+}
+
+#[tauri::command]
+fn list_scan_history(app: tauri::AppHandle) -> Result<Vec<HistoryEntry>, Message> {
+  Ok(history::load(&cache::app_dir(&app)?))
+}
+
+#[tauri::command(async)]
+fn diff_scans(
+  old_scan_id: u64,
+  new_scan_id: u64,
+  app: tauri::AppHandle,
+) -> Result<ScanDiff, Message> {
+  diff::diff_scans(&cache::app_dir(&app)?, old_scan_id, new_scan_id)
+}
+
+#[tauri::command(async)]
+fn export_diff_report(
+  old_scan_id: u64,
+  new_scan_id: u64,
+  path: String,
+  format: Option<String>,
+  app: tauri::AppHandle,
+) -> Result<(), Message> {
+  ensure_writable(&app, "export_diff_report")?;
+  let diff = diff::diff_scans(&cache::app_dir(&app)?, old_scan_id, new_scan_id)?;
+  diff::write_report(
+    &diff,
+    &PathBuf::from(path),
+    format.as_deref().unwrap_or("json"),
+  )
+}
+
+// Streams the folder tree of a completed scan as `scan_tree_chunk` events.
+#[tauri::command(async)]
+fn stream_tree(
+  scan_id: u64,
+  chunk_nodes: Option<usize>,
+  app: tauri::AppHandle,
+) -> Result<TreeStream, Message> {
+  let chunk_nodes = chunk_nodes
+    .unwrap_or(tree::DEFAULT_CHUNK_NODES)
+    .clamp(1, tree::MAX_CHUNK_NODES);
+  tree::stream_tree(&app, &cache::app_dir(&app)?, scan_id, chunk_nodes)
+}
+
+#[tauri::command]
+fn cancel_scan(scan_id: u64, coordinator: tauri::State<ScanCoordinator>) -> Result<bool, Message> {
+  coordinator.cancel(scan_id)
+}
+
+// Holds the running scan where it is until it's resumed or cancelled; its results so far stay
+// available. False when `scan_id` isn't the running scan.
+#[tauri::command]
+fn set_scan_paused(
+  scan_id: u64,
+  paused: bool,
+  coordinator: tauri::State<ScanCoordinator>,
+) -> Result<bool, Message> {
+  coordinator.set_paused(scan_id, paused)
+}
+
+#[derive(Serialize)]
+struct ScanStatus {
+  // The running scan, if any; a finished scan that is being watched isn't running.
+  #[serde(rename = "activeId")]
+  active_id: Option<u64>,
+  paused: bool,
+  watch: WatchStatus,
+}
+
+#[tauri::command]
+fn get_scan_status(coordinator: tauri::State<ScanCoordinator>) -> Result<ScanStatus, Message> {
+  let snapshot = coordinator.snapshot()?;
+  Ok(ScanStatus {
+    active_id: snapshot.active_id,
+    paused: snapshot.paused,
+    watch: snapshot.watch.unwrap_or_default(),
+  })
+}
+
+// Catches a reloaded frontend up on a scan: the state of the running scan, or the result of a
+// finished one.
+#[tauri::command(async)]
+fn resync(
+  scan_id: u64,
+  app: tauri::AppHandle,
+  coordinator: tauri::State<ScanCoordinator>,
+  state: tauri::State<SharedScanState>,
+) -> Result<ScanResync, Message> {
+  let snapshot = coordinator.snapshot()?;
+  if snapshot.active_id == Some(scan_id) {
+    if let Some(resync) = snapshot.resync.request() {
+      return Ok(resync);
+    }
+  }
+  let summary = state
+    .lock()
+    .scan_summaries
+    .get(&scan_id)
+    .cloned()
+    .ok_or_else(|| Message::new("scan.not_finished"))?;
+  let totals = cache::app_dir(&app)
+    .and_then(|app_dir| diff::scan_totals(&app_dir, scan_id))
+    .ok();
+  Ok(ScanResync::finished(
+    scan_id,
+    &summary,
+    totals.as_ref().map(|(_, totals)| totals),
+  ))
+}
+
+#[tauri::command]
+fn set_watch_focus(
+  path: String,
+  coordinator: tauri::State<ScanCoordinator>,
+) -> Result<bool, Message> {
+  Ok(coordinator.send_to_watch(WatchCommand::Focus(PathBuf::from(path))))
+}
+
+#[tauri::command]
+fn get_watch_status(coordinator: tauri::State<ScanCoordinator>) -> Result<WatchStatus, Message> {
+  Ok(coordinator.snapshot()?.watch.unwrap_or_default())
+}
+
+#[tauri::command]
+fn get_watch_ignore_patterns(state: tauri::State<SharedScanState>) -> Result<Vec<String>, Message> {
+  let state = state.lock();
+
+  Ok(state.watch_ignore_patterns.clone())
+}
+
+#[tauri::command]
+fn set_watch_ignore_patterns(
+  patterns: Vec<String>,
+  state: tauri::State<SharedScanState>,
+  coordinator: tauri::State<ScanCoordinator>,
+) -> Result<bool, Message> {
+  let patterns: Vec<String> = patterns
+    .into_iter()
+    .map(|pattern| pattern.trim().to_string())
+    .filter(|pattern| !pattern.is_empty())
+    .collect();
+  {
+    let mut state = state.lock();
+    state.watch_ignore_patterns = patterns.clone();
+    for session in state.watch_sessions.values() {
+      session.send(WatchCommand::Ignore(patterns.clone()));
+    }
+  }
+  coordinator.send_to_watch(WatchCommand::Ignore(patterns));
+  Ok(true)
+}
+
+#[tauri::command]
+fn start_watch_session(
+  roots: Vec<String>,
+  app: tauri::AppHandle,
+  state: tauri::State<SharedScanState>,
+) -> Result<u64, Message> {
+  let roots: Vec<PathBuf> = roots.into_iter().map(PathBuf::from).collect();
+  let settings = settings::load(&cache::app_dir(&app)?);
+  if let Some(root) = roots
+    .iter()
+    .find(|root| settings.for_path(root).never_watch)
+  {
+    return Err(Message::new("watch.never_watched_volume").with("path", root.display()));
+  }
+  let mut state = state.lock();
+
+  let session_id = state.next_session_id;
+  let handle = watch_session::start_watch_session(
+    app,
+    session_id,
+    roots,
+    state.watch_ignore_patterns.clone(),
+  )?;
+  state.next_session_id += 1;
+  state.watch_sessions.insert(session_id, handle);
+  Ok(session_id)
+}
+
+#[tauri::command]
+fn stop_watch_session(
+  session_id: u64,
+  state: tauri::State<SharedScanState>,
+) -> Result<bool, Message> {
+  let mut state = state.lock();
+
+  Ok(state.watch_sessions.remove(&session_id).is_some())
+}
+
+#[tauri::command]
+fn list_watch_sessions(
+  state: tauri::State<SharedScanState>,
+) -> Result<Vec<WatchSessionInfo>, Message> {
+  let state = state.lock();
+
+  let mut sessions: Vec<WatchSessionInfo> = state
+    .watch_sessions
+    .values()
+    .map(|session| session.info())
+    .collect();
+  sessions.sort_by_key(|session| session.session_id);
+  Ok(sessions)
+}
+
+// Folders that grew most over the last `minutes` (5 by default) across every watch session, with
+// the processes writing there when `processes` is set.
+#[tauri::command]
+fn get_growth_ticker(
+  minutes: Option<u64>,
+  processes: Option<bool>,
+  state: tauri::State<SharedScanState>,
+) -> Result<GrowthTicker, Message> {
+  let state = state.lock();
+
+  let minutes = minutes.unwrap_or(5).clamp(1, growth::MAX_WINDOW_MINUTES);
+  let mut totals = HashMap::new();
+  for session in state.watch_sessions.values() {
+    if let Ok(growth) = session.growth().lock() {
+      growth.collect(minutes, &mut totals);
+    }
+  }
+  drop(state);
+
+  let mut ticker = growth::ticker(minutes, totals);
+  if processes.unwrap_or(false) {
+    growth::attribute_processes(&mut ticker);
+  }
+  Ok(ticker)
+}
+
+// The `n` largest files of a completed scan (50 by default), up to the candidates it kept, so the
+// list can go deeper than the top files without a rescan.
+#[tauri::command]
+fn get_top_files(
+  scan_id: u64,
+  n: Option<usize>,
+  state: tauri::State<SharedScanState>,
+) -> Result<Vec<FileEntry>, Message> {
+  let state = state.lock();
+
+  let n = n.unwrap_or(DEFAULT_TOP_N).min(scanner::CANDIDATE_POOL);
+  state
+    .scan_summaries
+    .get(&scan_id)
+    .map(|summary| summary.largest(n))
+    .ok_or_else(|| Message::new("scan.not_finished"))
+}
+
+// Virtual machine and container disks among a completed scan's largest files.
+#[tauri::command(async)]
+fn list_vm_images(
+  scan_id: u64,
+  state: tauri::State<SharedScanState>,
+) -> Result<Vec<VmImage>, Message> {
+  let files = state
+    .lock()
+    .scan_summaries
+    .get(&scan_id)
+    .map(|summary| summary.largest(scanner::CANDIDATE_POOL))
+    .ok_or_else(|| Message::new("scan.not_finished"))?;
+  Ok(vm_images::find(&files))
+}
+
+#[tauri::command(async)]
+fn list_games() -> Result<Vec<GameLibrary>, Message> {
+  Ok(games::list_games())
+}
+
+#[tauri::command(async)]
+fn compact_vm_image(
+  path: String,
+  token: String,
+  app: tauri::AppHandle,
+  own_activity: tauri::State<OwnActivity>,
+) -> Result<Compaction, Message> {
+  authorize_destructive(
+    &app,
+    &token,
+    "compact_vm_image",
+    std::slice::from_ref(&path),
+  )?;
+  let path = PathBuf::from(path);
+  own_activity.record(&path);
+  vm_images::compact(&path)
+}
+
+// Entries of a completed scan that the watcher has since seen removed, most recent first.
+#[tauri::command]
+fn get_recent_removals(
+  scan_id: u64,
+  state: tauri::State<SharedScanState>,
+) -> Result<Vec<FileEntry>, Message> {
+  let state = state.lock();
+
+  state
+    .scan_summaries
+    .get(&scan_id)
+    .map(ScanSummary::removals)
+    .ok_or_else(|| Message::new("scan.not_finished"))
+}
+
+fn scan_root(state: &tauri::State<SharedScanState>, scan_id: u64) -> Result<PathBuf, Message> {
+  let state = state.lock();
+
+  state
+    .scan_roots
+    .get(&scan_id)
+    .cloned()
+    .ok_or_else(|| Message::new("scan.unknown"))
+}
+
+#[tauri::command]
+fn annotate_path(
+  scan_id: u64,
+  path: String,
+  note: String,
+  app: tauri::AppHandle,
+  state: tauri::State<SharedScanState>,
+) -> Result<bool, Message> {
+  ensure_writable(&app, "annotate_path")?;
+  let root = scan_root(&state, scan_id)?;
+  if !PathBuf::from(&path).starts_with(&root) {
+    return Err(Message::new("path.outside_scan"));
+  }
+  annotations::annotate(&cache::root_dir(&app, &root)?, &path, &note)?;
+  Ok(true)
+}
+
+#[tauri::command]
+fn get_annotations(
+  scan_id: u64,
+  app: tauri::AppHandle,
+  state: tauri::State<SharedScanState>,
+) -> Result<Vec<Annotation>, Message> {
+  let root = scan_root(&state, scan_id)?;
+  Ok(annotations::load(&cache::root_dir(&app, &root)?))
+}
+
+#[tauri::command]
+#[cfg(target_family = "unix")]
+fn enable_api_socket(
+  app: tauri::AppHandle,
+  state: tauri::State<SharedScanState>,
+) -> Result<String, Message> {
+  let mut state = state.lock();
+
+  if state.api_server.is_none() {
+    let path = api::socket_path(&app)?;
+    state.api_server = Some(api::start(app, path)?);
+  }
+  Ok(
+    state
+      .api_server
+      .as_ref()
+      .map(|server| server.path().to_string_lossy().to_string())
+      .unwrap_or_default(),
+  )
+}
+
+#[tauri::command]
+#[cfg(not(target_family = "unix"))]
+fn enable_api_socket() -> Result<String, Message> {
+  Err(Message::new("api.unsupported"))
+}
+
+#[tauri::command]
+#[cfg(target_family = "unix")]
+fn disable_api_socket(state: tauri::State<SharedScanState>) -> Result<bool, Message> {
+  let mut state = state.lock();
+
+  Ok(state.api_server.take().is_some())
+}
+
+#[tauri::command]
+#[cfg(not(target_family = "unix"))]
+fn disable_api_socket() -> Result<bool, Message> {
+  Ok(false)
+}
+
+#[tauri::command(async)]
+fn get_cache_stats(app: tauri::AppHandle) -> Result<CacheStats, Message> {
+  Ok(maintenance::stats(&cache::app_dir(&app)?))
+}
+
+#[tauri::command(async)]
+fn run_cache_maintenance(app: tauri::AppHandle) -> Result<MaintenanceReport, Message> {
+  ensure_writable(&app, "run_cache_maintenance")?;
+  let app_dir = cache::app_dir(&app)?;
+  let settings = settings::load(&app_dir);
+  Ok(maintenance::run(&app_dir, &settings.cache_retention))
+}
+
+#[tauri::command]
+fn get_memory_stats(coordinator: tauri::State<ScanCoordinator>) -> Result<MemoryStats, Message> {
+  Ok(coordinator.snapshot()?.memory.snapshot())
+}
+
+#[tauri::command]
+fn delete_file(
+  path: String,
+  confirm_permanent: Option<bool>,
+  token: String,
+  app: tauri::AppHandle,
+  own_activity: tauri::State<OwnActivity>,
+) -> Result<Removal, Message> {
+  authorize_destructive(&app, &token, "delete_file", std::slice::from_ref(&path))?;
+  let path = PathBuf::from(path);
+  let metadata = fs::symlink_metadata(&path).map_err(|_| Message::new("file.not_found"))?;
+  if !metadata.is_file() || metadata.file_type().is_symlink() {
+    return Err(Message::new("file.only_regular_delete"));
+  }
+  let app_dir = cache::app_dir(&app)?;
+  let settings = settings::load(&app_dir);
+  own_activity.record(&path);
+  let removal = trash::remove(
+    &path,
+    &settings,
+    &app_dir,
+    confirm_permanent.unwrap_or(false),
+  )?;
+  retire_path(&app, None, &path);
+  Ok(removal)
+}
+
+// Whether files have a copy in the latest Time Machine backup, for the permanent delete dialog.
+#[tauri::command(async)]
+fn get_backup_status(paths: Vec<String>) -> Result<BackupReport, Message> {
+  Ok(backups::backup_status(&paths))
+}
+
+// Every delete, trash, move, and truncate the app made since `since` (seconds since the epoch),
+// oldest first.
+#[tauri::command(async)]
+fn get_audit_log(since: Option<u64>, app: tauri::AppHandle) -> Result<Vec<AuditEntry>, Message> {
+  Ok(audit::load(&cache::app_dir(&app)?, since.unwrap_or(0)))
+}
+
+// Puts an item back from the trash, given the audit log entry that trashed it.
+#[tauri::command(async)]
+fn restore_item(
+  audit_id: u64,
+  token: String,
+  app: tauri::AppHandle,
+  own_activity: tauri::State<OwnActivity>,
+) -> Result<Restored, Message> {
+  let app_dir = cache::app_dir(&app)?;
+  let original = audit::find(&app_dir, audit_id)
+    .ok_or_else(|| Message::new("restore.unknown_entry"))?
+    .path;
+  authorize_destructive(&app, &token, "restore_item", &[original])?;
+  trash::restore(&app_dir, audit_id, &own_activity)
+}
+
+#[tauri::command(async)]
+fn archive_and_delete(
+  paths: Vec<String>,
+  archive_path: String,
+  token: String,
+  app: tauri::AppHandle,
+  own_activity: tauri::State<OwnActivity>,
+) -> Result<ArchiveExecution, Message> {
+  let targets: Vec<String> = paths.iter().chain([&archive_path]).cloned().collect();
+  authorize_destructive(&app, &token, "archive_and_delete", &targets)?;
+  let app_dir = cache::app_dir(&app)?;
+  archive::archive_then_delete(
+    &paths,
+    &PathBuf::from(archive_path),
+    &own_activity,
+    &app_dir,
+  )
+}
+
+#[tauri::command(async)]
+fn export_selection(
+  paths: Vec<String>,
+  dest: String,
+  mode: Option<String>,
+  on_conflict: Option<String>,
+  token: Option<String>,
+  app: tauri::AppHandle,
+) -> Result<ExportExecution, Message> {
+  let hard_link = match mode.as_deref().unwrap_or("copy") {
+    "copy" => false,
+    "hardlink" => true,
+    _ => return Err(Message::new("export.invalid_mode")),
+  };
+  let app_dir = cache::app_dir(&app)?;
+  let settings = settings::load(&app_dir);
+  let policy = ConflictPolicy::parse(on_conflict.or(settings.conflict_policy).as_deref())?;
+  // Copying out only adds files, unless it may replace ones already in `dest`.
+  if policy.may_overwrite() {
+    let mut targets = paths.clone();
+    targets.push(dest.clone());
+    authorize_destructive(
+      &app,
+      token.as_deref().unwrap_or(""),
+      "export_selection",
+      &targets,
+    )?;
+  } else {
+    ensure_writable(&app, "export_selection")?;
+  }
+  export::export_selection(
+    &paths,
+    &PathBuf::from(dest),
+    hard_link,
+    &mut Conflicts::new(policy, "export", Some(&app)),
+    &app_dir,
+    &mut |progress| {
+      let _ = app.emit_to("main", "export_progress", progress.clone());
+    },
+  )
+}
+
+#[tauri::command(async)]
+fn offload_item(
+  path: String,
+  destination: String,
+  on_conflict: Option<String>,
+  token: String,
+  app: tauri::AppHandle,
+  own_activity: tauri::State<OwnActivity>,
+) -> Result<Offload, Message> {
+  authorize_destructive(
+    &app,
+    &token,
+    "offload_item",
+    &[path.clone(), destination.clone()],
+  )?;
+  let app_dir = cache::app_dir(&app)?;
+  let settings = settings::load(&app_dir);
+  let policy = ConflictPolicy::parse(on_conflict.or(settings.conflict_policy).as_deref())?;
+  let path = PathBuf::from(path);
+  own_activity.record(&path);
+  offload::offload(
+    &path,
+    &PathBuf::from(destination),
+    &mut Conflicts::new(policy, "offload", Some(&app)),
+    &app_dir,
+  )
+}
+
+#[tauri::command(async)]
+fn list_offloads(app: tauri::AppHandle) -> Result<Vec<OffloadStatus>, Message> {
+  Ok(offload::list(&cache::app_dir(&app)?))
+}
+
+#[tauri::command(async)]
+fn revalidate_offloads(app: tauri::AppHandle) -> Result<OffloadReport, Message> {
+  Ok(offload::revalidate(&cache::app_dir(&app)?, Vec::new()))
+}
+
+#[tauri::command(async)]
+fn undo_offload(
+  path: String,
+  token: String,
+  app: tauri::AppHandle,
+  own_activity: tauri::State<OwnActivity>,
+) -> Result<Offload, Message> {
+  authorize_destructive(&app, &token, "undo_offload", std::slice::from_ref(&path))?;
+  let path = PathBuf::from(path);
+  own_activity.record(&path);
+  offload::undo(&path, &cache::app_dir(&app)?)
+}
+
+#[tauri::command]
+fn add_to_plan(
+  paths: Vec<String>,
+  plan: tauri::State<Mutex<CleanupPlan>>,
+) -> Result<PlanSummary, Message> {
+  let mut plan = plan
+    .lock()
+    .map_err(|_| Message::new("state.plan_lock_poisoned"))?;
+
+  let rejected = plan.add(paths);
+  Ok(PlanSummary {
+    rejected,
+    ..plan.summary()
+  })
+}
+
+#[tauri::command]
+fn remove_from_plan(
+  paths: Vec<String>,
+  plan: tauri::State<Mutex<CleanupPlan>>,
+) -> Result<PlanSummary, Message> {
+  let mut plan = plan
+    .lock()
+    .map_err(|_| Message::new("state.plan_lock_poisoned"))?;
+
+  plan.remove(&paths);
+  Ok(plan.summary())
+}
+
+#[tauri::command]
+fn clear_plan(plan: tauri::State<Mutex<CleanupPlan>>) -> Result<bool, Message> {
+  let mut plan = plan
+    .lock()
+    .map_err(|_| Message::new("state.plan_lock_poisoned"))?;
+
+  plan.clear();
+  Ok(true)
+}
+
+#[tauri::command]
+fn get_plan(plan: tauri::State<Mutex<CleanupPlan>>) -> Result<PlanSummary, Message> {
+  let plan = plan
+    .lock()
+    .map_err(|_| Message::new("state.plan_lock_poisoned"))?;
+
+  Ok(plan.summary())
+}
+
+#[tauri::command]
+fn execute_plan(
+  confirm_permanent: Option<bool>,
+  token: String,
+  app: tauri::AppHandle,
+  plan: tauri::State<Mutex<CleanupPlan>>,
+  own_activity: tauri::State<OwnActivity>,
+) -> Result<PlanExecution, Message> {
+  let app_dir = cache::app_dir(&app)?;
+  let settings = settings::load(&app_dir);
+  let (execution, baseline, paths) = {
+    let mut plan = plan
+      .lock()
+      .map_err(|_| Message::new("state.plan_lock_poisoned"))?;
+    // Checked under the plan's lock, so the plan can't change between the check and the run.
+    let targets: Vec<String> = plan
+      .summary()
+      .items
+      .into_iter()
+      .map(|item| item.path)
+      .collect();
+    authorize_destructive(&app, &token, "execute_plan", &targets)?;
+    let paths: Vec<PathBuf> = targets.into_iter().map(PathBuf::from).collect();
+    for path in &paths {
+      own_activity.record(path);
+    }
+    let (execution, baseline) =
+      plan.execute(&settings, &app_dir, confirm_permanent.unwrap_or(false))?;
+    (execution, baseline, paths)
+  };
+  for path in &paths {
+    if !execution
+      .failures
+      .iter()
+      .any(|failure| Path::new(&failure.path) == path)
+    {
+      retire_path(&app, None, path);
+    }
+  }
+
+  std::thread::spawn(move || {
+    std::thread::sleep(CLEANUP_SETTLE_DELAY);
+    let report = plan::cleanup_report(baseline);
+    let _ = app.emit_to("main", "cleanup_report", report);
+  });
+
+  Ok(execution)
+}
+
+#[tauri::command(async)]
+fn export_plan(
+  file_path: String,
+  app: tauri::AppHandle,
+  plan: tauri::State<Mutex<CleanupPlan>>,
+) -> Result<usize, Message> {
+  ensure_writable(&app, "export_plan")?;
+  let plan = plan
+    .lock()
+    .map_err(|_| Message::new("state.plan_lock_poisoned"))?;
+
+  plan.export(&PathBuf::from(file_path))
+}
+
+#[tauri::command(async)]
+fn import_plan(
+  file_path: String,
+  app: tauri::AppHandle,
+  plan: tauri::State<Mutex<CleanupPlan>>,
+) -> Result<PlanSummary, Message> {
+  ensure_writable(&app, "import_plan")?;
+  let mut plan = plan
+    .lock()
+    .map_err(|_| Message::new("state.plan_lock_poisoned"))?;
+
+  let rejected = plan.import(&PathBuf::from(file_path))?;
+  Ok(PlanSummary {
+    rejected,
+    ..plan.summary()
+  })
+}
+
+#[tauri::command(async)]
+fn get_suggestions(
+  scan_id: u64,
+  app: tauri::AppHandle,
+  state: tauri::State<SharedScanState>,
+) -> Result<Vec<Suggestion>, Message> {
+  let root = scan_root(&state, scan_id)?;
+  let top_files: Vec<FileEntry> = state
+    .lock()
+    .scan_summaries
+    .get(&scan_id)
+    .map(|summary| {
+      summary
+        .top_files
+        .iter()
+        .filter(|entry| entry.removed_at.is_none())
+        .cloned()
+        .collect()
+    })
+    .unwrap_or_default();
+
+  let disabled = cache::app_dir(&app)
+    .map(|app_dir| settings::load(&app_dir).disabled_analyzers)
+    .unwrap_or_default();
+  let checksums = ChecksumDb::open(cache::root_dir(&app, &root).ok().as_deref(), &root);
+  let suggestions = suggestions::suggestions(
+    &root,
+    &top_files,
+    &disabled,
+    &app.state::<HashThrottle>(),
+    &checksums,
+  );
+  let _ = checksums.save();
+  state
+    .lock()
+    .suggestions
+    .insert(scan_id, suggestions.clone());
+  Ok(suggestions)
+}
+
+#[tauri::command(async)]
+fn execute_suggestion(
+  scan_id: u64,
+  suggestion_id: String,
+  confirm_permanent: Option<bool>,
+  token: String,
+  app: tauri::AppHandle,
+  state: tauri::State<SharedScanState>,
+  own_activity: tauri::State<OwnActivity>,
+) -> Result<SuggestionReport, Message> {
+  let suggestion = state
+    .lock()
+    .suggestions
+    .get(&scan_id)
+    .and_then(|suggestions| {
+      suggestions
+        .iter()
+        .find(|suggestion| suggestion.id == suggestion_id)
+        .cloned()
+    })
+    .ok_or_else(|| Message::new("suggestion.unknown"))?;
+  authorize_destructive(&app, &token, "execute_suggestion", &suggestion.paths)?;
+  let app_dir = cache::app_dir(&app)?;
+  let settings = settings::load(&app_dir);
+  for path in &suggestion.paths {
+    own_activity.record(Path::new(path));
+  }
+  let report = suggestions::execute(
+    &suggestion,
+    &settings,
+    &app_dir,
+    confirm_permanent.unwrap_or(false),
+    &mut |path| retire_path(&app, None, path),
+    &mut |progress| {
+      let _ = app.emit_to("main", "suggestion_progress", progress.clone());
+    },
+  )?;
+
+  // What failed stays suggested so it can be retried.
+  if let Some(suggestions) = state.lock().suggestions.get_mut(&scan_id) {
+    suggestions.retain_mut(|kept| {
+      if kept.id != suggestion_id {
+        return true;
+      }
+      kept
+        .paths
+        .retain(|path| report.failures.iter().any(|failure| &failure.path == path));
+      !kept.paths.is_empty()
+    });
+  }
+  Ok(report)
+}
+
+#[tauri::command]
+fn list_analyzers() -> Result<Vec<&'static str>, Message> {
+  Ok(analyzers::Registry::default().names())
+}
+
+#[tauri::command(async)]
+fn simulate_savings(
+  scan_id: u64,
+  selections: Vec<String>,
+  state: tauri::State<SharedScanState>,
+) -> Result<SavingsSimulation, Message> {
+  let root = scan_root(&state, scan_id)?;
+  let selections: Vec<PathBuf> = selections.into_iter().map(PathBuf::from).collect();
+  if selections.iter().any(|path| !path.starts_with(&root)) {
+    return Err(Message::new("path.selection_outside_root"));
+  }
+  savings::simulate(&root, &selections)
+}
+
+#[tauri::command(async)]
+fn analyze_sharing(path: String) -> Result<SharingReport, Message> {
+  sharing::analyze(&PathBuf::from(path))
+}
+
+#[tauri::command(async)]
+fn get_free_space_map(path: String) -> Result<FreeSpaceMap, Message> {
+  freespace::free_space_map(&PathBuf::from(path))
+}
+
+#[tauri::command(async)]
+fn get_trash_usage(app: tauri::AppHandle) -> Result<TrashUsage, Message> {
+  Ok(trash::usage(&cache::app_dir(&app)?))
+}
+
+#[tauri::command(async)]
+fn check_delete(path: String, app: tauri::AppHandle) -> Result<DeleteCheck, Message> {
+  let path = PathBuf::from(path);
+  if fs::symlink_metadata(&path).is_err() {
+    return Err(Message::new("file.not_found"));
+  }
+  let settings = settings::load(&cache::app_dir(&app)?);
+  Ok(open_files::check_delete(
+    &path,
+    trash::trash_unavailable(&path, &settings),
+  ))
+}
+
+#[tauri::command(async)]
+fn find_unreleased_space(root_path: Option<String>) -> Result<UnreleasedSpace, Message> {
+  let root = root_path.map(PathBuf::from);
+  if let Some(root) = &root {
+    if !root.exists() {
+      return Err(Message::new("path.missing"));
+    }
+  }
+  Ok(open_files::unreleased_space(root.as_deref()))
+}
+
+#[tauri::command(async)]
+fn attribute_to_processes(paths: Vec<String>) -> Result<Vec<ProcessUsage>, Message> {
+  let paths: Vec<PathBuf> = paths.into_iter().map(PathBuf::from).collect();
+  Ok(attribution::attribute_paths(&paths))
+}
+
+#[tauri::command(async)]
+fn get_path_info(path: String) -> Result<PathInfo, Message> {
+  path_info::path_info(&PathBuf::from(path))
+}
+
+#[tauri::command(async)]
+fn sniff_type(path: String) -> Result<ContentType, Message> {
+  let path = PathBuf::from(path);
+  let metadata = fs::symlink_metadata(&path).map_err(|_| Message::new("file.not_found"))?;
+  if !metadata.is_file() {
+    return Err(Message::new("file.only_regular_inspect"));
+  }
+  content_type::sniff_type(&path)
+}
+
+#[tauri::command(async)]
+fn tail_file(path: String, bytes: u64) -> Result<FileTail, Message> {
+  let path = PathBuf::from(path);
+  let metadata = fs::symlink_metadata(&path).map_err(|_| Message::new("file.not_found"))?;
+  if !metadata.is_file() {
+    return Err(Message::new("file.only_regular_preview"));
+  }
+  logs::tail_file(&path, bytes)
+}
+
+#[tauri::command(async)]
+fn truncate_file(
+  path: String,
+  keep_bytes: Option<u64>,
+  token: String,
+  app: tauri::AppHandle,
+  own_activity: tauri::State<OwnActivity>,
+) -> Result<TruncateResult, Message> {
+  authorize_destructive(&app, &token, "truncate_file", std::slice::from_ref(&path))?;
+  let path = PathBuf::from(path);
+  let metadata = fs::symlink_metadata(&path).map_err(|_| Message::new("file.not_found"))?;
+  if !metadata.is_file() || metadata.file_type().is_symlink() {
+    return Err(Message::new("file.only_regular_truncate"));
+  }
+  own_activity.record(&path);
+  let truncated = logs::truncate_file(&path, keep_bytes.unwrap_or(0));
+  audit::record(
+    &cache::app_dir(&app)?,
+    "truncate",
+    &path,
+    metadata.len(),
+    None,
+    truncated.as_ref().map(|_| ()),
+  );
+  truncated
+}
+
+#[tauri::command]
+fn open_terminal_at(path: String, app: tauri::AppHandle) -> Result<bool, Message> {
+  ensure_writable(&app, "open_terminal_at")?;
+  let path = PathBuf::from(path);
+  let metadata = fs::metadata(&path).map_err(|_| Message::new("path.missing"))?;
+  let dir = if metadata.is_dir() {
+    path.as_path()
+  } else {
+    path
+      .parent()
+      .ok_or_else(|| Message::new("path.no_parent"))?
+  };
+  terminal::open_terminal(dir)?;
+  Ok(true)
+}
+
+#[tauri::command]
+fn mount_disk_image(path: String, app: tauri::AppHandle) -> Result<MountedImage, Message> {
+  ensure_writable(&app, "mount_disk_image")?;
+  let path = PathBuf::from(path);
+  if !path.exists() {
+    return Err(Message::new("path.missing"));
+  }
+  if !disk_image::is_disk_image(&path) {
+    return Err(Message::new("image.unsupported_type"));
+  }
+  disk_image::mount(&path)
+}
+
+#[tauri::command]
+fn unmount_disk_image(device: String, app: tauri::AppHandle) -> Result<bool, Message> {
+  ensure_writable(&app, "unmount_disk_image")?;
+  if !device.starts_with("/dev/") {
+    return Err(Message::new("image.invalid_device"));
+  }
+  disk_image::unmount(&device)?;
+  Ok(true)
+}
+
+#[tauri::command(async)]
+fn security_report(root_path: String) -> Result<SecurityReport, Message> {
+  let root = PathBuf::from(root_path);
+  if !root.is_dir() {
+    return Err(Message::new("path.not_directory"));
+  }
+  Ok(security::security_report(root))
+}
+
+#[tauri::command]
+fn list_snapshots(root_path: String) -> Result<Vec<SnapshotEntry>, Message> {
+  let root = PathBuf::from(root_path);
+  if !root.exists() {
+    return Err(Message::new("path.missing"));
+  }
+  snapshots::list_snapshots(&root)
+}
+
+#[tauri::command]
+fn get_drive_health(mount_point: String) -> Result<DriveHealth, Message> {
+  let mount_point = PathBuf::from(mount_point);
+  if !mount_point.exists() {
+    return Err(Message::new("path.missing"));
+  }
+  health::drive_health(&mount_point)
+}
+
+#[tauri::command]
+#[cfg(any(target_family = "unix", target_os = "windows"))]
+fn disk_overview(root_path: String, app: tauri::AppHandle) -> Result<DiskOverview, Message> {
+  let root = PathBuf::from(root_path.clone());
+  let (total, available) =
+    filesystem::volume_space(&root).ok_or_else(|| Message::new("disk.usage_unreadable"))?;
+  let used = total.saturating_sub(available);
+  let used_percent = if total > 0 {
+    (used as f64 / total as f64) * 100.0
+  } else {
+    0.0
+  };
+
+  #[cfg(target_os = "macos")]
+  let mount_point = mount_point_for_path(&root).unwrap_or_else(|| root_path.clone());
+  #[cfg(not(target_os = "macos"))]
+  let mount_point = root_path.clone();
+
+  #[cfg(target_os = "macos")]
+  let volume_name = volume_name_for_path(&PathBuf::from(&mount_point))
+    .unwrap_or_else(|| mount_point.clone());
+  #[cfg(not(target_os = "macos"))]
+  let volume_name = mount_point.clone();
+
+  let system_usage = system_usage::system_usage(&root);
+  let breakdown = breakdown::breakdown(
+    &cache::app_dir(&app)?,
+    &root,
+    used,
+    system_usage.total_bytes,
+  );
+  Ok(DiskOverview {
+    root_path,
+    mount_point,
+    volume_name,
+    volume_uuid: volumes::volume_for(&root).and_then(|volume| volumes::volume_uuid(&volume)),
+    total_bytes: total,
+    available_bytes: available,
+    used_bytes: used,
+    used_percent,
+    filesystem: filesystem::filesystem_info(&root),
+    storage: filesystem::storage_info(&root),
+    container: apfs::container_for(&root),
+    system_usage,
+    breakdown,
+    shadow_copies: shadow_copies::shadow_storage(&root),
+  })
+}
+
+#[tauri::command]
+#[cfg(not(any(target_family = "unix", target_os = "windows")))]
+fn disk_overview(_root_path: String, _app: tauri::AppHandle) -> Result<DiskOverview, Message> {
+  Err(Message::new("disk.usage_unsupported"))
+}
+
+// The app's entry point; `main.rs` only calls this, so the fuzz targets can link the library.
+pub fn run() {
+  if let Some(profile) = benchmark_profile_arg() {
+    match benchmark::run(&profile).and_then(|report| {
+      serde_json::to_string_pretty(&report).map_err(|_| Message::new("app.encode_report_failed"))
+    }) {
+      Ok(report) => println!("{}", report),
+      Err(error) => {
+        eprintln!("{}", error);
+        std::process::exit(1);
+      }
+    }
+    return;
+  }
+
+  let context = tauri::generate_context!();
+  if let Some(args) = diff_args() {
+    let result = tauri::api::path::app_data_dir(context.config())
+      .ok_or_else(|| Message::new("app.data_dir_unavailable"))
+      .and_then(|app_dir| run_diff(&app_dir, &args));
+    if let Err(error) = result {
+      eprintln!("{}", error);
+      std::process::exit(1);
+    }
+    return;
+  }
+
+  tauri::Builder::default()
+    .manage(SharedScanState::new(ScanState::default()))
+    .manage(ScanCoordinator::start())
+    .manage(Mutex::new(CleanupPlan::default()))
+    .manage(OwnActivity::default())
+    .manage(ConflictPrompts::default())
+    .manage(HashThrottle::default())
+    .manage(DestructiveTokens::default())
+    .setup(|app| {
+      let handle = app.handle();
+      if let Ok(app_dir) = cache::app_dir(&handle) {
+        let settings = settings::load(&app_dir);
+        handle.state::<HashThrottle>().configure(&settings.hashing);
+        let next_id = history::load(&app_dir)
+          .iter()
+          .map(|entry| entry.scan_id + 1)
+          .max();
+        if let Some(next_id) = next_id {
+          handle.state::<ScanCoordinator>().skip_ids_below(next_id);
+        }
+        let state = handle.state::<SharedScanState>();
+        let mut state = state.lock();
+        let _ = apply_metrics_settings(&handle, &mut state, &settings);
+        let _ = apply_budget_settings(&handle, &mut state, &settings);
+        apply_policy_settings(&handle, &mut state, &settings);
+      }
+      offload::start_watcher(handle.clone());
+      maintenance::start(handle);
+      Ok(())
+    })
+    .invoke_handler(tauri::generate_handler![
+      start_scan,
+      start_scan_profiling,
+      run_benchmark,
+      list_interrupted_scans,
+      resume_interrupted_scan,
+      resume_scan,
+      discard_interrupted_scan,
+      list_scan_history,
+      diff_scans,
+      export_diff_report,
+      stream_tree,
+      get_settings,
+      set_settings,
+      get_budget_status,
+      preview_archive_policy,
+      run_archive_policy,
+      resolve_conflict,
+      list_volumes,
+      scan_all_volumes,
+      start_remote_scan,
+      cancel_scan,
+      set_scan_paused,
+      get_scan_status,
+      resync,
+      set_watch_focus,
+      get_watch_status,
+      get_watch_ignore_patterns,
+      set_watch_ignore_patterns,
+      start_watch_session,
+      stop_watch_session,
+      list_watch_sessions,
+      get_growth_ticker,
+      get_recent_removals,
+      get_top_files,
+      get_memory_stats,
+      get_cache_stats,
+      run_cache_maintenance,
+      enable_api_socket,
+      disable_api_socket,
+      annotate_path,
+      get_annotations,
+      get_suggestions,
+      list_vm_images,
+      compact_vm_image,
+      list_games,
+      execute_suggestion,
+      list_analyzers,
+      simulate_savings,
+      analyze_sharing,
+      get_trash_usage,
+      get_free_space_map,
+      check_delete,
+      request_destructive_token,
+      delete_file,
+      get_backup_status,
+      get_audit_log,
+      restore_item,
+      archive_and_delete,
+      export_selection,
+      offload_item,
+      list_offloads,
+      revalidate_offloads,
+      undo_offload,
+      add_to_plan,
+      remove_from_plan,
+      clear_plan,
+      get_plan,
+      execute_plan,
+      export_plan,
+      import_plan,
+      find_unreleased_space,
+      attribute_to_processes,
+      get_path_info,
+      sniff_type,
+      tail_file,
+      truncate_file,
+      open_terminal_at,
+      mount_disk_image,
+      unmount_disk_image,
+      list_snapshots,
+      security_report,
+      get_drive_health,
+      disk_overview
+    ])
+    .run(context)
+    .expect("error while running tauri application");
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use std::thread;
+
+  #[test]
+  fn state_stays_usable_after_a_panic_while_locked() {
+    let state = Arc::new(SharedScanState::new(ScanState::default()));
+    let panicking = {
+      let state = state.clone();
+      thread::spawn(move || {
+        let mut state = state.lock();
+        state.scan_roots.insert(1, PathBuf::from("/crashed"));
+        panic!("worker crashed while holding the scan state");
+      })
+    };
+    assert!(panicking.join().is_err());
+
+    let mut state = state.lock();
+    assert_eq!(state.scan_roots.get(&1), Some(&PathBuf::from("/crashed")));
+    state.scan_roots.insert(2, PathBuf::from("/next"));
+    assert_eq!(state.scan_roots.len(), 2);
+  }
+}
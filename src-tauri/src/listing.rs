@@ -0,0 +1,265 @@
+use crate::dataless::NoMaterialize;
+use crate::rules::NameFilter;
+use std::any::Any;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::fs;
+use std::panic::{self, AssertUnwindSafe};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Condvar, Mutex};
+
+// Directories handed to the pool ahead of the walk, per listing thread.
+const READ_AHEAD_PER_THREAD: usize = 8;
+// Entries the pool holds stat'ed ahead of the walk, per listing thread. Past that a folder's
+// remaining entries are left for the walk to read.
+const BUFFERED_ENTRIES_PER_THREAD: usize = 4096;
+
+// A directory entry with the file type and metadata the walk needs from it. Metadata is read for
+// files, and for folders only when the walk has to compare devices. Files an include filter
+// rejects are left out before they're stat'ed.
+pub struct Listed {
+  pub entry: fs::DirEntry,
+  pub file_type: Option<fs::FileType>,
+  pub metadata: Option<fs::Metadata>,
+}
+
+impl Listed {
+  fn new(entry: fs::DirEntry, stat_dirs: bool, include: Option<&NameFilter>) -> Option<Self> {
+    let file_type = entry.file_type().ok();
+    if let (Some(include), Some(file_type)) = (include, file_type) {
+      if file_type.is_file() && !include.matches(&entry.file_name().to_string_lossy()) {
+        return None;
+      }
+    }
+    let stat = file_type
+      .filter(|file_type| file_type.is_file() || (stat_dirs && file_type.is_dir()))
+      .is_some();
+    let metadata = if stat { entry.metadata().ok() } else { None };
+    Some(Self {
+      entry,
+      file_type,
+      metadata,
+    })
+  }
+}
+
+pub type Listing = Box<dyn Iterator<Item = Listed> + Send>;
+
+// Reads `dir` lazily, in name order when `sorted`; None if it can't be opened. Sorting has to
+// read every name first, which stops early once `cancel` is set.
+pub fn read(
+  dir: &Path,
+  sorted: bool,
+  stat_dirs: bool,
+  include: Option<Arc<NameFilter>>,
+  cancel: &AtomicBool,
+) -> Option<Listing> {
+  let entries = fs::read_dir(dir).ok()?.flatten();
+  if !sorted {
+    return Some(Box::new(entries.filter_map(move |entry| {
+      Listed::new(entry, stat_dirs, include.as_deref())
+    })));
+  }
+  let mut entries: Vec<fs::DirEntry> = entries
+    .take_while(|_| !cancel.load(Ordering::Relaxed))
+    .collect();
+  entries.sort_by_key(|entry| entry.file_name());
+  Some(Box::new(entries.into_iter().filter_map(move |entry| {
+    Listed::new(entry, stat_dirs, include.as_deref())
+  })))
+}
+
+// A folder read ahead: the entries stat'ed so far, then the rest of its listing.
+struct Ready {
+  buffered: Vec<Listed>,
+  rest: Listing,
+}
+
+#[derive(Default)]
+struct Queue {
+  pending: VecDeque<PathBuf>,
+  reading: HashSet<PathBuf>,
+  ready: HashMap<PathBuf, Option<Ready>>,
+  // Entries in `ready`, plus those the listing threads have set aside room for.
+  buffered: usize,
+  stopped: bool,
+  // A listing thread's panic, raised again on the walk's thread by `take`.
+  panic: Option<Box<dyn Any + Send>>,
+}
+
+// Reads the directories at the front of the walk's queue on other threads, so their entries
+// and metadata are ready by the time the walk gets to them. The walk still processes folders in
+// queue order, so results, checkpoints and cancellation behave as with a single thread.
+pub struct ListingPool {
+  threads: usize,
+  sorted: bool,
+  stat_dirs: bool,
+  include: Option<Arc<NameFilter>>,
+  queue: Mutex<Queue>,
+  work: Condvar,
+  done: Condvar,
+}
+
+impl ListingPool {
+  pub fn new(
+    threads: usize,
+    sorted: bool,
+    stat_dirs: bool,
+    include: Option<Arc<NameFilter>>,
+  ) -> Self {
+    Self {
+      threads,
+      sorted,
+      stat_dirs,
+      include,
+      queue: Mutex::new(Queue::default()),
+      work: Condvar::new(),
+      done: Condvar::new(),
+    }
+  }
+
+  pub fn threads(&self) -> usize {
+    self.threads
+  }
+
+  pub fn read_ahead(&self) -> usize {
+    self.threads * READ_AHEAD_PER_THREAD
+  }
+
+  // Entries read ahead that the walk hasn't taken yet.
+  pub fn buffered(&self) -> usize {
+    self.queue.lock().map(|queue| queue.buffered).unwrap_or(0)
+  }
+
+  pub fn request(&self, dir: PathBuf) {
+    if let Ok(mut queue) = self.queue.lock() {
+      queue.pending.push_back(dir);
+      self.work.notify_one();
+    }
+  }
+
+  // The entries of `dir`, waiting for a listing thread that is already reading it. A folder that
+  // no thread has started on yet is read right here instead.
+  pub fn take(&self, dir: &Path, cancel: &AtomicBool) -> Option<Listing> {
+    let mut queue = self.queue.lock().ok()?;
+    loop {
+      if let Some(payload) = queue.panic.take() {
+        drop(queue);
+        panic::resume_unwind(payload);
+      }
+      if let Some(ready) = queue.ready.remove(dir) {
+        return ready.map(|ready| {
+          queue.buffered -= ready.buffered.len();
+          Box::new(ready.buffered.into_iter().chain(ready.rest)) as Listing
+        });
+      }
+      if queue.reading.contains(dir) {
+        queue = self.done.wait(queue).ok()?;
+        continue;
+      }
+      if let Some(index) = queue.pending.iter().position(|pending| pending == dir) {
+        queue.pending.remove(index);
+      }
+      drop(queue);
+      return read(
+        dir,
+        self.sorted,
+        self.stat_dirs,
+        self.include.clone(),
+        cancel,
+      );
+    }
+  }
+
+  // Runs on each listing thread until `stop`. Cancelling the scan cuts the current folder short
+  // after at most one more stat, and no further folders are picked up; the walk notices the flag
+  // before it uses a partial listing, and reads anything it still needs itself. A thread sets
+  // aside room for at most its own share of the pool's budget per folder, so the others can read
+  // ahead at the same time.
+  pub fn work(&self, cancel: &AtomicBool) {
+    let _no_materialize = NoMaterialize::enter();
+    let budget = self.threads * BUFFERED_ENTRIES_PER_THREAD;
+    loop {
+      let (dir, room) = {
+        let mut queue = match self.queue.lock() {
+          Ok(queue) => queue,
+          Err(_) => return,
+        };
+        loop {
+          if queue.stopped || cancel.load(Ordering::Relaxed) {
+            return;
+          }
+          if let Some(dir) = queue.pending.pop_front() {
+            queue.reading.insert(dir.clone());
+            let room = budget
+              .saturating_sub(queue.buffered)
+              .min(BUFFERED_ENTRIES_PER_THREAD);
+            queue.buffered += room;
+            break (dir, room);
+          }
+          queue = match self.work.wait(queue) {
+            Ok(queue) => queue,
+            Err(_) => return,
+          };
+        }
+      };
+      let listing = panic::catch_unwind(AssertUnwindSafe(|| {
+        read(
+          &dir,
+          self.sorted,
+          self.stat_dirs,
+          self.include.clone(),
+          cancel,
+        )
+        .map(|mut rest| {
+          let buffered: Vec<Listed> = rest
+            .by_ref()
+            .take(room)
+            .take_while(|_| !cancel.load(Ordering::Relaxed))
+            .collect();
+          Ready { buffered, rest }
+        })
+      }));
+      let mut queue = match self.queue.lock() {
+        Ok(queue) => queue,
+        Err(_) => return,
+      };
+      queue.reading.remove(&dir);
+      queue.buffered -= room;
+      match listing {
+        Ok(listing) => {
+          if let Some(ready) = &listing {
+            queue.buffered += ready.buffered.len();
+          }
+          queue.ready.insert(dir, listing);
+        }
+        // Otherwise the walk would wait for this folder forever.
+        Err(payload) => {
+          queue.panic = Some(payload);
+          queue.stopped = true;
+          self.work.notify_all();
+          self.done.notify_all();
+          return;
+        }
+      }
+      self.done.notify_all();
+    }
+  }
+
+  pub fn stop(&self) {
+    if let Ok(mut queue) = self.queue.lock() {
+      queue.stopped = true;
+    }
+    self.work.notify_all();
+    self.done.notify_all();
+  }
+}
+
+// Stops the pool when the walk ends, including by panic, so the scoped threads can be joined.
+pub struct StopOnDrop<'a>(pub &'a ListingPool);
+
+impl Drop for StopOnDrop<'_> {
+  fn drop(&mut self) {
+    self.0.stop();
+  }
+}
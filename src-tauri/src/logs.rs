@@ -0,0 +1,103 @@
+use crate::messages::Message;
+use serde::Serialize;
+use std::fs::{File, OpenOptions};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::Path;
+
+const MAX_TAIL_BYTES: u64 = 1024 * 1024;
+const COPY_CHUNK_BYTES: usize = 64 * 1024;
+
+#[derive(Serialize)]
+pub struct FileTail {
+  pub path: String,
+  pub size: u64,
+  pub text: String,
+  // True when the preview starts after the beginning of the file.
+  pub partial: bool,
+}
+
+#[derive(Serialize)]
+pub struct TruncateResult {
+  pub path: String,
+  #[serde(rename = "previousSize")]
+  pub previous_size: u64,
+  #[serde(rename = "newSize")]
+  pub new_size: u64,
+}
+
+pub fn tail_file(path: &Path, bytes: u64) -> Result<FileTail, Message> {
+  let mut file = File::open(path).map_err(|_| Message::new("file.open_failed"))?;
+  let size = file
+    .metadata()
+    .map_err(|_| Message::new("file.read_failed"))?
+    .len();
+  let start = size.saturating_sub(bytes.min(MAX_TAIL_BYTES));
+
+  file
+    .seek(SeekFrom::Start(start))
+    .map_err(|_| Message::new("file.read_failed"))?;
+  let mut buffer = Vec::new();
+  file
+    .take(size - start)
+    .read_to_end(&mut buffer)
+    .map_err(|_| Message::new("file.read_failed"))?;
+
+  if buffer.contains(&0) {
+    return Err(Message::new("file.not_text"));
+  }
+
+  // Drop the partial first line so the preview starts on a line boundary.
+  let mut content = &buffer[..];
+  if start > 0 {
+    if let Some(newline) = content.iter().position(|byte| *byte == b'\n') {
+      content = &content[newline + 1..];
+    }
+  }
+
+  Ok(FileTail {
+    path: path.to_string_lossy().to_string(),
+    size,
+    text: String::from_utf8_lossy(content).to_string(),
+    partial: start > 0,
+  })
+}
+
+// Truncates in place instead of replacing the file, so daemons holding it open keep logging to it.
+pub fn truncate_file(path: &Path, keep_bytes: u64) -> Result<TruncateResult, Message> {
+  let mut file = OpenOptions::new()
+    .read(true)
+    .write(true)
+    .open(path)
+    .map_err(|_| Message::new("file.open_for_writing_failed"))?;
+  let previous_size = file
+    .metadata()
+    .map_err(|_| Message::new("file.read_failed"))?
+    .len();
+
+  if keep_bytes < previous_size && keep_bytes > 0 {
+    let mut buffer = vec![0u8; COPY_CHUNK_BYTES];
+    let source_start = previous_size - keep_bytes;
+    let mut copied = 0u64;
+    while copied < keep_bytes {
+      let chunk = (keep_bytes - copied).min(COPY_CHUNK_BYTES as u64) as usize;
+      file
+        .seek(SeekFrom::Start(source_start + copied))
+        .and_then(|_| file.read_exact(&mut buffer[..chunk]))
+        .and_then(|_| file.seek(SeekFrom::Start(copied)))
+        .and_then(|_| file.write_all(&buffer[..chunk]))
+        .map_err(|_| Message::new("file.rewrite_failed"))?;
+      copied += chunk as u64;
+    }
+  }
+
+  let new_size = keep_bytes.min(previous_size);
+  file
+    .set_len(new_size)
+    .map_err(|_| Message::new("file.truncate_failed"))?;
+
+  Ok(TruncateResult {
+    path: path.to_string_lossy().to_string(),
+    previous_size,
+    new_size,
+  })
+}
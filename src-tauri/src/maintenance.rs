@@ -0,0 +1,200 @@
+use crate::{cache, checksums, history, settings};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::Path;
+use std::thread;
+use std::time::{Duration, UNIX_EPOCH};
+use tauri::Manager;
+
+const MAINTENANCE_FILE: &str = "maintenance.json";
+const ANNOTATIONS_FILE: &str = "annotations.json";
+const DEFAULT_MAX_AGE_DAYS: u64 = 90;
+const DEFAULT_MAX_HISTORY_ENTRIES: usize = 500;
+const STARTUP_DELAY: Duration = Duration::from_secs(5 * 60);
+const CHECK_INTERVAL: Duration = Duration::from_secs(60 * 60);
+const MAINTENANCE_INTERVAL_SECS: u64 = 24 * 60 * 60;
+const SECONDS_PER_DAY: u64 = 24 * 60 * 60;
+
+// e.g. `{ "maxAgeDays": 30, "maxHistoryEntries": 200 }`.
+#[derive(Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CacheRetention {
+  // Caches of roots not scanned for this long are removed; 90 days when unset.
+  #[serde(rename = "maxAgeDays")]
+  pub max_age_days: Option<u64>,
+  #[serde(rename = "maxHistoryEntries")]
+  pub max_history_entries: Option<usize>,
+}
+
+#[derive(Clone, Default, Serialize, Deserialize)]
+pub struct MaintenanceReport {
+  #[serde(rename = "finishedAt")]
+  pub finished_at: u64,
+  #[serde(rename = "removedRoots")]
+  pub removed_roots: u64,
+  #[serde(rename = "prunedHistoryEntries")]
+  pub pruned_history_entries: u64,
+  // Freed by removing old caches and compacting the rest.
+  #[serde(rename = "freedBytes")]
+  pub freed_bytes: u64,
+}
+
+#[derive(Serialize)]
+pub struct CacheStats {
+  #[serde(rename = "totalBytes")]
+  pub total_bytes: u64,
+  #[serde(rename = "scanCacheBytes")]
+  pub scan_cache_bytes: u64,
+  #[serde(rename = "scanRoots")]
+  pub scan_roots: u64,
+  #[serde(rename = "historyEntries")]
+  pub history_entries: u64,
+  #[serde(rename = "lastMaintenance")]
+  pub last_maintenance: Option<MaintenanceReport>,
+}
+
+fn dir_bytes(dir: &Path) -> u64 {
+  let entries = match fs::read_dir(dir) {
+    Ok(entries) => entries,
+    Err(_) => return 0,
+  };
+  entries
+    .flatten()
+    .map(|entry| match entry.file_type() {
+      Ok(file_type) if file_type.is_dir() => dir_bytes(&entry.path()),
+      Ok(file_type) if file_type.is_file() => {
+        entry.metadata().map(|metadata| metadata.len()).unwrap_or(0)
+      }
+      _ => 0,
+    })
+    .sum()
+}
+
+// When anything in the folder was last written, i.e. the root's last scan or duplicate search.
+fn last_used(dir: &Path) -> u64 {
+  fs::read_dir(dir)
+    .map(|entries| {
+      entries
+        .flatten()
+        .filter_map(|entry| entry.metadata().ok()?.modified().ok())
+        .filter_map(|modified| modified.duration_since(UNIX_EPOCH).ok())
+        .map(|since| since.as_secs())
+        .max()
+        .unwrap_or(0)
+    })
+    .unwrap_or(0)
+}
+
+// Notes are the user's own data, so a stale root only loses its caches while it has any.
+fn remove_root_cache(dir: &Path) -> u64 {
+  let freed = dir_bytes(dir);
+  if !dir.join(ANNOTATIONS_FILE).exists() {
+    return if fs::remove_dir_all(dir).is_ok() {
+      freed
+    } else {
+      0
+    };
+  }
+  if let Ok(entries) = fs::read_dir(dir) {
+    for entry in entries.flatten() {
+      if entry.file_name() != ANNOTATIONS_FILE {
+        let _ = fs::remove_file(entry.path());
+      }
+    }
+  }
+  freed.saturating_sub(dir_bytes(dir))
+}
+
+// Leftovers of interrupted writes.
+fn remove_temp_files(dir: &Path) -> u64 {
+  let mut freed = 0;
+  if let Ok(entries) = fs::read_dir(dir) {
+    for entry in entries.flatten() {
+      let path = entry.path();
+      if path
+        .extension()
+        .filter(|extension| *extension == "tmp")
+        .is_some()
+      {
+        let size = entry.metadata().map(|metadata| metadata.len()).unwrap_or(0);
+        if fs::remove_file(&path).is_ok() {
+          freed += size;
+        }
+      }
+    }
+  }
+  freed
+}
+
+pub fn run(app_dir: &Path, retention: &CacheRetention) -> MaintenanceReport {
+  let max_age = retention.max_age_days.unwrap_or(DEFAULT_MAX_AGE_DAYS) * SECONDS_PER_DAY;
+  let cutoff = history::now_secs().saturating_sub(max_age);
+  let mut report = MaintenanceReport::default();
+
+  report.freed_bytes += remove_temp_files(app_dir);
+  if let Ok(entries) = fs::read_dir(app_dir.join("scans")) {
+    for entry in entries.flatten() {
+      let dir = entry.path();
+      if !dir.is_dir() {
+        continue;
+      }
+      if last_used(&dir) < cutoff {
+        let freed = remove_root_cache(&dir);
+        if freed > 0 {
+          report.removed_roots += 1;
+          report.freed_bytes += freed;
+        }
+        continue;
+      }
+      report.freed_bytes += remove_temp_files(&dir);
+      report.freed_bytes += checksums::compact(&dir);
+    }
+  }
+
+  let max_entries = retention
+    .max_history_entries
+    .unwrap_or(DEFAULT_MAX_HISTORY_ENTRIES);
+  report.pruned_history_entries = history::prune(app_dir, max_entries, cutoff).unwrap_or(0);
+  report.finished_at = history::now_secs();
+  let _ = cache::write_json(&app_dir.join(MAINTENANCE_FILE), &report);
+  report
+}
+
+pub fn stats(app_dir: &Path) -> CacheStats {
+  let scans = app_dir.join("scans");
+  let scan_roots = fs::read_dir(&scans)
+    .map(|entries| {
+      entries
+        .flatten()
+        .filter(|entry| entry.path().is_dir())
+        .count() as u64
+    })
+    .unwrap_or(0);
+  let last_maintenance: MaintenanceReport = cache::read_json(&app_dir.join(MAINTENANCE_FILE));
+  CacheStats {
+    total_bytes: dir_bytes(app_dir),
+    scan_cache_bytes: dir_bytes(&scans),
+    scan_roots,
+    history_entries: history::load(app_dir).len() as u64,
+    last_maintenance: Some(last_maintenance).filter(|report| report.finished_at > 0),
+  }
+}
+
+// Runs a few minutes after startup, then once a day for as long as the app is open, reading the
+// retention settings fresh each time.
+pub fn start(app: tauri::AppHandle) {
+  thread::spawn(move || {
+    thread::sleep(STARTUP_DELAY);
+    loop {
+      if let Ok(app_dir) = cache::app_dir(&app) {
+        let last: MaintenanceReport = cache::read_json(&app_dir.join(MAINTENANCE_FILE));
+        if history::now_secs().saturating_sub(last.finished_at) >= MAINTENANCE_INTERVAL_SECS {
+          let settings = settings::load(&app_dir);
+          let report = run(&app_dir, &settings.cache_retention);
+          let _ = app.emit_to("main", "cache_maintenance_complete", report);
+        }
+      }
+      thread::sleep(CHECK_INTERVAL);
+    }
+  });
+}
@@ -0,0 +1,318 @@
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom};
+use std::path::Path;
+
+// Only this much of an ID3 tag is read; cover art usually comes after the text frames.
+const MAX_ID3_BYTES: usize = 512 * 1024;
+// Movies with larger `moov` boxes (long videos have big sample tables) are left unread.
+const MAX_MOOV_BYTES: u64 = 32 * 1024 * 1024;
+const MAX_FLAC_BLOCKS: usize = 64;
+const MAX_WAV_CHUNKS: usize = 64;
+
+// What the container says about a track or video.
+#[derive(Default)]
+pub struct MediaInfo {
+  pub title: Option<String>,
+  pub artist: Option<String>,
+  pub duration_secs: Option<f64>,
+}
+
+// Reads the tags and duration of an MP3 (ID3v2), FLAC, MP4/M4A/MOV, or WAV file from its
+// headers, without decoding any audio. None for other formats or unreadable files.
+pub fn read(path: &Path) -> Option<MediaInfo> {
+  let mut file = File::open(path).ok()?;
+  let mut magic = [0u8; 12];
+  file.read_exact(&mut magic).ok()?;
+  file.seek(SeekFrom::Start(0)).ok()?;
+  if magic.starts_with(b"ID3") {
+    read_id3(&mut file)
+  } else if magic.starts_with(b"fLaC") {
+    read_flac(&mut file)
+  } else if &magic[4..8] == b"ftyp" {
+    read_mp4(&mut file)
+  } else if magic.starts_with(b"RIFF") && &magic[8..12] == b"WAVE" {
+    read_wav(&mut file)
+  } else {
+    None
+  }
+}
+
+fn clean(text: String) -> Option<String> {
+  let text = text.trim_matches(|c: char| c == '\0' || c.is_whitespace());
+  (!text.is_empty()).then(|| text.to_string())
+}
+
+fn u32_be(bytes: &[u8]) -> Option<u32> {
+  Some(u32::from_be_bytes(bytes.get(..4)?.try_into().ok()?))
+}
+
+fn u32_le(bytes: &[u8]) -> Option<u32> {
+  Some(u32::from_le_bytes(bytes.get(..4)?.try_into().ok()?))
+}
+
+fn u64_be(bytes: &[u8]) -> Option<u64> {
+  Some(u64::from_be_bytes(bytes.get(..8)?.try_into().ok()?))
+}
+
+fn synchsafe(bytes: &[u8]) -> usize {
+  bytes
+    .iter()
+    .take(4)
+    .fold(0, |size, byte| (size << 7) | (*byte as usize & 0x7f))
+}
+
+fn utf16(bytes: &[u8], big_endian: bool) -> String {
+  let units: Vec<u16> = bytes
+    .chunks_exact(2)
+    .map(|pair| {
+      if big_endian {
+        u16::from_be_bytes([pair[0], pair[1]])
+      } else {
+        u16::from_le_bytes([pair[0], pair[1]])
+      }
+    })
+    .collect();
+  String::from_utf16_lossy(&units)
+}
+
+// An ID3 text frame: an encoding byte, then the text.
+fn id3_text(frame: &[u8]) -> Option<String> {
+  let (encoding, text) = frame.split_first()?;
+  let text = match encoding {
+    0 => text.iter().map(|byte| *byte as char).collect(),
+    1 => match text {
+      [0xff, 0xfe, rest @ ..] => utf16(rest, false),
+      [0xfe, 0xff, rest @ ..] => utf16(rest, true),
+      _ => utf16(text, false),
+    },
+    2 => utf16(text, true),
+    _ => String::from_utf8_lossy(text).to_string(),
+  };
+  clean(text)
+}
+
+fn read_id3(file: &mut File) -> Option<MediaInfo> {
+  let mut header = [0u8; 10];
+  file.read_exact(&mut header).ok()?;
+  let version = header[3];
+  let size = synchsafe(&header[6..10]).min(MAX_ID3_BYTES);
+  let mut tag = Vec::with_capacity(size);
+  file.take(size as u64).read_to_end(&mut tag).ok()?;
+
+  let mut offset = 0;
+  if header[5] & 0x40 != 0 {
+    offset = match version {
+      3 => u32_be(&tag)? as usize + 4,
+      _ => synchsafe(&tag),
+    };
+  }
+  let (id_len, header_len) = if version == 2 { (3, 6) } else { (4, 10) };
+  let mut info = MediaInfo::default();
+  while offset + header_len <= tag.len() {
+    let id = &tag[offset..offset + id_len];
+    if id[0] == 0 {
+      break;
+    }
+    let size_bytes = &tag[offset + id_len..offset + id_len + if version == 2 { 3 } else { 4 }];
+    let frame_size = match version {
+      2 => size_bytes
+        .iter()
+        .fold(0, |size, byte| (size << 8) | *byte as usize),
+      3 => u32_be(size_bytes)? as usize,
+      _ => synchsafe(size_bytes),
+    };
+    let start = offset + header_len;
+    let frame = match tag.get(start..start + frame_size) {
+      Some(frame) => frame,
+      None => break,
+    };
+    match id {
+      b"TIT2" | b"TT2" => info.title = id3_text(frame),
+      b"TPE1" | b"TP1" => info.artist = id3_text(frame),
+      b"TLEN" | b"TLE" => {
+        info.duration_secs = id3_text(frame)
+          .and_then(|millis| millis.parse::<f64>().ok())
+          .map(|millis| millis / 1000.0)
+      }
+      _ => {}
+    }
+    offset = start + frame_size;
+  }
+  Some(info)
+}
+
+fn read_flac(file: &mut File) -> Option<MediaInfo> {
+  file.seek(SeekFrom::Start(4)).ok()?;
+  let mut info = MediaInfo::default();
+  for _ in 0..MAX_FLAC_BLOCKS {
+    let mut header = [0u8; 4];
+    file.read_exact(&mut header).ok()?;
+    let last = header[0] & 0x80 != 0;
+    let length = u32_be(&[0, header[1], header[2], header[3]])? as usize;
+    match header[0] & 0x7f {
+      // STREAMINFO: a 20-bit sample rate and a 36-bit sample count.
+      0 => {
+        let mut block = vec![0u8; length];
+        file.read_exact(&mut block).ok()?;
+        let rate = (*block.get(10)? as u64) << 12
+          | (*block.get(11)? as u64) << 4
+          | (*block.get(12)? as u64) >> 4;
+        let samples = ((*block.get(13)? as u64) & 0x0f) << 32 | u32_be(block.get(14..18)?)? as u64;
+        if rate > 0 && samples > 0 {
+          info.duration_secs = Some(samples as f64 / rate as f64);
+        }
+      }
+      // VORBIS_COMMENT: little-endian lengths, then `KEY=value` strings.
+      4 => {
+        let mut block = vec![0u8; length];
+        file.read_exact(&mut block).ok()?;
+        let vendor = u32_le(&block)? as usize;
+        let mut offset = 4 + vendor;
+        let count = u32_le(block.get(offset..)?)?;
+        offset += 4;
+        for _ in 0..count {
+          let len = u32_le(block.get(offset..)?)? as usize;
+          let comment = String::from_utf8_lossy(block.get(offset + 4..offset + 4 + len)?);
+          offset += 4 + len;
+          if let Some((key, value)) = comment.split_once('=') {
+            match key.to_ascii_uppercase().as_str() {
+              "TITLE" => info.title = clean(value.to_string()),
+              "ARTIST" => info.artist = clean(value.to_string()),
+              _ => {}
+            }
+          }
+        }
+      }
+      _ => {
+        file.seek(SeekFrom::Current(length as i64)).ok()?;
+      }
+    }
+    if last {
+      break;
+    }
+  }
+  Some(info)
+}
+
+// The child boxes of an MP4 box's payload, as (type, payload).
+fn boxes(data: &[u8]) -> Vec<(&[u8], &[u8])> {
+  let mut children = Vec::new();
+  let mut offset = 0;
+  while offset + 8 <= data.len() {
+    let size = match u32_be(&data[offset..]) {
+      Some(0) => data.len() - offset,
+      Some(size) => size as usize,
+      None => break,
+    };
+    if size < 8 || offset + size > data.len() {
+      break;
+    }
+    children.push((
+      &data[offset + 4..offset + 8],
+      &data[offset + 8..offset + size],
+    ));
+    offset += size;
+  }
+  children
+}
+
+fn child<'a>(data: &'a [u8], kind: &[u8]) -> Option<&'a [u8]> {
+  boxes(data)
+    .into_iter()
+    .find(|(child_kind, _)| *child_kind == kind)
+    .map(|(_, payload)| payload)
+}
+
+// An iTunes-style metadata item: a `data` box with 8 bytes of type and locale before the text.
+fn ilst_text(ilst: &[u8], kind: &[u8]) -> Option<String> {
+  let data = child(child(ilst, kind)?, b"data")?;
+  clean(String::from_utf8_lossy(data.get(8..)?).to_string())
+}
+
+fn read_mp4(file: &mut File) -> Option<MediaInfo> {
+  let length = file.metadata().ok()?.len();
+  let mut offset = 0;
+  let moov = loop {
+    if offset + 8 > length {
+      return None;
+    }
+    file.seek(SeekFrom::Start(offset)).ok()?;
+    let mut header = [0u8; 16];
+    file.read_exact(&mut header[..8]).ok()?;
+    let (size, header_len) = match u32_be(&header)? {
+      0 => (length - offset, 8),
+      1 => {
+        file.read_exact(&mut header[8..]).ok()?;
+        (u64_be(&header[8..])?, 16)
+      }
+      size => (size as u64, 8),
+    };
+    if size < header_len {
+      return None;
+    }
+    if &header[4..8] == b"moov" {
+      if size > MAX_MOOV_BYTES {
+        return None;
+      }
+      let mut moov = vec![0u8; (size - header_len) as usize];
+      file.read_exact(&mut moov).ok()?;
+      break moov;
+    }
+    offset += size;
+  };
+
+  let mut info = MediaInfo::default();
+  if let Some(mvhd) = child(&moov, b"mvhd") {
+    let (timescale, duration) = if mvhd.first() == Some(&1) {
+      (u32_be(mvhd.get(20..)?)? as u64, u64_be(mvhd.get(24..)?)?)
+    } else {
+      (
+        u32_be(mvhd.get(12..)?)? as u64,
+        u32_be(mvhd.get(16..)?)? as u64,
+      )
+    };
+    if timescale > 0 {
+      info.duration_secs = Some(duration as f64 / timescale as f64);
+    }
+  }
+  // `meta` is a full box: 4 bytes of version and flags before its children.
+  let ilst = child(&moov, b"udta")
+    .and_then(|udta| child(udta, b"meta"))
+    .and_then(|meta| child(meta.get(4..)?, b"ilst"));
+  if let Some(ilst) = ilst {
+    info.title = ilst_text(ilst, b"\xa9nam");
+    info.artist = ilst_text(ilst, b"\xa9ART");
+  }
+  Some(info)
+}
+
+fn read_wav(file: &mut File) -> Option<MediaInfo> {
+  file.seek(SeekFrom::Start(12)).ok()?;
+  let mut byte_rate = None;
+  let mut info = MediaInfo::default();
+  for _ in 0..MAX_WAV_CHUNKS {
+    let mut header = [0u8; 8];
+    if file.read_exact(&mut header).is_err() {
+      break;
+    }
+    let size = u32_le(&header[4..])? as u64;
+    match &header[..4] {
+      b"fmt " => {
+        let mut format = [0u8; 12];
+        file.read_exact(&mut format).ok()?;
+        byte_rate = u32_le(&format[8..]).filter(|rate| *rate > 0);
+        file.seek(SeekFrom::Current(size as i64 - 12)).ok()?;
+      }
+      b"data" => {
+        info.duration_secs = byte_rate.map(|rate| size as f64 / rate as f64);
+        break;
+      }
+      _ => {
+        file
+          .seek(SeekFrom::Current((size + size % 2) as i64))
+          .ok()?;
+      }
+    }
+  }
+  Some(info)
+}
@@ -0,0 +1,642 @@
+use serde::ser::{Serialize, SerializeStruct, Serializer};
+use std::collections::BTreeMap;
+use std::fmt;
+
+// Every message the backend puts in a result or event, by the identifier the frontend translates
+// from, with its English wording; `{name}` stands for a parameter. Identifiers are stable: reword
+// the English freely, but add a new identifier rather than changing what one means.
+const CATALOG: &[(&str, &str)] = &[
+  // General
+  ("error.unexpected", "{message}"),
+  ("path.not_found", "Path not found"),
+  ("path.missing", "Path does not exist"),
+  ("path.not_directory", "Path is not a directory"),
+  ("path.not_folder", "{path} is not a folder"),
+  ("path.no_parent", "Path has no parent directory"),
+  ("path.outside_scan", "Path is outside the scanned folder"),
+  (
+    "path.selection_outside_root",
+    "Selection is outside the scan root",
+  ),
+  ("path.invalid_file_name", "Invalid file name"),
+  ("path.invalid_disk_lookup", "Invalid path for disk lookup"),
+  ("path.home_unavailable", "Home folder unavailable"),
+  ("file.not_found", "File not found"),
+  ("file.read_failed", "Unable to read file"),
+  ("file.open_failed", "Unable to open file"),
+  (
+    "file.open_for_writing_failed",
+    "Unable to open file for writing",
+  ),
+  ("file.copy_failed", "Unable to copy file"),
+  ("file.delete_failed", "Unable to delete file"),
+  ("file.not_text", "File does not look like text"),
+  (
+    "file.only_regular_delete",
+    "Only regular files can be deleted",
+  ),
+  (
+    "file.only_regular_inspect",
+    "Only regular files can be inspected",
+  ),
+  (
+    "file.only_regular_preview",
+    "Only regular files can be previewed",
+  ),
+  (
+    "file.only_regular_truncate",
+    "Only regular files can be truncated",
+  ),
+  ("file.truncate_failed", "Unable to truncate file"),
+  ("file.rewrite_failed", "Unable to rewrite file"),
+  ("file.compress_failed", "Unable to compress file"),
+  ("file.gzip_failed", "Unable to run gzip"),
+  ("folder.read_failed", "Unable to read folder"),
+  ("link.read_failed", "Unable to read link"),
+  ("link.copy_failed", "Unable to copy link"),
+  ("link.remove_failed", "Unable to remove the link"),
+  ("link.already_link", "This is already a link"),
+  ("system.busy", "System is busy"),
+  ("app.data_dir_unavailable", "App data directory unavailable"),
+  ("app.unsupported_action", "Unsupported action"),
+  ("state.plan_lock_poisoned", "Cleanup plan lock poisoned"),
+  (
+    "state.checksum_lock_poisoned",
+    "Checksum database lock poisoned",
+  ),
+  (
+    "state.conflict_lock_poisoned",
+    "Conflict prompt lock poisoned",
+  ),
+  // Scans
+  ("scan.unknown", "Unknown scan"),
+  ("scan.not_finished", "Scan has not finished"),
+  ("scan.cancelled", "Scan was cancelled"),
+  ("scan.root_unavailable", "Scan root is no longer available"),
+  ("scan.no_checkpoint", "Scan has no checkpoint to resume"),
+  ("scan.no_interrupted", "No interrupted scan for this folder"),
+  ("scan.invalid_ncdu_export", "Invalid ncdu export"),
+  ("scan.ncdu_path_too_long", "Path in ncdu export is too long"),
+  ("scan.ncdu_entry_too_large", "ncdu export entry is too large"),
+  ("scan.ncdu_too_deep", "ncdu export is nested too deeply"),
+  ("scan.trace_dir_missing", "Trace directory does not exist"),
+  ("scan.trace_write_failed", "Unable to write trace file"),
+  ("scan.panicked", "The scan stopped unexpectedly: {reason}"),
+  ("scan.coordinator_stopped", "Scan coordinator stopped"),
+  ("remote.invalid_host", "Invalid SSH host"),
+  ("remote.path_not_absolute", "Remote path must be absolute"),
+  ("remote.ssh_failed", "Unable to run ssh"),
+  ("remote.ssh_output_failed", "Unable to read ssh output"),
+  ("remote.scan_failed", "Remote scan failed"),
+  // Cache and history
+  (
+    "cache.create_dir_failed",
+    "Unable to create cache directory",
+  ),
+  ("cache.encode_failed", "Unable to encode cache file"),
+  ("cache.write_failed", "Unable to write cache file"),
+  (
+    "cache.invalid_retention",
+    "Cache retention must keep at least one day and one history entry",
+  ),
+  (
+    "cache.invalid_verification_interval",
+    "Full verification must be at least one day apart",
+  ),
+  // Disks and volumes
+  ("disk.usage_unreadable", "Unable to read disk usage"),
+  (
+    "disk.usage_unsupported",
+    "Disk usage not supported on this platform",
+  ),
+  ("volume.not_found", "Volume not found"),
+  ("volume.none_mounted", "No mounted volumes found"),
+  ("health.no_smart", "Drive does not report SMART data"),
+  (
+    "health.unavailable",
+    "Drive health is not available for this drive",
+  ),
+  (
+    "health.needs_smartctl",
+    "Drive health requires smartmontools (smartctl)",
+  ),
+  (
+    "health.no_physical_drive",
+    "Unable to map mount point to a physical drive",
+  ),
+  ("health.invalid_device", "Invalid device"),
+  (
+    "snapshots.linux_only",
+    "Snapshot reporting is only supported on Linux",
+  ),
+  (
+    "snapshots.no_tooling",
+    "No btrfs or ZFS tooling available for this path",
+  ),
+  (
+    "freespace.tools_missing",
+    "The filesystem's tools for reading free space are not installed",
+  ),
+  (
+    "freespace.needs_admin",
+    "Reading the free-space map needs administrator access",
+  ),
+  ("freespace.read_failed", "Unable to read the free-space map"),
+  (
+    "freespace.unexpected_e2freefrag",
+    "Unexpected e2freefrag output",
+  ),
+  ("freespace.unexpected_xfs_db", "Unexpected xfs_db output"),
+  (
+    "freespace.unsupported",
+    "Free-space maps are not available for {fsType} volumes",
+  ),
+  ("image.tools_missing", "Disk image tools are not available"),
+  ("image.command_failed", "Disk image command failed"),
+  ("image.no_volume", "Disk image has no mountable volume"),
+  ("image.loop_device_failed", "Unable to set up loop device"),
+  ("image.invalid_device", "Not a disk image device"),
+  (
+    "image.unsupported_platform",
+    "Mounting disk images is not supported on this platform",
+  ),
+  (
+    "image.unsupported_type",
+    "Only .dmg, .iso, .img, .sparsebundle and .sparseimage files can be mounted",
+  ),
+  ("vm.not_image", "Not a virtual machine disk"),
+  ("vm.not_compactable", "This disk can't be compacted here"),
+  ("vm.compaction_failed", "Compaction command failed"),
+  (
+    "vm.compact_qemu",
+    "Shut the VM down, rewrite the disk into a new file with qemu-img convert -O qcow2, then replace the old one",
+  ),
+  (
+    "vm.compact_parallels",
+    "Shut the VM down, then run prl_disk_tool compact --hdd on the disk",
+  ),
+  (
+    "vm.compact_vmware",
+    "Zero the free space inside the guest, shut the VM down, then run vmware-vdiskmanager -k on the disk",
+  ),
+  (
+    "vm.compact_virtualbox",
+    "Zero the free space inside the guest, shut the VM down, then run VBoxManage modifymedium disk --compact",
+  ),
+  (
+    "vm.compact_wsl",
+    "Run wsl --shutdown, then compact the disk with Optimize-VHD or diskpart's compact vdisk",
+  ),
+  (
+    "vm.compact_docker",
+    "Remove unused images, containers and volumes with docker system prune, then restart Docker so it releases the space",
+  ),
+  ("games.uninstall_steam", "Uninstall {name} from Steam"),
+  (
+    "games.uninstall_epic",
+    "Uninstall {name} from the Epic Games Launcher library",
+  ),
+  ("games.uninstall_battlenet", "Uninstall {name} from the Battle.net app"),
+  // Deleting, the trash and cleanup plans
+  ("audit.read_only", "Audit mode is on; {command} is disabled"),
+  ("token.invalid", "Confirmation token is not valid"),
+  ("token.expired", "Confirmation token has expired"),
+  (
+    "token.wrong_command",
+    "Confirmation token was not issued for {command}",
+  ),
+  (
+    "token.wrong_targets",
+    "Confirmation token was issued for different items",
+  ),
+  ("token.lock_poisoned", "Token lock poisoned"),
+  ("token.confirm_title", "Confirm {command}"),
+  ("token.more_targets", "and {count} more"),
+  ("token.declined", "The action was not confirmed"),
+  ("role.invalid", "Role must be owner, operator or viewer"),
+  (
+    "role.not_allowed",
+    "The {role} role can't confirm {command}",
+  ),
+  (
+    "role.locked",
+    "The role can only be raised by editing settings.json",
+  ),
+  (
+    "audit.locked",
+    "Audit mode can only be turned off by editing settings.json",
+  ),
+  (
+    "audit.automation_locked",
+    "Hooks and archive policies can't be changed in audit mode",
+  ),
+  ("trash.move_failed", "Unable to move file to the trash"),
+  ("trash.create_failed", "Unable to create trash folder"),
+  (
+    "trash.permanent_unconfirmed",
+    "The trash is unavailable on this volume; confirm to delete permanently",
+  ),
+  ("restore.unknown_entry", "No audit log entry with that ID"),
+  ("restore.not_trashed", "Only items moved to the trash can be restored"),
+  ("restore.not_found", "Item is no longer in the trash"),
+  (
+    "restore.original_taken",
+    "Something else is already at the original location",
+  ),
+  ("restore.folder_failed", "Unable to recreate the original folder"),
+  ("restore.failed", "Unable to move the item out of the trash"),
+  (
+    "trash.unsupported",
+    "Moving files to the trash is not supported on this platform",
+  ),
+  ("plan.empty", "Cleanup plan is empty"),
+  ("suggestion.unknown", "Unknown suggestion"),
+  (
+    "suggestion.kept_changed",
+    "{path}, the copy being kept, is missing or has changed, so no copies were removed",
+  ),
+  ("plan.encode_failed", "Unable to encode plan"),
+  ("plan.write_failed", "Unable to write plan file"),
+  ("plan.read_failed", "Unable to read plan file"),
+  ("plan.invalid_file", "Invalid plan file"),
+  ("plan.file_too_large", "Plan file is too large"),
+  ("plan.unsupported_version", "Unsupported plan file version"),
+  (
+    "plan.size_changed",
+    "File size changed since the plan was exported",
+  ),
+  (
+    "plan.contents_changed",
+    "File contents changed since the plan was exported",
+  ),
+  // Copying, moving and name conflicts
+  (
+    "conflict.folder_in_way",
+    "A folder of that name is in the way",
+  ),
+  (
+    "conflict.folder_replaces_file",
+    "A folder can't replace a file",
+  ),
+  (
+    "conflict.same_file",
+    "Source and destination are the same file",
+  ),
+  ("conflict.no_free_name", "No free name for the file"),
+  (
+    "conflict.replace_failed",
+    "Unable to replace the existing file",
+  ),
+  (
+    "conflict.item_exists",
+    "An item of that name is already in the destination",
+  ),
+  (
+    "conflict.none_waiting",
+    "No conflict is waiting for that answer",
+  ),
+  (
+    "conflict.invalid_resolution",
+    "Conflict resolution must be skip, overwrite, or rename",
+  ),
+  (
+    "conflict.invalid_policy",
+    "Conflict policy must be skip, overwrite, rename, or ask",
+  ),
+  (
+    "copy.create_folder_failed",
+    "Unable to create destination folder",
+  ),
+  (
+    "copy.create_file_failed",
+    "Unable to create file in the destination",
+  ),
+  (
+    "copy.write_failed",
+    "Unable to write file, the destination may be full",
+  ),
+  (
+    "copy.verification_failed",
+    "Copied file failed verification",
+  ),
+  (
+    "copy.remove_original_failed",
+    "Copied, but unable to remove the original",
+  ),
+  (
+    "copy.too_large",
+    "File is too large for the destination volume",
+  ),
+  ("export.nothing", "Nothing to export"),
+  (
+    "export.destination_not_folder",
+    "Export destination must be a folder",
+  ),
+  (
+    "export.invalid_mode",
+    "Export mode must be copy or hardlink",
+  ),
+  (
+    "export.hardlink_other_volume",
+    "Unable to hard link, the destination must be on the same volume",
+  ),
+  ("app.encode_report_failed", "Unable to encode report"),
+  ("app.encode_result_failed", "Unable to encode result"),
+  // Archives and offloads
+  ("archive.nothing", "Nothing to archive"),
+  ("archive.invalid_path", "Invalid archive path"),
+  (
+    "archive.destination_not_folder",
+    "Archive destination must be a folder",
+  ),
+  ("archive.folder_not_found", "Archive folder not found"),
+  (
+    "archive.same_volume",
+    "Archive must be on a different volume",
+  ),
+  ("archive.write_failed", "Unable to write to archive"),
+  (
+    "archive.verification_failed",
+    "Archived copy failed verification",
+  ),
+  (
+    "archive.file_changed",
+    "File changed while it was archived; original kept",
+  ),
+  (
+    "archive.delete_failed",
+    "Archived, but unable to delete file",
+  ),
+  (
+    "archive.destination_not_found",
+    "Destination folder not found",
+  ),
+  ("policy.unknown", "Unknown archive policy"),
+  (
+    "policy.incomplete",
+    "Archive policies need a name, a source, and a destination",
+  ),
+  (
+    "policy.destination_inside_source",
+    "Archive policy destination must be outside its source",
+  ),
+  (
+    "policy.schedule_too_frequent",
+    "Archive policy schedules must be at least one hour",
+  ),
+  (
+    "policy.duplicate_name",
+    "Duplicate archive policy name: {name}",
+  ),
+  (
+    "offload.destination_not_folder",
+    "Offload destination must be a folder",
+  ),
+  (
+    "offload.same_volume",
+    "Offload destination must be on another volume",
+  ),
+  (
+    "offload.unsupported_type",
+    "Only files and folders can be offloaded",
+  ),
+  (
+    "offload.unsupported_item",
+    "{path}: only files, folders and links can be moved",
+  ),
+  (
+    "offload.link_failed",
+    "Moved to {target}, but unable to leave a link in its place",
+  ),
+  ("offload.not_offloaded", "Not an offloaded item"),
+  ("offload.drive_offline", "Connect the offload's drive first"),
+  (
+    "offload.target_missing",
+    "Offloaded item not found on its drive",
+  ),
+  (
+    "offload.link_changed",
+    "The original location no longer links to the offloaded item",
+  ),
+  (
+    "offload.remove_copy_failed",
+    "Moved back, but unable to remove the offloaded copy",
+  ),
+  // Watching
+  ("watch.no_roots", "No roots to watch"),
+  (
+    "watch.never_watched_volume",
+    "{path} is on a volume that is never watched",
+  ),
+  ("watch.start_failed", "Unable to start file watcher"),
+  ("watch.root_failed", "Unable to watch scan root"),
+  ("watch.path_failed", "Unable to watch {path}"),
+  ("watch.disconnected", "File watcher disconnected"),
+  ("watch.stopped", "Watch stopped"),
+  ("watch.session_stopped", "Watch session stopped"),
+  // Settings, rules, budgets and hooks
+  ("rules.incomplete", "Rules need a glob and a category"),
+  (
+    "settings.invalid_delete_mode",
+    "Volume delete mode must be \"delete\" or \"trash\"",
+  ),
+  (
+    "budget.incomplete",
+    "Budgets need a folder and a size above zero",
+  ),
+  (
+    "settings.invalid_low_space",
+    "Low-space thresholds must be between 0 and 100",
+  ),
+  ("hooks.incomplete", "Hooks need a command or a URL"),
+  (
+    "hooks.invalid_url",
+    "Hook URLs must start with http:// or https://",
+  ),
+  (
+    "hooks.invalid_threshold",
+    "Hook thresholds must be between 0 and 100",
+  ),
+  ("hooks.start_failed", "Unable to start hook"),
+  ("hooks.run_failed", "Unable to run hook"),
+  ("hooks.exited_with_error", "Hook exited with an error"),
+  // Terminal, API and metrics
+  ("terminal.not_found", "No terminal emulator found"),
+  ("terminal.open_failed", "Unable to open a terminal"),
+  (
+    "terminal.open_terminal_app_failed",
+    "Unable to open Terminal",
+  ),
+  ("api.socket_in_use", "API socket is already in use"),
+  ("api.socket_create_failed", "Unable to create API socket"),
+  ("api.socket_secure_failed", "Unable to secure API socket"),
+  (
+    "api.unsupported",
+    "The API socket is not supported on this platform",
+  ),
+  ("api.invalid_request", "Invalid JSON-RPC request"),
+  (
+    "api.request_too_long",
+    "Requests can be at most {limit} long",
+  ),
+  (
+    "api.invalid_parameter",
+    "Invalid or missing parameter: {key}",
+  ),
+  ("api.unknown_method", "Unknown method: {method}"),
+  (
+    "metrics.invalid_address",
+    "Metrics address must look like 127.0.0.1:9393",
+  ),
+  ("metrics.start_failed", "Unable to start metrics server"),
+  ("metrics.listen_failed", "Unable to listen on {address}"),
+  // Benchmarks and synthetic trees
+  (
+    "benchmark.unknown_profile",
+    "Unknown benchmark profile, expected one of: {profiles}",
+  ),
+  (
+    "benchmark.mismatch",
+    "Benchmark scan did not match the synthetic tree",
+  ),
+  ("synthetic.clear_failed", "Unable to clear synthetic folder"),
+  (
+    "synthetic.create_file_failed",
+    "Unable to create synthetic file",
+  ),
+  (
+    "synthetic.create_folder_failed",
+    "Unable to create synthetic folder",
+  ),
+  (
+    "synthetic.create_hard_link_failed",
+    "Unable to create synthetic hard link",
+  ),
+  (
+    "synthetic.create_symlink_failed",
+    "Unable to create synthetic symlink",
+  ),
+  ("synthetic.lock_failed", "Unable to lock synthetic folder"),
+  ("synthetic.read_failed", "Unable to read synthetic folder"),
+  // Scan diffs
+  ("diff.different_roots", "Scans are of different folders"),
+  ("diff.different_filters", "Scans counted different files"),
+  ("diff.no_totals", "No folder totals were kept for that scan"),
+  (
+    "diff.needs_two_scans",
+    "The folder needs two completed scans to compare",
+  ),
+  ("diff.invalid_format", "Report format must be json or csv"),
+  ("diff.write_failed", "Unable to write report file"),
+  ("diff.invalid_scan_ids", "Scan IDs must be numbers"),
+  (
+    "diff.usage",
+    "Usage: --diff <root> or --diff <old scan id> <new scan id>",
+  ),
+  // Scan milestones, announced by screen readers
+  (
+    "milestone.progress",
+    "{percent}% scanned: {size} in {files} files",
+  ),
+  ("milestone.scanned", "{size} scanned in {files} files"),
+  ("milestone.done", "Scan complete: {size} in {files} files"),
+  (
+    "milestone.cancelled",
+    "Scan cancelled after {size} in {files} files",
+  ),
+  // Suggestion titles
+  (
+    "suggestion.junk_metadata",
+    "Finder and Explorer metadata files",
+  ),
+  ("suggestion.junk_downloads", "Abandoned partial downloads"),
+  ("suggestion.caches", "Cache files for {name}"),
+  ("suggestion.duplicates", "{count} extra copies of {name}"),
+  (
+    "suggestion.duplicate_clones",
+    "{count} clones of {name} that already share its storage",
+  ),
+  (
+    "suggestion.old_large",
+    "Large files not modified in over a year",
+  ),
+  (
+    "suggestion.media_duplicates",
+    "{count} other versions of {name} in different formats or bitrates",
+  ),
+];
+
+// A message as an identifier and its parameters, for the frontend to word in the user's
+// language. It serializes with its English `text` as well, for clients without translations.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Message {
+  pub key: &'static str,
+  pub params: BTreeMap<&'static str, String>,
+}
+
+impl Message {
+  pub fn new(key: &'static str) -> Self {
+    Self {
+      key,
+      params: BTreeMap::new(),
+    }
+  }
+
+  pub fn with(mut self, name: &'static str, value: impl ToString) -> Self {
+    self.params.insert(name, value.to_string());
+    self
+  }
+
+  // An error the catalog has no identifier for, such as one from the OS, passed through as is.
+  pub fn unexpected(text: impl ToString) -> Self {
+    Message::new("error.unexpected").with("message", text)
+  }
+
+  // The English wording.
+  pub fn text(&self) -> String {
+    let template = CATALOG
+      .iter()
+      .find(|(key, _)| *key == self.key)
+      .map(|(_, template)| *template)
+      .unwrap_or(self.key);
+    let mut text = template.to_string();
+    for (name, value) in &self.params {
+      text = text.replace(&format!("{{{}}}", name), value);
+    }
+    text
+  }
+}
+
+// A size the way the frontend shows it, e.g. "12.4 GB".
+pub fn size(bytes: u64) -> String {
+  const UNITS: [&str; 5] = ["B", "KB", "MB", "GB", "TB"];
+  if bytes == 0 {
+    return "0 B".to_string();
+  }
+  let exponent = ((bytes as f64).log(1024.0).floor() as usize).min(UNITS.len() - 1);
+  let value = bytes as f64 / 1024f64.powi(exponent as i32);
+  let decimals = if value >= 100.0 {
+    0
+  } else if value >= 10.0 {
+    1
+  } else {
+    2
+  };
+  format!("{:.*} {}", decimals, value, UNITS[exponent])
+}
+
+impl fmt::Display for Message {
+  fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+    formatter.write_str(&self.text())
+  }
+}
+
+impl Serialize for Message {
+  fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+    let mut state = serializer.serialize_struct("Message", 3)?;
+    state.serialize_field("key", self.key)?;
+    state.serialize_field("params", &self.params)?;
+    state.serialize_field("text", &self.text())?;
+    state.end()
+  }
+}
@@ -0,0 +1,178 @@
+use crate::messages::Message;
+use crate::{cache, filesystem, history, volumes};
+use std::collections::HashSet;
+use std::fmt::Write as _;
+use std::io::{BufRead, BufReader, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::sync::{
+  atomic::{AtomicBool, Ordering},
+  Arc,
+};
+use std::thread;
+use std::time::Duration;
+
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+
+pub struct MetricsServer {
+  address: SocketAddr,
+  stop: Arc<AtomicBool>,
+}
+
+impl MetricsServer {
+  pub fn address(&self) -> SocketAddr {
+    self.address
+  }
+}
+
+impl Drop for MetricsServer {
+  fn drop(&mut self) {
+    self.stop.store(true, Ordering::Relaxed);
+    // Wakes the blocking accept so the listener thread can exit.
+    let _ = TcpStream::connect_timeout(&self.address, REQUEST_TIMEOUT);
+  }
+}
+
+fn escape_label(value: &str) -> String {
+  value
+    .replace('\\', "\\\\")
+    .replace('"', "\\\"")
+    .replace('\n', "\\n")
+}
+
+fn metric(out: &mut String, name: &str, help: &str, kind: &str, samples: &[(String, f64)]) {
+  let _ = writeln!(out, "# HELP {} {}", name, help);
+  let _ = writeln!(out, "# TYPE {} {}", name, kind);
+  for (labels, value) in samples {
+    let _ = writeln!(out, "{}{{{}}} {}", name, labels, value);
+  }
+}
+
+// Prometheus text exposition format: volume capacity plus the latest completed scan of each root.
+fn render(app: &tauri::AppHandle) -> String {
+  let mut out = String::new();
+
+  let mut size = Vec::new();
+  let mut available = Vec::new();
+  for volume in volumes::mounted_volumes() {
+    if let Some((total, free)) = filesystem::volume_space(&volume.mount_point) {
+      let labels = format!(
+        "mount=\"{}\",device=\"{}\",fstype=\"{}\"",
+        escape_label(&volume.mount_point.to_string_lossy()),
+        escape_label(&volume.device),
+        escape_label(&volume.fs_type)
+      );
+      size.push((labels.clone(), total as f64));
+      available.push((labels, free as f64));
+    }
+  }
+  metric(
+    &mut out,
+    "chonky_volume_size_bytes",
+    "Volume capacity.",
+    "gauge",
+    &size,
+  );
+  metric(
+    &mut out,
+    "chonky_volume_available_bytes",
+    "Space available to unprivileged users.",
+    "gauge",
+    &available,
+  );
+
+  let entries = cache::app_dir(app)
+    .map(|dir| history::load(&dir))
+    .unwrap_or_default();
+  let mut seen = HashSet::new();
+  let (mut files, mut bytes, mut finished, mut duration) =
+    (Vec::new(), Vec::new(), Vec::new(), Vec::new());
+  for entry in entries.iter().filter(|entry| !entry.cancelled) {
+    if !seen.insert(entry.root.as_str()) {
+      continue;
+    }
+    let labels = format!("root=\"{}\"", escape_label(&entry.root));
+    files.push((labels.clone(), entry.scanned_files as f64));
+    bytes.push((labels.clone(), entry.scanned_bytes as f64));
+    finished.push((labels.clone(), entry.finished_at as f64));
+    duration.push((
+      labels,
+      entry.finished_at.saturating_sub(entry.started_at) as f64,
+    ));
+  }
+  metric(
+    &mut out,
+    "chonky_last_scan_files",
+    "Files counted by the last completed scan.",
+    "gauge",
+    &files,
+  );
+  metric(
+    &mut out,
+    "chonky_last_scan_bytes",
+    "Bytes counted by the last completed scan.",
+    "gauge",
+    &bytes,
+  );
+  metric(
+    &mut out,
+    "chonky_last_scan_timestamp_seconds",
+    "When the last completed scan finished.",
+    "gauge",
+    &finished,
+  );
+  metric(
+    &mut out,
+    "chonky_last_scan_duration_seconds",
+    "How long the last completed scan took.",
+    "gauge",
+    &duration,
+  );
+  out
+}
+
+fn serve(app: &tauri::AppHandle, mut stream: TcpStream) {
+  let _ = stream.set_read_timeout(Some(REQUEST_TIMEOUT));
+  let mut request_line = String::new();
+  if let Ok(reader) = stream.try_clone() {
+    let _ = BufReader::new(reader).read_line(&mut request_line);
+  }
+
+  let path = request_line.split_whitespace().nth(1).unwrap_or("");
+  let (status, body) = if request_line.starts_with("GET ") && path == "/metrics" {
+    ("200 OK", render(app))
+  } else {
+    ("404 Not Found", "Not found\n".to_string())
+  };
+  let _ = write!(
+    stream,
+    "HTTP/1.1 {}\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+    status,
+    body.len(),
+    body
+  );
+}
+
+pub fn start(app: tauri::AppHandle, address: SocketAddr) -> Result<MetricsServer, Message> {
+  let listener = TcpListener::bind(address)
+    .map_err(|_| Message::new("metrics.listen_failed").with("address", address))?;
+  let address = listener
+    .local_addr()
+    .map_err(|_| Message::new("metrics.start_failed"))?;
+
+  let stop = Arc::new(AtomicBool::new(false));
+  let server = MetricsServer {
+    address,
+    stop: stop.clone(),
+  };
+  thread::spawn(move || {
+    for stream in listener.incoming() {
+      if stop.load(Ordering::Relaxed) {
+        return;
+      }
+      if let Ok(stream) = stream {
+        serve(&app, stream);
+      }
+    }
+  });
+  Ok(server)
+}
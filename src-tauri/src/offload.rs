@@ -0,0 +1,370 @@
+use crate::conflicts::{self, Conflicts};
+use crate::messages::Message;
+use crate::volumes::{self, VolumeLocation};
+use crate::{audit, cache, history, policies};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::fs::{self, Metadata};
+use std::io;
+#[cfg(target_family = "unix")]
+use std::os::unix::fs::MetadataExt;
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::Duration;
+use tauri::Manager;
+
+const OFFLOADS_FILE: &str = "offloads.json";
+const MOUNT_POLL_INTERVAL: Duration = Duration::from_secs(10);
+
+// A file or folder moved to another volume with a symlink left in its place.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Offload {
+  pub original: String,
+  pub target: String,
+  pub bytes: u64,
+  #[serde(rename = "isDir")]
+  pub is_dir: bool,
+  #[serde(rename = "offloadedAt")]
+  pub offloaded_at: u64,
+  // The target's volume and path on it, to find it again if the drive mounts elsewhere.
+  pub location: Option<VolumeLocation>,
+}
+
+#[derive(Clone, Serialize)]
+pub struct OffloadStatus {
+  #[serde(flatten)]
+  pub offload: Offload,
+  // "ok", "volume_offline" (its drive isn't connected), "target_missing" (the drive is there
+  // but the item isn't) or "link_changed" (the original path no longer links to the target).
+  pub status: &'static str,
+}
+
+#[derive(Clone, Serialize)]
+pub struct OffloadReport {
+  // The newly mounted volumes that prompted the check, if any.
+  #[serde(rename = "mountPoints")]
+  pub mount_points: Vec<String>,
+  pub checked: u64,
+  pub ok: u64,
+  pub offline: u64,
+  // Offloads found on a drive now mounted elsewhere, whose links were pointed there.
+  pub relinked: Vec<OffloadStatus>,
+  pub broken: Vec<OffloadStatus>,
+}
+
+#[cfg(target_family = "unix")]
+fn device(metadata: &Metadata) -> Option<u64> {
+  Some(metadata.dev())
+}
+
+#[cfg(not(target_family = "unix"))]
+fn device(_metadata: &Metadata) -> Option<u64> {
+  None
+}
+
+#[cfg(target_family = "unix")]
+fn make_link(target: &Path, link: &Path, _is_dir: bool) -> io::Result<()> {
+  std::os::unix::fs::symlink(target, link)
+}
+
+#[cfg(target_os = "windows")]
+fn make_link(target: &Path, link: &Path, is_dir: bool) -> io::Result<()> {
+  if is_dir {
+    std::os::windows::fs::symlink_dir(target, link)
+  } else {
+    std::os::windows::fs::symlink_file(target, link)
+  }
+}
+
+#[cfg(not(any(target_family = "unix", target_os = "windows")))]
+fn make_link(_target: &Path, _link: &Path, _is_dir: bool) -> io::Result<()> {
+  Err(io::ErrorKind::Unsupported.into())
+}
+
+fn remove_item(path: &Path) -> io::Result<()> {
+  match fs::symlink_metadata(path) {
+    Ok(metadata) if metadata.is_dir() => fs::remove_dir_all(path),
+    _ => fs::remove_file(path),
+  }
+}
+
+// Copies a file or a whole folder to `target`, verifying every file by hash; links inside a
+// folder are copied as links. Returns the bytes copied.
+fn copy_tree(source: &Path, target: &Path) -> Result<u64, Message> {
+  let metadata = fs::symlink_metadata(source).map_err(|_| Message::new("file.not_found"))?;
+  if metadata.file_type().is_symlink() {
+    let link = fs::read_link(source).map_err(|_| Message::new("link.read_failed"))?;
+    let is_dir = fs::metadata(source).is_ok_and(|metadata| metadata.is_dir());
+    make_link(&link, target, is_dir).map_err(|_| Message::new("link.copy_failed"))?;
+    return Ok(0);
+  }
+  if metadata.is_file() {
+    policies::copy_verified(source, target)?;
+    return Ok(metadata.len());
+  }
+  if !metadata.is_dir() {
+    return Err(Message::new("offload.unsupported_item").with("path", source.display()));
+  }
+  fs::create_dir(target).map_err(|_| Message::new("copy.create_folder_failed"))?;
+  let entries = fs::read_dir(source).map_err(|_| Message::new("folder.read_failed"))?;
+  let mut bytes = 0;
+  for entry in entries {
+    let entry = entry.map_err(|_| Message::new("folder.read_failed"))?;
+    bytes += copy_tree(&entry.path(), &target.join(entry.file_name()))?;
+  }
+  Ok(bytes)
+}
+
+pub fn load(cache_dir: &Path) -> Vec<Offload> {
+  cache::read_json(&cache_dir.join(OFFLOADS_FILE))
+}
+
+fn save(cache_dir: &Path, offloads: &[Offload]) -> Result<(), Message> {
+  cache::write_json(&cache_dir.join(OFFLOADS_FILE), &offloads)
+}
+
+// Moves `path` into `destination` on another volume and leaves a symlink at `path`, so apps
+// that expect it there keep working. A taken name in the destination is settled by
+// `conflicts`; skipping it cancels the offload.
+pub fn offload(
+  path: &Path,
+  destination: &Path,
+  conflicts: &mut Conflicts,
+  cache_dir: &Path,
+) -> Result<Offload, Message> {
+  let metadata = fs::symlink_metadata(path).map_err(|_| Message::new("file.not_found"))?;
+  if metadata.file_type().is_symlink() {
+    return Err(Message::new("link.already_link"));
+  }
+  if !metadata.is_file() && !metadata.is_dir() {
+    return Err(Message::new("offload.unsupported_type"));
+  }
+  let destination_metadata =
+    fs::metadata(destination).map_err(|_| Message::new("archive.destination_not_found"))?;
+  if !destination_metadata.is_dir() {
+    return Err(Message::new("offload.destination_not_folder"));
+  }
+  if device(&metadata).is_some() && device(&metadata) == device(&destination_metadata) {
+    return Err(Message::new("offload.same_volume"));
+  }
+  let name = path
+    .file_name()
+    .ok_or_else(|| Message::new("path.invalid_file_name"))?;
+  let placement = conflicts
+    .resolve(path, &destination.join(name))?
+    .ok_or_else(|| Message::new("conflict.item_exists"))?;
+  if placement.replace && metadata.is_dir() {
+    return Err(Message::new("conflict.folder_replaces_file"));
+  }
+
+  let mut bytes = 0;
+  let moved = conflicts::write_then_place(&placement, |written| {
+    bytes = copy_tree(path, written).inspect_err(|_| {
+      let _ = remove_item(written);
+    })?;
+    Ok(())
+  })
+  .and_then(|_| remove_item(path).map_err(|_| Message::new("copy.remove_original_failed")));
+  audit::record(
+    cache_dir,
+    "move",
+    path,
+    bytes,
+    Some(&placement.path),
+    moved.as_ref().map(|_| ()),
+  );
+  moved?;
+
+  let offload = Offload {
+    original: path.to_string_lossy().to_string(),
+    target: placement.path.to_string_lossy().to_string(),
+    bytes,
+    is_dir: metadata.is_dir(),
+    offloaded_at: history::now_secs(),
+    location: volumes::locate(&placement.path),
+  };
+  let mut offloads = load(cache_dir);
+  offloads.retain(|existing| existing.original != offload.original);
+  offloads.push(offload.clone());
+  save(cache_dir, &offloads)?;
+  make_link(&placement.path, path, offload.is_dir)
+    .map_err(|_| Message::new("offload.link_failed").with("target", &offload.target))?;
+  Ok(offload)
+}
+
+// Mounted volumes by UUID, read only once some offload's target turns out to be missing.
+struct Mounted(Option<Vec<(PathBuf, String)>>);
+
+impl Mounted {
+  fn get(&mut self) -> &[(PathBuf, String)] {
+    self.0.get_or_insert_with(|| {
+      volumes::mounted_volumes()
+        .into_iter()
+        .filter_map(|volume| Some((volume.mount_point.clone(), volumes::volume_uuid(&volume)?)))
+        .collect()
+    })
+  }
+
+  // Where `location` is right now, if its volume is mounted.
+  fn find(&mut self, location: &VolumeLocation) -> Option<PathBuf> {
+    self
+      .get()
+      .iter()
+      .find(|(_, uuid)| *uuid == location.uuid)
+      .map(|(mount_point, _)| mount_point.join(&location.relative_path))
+  }
+}
+
+fn status(offload: &Offload, mounted: &mut Mounted) -> &'static str {
+  if fs::read_link(&offload.original).ok() != Some(offload.target.clone().into()) {
+    "link_changed"
+  } else if fs::symlink_metadata(&offload.target).is_ok() {
+    "ok"
+  } else if offload
+    .location
+    .as_ref()
+    .is_some_and(|location| mounted.find(location).is_none())
+  {
+    "volume_offline"
+  } else {
+    "target_missing"
+  }
+}
+
+pub fn list(cache_dir: &Path) -> Vec<OffloadStatus> {
+  let mut mounted = Mounted(None);
+  load(cache_dir)
+    .into_iter()
+    .map(|offload| OffloadStatus {
+      status: status(&offload, &mut mounted),
+      offload,
+    })
+    .collect()
+}
+
+// Checks every offload's target. One whose drive came back under another mount point, e.g.
+// `/Volumes/Backup 1`, gets its link pointed at the new path.
+pub fn revalidate(cache_dir: &Path, mount_points: Vec<String>) -> OffloadReport {
+  let mut offloads = load(cache_dir);
+  let mut mounted = Mounted(None);
+  let mut report = OffloadReport {
+    mount_points,
+    checked: offloads.len() as u64,
+    ok: 0,
+    offline: 0,
+    relinked: Vec::new(),
+    broken: Vec::new(),
+  };
+  let mut changed = false;
+  for offload in &mut offloads {
+    let mut status = status(offload, &mut mounted);
+    if status == "target_missing" {
+      let moved = offload
+        .location
+        .as_ref()
+        .and_then(|location| mounted.find(location))
+        .filter(|moved| fs::symlink_metadata(moved).is_ok());
+      if let Some(moved) = moved {
+        let original = Path::new(&offload.original);
+        if fs::remove_file(original).is_ok() && make_link(&moved, original, offload.is_dir).is_ok()
+        {
+          offload.target = moved.to_string_lossy().to_string();
+          changed = true;
+          report.relinked.push(OffloadStatus {
+            offload: offload.clone(),
+            status: "ok",
+          });
+          continue;
+        }
+        status = "link_changed";
+      }
+    }
+    match status {
+      "ok" => report.ok += 1,
+      "volume_offline" => report.offline += 1,
+      _ => report.broken.push(OffloadStatus {
+        offload: offload.clone(),
+        status,
+      }),
+    }
+  }
+  if changed {
+    let _ = save(cache_dir, &offloads);
+  }
+  report
+}
+
+// Revalidates the offloads whenever a volume is mounted, and emits `offload_report` when that
+// finds anything to tell.
+pub fn start_watcher(app: tauri::AppHandle) {
+  thread::spawn(move || {
+    let mut known: HashSet<PathBuf> = volumes::mounted_volumes()
+      .into_iter()
+      .map(|volume| volume.mount_point)
+      .collect();
+    loop {
+      thread::sleep(MOUNT_POLL_INTERVAL);
+      let current: HashSet<PathBuf> = volumes::mounted_volumes()
+        .into_iter()
+        .map(|volume| volume.mount_point)
+        .collect();
+      let mounted: Vec<String> = current
+        .difference(&known)
+        .map(|mount_point| mount_point.to_string_lossy().to_string())
+        .collect();
+      known = current;
+      if mounted.is_empty() {
+        continue;
+      }
+      let app_dir = match cache::app_dir(&app) {
+        Ok(app_dir) => app_dir,
+        Err(_) => continue,
+      };
+      if load(&app_dir).is_empty() {
+        continue;
+      }
+      let report = revalidate(&app_dir, mounted);
+      if !report.relinked.is_empty() || !report.broken.is_empty() {
+        let _ = app.emit_to("main", "offload_report", report);
+      }
+    }
+  });
+}
+
+// Moves an offloaded item back in place of its link.
+pub fn undo(path: &Path, cache_dir: &Path) -> Result<Offload, Message> {
+  let original = path.to_string_lossy().to_string();
+  let mut offloads = load(cache_dir);
+  let offload = offloads
+    .iter()
+    .find(|offload| offload.original == original)
+    .cloned()
+    .ok_or_else(|| Message::new("offload.not_offloaded"))?;
+  match status(&offload, &mut Mounted(None)) {
+    "link_changed" => return Err(Message::new("offload.link_changed")),
+    "volume_offline" => return Err(Message::new("offload.drive_offline")),
+    "target_missing" => return Err(Message::new("offload.target_missing")),
+    _ => {}
+  }
+
+  let target = Path::new(&offload.target);
+  fs::remove_file(path).map_err(|_| Message::new("link.remove_failed"))?;
+  if let Err(error) = copy_tree(target, path) {
+    let _ = remove_item(path);
+    let _ = make_link(target, path, offload.is_dir);
+    audit::record(
+      cache_dir,
+      "move",
+      target,
+      offload.bytes,
+      Some(path),
+      Err(&error),
+    );
+    return Err(error);
+  }
+  audit::record(cache_dir, "move", target, offload.bytes, Some(path), Ok(()));
+  offloads.retain(|existing| existing.original != original);
+  save(cache_dir, &offloads)?;
+  remove_item(target).map_err(|_| Message::new("offload.remove_copy_failed"))?;
+  Ok(offload)
+}
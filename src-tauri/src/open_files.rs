@@ -0,0 +1,386 @@
+use serde::Serialize;
+use std::cmp::Reverse;
+use std::collections::HashMap;
+#[cfg(target_os = "macos")]
+use std::ffi::CString;
+#[cfg(target_os = "linux")]
+use std::fs;
+#[cfg(target_os = "macos")]
+use std::os::unix::ffi::OsStrExt;
+#[cfg(target_family = "unix")]
+use std::os::unix::fs::MetadataExt;
+use std::path::Path;
+
+#[derive(Clone, Serialize)]
+pub struct OpenProcess {
+  pub pid: u32,
+  pub name: String,
+}
+
+#[derive(Serialize)]
+pub struct DeletedOpenFile {
+  pub path: String,
+  pub size: u64,
+  pub processes: Vec<OpenProcess>,
+}
+
+#[derive(Serialize)]
+pub struct UnreleasedSpace {
+  pub files: Vec<DeletedOpenFile>,
+  #[serde(rename = "totalBytes")]
+  pub total_bytes: u64,
+}
+
+#[derive(Serialize)]
+pub struct DeleteCheck {
+  pub path: String,
+  #[serde(rename = "openBy")]
+  pub open_by: Vec<OpenProcess>,
+  // The volume is set to use the trash but the file can only be deleted permanently.
+  #[serde(rename = "trashUnavailable")]
+  pub trash_unavailable: bool,
+}
+
+// A regular file held open by a process.
+pub struct OpenFile {
+  pub pid: u32,
+  pub dev: u64,
+  pub ino: u64,
+  pub links: u64,
+  pub size: u64,
+  pub path: String,
+}
+
+#[cfg(target_os = "linux")]
+fn process_name(pid: u32) -> String {
+  fs::read_to_string(format!("/proc/{}/comm", pid))
+    .map(|name| name.trim().to_string())
+    .unwrap_or_else(|_| pid.to_string())
+}
+
+#[cfg(target_os = "linux")]
+fn all_pids() -> Vec<u32> {
+  match fs::read_dir("/proc") {
+    Ok(entries) => entries
+      .flatten()
+      .filter_map(|entry| entry.file_name().to_string_lossy().parse::<u32>().ok())
+      .collect(),
+    Err(_) => Vec::new(),
+  }
+}
+
+// Without root, only this user's processes are visible.
+#[cfg(target_os = "linux")]
+pub fn open_files() -> Vec<OpenFile> {
+  let mut files = Vec::new();
+  for pid in all_pids() {
+    let fds = match fs::read_dir(format!("/proc/{}/fd", pid)) {
+      Ok(fds) => fds,
+      Err(_) => continue,
+    };
+
+    for fd in fds.flatten() {
+      let target = match fs::read_link(fd.path()) {
+        Ok(target) => target.to_string_lossy().to_string(),
+        Err(_) => continue,
+      };
+      let path = target.strip_suffix(" (deleted)").unwrap_or(&target);
+      if !path.starts_with('/') || path.starts_with("/memfd:") {
+        continue;
+      }
+      let metadata = match fs::metadata(fd.path()) {
+        Ok(metadata) if metadata.is_file() => metadata,
+        _ => continue,
+      };
+      files.push(OpenFile {
+        pid,
+        dev: metadata.dev(),
+        ino: metadata.ino(),
+        links: metadata.nlink(),
+        size: metadata.len(),
+        path: path.to_string(),
+      });
+    }
+  }
+  files
+}
+
+#[cfg(target_os = "linux")]
+pub fn processes_holding(path: &Path) -> Vec<OpenProcess> {
+  let target = match fs::metadata(path) {
+    Ok(metadata) => (metadata.dev(), metadata.ino()),
+    Err(_) => return Vec::new(),
+  };
+  let own_pid = std::process::id();
+  let mut holders: Vec<OpenProcess> = Vec::new();
+
+  for pid in all_pids().into_iter().filter(|pid| *pid != own_pid) {
+    let fds = match fs::read_dir(format!("/proc/{}/fd", pid)) {
+      Ok(fds) => fds,
+      Err(_) => continue,
+    };
+    let holds = fds.flatten().any(|fd| {
+      fs::metadata(fd.path())
+        .map(|metadata| (metadata.dev(), metadata.ino()) == target)
+        .unwrap_or(false)
+    });
+    if holds {
+      holders.push(OpenProcess {
+        pid,
+        name: process_name(pid),
+      });
+    }
+  }
+  holders
+}
+
+#[cfg(target_os = "macos")]
+const PROC_ALL_PIDS: u32 = 1;
+#[cfg(target_os = "macos")]
+const PROC_LISTPIDSPATH_EXCLUDE_EVTONLY: u32 = 2;
+#[cfg(target_os = "macos")]
+const MAX_HOLDERS: usize = 256;
+#[cfg(target_os = "macos")]
+const PROC_PIDFDVNODEPATHINFO: libc::c_int = 2;
+
+// struct vnode_fdinfowithpath: a 24-byte proc_fileinfo header followed by the vnode info.
+#[cfg(target_os = "macos")]
+#[repr(C)]
+struct VnodeFdInfoWithPath {
+  _pfi: [u64; 3],
+  pvip: libc::vnode_info_path,
+}
+
+#[cfg(target_os = "macos")]
+extern "C" {
+  fn proc_listpidspath(
+    kind: u32,
+    typeinfo: u32,
+    path: *const libc::c_char,
+    pathflags: u32,
+    buffer: *mut libc::c_void,
+    buffersize: libc::c_int,
+  ) -> libc::c_int;
+}
+
+#[cfg(target_os = "macos")]
+fn process_name(pid: u32) -> String {
+  let mut buffer = vec![0u8; 256];
+  let length = unsafe {
+    libc::proc_name(
+      pid as libc::c_int,
+      buffer.as_mut_ptr() as *mut libc::c_void,
+      buffer.len() as u32,
+    )
+  };
+  if length <= 0 {
+    return pid.to_string();
+  }
+  String::from_utf8_lossy(&buffer[..length as usize]).to_string()
+}
+
+#[cfg(target_os = "macos")]
+fn all_pids() -> Vec<u32> {
+  let count = unsafe { libc::proc_listallpids(std::ptr::null_mut(), 0) };
+  if count <= 0 {
+    return Vec::new();
+  }
+  // Leave headroom for processes started between the two calls.
+  let mut pids = vec![0 as libc::c_int; count as usize + 64];
+  let count = unsafe {
+    libc::proc_listallpids(
+      pids.as_mut_ptr() as *mut libc::c_void,
+      (pids.len() * std::mem::size_of::<libc::c_int>()) as libc::c_int,
+    )
+  };
+  pids.truncate(count.max(0) as usize);
+  pids
+    .into_iter()
+    .filter(|pid| *pid > 0)
+    .map(|pid| pid as u32)
+    .collect()
+}
+
+#[cfg(target_os = "macos")]
+pub fn processes_holding(path: &Path) -> Vec<OpenProcess> {
+  let c_path = match CString::new(path.as_os_str().as_bytes()) {
+    Ok(c_path) => c_path,
+    Err(_) => return Vec::new(),
+  };
+  let mut pids = vec![0 as libc::c_int; MAX_HOLDERS];
+  let bytes = unsafe {
+    proc_listpidspath(
+      PROC_ALL_PIDS,
+      0,
+      c_path.as_ptr(),
+      PROC_LISTPIDSPATH_EXCLUDE_EVTONLY,
+      pids.as_mut_ptr() as *mut libc::c_void,
+      (pids.len() * std::mem::size_of::<libc::c_int>()) as libc::c_int,
+    )
+  };
+  if bytes <= 0 {
+    return Vec::new();
+  }
+  let own_pid = std::process::id();
+  pids.truncate(bytes as usize / std::mem::size_of::<libc::c_int>());
+
+  pids
+    .into_iter()
+    .filter(|pid| *pid > 0 && *pid as u32 != own_pid)
+    .map(|pid| OpenProcess {
+      pid: pid as u32,
+      name: process_name(pid as u32),
+    })
+    .collect()
+}
+
+#[cfg(target_os = "macos")]
+fn process_fds(pid: libc::c_int) -> Vec<libc::proc_fdinfo> {
+  let fd_size = std::mem::size_of::<libc::proc_fdinfo>();
+  let bytes = unsafe { libc::proc_pidinfo(pid, libc::PROC_PIDLISTFDS, 0, std::ptr::null_mut(), 0) };
+  if bytes <= 0 {
+    return Vec::new();
+  }
+  let mut fds: Vec<libc::proc_fdinfo> = Vec::with_capacity(bytes as usize / fd_size);
+  let bytes = unsafe {
+    libc::proc_pidinfo(
+      pid,
+      libc::PROC_PIDLISTFDS,
+      0,
+      fds.as_mut_ptr() as *mut libc::c_void,
+      (fds.capacity() * fd_size) as libc::c_int,
+    )
+  };
+  if bytes <= 0 {
+    return Vec::new();
+  }
+  unsafe { fds.set_len(bytes as usize / fd_size) };
+  fds
+}
+
+#[cfg(target_os = "macos")]
+pub fn open_files() -> Vec<OpenFile> {
+  let mut files = Vec::new();
+  for pid in all_pids() {
+    for fd in process_fds(pid as libc::c_int) {
+      if fd.proc_fdtype != libc::PROX_FDTYPE_VNODE as u32 {
+        continue;
+      }
+      let mut info: VnodeFdInfoWithPath = unsafe { std::mem::zeroed() };
+      let size = std::mem::size_of::<VnodeFdInfoWithPath>() as libc::c_int;
+      let result = unsafe {
+        libc::proc_pidfdinfo(
+          pid as libc::c_int,
+          fd.proc_fd,
+          PROC_PIDFDVNODEPATHINFO,
+          &mut info as *mut _ as *mut libc::c_void,
+          size,
+        )
+      };
+      if result != size {
+        continue;
+      }
+      let stat = &info.pvip.vip_vi.vi_stat;
+      if stat.vst_mode as u32 & libc::S_IFMT as u32 != libc::S_IFREG as u32 {
+        continue;
+      }
+
+      let path_bytes: Vec<u8> = info
+        .pvip
+        .vip_path
+        .iter()
+        .flatten()
+        .take_while(|byte| **byte != 0)
+        .map(|byte| *byte as u8)
+        .collect();
+      files.push(OpenFile {
+        pid,
+        dev: stat.vst_dev as u64,
+        ino: stat.vst_ino,
+        links: stat.vst_nlink as u64,
+        size: stat.vst_size.max(0) as u64,
+        path: String::from_utf8_lossy(&path_bytes).to_string(),
+      });
+    }
+  }
+  files
+}
+
+#[cfg(not(any(target_os = "macos", target_os = "linux")))]
+fn process_name(pid: u32) -> String {
+  pid.to_string()
+}
+
+#[cfg(not(any(target_os = "macos", target_os = "linux")))]
+fn all_pids() -> Vec<u32> {
+  Vec::new()
+}
+
+#[cfg(not(any(target_os = "macos", target_os = "linux")))]
+pub fn open_files() -> Vec<OpenFile> {
+  Vec::new()
+}
+
+#[cfg(not(any(target_os = "macos", target_os = "linux")))]
+pub fn processes_holding(_path: &Path) -> Vec<OpenProcess> {
+  Vec::new()
+}
+
+pub fn running_processes() -> Vec<OpenProcess> {
+  all_pids()
+    .into_iter()
+    .map(|pid| OpenProcess {
+      pid,
+      name: process_name(pid),
+    })
+    .collect()
+}
+
+pub fn process(pid: u32) -> OpenProcess {
+  OpenProcess {
+    pid,
+    name: process_name(pid),
+  }
+}
+
+pub fn check_delete(path: &Path, trash_unavailable: bool) -> DeleteCheck {
+  DeleteCheck {
+    path: path.to_string_lossy().to_string(),
+    open_by: processes_holding(path),
+    trash_unavailable,
+  }
+}
+
+// Limits results to the volume holding `root` when given.
+pub fn unreleased_space(root: Option<&Path>) -> UnreleasedSpace {
+  #[cfg(target_family = "unix")]
+  let device = root
+    .and_then(|root| std::fs::metadata(root).ok())
+    .map(|metadata| metadata.dev());
+  #[cfg(not(target_family = "unix"))]
+  let device = root.map(|_| 0);
+
+  let mut deleted: HashMap<(u64, u64), DeletedOpenFile> = HashMap::new();
+  for open in open_files() {
+    if open.links != 0 || device.is_some_and(|device| device != open.dev) {
+      continue;
+    }
+    let file = deleted
+      .entry((open.dev, open.ino))
+      .or_insert_with(|| DeletedOpenFile {
+        path: open.path.clone(),
+        size: open.size,
+        processes: Vec::new(),
+      });
+    if !file.processes.iter().any(|holder| holder.pid == open.pid) {
+      file.processes.push(process(open.pid));
+    }
+  }
+
+  let mut files: Vec<DeletedOpenFile> = deleted.into_values().collect();
+  files.sort_by_key(|file| Reverse(file.size));
+  UnreleasedSpace {
+    total_bytes: files.iter().map(|file| file.size).sum(),
+    files,
+  }
+}
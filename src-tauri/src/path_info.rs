@@ -0,0 +1,175 @@
+use crate::messages::Message;
+use serde::Serialize;
+#[cfg(target_os = "macos")]
+use std::ffi::CString;
+use std::fs;
+#[cfg(target_os = "macos")]
+use std::os::unix::ffi::OsStrExt;
+#[cfg(target_family = "unix")]
+use std::os::unix::fs::PermissionsExt;
+use std::path::Path;
+#[cfg(target_os = "macos")]
+use std::process::Command;
+use std::time::UNIX_EPOCH;
+
+#[derive(Serialize)]
+pub struct QuarantineInfo {
+  pub agent: Option<String>,
+  #[serde(rename = "quarantinedAt")]
+  pub quarantined_at: Option<u64>,
+  pub raw: String,
+}
+
+#[derive(Serialize)]
+pub struct SignatureInfo {
+  pub signed: bool,
+  pub notarized: bool,
+  pub authority: Option<String>,
+}
+
+#[derive(Serialize)]
+pub struct PathInfo {
+  pub path: String,
+  pub kind: String,
+  pub size: u64,
+  #[serde(rename = "modifiedAt")]
+  pub modified_at: Option<u64>,
+  pub executable: bool,
+  pub quarantine: Option<QuarantineInfo>,
+  pub signature: Option<SignatureInfo>,
+}
+
+#[cfg(target_family = "unix")]
+fn is_executable(metadata: &fs::Metadata) -> bool {
+  metadata.is_file() && metadata.permissions().mode() & 0o111 != 0
+}
+
+#[cfg(not(target_family = "unix"))]
+fn is_executable(_metadata: &fs::Metadata) -> bool {
+  false
+}
+
+fn is_app_bundle(path: &Path) -> bool {
+  path
+    .extension()
+    .map(|extension| extension.eq_ignore_ascii_case("app"))
+    .unwrap_or(false)
+}
+
+// The attribute looks like "0083;5f3c1a2b;Safari;<uuid>": flags, hex timestamp, agent, event id.
+#[cfg(target_os = "macos")]
+fn parse_quarantine(raw: String) -> QuarantineInfo {
+  let fields: Vec<&str> = raw.split(';').collect();
+  QuarantineInfo {
+    agent: fields
+      .get(2)
+      .filter(|agent| !agent.is_empty())
+      .map(|agent| agent.to_string()),
+    quarantined_at: fields
+      .get(1)
+      .and_then(|timestamp| u64::from_str_radix(timestamp, 16).ok()),
+    raw,
+  }
+}
+
+#[cfg(target_os = "macos")]
+fn quarantine_attribute(path: &Path) -> Option<QuarantineInfo> {
+  let c_path = CString::new(path.as_os_str().as_bytes()).ok()?;
+  let name = CString::new("com.apple.quarantine").ok()?;
+  let mut buffer = vec![0u8; 1024];
+  let length = unsafe {
+    libc::getxattr(
+      c_path.as_ptr(),
+      name.as_ptr(),
+      buffer.as_mut_ptr() as *mut libc::c_void,
+      buffer.len(),
+      0,
+      libc::XATTR_NOFOLLOW,
+    )
+  };
+  if length <= 0 {
+    return None;
+  }
+  buffer.truncate(length as usize);
+  Some(parse_quarantine(
+    String::from_utf8_lossy(&buffer)
+      .trim_end_matches('\0')
+      .to_string(),
+  ))
+}
+
+#[cfg(not(target_os = "macos"))]
+fn quarantine_attribute(_path: &Path) -> Option<QuarantineInfo> {
+  None
+}
+
+#[cfg(target_os = "macos")]
+fn signature(path: &Path) -> Option<SignatureInfo> {
+  let codesign = Command::new("codesign")
+    .arg("--display")
+    .arg("--verbose=2")
+    .arg(path)
+    .output()
+    .ok()?;
+  let details = String::from_utf8_lossy(&codesign.stderr);
+  let authority = details
+    .lines()
+    .find_map(|line| line.strip_prefix("Authority="))
+    .map(|authority| authority.to_string());
+
+  let notarized = Command::new("spctl")
+    .arg("--assess")
+    .arg("-vv")
+    .arg("--type")
+    .arg("execute")
+    .arg(path)
+    .output()
+    .map(|output| String::from_utf8_lossy(&output.stderr).contains("source=Notarized"))
+    .unwrap_or(false);
+
+  Some(SignatureInfo {
+    signed: codesign.status.success(),
+    notarized,
+    authority,
+  })
+}
+
+#[cfg(not(target_os = "macos"))]
+fn signature(_path: &Path) -> Option<SignatureInfo> {
+  None
+}
+
+pub fn path_info(path: &Path) -> Result<PathInfo, Message> {
+  let metadata = fs::symlink_metadata(path).map_err(|_| Message::new("path.not_found"))?;
+  let file_type = metadata.file_type();
+  let kind = if file_type.is_symlink() {
+    "symlink"
+  } else if file_type.is_dir() {
+    "directory"
+  } else if file_type.is_file() {
+    "file"
+  } else {
+    "other"
+  };
+
+  let executable = is_executable(&metadata);
+  let signature = if !file_type.is_symlink() && (executable || is_app_bundle(path)) {
+    signature(path)
+  } else {
+    None
+  };
+
+  Ok(PathInfo {
+    path: path.to_string_lossy().to_string(),
+    kind: kind.to_string(),
+    size: metadata.len(),
+    modified_at: metadata
+      .modified()
+      .ok()
+      .and_then(|modified| modified.duration_since(UNIX_EPOCH).ok())
+      .map(|duration| duration.as_secs()),
+    executable,
+    quarantine: quarantine_attribute(path),
+    signature,
+  })
+}
@@ -0,0 +1,383 @@
+use crate::messages::Message;
+use crate::settings::Settings;
+use crate::{filesystem, hashing, trash};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::fs;
+#[cfg(target_family = "unix")]
+use std::os::unix::fs::MetadataExt;
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+const PLAN_FILE_VERSION: u32 = 1;
+const MAX_PLAN_FILE_BYTES: u64 = 64 * 1024 * 1024;
+
+#[derive(Clone, Serialize)]
+pub struct PlanItem {
+  pub path: String,
+  pub size: u64,
+  pub action: String,
+}
+
+#[derive(Serialize)]
+pub struct PlanSummary {
+  pub items: Vec<PlanItem>,
+  #[serde(rename = "totalBytes")]
+  pub total_bytes: u64,
+  #[serde(skip_serializing_if = "Vec::is_empty")]
+  pub rejected: Vec<PlanFailure>,
+}
+
+#[derive(Clone, Serialize)]
+pub struct PlanFailure {
+  pub path: String,
+  pub error: Message,
+}
+
+#[derive(Serialize)]
+pub struct PlanExecution {
+  #[serde(rename = "deletedFiles")]
+  pub deleted_files: u64,
+  // Logically removed, whether deleted or moved to the trash.
+  #[serde(rename = "deletedBytes")]
+  pub deleted_bytes: u64,
+  // Given back right away, and still held in the trash until it's emptied.
+  #[serde(rename = "freedBytes")]
+  pub freed_bytes: u64,
+  #[serde(rename = "trashedBytes")]
+  pub trashed_bytes: u64,
+  pub failures: Vec<PlanFailure>,
+}
+
+#[derive(Clone, Serialize)]
+pub struct VolumeDelta {
+  pub path: String,
+  #[serde(rename = "availableBefore")]
+  pub available_before: Option<u64>,
+  #[serde(rename = "availableAfter")]
+  pub available_after: Option<u64>,
+}
+
+#[derive(Clone, Serialize)]
+pub struct CleanupFlag {
+  pub path: String,
+  pub reason: String,
+}
+
+#[derive(Clone, Serialize)]
+pub struct CleanupReport {
+  #[serde(rename = "projectedBytes")]
+  pub projected_bytes: u64,
+  #[serde(rename = "actualFreedBytes")]
+  pub actual_freed_bytes: Option<i64>,
+  // Not in actualFreedBytes yet; freed when the trash is emptied.
+  #[serde(rename = "trashedBytes")]
+  pub trashed_bytes: u64,
+  pub volumes: Vec<VolumeDelta>,
+  pub flagged: Vec<CleanupFlag>,
+}
+
+// What execute_plan saw before deleting, re-checked afterwards to build the cleanup report.
+pub struct CleanupBaseline {
+  projected_bytes: u64,
+  trashed_bytes: u64,
+  deleted: Vec<(String, u64)>,
+  failed: Vec<String>,
+  volumes: Vec<(u64, String, Option<u64>)>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct PlanFileItem {
+  pub path: String,
+  pub size: u64,
+  pub sha256: String,
+  pub action: String,
+}
+
+// The on-disk format shared between accounts: an admin exports it, the user imports and approves it.
+#[derive(Serialize, Deserialize)]
+pub struct PlanFile {
+  pub version: u32,
+  #[serde(rename = "createdAt")]
+  pub created_at: u64,
+  pub items: Vec<PlanFileItem>,
+}
+
+impl PlanFile {
+  pub fn parse(bytes: &[u8]) -> Result<Self, Message> {
+    let plan_file: PlanFile =
+      serde_json::from_slice(bytes).map_err(|_| Message::new("plan.invalid_file"))?;
+    if plan_file.version != PLAN_FILE_VERSION {
+      return Err(Message::new("plan.unsupported_version"));
+    }
+    Ok(plan_file)
+  }
+}
+
+#[derive(Default)]
+pub struct CleanupPlan {
+  items: Vec<PlanItem>,
+}
+
+pub fn regular_file_size(path: &Path) -> Result<u64, Message> {
+  let metadata = fs::symlink_metadata(path).map_err(|_| Message::new("file.not_found"))?;
+  if !metadata.is_file() || metadata.file_type().is_symlink() {
+    return Err(Message::new("file.only_regular_delete"));
+  }
+  Ok(metadata.len())
+}
+
+impl CleanupPlan {
+  pub fn add(&mut self, paths: Vec<String>) -> Vec<PlanFailure> {
+    let mut rejected = Vec::new();
+    for path in paths {
+      if self.items.iter().any(|item| item.path == path) {
+        continue;
+      }
+      match regular_file_size(Path::new(&path)) {
+        Ok(size) => self.items.push(PlanItem {
+          path,
+          size,
+          action: "delete".to_string(),
+        }),
+        Err(error) => rejected.push(PlanFailure { path, error }),
+      }
+    }
+    rejected
+  }
+
+  pub fn remove(&mut self, paths: &[String]) {
+    self.items.retain(|item| !paths.contains(&item.path));
+  }
+
+  pub fn clear(&mut self) {
+    self.items.clear();
+  }
+
+  pub fn summary(&self) -> PlanSummary {
+    PlanSummary {
+      items: self.items.clone(),
+      total_bytes: self.items.iter().map(|item| item.size).sum(),
+      rejected: Vec::new(),
+    }
+  }
+
+  pub fn export(&self, file_path: &Path) -> Result<usize, Message> {
+    let items = self
+      .items
+      .iter()
+      .map(|item| {
+        Ok(PlanFileItem {
+          path: item.path.clone(),
+          size: item.size,
+          sha256: hashing::sha256_file(Path::new(&item.path))
+            .map_err(|error| error.with("path", &item.path))?,
+          action: item.action.clone(),
+        })
+      })
+      .collect::<Result<Vec<_>, Message>>()?;
+
+    let plan_file = PlanFile {
+      version: PLAN_FILE_VERSION,
+      created_at: SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or(0),
+      items,
+    };
+    let bytes =
+      serde_json::to_vec_pretty(&plan_file).map_err(|_| Message::new("plan.encode_failed"))?;
+    fs::write(file_path, bytes).map_err(|_| Message::new("plan.write_failed"))?;
+    Ok(plan_file.items.len())
+  }
+
+  // Only files that still match the exported size and hash are added.
+  pub fn import(&mut self, file_path: &Path) -> Result<Vec<PlanFailure>, Message> {
+    let metadata = fs::metadata(file_path).map_err(|_| Message::new("plan.read_failed"))?;
+    if metadata.len() > MAX_PLAN_FILE_BYTES {
+      return Err(Message::new("plan.file_too_large"));
+    }
+    let bytes = fs::read(file_path).map_err(|_| Message::new("plan.read_failed"))?;
+    let plan_file = PlanFile::parse(&bytes)?;
+
+    let mut known: HashSet<String> = self.items.iter().map(|item| item.path.clone()).collect();
+    let mut rejected = Vec::new();
+    for item in plan_file.items {
+      if !known.insert(item.path.clone()) {
+        continue;
+      }
+      let path = Path::new(&item.path);
+      let check = if item.action != "delete" {
+        Err(Message::new("app.unsupported_action"))
+      } else {
+        regular_file_size(path).and_then(|size| {
+          if size != item.size {
+            return Err(Message::new("plan.size_changed"));
+          }
+          if hashing::sha256_file(path)? != item.sha256 {
+            return Err(Message::new("plan.contents_changed"));
+          }
+          Ok(size)
+        })
+      };
+
+      match check {
+        Ok(size) => self.items.push(PlanItem {
+          path: item.path,
+          size,
+          action: item.action,
+        }),
+        Err(error) => rejected.push(PlanFailure {
+          path: item.path,
+          error,
+        }),
+      }
+    }
+    Ok(rejected)
+  }
+
+  // Every item is checked before anything is deleted, so a stale plan fails as a whole. Items on
+  // volumes set to "trash" are moved to the trash instead, and nothing runs while an item on a
+  // volume without a usable trash still needs `confirmed_permanent`.
+  pub fn execute(
+    &mut self,
+    settings: &Settings,
+    cache_dir: &Path,
+    confirmed_permanent: bool,
+  ) -> Result<(PlanExecution, CleanupBaseline), Message> {
+    if self.items.is_empty() {
+      return Err(Message::new("plan.empty"));
+    }
+    for item in &self.items {
+      regular_file_size(Path::new(&item.path)).map_err(|error| error.with("path", &item.path))?;
+      if !confirmed_permanent && trash::trash_unavailable(Path::new(&item.path), settings) {
+        return Err(Message::new(trash::PERMANENT_DELETE_UNCONFIRMED).with("path", &item.path));
+      }
+    }
+
+    let mut baseline = CleanupBaseline {
+      projected_bytes: self.items.iter().map(|item| item.size).sum(),
+      trashed_bytes: 0,
+      deleted: Vec::new(),
+      failed: Vec::new(),
+      volumes: Vec::new(),
+    };
+    let mut links = Vec::new();
+    for item in &self.items {
+      let path = Path::new(&item.path);
+      let (device, link_count) = file_identity(path);
+      links.push(link_count);
+      if !baseline
+        .volumes
+        .iter()
+        .any(|(known, _, _)| *known == device)
+      {
+        let dir = path.parent().unwrap_or(path);
+        baseline.volumes.push((
+          device,
+          dir.to_string_lossy().to_string(),
+          filesystem::available_bytes(dir),
+        ));
+      }
+    }
+
+    let mut execution = PlanExecution {
+      deleted_files: 0,
+      deleted_bytes: 0,
+      freed_bytes: 0,
+      trashed_bytes: 0,
+      failures: Vec::new(),
+    };
+    // Items that fail stay in the plan so they can be retried.
+    let mut remaining = Vec::new();
+    for (item, link_count) in self.items.drain(..).zip(links) {
+      match trash::remove(
+        Path::new(&item.path),
+        settings,
+        cache_dir,
+        confirmed_permanent,
+      ) {
+        Ok(removal) => {
+          execution.deleted_files += 1;
+          execution.deleted_bytes += item.size;
+          execution.freed_bytes += removal.freed_bytes;
+          execution.trashed_bytes += removal.trashed_bytes;
+          baseline.trashed_bytes += removal.trashed_bytes;
+          baseline.deleted.push((item.path, link_count));
+        }
+        Err(error) => {
+          baseline.failed.push(item.path.clone());
+          execution.failures.push(PlanFailure {
+            path: item.path.clone(),
+            error,
+          });
+          remaining.push(item);
+        }
+      }
+    }
+    self.items = remaining;
+    Ok((execution, baseline))
+  }
+}
+
+#[cfg(target_family = "unix")]
+fn file_identity(path: &Path) -> (u64, u64) {
+  fs::symlink_metadata(path)
+    .map(|metadata| (metadata.dev(), metadata.nlink()))
+    .unwrap_or((0, 1))
+}
+
+#[cfg(not(target_family = "unix"))]
+fn file_identity(_path: &Path) -> (u64, u64) {
+  (0, 1)
+}
+
+pub fn cleanup_report(baseline: CleanupBaseline) -> CleanupReport {
+  let mut flagged = Vec::new();
+  for (path, link_count) in baseline.deleted {
+    let reason = if fs::symlink_metadata(&path).is_ok() {
+      "still_exists"
+    } else if link_count > 1 {
+      "hard_linked"
+    } else {
+      continue;
+    };
+    flagged.push(CleanupFlag {
+      path,
+      reason: reason.to_string(),
+    });
+  }
+  for path in baseline.failed {
+    flagged.push(CleanupFlag {
+      path,
+      reason: "delete_failed".to_string(),
+    });
+  }
+
+  let volumes: Vec<VolumeDelta> = baseline
+    .volumes
+    .into_iter()
+    .map(|(_, path, available_before)| VolumeDelta {
+      available_after: filesystem::available_bytes(Path::new(&path)),
+      path,
+      available_before,
+    })
+    .collect();
+  let actual_freed_bytes = volumes
+    .iter()
+    .map(
+      |volume| match (volume.available_before, volume.available_after) {
+        (Some(before), Some(after)) => Some(after as i64 - before as i64),
+        _ => None,
+      },
+    )
+    .sum();
+
+  CleanupReport {
+    projected_bytes: baseline.projected_bytes,
+    actual_freed_bytes,
+    trashed_bytes: baseline.trashed_bytes,
+    volumes,
+    flagged,
+  }
+}
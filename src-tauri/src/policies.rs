@@ -0,0 +1,376 @@
+use crate::conflicts::{self, ConflictPolicy, Conflicts, Resolution};
+use crate::dataless::{self, NoMaterialize};
+use crate::messages::Message;
+use crate::plan::PlanFailure;
+use crate::watcher::OwnActivity;
+use crate::{audit, cache, filesystem, hashing, history, rules, settings, snapshots};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, SystemTime};
+use tauri::Manager;
+
+const POLICY_RUNS_FILE: &str = "policy_runs.json";
+const SCHEDULE_POLL_INTERVAL: Duration = Duration::from_secs(60);
+const SECONDS_PER_DAY: u64 = 24 * 60 * 60;
+const SECONDS_PER_HOUR: u64 = 60 * 60;
+
+// e.g. `{ "name": "Old footage", "source": "~/Footage", "olderThanDays": 365,
+// "destination": "/Volumes/Archive" }`. Files keep their path below the source folder's name,
+// so ~/Footage/2019/a.mov moves to /Volumes/Archive/Footage/2019/a.mov.
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+pub struct ArchivePolicy {
+  pub name: String,
+  pub source: String,
+  #[serde(rename = "olderThanDays")]
+  pub older_than_days: u64,
+  pub destination: String,
+  // Runs automatically every N hours while the app is open; manual only when unset.
+  #[serde(rename = "everyHours")]
+  pub every_hours: Option<u64>,
+  // "skip" (the default), "overwrite", "rename" or "ask" when a target already exists.
+  #[serde(rename = "onConflict")]
+  pub on_conflict: Option<String>,
+}
+
+#[derive(Clone, Serialize)]
+pub struct PolicyMove {
+  pub path: String,
+  pub target: String,
+  pub size: u64,
+  #[serde(rename = "modifiedAt")]
+  pub modified_at: u64,
+}
+
+#[derive(Serialize)]
+pub struct PolicyPreview {
+  pub name: String,
+  pub moves: Vec<PolicyMove>,
+  #[serde(rename = "totalBytes")]
+  pub total_bytes: u64,
+  // Targets that already exist, left to the policy's `onConflict` when it runs.
+  pub conflicts: Vec<PolicyMove>,
+  // Larger than the destination filesystem allows, e.g. over 4 GB on FAT32.
+  #[serde(rename = "tooLarge")]
+  pub too_large: Vec<PolicyMove>,
+}
+
+#[derive(Clone, Serialize)]
+pub struct PolicyRun {
+  pub name: String,
+  #[serde(rename = "movedFiles")]
+  pub moved_files: u64,
+  #[serde(rename = "movedBytes")]
+  pub moved_bytes: u64,
+  pub skipped: Vec<String>,
+  pub failures: Vec<PlanFailure>,
+  pub scheduled: bool,
+}
+
+impl ArchivePolicy {
+  fn source_dir(&self) -> PathBuf {
+    PathBuf::from(rules::expand_home(&self.source))
+  }
+
+  fn destination_dir(&self) -> PathBuf {
+    let destination = PathBuf::from(rules::expand_home(&self.destination));
+    match self.source_dir().file_name() {
+      Some(name) => destination.join(name),
+      None => destination,
+    }
+  }
+}
+
+pub fn validate(policies: &[ArchivePolicy]) -> Result<(), Message> {
+  for (index, policy) in policies.iter().enumerate() {
+    if policy.name.trim().is_empty()
+      || policy.source.trim().is_empty()
+      || policy.destination.trim().is_empty()
+    {
+      return Err(Message::new("policy.incomplete"));
+    }
+    if policies[..index]
+      .iter()
+      .any(|other| other.name == policy.name)
+    {
+      return Err(Message::new("policy.duplicate_name").with("name", &policy.name));
+    }
+    if policy.every_hours == Some(0) {
+      return Err(Message::new("policy.schedule_too_frequent"));
+    }
+    ConflictPolicy::parse(policy.on_conflict.as_deref())?;
+    let (source, destination) = (policy.source_dir(), policy.destination_dir());
+    if destination.starts_with(&source) || source.starts_with(&destination) {
+      return Err(Message::new("policy.destination_inside_source"));
+    }
+  }
+  Ok(())
+}
+
+pub fn find<'a>(policies: &'a [ArchivePolicy], name: &str) -> Result<&'a ArchivePolicy, Message> {
+  policies
+    .iter()
+    .find(|policy| policy.name == name)
+    .ok_or_else(|| Message::new("policy.unknown"))
+}
+
+fn modified_secs(metadata: &fs::Metadata) -> Option<u64> {
+  metadata
+    .modified()
+    .ok()?
+    .duration_since(SystemTime::UNIX_EPOCH)
+    .ok()
+    .map(|duration| duration.as_secs())
+}
+
+// Exactly what `run` would move right now. Evicted cloud files are left alone: they take no
+// local space and moving them would download them.
+pub fn preview(policy: &ArchivePolicy) -> Result<PolicyPreview, Message> {
+  let _no_materialize = NoMaterialize::enter();
+  let source = policy.source_dir();
+  if !source.is_dir() {
+    return Err(Message::new("path.not_folder").with("path", source.display()));
+  }
+  let destination = policy.destination_dir();
+  let max_file_bytes = destination
+    .ancestors()
+    .find(|dir| dir.exists())
+    .and_then(filesystem::filesystem_info)
+    .and_then(|info| info.max_file_bytes);
+  let cutoff = history::now_secs().saturating_sub(policy.older_than_days * SECONDS_PER_DAY);
+
+  let mut preview = PolicyPreview {
+    name: policy.name.clone(),
+    moves: Vec::new(),
+    total_bytes: 0,
+    conflicts: Vec::new(),
+    too_large: Vec::new(),
+  };
+  let mut dirs = vec![source.clone()];
+  while let Some(dir) = dirs.pop() {
+    let entries = match fs::read_dir(&dir) {
+      Ok(entries) => entries,
+      Err(_) => continue,
+    };
+    for entry in entries.flatten() {
+      let file_type = match entry.file_type() {
+        Ok(file_type) => file_type,
+        Err(_) => continue,
+      };
+      let path = entry.path();
+      if file_type.is_symlink() {
+        continue;
+      }
+      if file_type.is_dir() {
+        if !snapshots::is_snapshot_dir(&entry) {
+          dirs.push(path);
+        }
+        continue;
+      }
+      let metadata = match entry.metadata() {
+        Ok(metadata) if metadata.is_file() && !dataless::is_dataless(&metadata) => metadata,
+        _ => continue,
+      };
+      let modified_at = match modified_secs(&metadata).filter(|modified| *modified <= cutoff) {
+        Some(modified_at) => modified_at,
+        None => continue,
+      };
+      let relative = match path.strip_prefix(&source) {
+        Ok(relative) => relative,
+        Err(_) => continue,
+      };
+      let target = destination.join(relative);
+      let item = PolicyMove {
+        path: path.to_string_lossy().to_string(),
+        target: target.to_string_lossy().to_string(),
+        size: metadata.len(),
+        modified_at,
+      };
+      if max_file_bytes.is_some_and(|max| item.size > max) {
+        preview.too_large.push(item);
+      } else if fs::symlink_metadata(&target).is_ok() {
+        preview.conflicts.push(item);
+      } else {
+        preview.total_bytes += item.size;
+        preview.moves.push(item);
+      }
+    }
+  }
+  preview.moves.sort_by(|a, b| a.path.cmp(&b.path));
+  Ok(preview)
+}
+
+// Copies `source` to `target` with its modification time, keeping the copy only if it hashes
+// the same.
+pub fn copy_verified(source: &Path, target: &Path) -> Result<(), Message> {
+  fs::copy(source, target).map_err(|_| {
+    let _ = fs::remove_file(target);
+    Message::new("file.copy_failed")
+  })?;
+  let matches = match (hashing::sha256_file(source), hashing::sha256_file(target)) {
+    (Ok(source_hash), Ok(target_hash)) => source_hash == target_hash,
+    _ => false,
+  };
+  if !matches {
+    let _ = fs::remove_file(target);
+    return Err(Message::new("copy.verification_failed"));
+  }
+  if let Ok(modified) = fs::metadata(source).and_then(|metadata| metadata.modified()) {
+    if let Ok(file) = fs::File::options().write(true).open(target) {
+      let _ = file.set_modified(modified);
+    }
+  }
+  Ok(())
+}
+
+// Renames within a volume; across volumes the file is copied, verified by hash, and only then
+// removed from the source. Returns false when the conflict policy skipped it.
+fn move_file(source: &Path, target: &Path, conflicts: &mut Conflicts) -> Result<bool, Message> {
+  if let Some(parent) = target.parent() {
+    fs::create_dir_all(parent).map_err(|_| Message::new("copy.create_folder_failed"))?;
+  }
+  let placement = match conflicts.resolve(source, target)? {
+    Some(placement) => placement,
+    None => return Ok(false),
+  };
+  if fs::rename(source, &placement.path).is_ok() {
+    return Ok(true);
+  }
+
+  conflicts::write_then_place(&placement, |written| copy_verified(source, written))?;
+  fs::remove_file(source)
+    .map(|()| true)
+    .map_err(|_| Message::new("copy.remove_original_failed"))
+}
+
+pub fn run(
+  policy: &ArchivePolicy,
+  own_activity: &OwnActivity,
+  scheduled: bool,
+  app: Option<&tauri::AppHandle>,
+) -> PolicyRun {
+  let mut run = PolicyRun {
+    name: policy.name.clone(),
+    moved_files: 0,
+    moved_bytes: 0,
+    skipped: Vec::new(),
+    failures: Vec::new(),
+    scheduled,
+  };
+  let preview = match preview(policy) {
+    Ok(preview) => preview,
+    Err(error) => {
+      run.failures.push(PlanFailure {
+        path: policy.source.clone(),
+        error,
+      });
+      return run;
+    }
+  };
+
+  for item in preview.too_large {
+    run.failures.push(PlanFailure {
+      path: item.path,
+      error: Message::new("copy.too_large"),
+    });
+  }
+  // Validated with the settings, so only a hand-edited file gets here with a bad policy.
+  let policy_conflicts = ConflictPolicy::parse(policy.on_conflict.as_deref())
+    .unwrap_or(ConflictPolicy::Always(Resolution::Skip));
+  let mut conflicts = Conflicts::new(policy_conflicts, "archive_policy", app);
+  let app_dir = app.and_then(|app| cache::app_dir(app).ok());
+  for item in preview.moves.into_iter().chain(preview.conflicts) {
+    let source = Path::new(&item.path);
+    let target = Path::new(&item.target);
+    own_activity.record(source);
+    let moved = move_file(source, target, &mut conflicts);
+    if let (Some(app_dir), Ok(true) | Err(_)) = (&app_dir, &moved) {
+      let result = moved.as_ref().map(|_| ());
+      audit::record(app_dir, "move", source, item.size, Some(target), result);
+    }
+    match moved {
+      Ok(true) => {
+        run.moved_files += 1;
+        run.moved_bytes += item.size;
+      }
+      Ok(false) => run.skipped.push(item.path),
+      Err(error) => run.failures.push(PlanFailure {
+        path: item.path,
+        error,
+      }),
+    }
+  }
+  run
+}
+
+// Checks the scheduled policies once a minute; the last run of each is kept in
+// policy_runs.json so schedules survive restarts. Dropping the scheduler stops it.
+pub struct PolicyScheduler {
+  policies: Vec<ArchivePolicy>,
+  stop: Arc<AtomicBool>,
+}
+
+impl PolicyScheduler {
+  pub fn policies(&self) -> &[ArchivePolicy] {
+    &self.policies
+  }
+}
+
+impl Drop for PolicyScheduler {
+  fn drop(&mut self) {
+    self.stop.store(true, Ordering::Relaxed);
+  }
+}
+
+pub fn start_scheduler(app: tauri::AppHandle, policies: Vec<ArchivePolicy>) -> PolicyScheduler {
+  let stop = Arc::new(AtomicBool::new(false));
+  let scheduler = PolicyScheduler {
+    policies: policies.clone(),
+    stop: stop.clone(),
+  };
+
+  thread::spawn(move || {
+    let mut waited = SCHEDULE_POLL_INTERVAL;
+    while !stop.load(Ordering::Relaxed) {
+      if waited < SCHEDULE_POLL_INTERVAL {
+        thread::sleep(Duration::from_secs(1));
+        waited += Duration::from_secs(1);
+        continue;
+      }
+      waited = Duration::ZERO;
+
+      let app_dir = match cache::app_dir(&app) {
+        Ok(app_dir) => app_dir,
+        Err(_) => continue,
+      };
+      if settings::load(&app_dir).audit_mode {
+        continue;
+      }
+      let runs_file = app_dir.join(POLICY_RUNS_FILE);
+      let mut last_runs: HashMap<String, u64> = cache::read_json(&runs_file);
+      for policy in &policies {
+        let every = match policy.every_hours {
+          Some(every) => every * SECONDS_PER_HOUR,
+          None => continue,
+        };
+        let now = history::now_secs();
+        let due = last_runs
+          .get(&policy.name)
+          .filter(|last| now.saturating_sub(**last) < every)
+          .is_none();
+        if !due || stop.load(Ordering::Relaxed) {
+          continue;
+        }
+        let result = run(policy, &app.state::<OwnActivity>(), true, Some(&app));
+        last_runs.insert(policy.name.clone(), now);
+        let _ = cache::write_json(&runs_file, &last_runs);
+        let _ = app.emit_to("main", "archive_policy_complete", result);
+      }
+    }
+  });
+  scheduler
+}
@@ -0,0 +1,109 @@
+use serde::Serialize;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+use std::time::Instant;
+
+#[derive(Clone, Copy, Default)]
+pub struct DirCalls {
+  pub read_dir: u64,
+  pub metadata: u64,
+  pub entries: u64,
+}
+
+struct DirRecord {
+  path: String,
+  start_us: u64,
+  duration_us: u64,
+  calls: DirCalls,
+}
+
+#[derive(Serialize)]
+struct TraceArgs {
+  #[serde(rename = "readDirCalls")]
+  read_dir_calls: u64,
+  #[serde(rename = "metadataCalls")]
+  metadata_calls: u64,
+  entries: u64,
+}
+
+#[derive(Serialize)]
+struct TraceEvent<'a> {
+  name: &'a str,
+  cat: &'static str,
+  ph: &'static str,
+  ts: u64,
+  dur: u64,
+  pid: u32,
+  tid: u32,
+  args: TraceArgs,
+}
+
+#[derive(Serialize)]
+struct Trace<'a> {
+  #[serde(rename = "traceEvents")]
+  trace_events: Vec<TraceEvent<'a>>,
+  #[serde(rename = "displayTimeUnit")]
+  display_time_unit: &'static str,
+}
+
+pub struct ScanProfiler {
+  started: Instant,
+  records: Vec<DirRecord>,
+}
+
+impl Default for ScanProfiler {
+  fn default() -> Self {
+    Self {
+      started: Instant::now(),
+      records: Vec::new(),
+    }
+  }
+}
+
+impl ScanProfiler {
+  pub fn record_dir(&mut self, dir: &Path, dir_started: Instant, calls: DirCalls) {
+    let start_us = dir_started.duration_since(self.started).as_micros() as u64;
+    let duration_us = dir_started.elapsed().as_micros() as u64;
+    self.records.push(DirRecord {
+      path: dir.to_string_lossy().to_string(),
+      start_us,
+      duration_us,
+      calls,
+    });
+  }
+
+  pub fn directories(&self) -> usize {
+    self.records.len()
+  }
+
+  pub fn write_chrome_trace(&self, path: &Path) -> io::Result<()> {
+    let trace_events = self
+      .records
+      .iter()
+      .map(|record| TraceEvent {
+        name: &record.path,
+        cat: "read_dir",
+        ph: "X",
+        ts: record.start_us,
+        dur: record.duration_us,
+        pid: 1,
+        tid: 1,
+        args: TraceArgs {
+          read_dir_calls: record.calls.read_dir,
+          metadata_calls: record.calls.metadata,
+          entries: record.calls.entries,
+        },
+      })
+      .collect();
+
+    let trace = Trace {
+      trace_events,
+      display_time_unit: "ms",
+    };
+
+    let mut writer = BufWriter::new(File::create(path)?);
+    serde_json::to_writer(&mut writer, &trace)?;
+    writer.flush()
+  }
+}
@@ -0,0 +1,209 @@
+use crate::messages::Message;
+use crate::scanner::StreamedScan;
+use serde::Deserialize;
+use std::collections::HashSet;
+use std::io::{BufReader, Read};
+use std::process::{Command, Stdio};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+
+// Limits for what a malformed or hostile export can make the reader hold in memory.
+const MAX_ENTRY_BYTES: usize = 64 * 1024;
+const MAX_DEPTH: usize = 1024;
+const MAX_PATH_BYTES: usize = 64 * 1024;
+const MAX_STDERR_BYTES: u64 = 64 * 1024;
+
+#[derive(Deserialize)]
+struct NcduEntry {
+  name: String,
+  asize: Option<u64>,
+  dsize: Option<u64>,
+  ino: Option<u64>,
+  #[serde(default)]
+  hlnkc: bool,
+  #[serde(default)]
+  notreg: bool,
+}
+
+// ncdu's export is one nested array: `[1, 2, {meta}, [{root}, {file}, [{dir}, ...], ...]]`.
+// Every array below the top level is a directory whose first element describes it, so the
+// stream can be consumed entry by entry without holding the whole tree.
+#[derive(Default)]
+pub struct NcduReader {
+  depth: usize,
+  in_string: bool,
+  escaped: bool,
+  object: Option<Vec<u8>>,
+  expecting_dir: bool,
+  dirs: Vec<String>,
+  hard_links: HashSet<u64>,
+}
+
+impl NcduReader {
+  pub fn feed(&mut self, byte: u8, mut on_file: impl FnMut(String, u64)) -> Result<(), Message> {
+    if let Some(object) = self.object.as_mut() {
+      if object.len() >= MAX_ENTRY_BYTES {
+        return Err(Message::new("scan.ncdu_entry_too_large"));
+      }
+      object.push(byte);
+    }
+
+    if self.in_string {
+      if self.escaped {
+        self.escaped = false;
+      } else if byte == b'\\' {
+        self.escaped = true;
+      } else if byte == b'"' {
+        self.in_string = false;
+      }
+      return Ok(());
+    }
+
+    match byte {
+      b'"' => self.in_string = true,
+      b'[' => {
+        if self.depth >= MAX_DEPTH {
+          return Err(Message::new("scan.ncdu_too_deep"));
+        }
+        self.depth += 1;
+        self.expecting_dir = self.depth >= 2;
+      }
+      b']' => {
+        if self.depth >= 2 {
+          self.dirs.pop();
+        }
+        self.depth = self.depth.saturating_sub(1);
+      }
+      b'{' if self.object.is_none() => self.object = Some(vec![byte]),
+      b'}' => {
+        if let Some(object) = self.object.take() {
+          if self.depth >= 2 {
+            self.entry(&object, &mut on_file)?;
+          }
+        }
+      }
+      _ => {}
+    }
+    Ok(())
+  }
+
+  fn entry(&mut self, object: &[u8], on_file: &mut impl FnMut(String, u64)) -> Result<(), Message> {
+    let entry: NcduEntry =
+      serde_json::from_slice(object).map_err(|_| Message::new("scan.invalid_ncdu_export"))?;
+    let path = match self.dirs.last() {
+      Some(parent) => format!("{}/{}", parent.trim_end_matches('/'), entry.name),
+      None => entry.name,
+    };
+    if path.len() > MAX_PATH_BYTES {
+      return Err(Message::new("scan.ncdu_path_too_long"));
+    }
+
+    if self.expecting_dir {
+      self.expecting_dir = false;
+      self.dirs.push(path);
+      return Ok(());
+    }
+    if entry.notreg {
+      return Ok(());
+    }
+    if entry.hlnkc {
+      if let Some(ino) = entry.ino {
+        if !self.hard_links.insert(ino) {
+          return Ok(());
+        }
+      }
+    }
+    on_file(path, entry.dsize.or(entry.asize).unwrap_or(0));
+    Ok(())
+  }
+}
+
+fn shell_quote(value: &str) -> String {
+  format!("'{}'", value.replace('\'', "'\\''"))
+}
+
+pub fn validate_host(host: &str) -> Result<(), Message> {
+  if host.is_empty() || host.starts_with('-') || host.chars().any(char::is_whitespace) {
+    return Err(Message::new("remote.invalid_host"));
+  }
+  Ok(())
+}
+
+// Runs `ncdu -o -` on the host over SSH and streams its export into `scan`, with paths
+// reported as `host:/path`.
+// Returns false if the scan was cancelled.
+pub fn scan_over_ssh(
+  host: &str,
+  remote_path: &str,
+  scan: &mut StreamedScan,
+  cancel: &AtomicBool,
+) -> Result<bool, Message> {
+  let mut child = Command::new("ssh")
+    .arg("-o")
+    .arg("BatchMode=yes")
+    .arg(host)
+    .arg("--")
+    .arg(format!("ncdu -0 -x -o - {}", shell_quote(remote_path)))
+    .stdin(Stdio::null())
+    .stdout(Stdio::piped())
+    .stderr(Stdio::piped())
+    .spawn()
+    .map_err(|_| Message::new("remote.ssh_failed"))?;
+
+  let stdout = child
+    .stdout
+    .take()
+    .ok_or_else(|| Message::new("remote.ssh_output_failed"))?;
+  let stderr = child.stderr.take().map(|pipe| {
+    thread::spawn(move || {
+      let mut output = String::new();
+      let _ = pipe.take(MAX_STDERR_BYTES).read_to_string(&mut output);
+      output
+    })
+  });
+  let mut reader = NcduReader::default();
+  let mut files = 0u64;
+  let mut parse_error = None;
+  for byte in BufReader::new(stdout).bytes() {
+    if cancel.load(Ordering::Relaxed) {
+      let _ = child.kill();
+      let _ = child.wait();
+      return Ok(false);
+    }
+    let byte = match byte {
+      Ok(byte) => byte,
+      Err(_) => break,
+    };
+    let fed = reader.feed(byte, |path, size| {
+      files += 1;
+      scan.add_file(format!("{}:{}", host, path), size);
+    });
+    if let Err(error) = fed {
+      parse_error = Some(error);
+      let _ = child.kill();
+      break;
+    }
+  }
+
+  let stderr = stderr
+    .and_then(|reader| reader.join().ok())
+    .unwrap_or_default();
+  let status = child
+    .wait()
+    .map_err(|_| Message::new("remote.ssh_failed"))?;
+  if let Some(error) = parse_error {
+    return Err(error);
+  }
+  if !status.success() && files == 0 {
+    return Err(
+      stderr
+        .lines()
+        .find(|line| !line.trim().is_empty())
+        .map_or_else(
+          || Message::new("remote.scan_failed"),
+          |line| Message::unexpected(line.trim()),
+        ),
+    );
+  }
+  Ok(true)
+}
@@ -0,0 +1,132 @@
+use serde::{Deserialize, Serialize};
+use std::env;
+use std::fs;
+use std::path::Path;
+use std::time::{Duration, SystemTime};
+
+const SECONDS_PER_DAY: u64 = 24 * 60 * 60;
+
+// e.g. `{ "glob": "~/Renders/**", "olderThanDays": 90, "category": "safe to archive" }`.
+// A glob without a `/` matches the file name anywhere; `**` crosses folders, `*` does not.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Rule {
+  pub glob: String,
+  #[serde(rename = "olderThanDays")]
+  pub older_than_days: Option<u64>,
+  #[serde(rename = "minBytes")]
+  pub min_bytes: Option<u64>,
+  pub category: String,
+  pub action: Option<String>,
+}
+
+#[derive(Clone, Serialize)]
+pub struct Classification {
+  pub category: String,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub action: Option<String>,
+}
+
+// Rules with `~` expanded; the first matching rule wins.
+#[derive(Default)]
+pub struct RuleSet {
+  rules: Vec<(String, Rule)>,
+}
+
+pub fn expand_home(glob: &str) -> String {
+  match (glob.strip_prefix("~/"), env::var("HOME")) {
+    (Some(rest), Ok(home)) => format!("{}/{}", home.trim_end_matches('/'), rest),
+    _ => glob.to_string(),
+  }
+}
+
+fn glob_match(pattern: &[u8], text: &[u8]) -> bool {
+  match pattern.first() {
+    None => text.is_empty(),
+    Some(b'*') if pattern.get(1) == Some(&b'*') => {
+      let rest = pattern[2..].strip_prefix(b"/").unwrap_or(&pattern[2..]);
+      (0..=text.len()).any(|skip| glob_match(rest, &text[skip..]))
+    }
+    Some(b'*') => (0..=text.len())
+      .take_while(|&skip| skip == 0 || text[skip - 1] != b'/')
+      .any(|skip| glob_match(&pattern[1..], &text[skip..])),
+    Some(b'?') => !text.is_empty() && text[0] != b'/' && glob_match(&pattern[1..], &text[1..]),
+    Some(&byte) => text.first() == Some(&byte) && glob_match(&pattern[1..], &text[1..]),
+  }
+}
+
+impl RuleSet {
+  pub fn new(rules: &[Rule]) -> Self {
+    Self {
+      rules: rules
+        .iter()
+        .map(|rule| (expand_home(&rule.glob), rule.clone()))
+        .collect(),
+    }
+  }
+
+  pub fn classify(&self, path: &str, size: u64) -> Option<Classification> {
+    let name = Path::new(path)
+      .file_name()
+      .map(|name| name.to_string_lossy().to_string())
+      .unwrap_or_default();
+
+    self
+      .rules
+      .iter()
+      .find(|(glob, rule)| {
+        let text = if glob.contains('/') { path } else { &name };
+        glob_match(glob.as_bytes(), text.as_bytes())
+          && rule.min_bytes.filter(|min| size < *min).is_none()
+          && rule
+            .older_than_days
+            .filter(|days| !modified_before(path, *days))
+            .is_none()
+      })
+      .map(|(_, rule)| Classification {
+        category: rule.category.clone(),
+        action: rule.action.clone(),
+      })
+  }
+}
+
+// Include-only filter for a scan, e.g. `*.mp4,*.mov,*.mkv`: globs matched case-insensitively
+// against file names. Folders are always walked.
+#[derive(Default)]
+pub struct NameFilter {
+  globs: Vec<String>,
+}
+
+impl NameFilter {
+  // Each pattern may itself be a comma-separated list.
+  pub fn new(patterns: &[String]) -> Self {
+    Self {
+      globs: patterns
+        .iter()
+        .flat_map(|pattern| pattern.split(','))
+        .map(|glob| glob.trim().to_lowercase())
+        .filter(|glob| !glob.is_empty())
+        .collect(),
+    }
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.globs.is_empty()
+  }
+
+  pub fn matches(&self, name: &str) -> bool {
+    let name = name.to_lowercase();
+    self
+      .globs
+      .iter()
+      .any(|glob| glob_match(glob.as_bytes(), name.as_bytes()))
+  }
+}
+
+fn modified_before(path: &str, days: u64) -> bool {
+  fs::symlink_metadata(path)
+    .and_then(|metadata| metadata.modified())
+    .ok()
+    .and_then(|modified| SystemTime::now().duration_since(modified).ok())
+    .map(|age| age >= Duration::from_secs(days * SECONDS_PER_DAY))
+    .unwrap_or(false)
+}
@@ -0,0 +1,162 @@
+use crate::dataless;
+use crate::disk_image;
+use crate::filesystem;
+use crate::messages::Message;
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
+use std::fs::{self, Metadata};
+#[cfg(target_family = "unix")]
+use std::os::unix::fs::MetadataExt;
+use std::path::{Path, PathBuf};
+
+#[derive(Serialize)]
+pub struct SavingsSimulation {
+  pub files: u64,
+  #[serde(rename = "selectedBytes")]
+  pub selected_bytes: u64,
+  #[serde(rename = "freedBytes")]
+  pub freed_bytes: u64,
+  // Still referenced by hard links outside the selection, or by APFS clones.
+  #[serde(rename = "sharedBytes")]
+  pub shared_bytes: u64,
+  // Cloud files whose contents are already evicted; deleting them frees nothing locally.
+  #[serde(rename = "purgeableBytes")]
+  pub purgeable_bytes: u64,
+  #[serde(rename = "totalBytes")]
+  pub total_bytes: u64,
+  #[serde(rename = "usedBytes")]
+  pub used_bytes: u64,
+  #[serde(rename = "usedPercent")]
+  pub used_percent: f64,
+  #[serde(rename = "projectedUsedBytes")]
+  pub projected_used_bytes: u64,
+  #[serde(rename = "projectedUsedPercent")]
+  pub projected_used_percent: f64,
+}
+
+struct SelectedFile {
+  path: PathBuf,
+  metadata: Metadata,
+  selected_links: u64,
+}
+
+#[derive(PartialEq, Eq, Hash)]
+enum FileKey {
+  #[cfg(target_family = "unix")]
+  Inode(u64, u64),
+  Path(PathBuf),
+}
+
+// FAT and exFAT have no hard links and synthesize inode numbers (empty files can share one),
+// so files there are told apart by path.
+#[cfg(target_family = "unix")]
+fn file_key(path: &Path, metadata: &Metadata, hard_links: bool) -> FileKey {
+  if hard_links {
+    FileKey::Inode(metadata.dev(), metadata.ino())
+  } else {
+    FileKey::Path(path.to_path_buf())
+  }
+}
+
+#[cfg(not(target_family = "unix"))]
+fn file_key(path: &Path, _metadata: &Metadata, _hard_links: bool) -> FileKey {
+  FileKey::Path(path.to_path_buf())
+}
+
+#[cfg(target_family = "unix")]
+fn link_count(metadata: &Metadata, hard_links: bool) -> u64 {
+  if hard_links {
+    metadata.nlink()
+  } else {
+    1
+  }
+}
+
+#[cfg(not(target_family = "unix"))]
+fn link_count(_metadata: &Metadata, _hard_links: bool) -> u64 {
+  1
+}
+
+fn percent(part: u64, total: u64) -> f64 {
+  if total > 0 {
+    (part as f64 / total as f64) * 100.0
+  } else {
+    0.0
+  }
+}
+
+// Expands folders, and counts each inode once together with how many of its links were selected.
+fn collect(selections: &[PathBuf], hard_links: bool) -> Vec<SelectedFile> {
+  let mut seen_paths: HashSet<PathBuf> = HashSet::new();
+  let mut files: HashMap<FileKey, SelectedFile> = HashMap::new();
+  let mut pending: Vec<PathBuf> = selections.to_vec();
+
+  while let Some(path) = pending.pop() {
+    let metadata = match fs::symlink_metadata(&path) {
+      Ok(metadata) => metadata,
+      Err(_) => continue,
+    };
+    if metadata.is_dir() {
+      if let Ok(entries) = fs::read_dir(&path) {
+        pending.extend(entries.flatten().map(|entry| entry.path()));
+      }
+      continue;
+    }
+    if !metadata.is_file() || !seen_paths.insert(path.clone()) {
+      continue;
+    }
+
+    files
+      .entry(file_key(&path, &metadata, hard_links))
+      .or_insert_with(|| SelectedFile {
+        path,
+        metadata,
+        selected_links: 0,
+      })
+      .selected_links += 1;
+  }
+
+  files.into_values().collect()
+}
+
+pub fn simulate(root: &Path, selections: &[PathBuf]) -> Result<SavingsSimulation, Message> {
+  let (total_bytes, available) =
+    filesystem::volume_space(root).ok_or_else(|| Message::new("disk.usage_unreadable"))?;
+  let used_bytes = total_bytes.saturating_sub(available);
+
+  let mut simulation = SavingsSimulation {
+    files: 0,
+    selected_bytes: 0,
+    freed_bytes: 0,
+    shared_bytes: 0,
+    purgeable_bytes: 0,
+    total_bytes,
+    used_bytes,
+    used_percent: percent(used_bytes, total_bytes),
+    projected_used_bytes: used_bytes,
+    projected_used_percent: 0.0,
+  };
+
+  let hard_links = !filesystem::is_fat(root);
+  for file in collect(selections, hard_links) {
+    let allocated = disk_image::allocated_size(&file.metadata);
+    simulation.files += 1;
+    simulation.selected_bytes += file.metadata.len();
+
+    if dataless::is_dataless(&file.metadata) {
+      simulation.purgeable_bytes += file.metadata.len();
+    } else if file.selected_links < link_count(&file.metadata, hard_links) {
+      simulation.shared_bytes += allocated;
+    } else {
+      let private = filesystem::private_size(&file.path)
+        .unwrap_or(allocated)
+        .min(allocated);
+      simulation.freed_bytes += private;
+      simulation.shared_bytes += allocated - private;
+    }
+  }
+
+  simulation.projected_used_bytes = used_bytes.saturating_sub(simulation.freed_bytes);
+  simulation.projected_used_percent = percent(simulation.projected_used_bytes, total_bytes);
+  Ok(simulation)
+}
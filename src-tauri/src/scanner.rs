@@ -1,24 +1,144 @@
-use serde::Serialize;
+use crate::cache;
+use crate::dataless::{self, NoMaterialize};
+use crate::dircache::{DirCache, IncrementalStats};
+use crate::disk_image;
+use crate::filesystem;
+use crate::journal::ScanJournal;
+use crate::listing::{self, Listed, ListingPool, StopOnDrop};
+use crate::messages::{self, Message};
+use crate::profiler::{DirCalls, ScanProfiler};
+use crate::rules::{Classification, NameFilter, RuleSet};
+use crate::snapshots;
+use crate::tree::{self, TreeNode};
+use crate::volumes::{self, SkippedMount};
+use serde::{Deserialize, Serialize};
 use std::cmp::Reverse;
-use std::collections::{BinaryHeap, VecDeque};
+use std::collections::{BinaryHeap, HashMap, HashSet, VecDeque};
 use std::fs;
-use std::path::PathBuf;
+use std::mem;
+#[cfg(target_family = "unix")]
+use std::os::unix::fs::MetadataExt;
+use std::path::{Path, PathBuf};
 use std::sync::{
-  atomic::{AtomicBool, Ordering},
+  atomic::{AtomicBool, AtomicU64, Ordering},
   Arc,
 };
+use std::thread;
 use std::time::{Duration, Instant};
 use tauri::{AppHandle, Manager};
 
 const EMIT_INTERVAL: Duration = Duration::from_millis(200);
+// How often a paused scan checks whether it was resumed or cancelled.
+const PAUSE_POLL_INTERVAL: Duration = Duration::from_millis(100);
+// How long `resync` waits for a running scan to report its state.
+const RESYNC_TIMEOUT: Duration = Duration::from_secs(2);
+// Every this many progress updates is a full `scan_progress` snapshot; the rest are deltas.
+const SNAPSHOT_EVERY: u64 = 50;
 pub const DEFAULT_TOP_N: usize = 50;
+// Files kept beyond the top N of a scan, to refill the list as entries are deleted.
+pub const CANDIDATE_POOL: usize = 1000;
+const MAX_CASE_CONFLICTS: usize = 200;
+// Folders down to this many levels below the root get size totals, for comparing scans.
+const TOTALS_DEPTH: usize = 4;
+// Without an estimate of the scan's size, a milestone every this many bytes.
+const MILESTONE_BYTES: u64 = 10 * 1024 * 1024 * 1024;
+pub const MAX_THREADS: usize = 64;
+// With a minimum file size, the summary lists this many of the folders with the most small files.
+const SMALL_FILE_FOLDERS: usize = 100;
 
-type HeapEntry = (u64, String);
+type HeapEntry = (u64, String, Option<u64>);
+
+// A numeric ID for the entry at `path`, the same in every payload and scan that reports it, so
+// the frontend can key entries by it instead of by path. Kept to 53 bits to fit a JS number.
+pub fn entry_id(path: &str) -> u64 {
+  cache::fnv1a(path) & ((1 << 53) - 1)
+}
 
 #[derive(Clone, Serialize)]
 pub struct FileEntry {
+  // See `entry_id`.
+  pub id: u64,
   pub path: String,
   pub size: u64,
+  #[serde(rename = "logicalSize", skip_serializing_if = "Option::is_none")]
+  pub logical_size: Option<u64>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub classification: Option<Classification>,
+  // Set once the watcher saw the file go; the entry stays in the retained result as a tombstone.
+  #[serde(rename = "removedAt", skip_serializing_if = "Option::is_none")]
+  pub removed_at: Option<u64>,
+}
+
+#[derive(Clone, Copy, Default, Serialize)]
+pub struct MemoryStats {
+  #[serde(rename = "retainedEntries")]
+  pub retained_entries: u64,
+  #[serde(rename = "pathBytes")]
+  pub path_bytes: u64,
+  #[serde(rename = "approxBytes")]
+  pub approx_bytes: u64,
+  // Entries listing threads have read ahead of the walk.
+  #[serde(rename = "readAheadEntries")]
+  pub read_ahead_entries: u64,
+}
+
+#[derive(Default)]
+pub struct MemoryCounters {
+  retained_entries: AtomicU64,
+  path_bytes: AtomicU64,
+  approx_bytes: AtomicU64,
+  read_ahead_entries: AtomicU64,
+}
+
+impl MemoryCounters {
+  pub fn snapshot(&self) -> MemoryStats {
+    MemoryStats {
+      retained_entries: self.retained_entries.load(Ordering::Relaxed),
+      path_bytes: self.path_bytes.load(Ordering::Relaxed),
+      approx_bytes: self.approx_bytes.load(Ordering::Relaxed),
+      read_ahead_entries: self.read_ahead_entries.load(Ordering::Relaxed),
+    }
+  }
+
+  fn store(&self, stats: &MemoryStats) {
+    self
+      .retained_entries
+      .store(stats.retained_entries, Ordering::Relaxed);
+    self.path_bytes.store(stats.path_bytes, Ordering::Relaxed);
+    self
+      .approx_bytes
+      .store(stats.approx_bytes, Ordering::Relaxed);
+    self
+      .read_ahead_entries
+      .store(stats.read_ahead_entries, Ordering::Relaxed);
+  }
+}
+
+#[derive(Default)]
+struct MemoryTracker {
+  queued_dirs: u64,
+  queued_path_bytes: u64,
+  heap_path_bytes: u64,
+  read_ahead_entries: u64,
+}
+
+impl MemoryTracker {
+  fn stats(&self, heap_len: usize) -> MemoryStats {
+    let heap_entries = heap_len as u64;
+    let retained_entries = self.queued_dirs + heap_entries;
+    let path_bytes = self.queued_path_bytes + self.heap_path_bytes;
+    let approx_bytes = path_bytes
+      + self.queued_dirs * mem::size_of::<PathBuf>() as u64
+      + heap_entries * mem::size_of::<Reverse<HeapEntry>>() as u64
+      + self.read_ahead_entries * mem::size_of::<Listed>() as u64;
+
+    MemoryStats {
+      retained_entries,
+      path_bytes,
+      approx_bytes,
+      read_ahead_entries: self.read_ahead_entries,
+    }
+  }
 }
 
 #[derive(Clone, Serialize)]
@@ -33,173 +153,1246 @@ pub struct ProgressPayload {
   pub current_path: String,
   #[serde(rename = "topFiles")]
   pub top_files: Vec<FileEntry>,
+  pub memory: MemoryStats,
+  #[serde(rename = "caseConflicts", skip_serializing_if = "Vec::is_empty")]
+  pub case_conflicts: Vec<[String; 2]>,
+  // Orders snapshots among the deltas that follow them; unset on `scan_complete`.
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub sequence: Option<u64>,
+}
+
+// What changed since the previous `scan_progress` or `scan_progress_delta` of the scan.
+#[derive(Clone, Serialize)]
+pub struct ProgressDeltaPayload {
+  #[serde(rename = "scanId")]
+  pub scan_id: u64,
+  pub sequence: u64,
+  #[serde(rename = "addedFiles")]
+  pub added_files: u64,
+  #[serde(rename = "addedBytes")]
+  pub added_bytes: u64,
+  #[serde(rename = "currentPath")]
+  pub current_path: String,
+  // Entries that entered the top files, and the IDs of those that left.
+  pub added: Vec<FileEntry>,
+  pub evicted: Vec<u64>,
+  pub memory: MemoryStats,
+  // Only the newly found ones.
+  #[serde(rename = "caseConflicts", skip_serializing_if = "Vec::is_empty")]
+  pub case_conflicts: Vec<[String; 2]>,
+}
+
+// Everything a reloaded frontend needs to pick a scan up where its events left off.
+#[derive(Clone, Serialize)]
+pub struct ScanResync {
+  // For a running scan, `sequence` is its place among the progress events: deltas with a higher
+  // one build on it, as they would on a `scan_progress` snapshot.
+  #[serde(flatten)]
+  pub progress: ProgressPayload,
+  pub done: bool,
+  pub paused: bool,
+  // The folder totals so far, in `tree::nodes` order, cut to the first `tree::MAX_CHUNK_NODES`.
+  // Empty for resumed and remote scans, which don't keep them.
+  pub tree: Vec<TreeNode>,
+  #[serde(rename = "totalNodes")]
+  pub total_nodes: usize,
+}
+
+impl ScanResync {
+  fn new(progress: ProgressPayload, done: bool, paused: bool, mut tree: Vec<TreeNode>) -> Self {
+    let total_nodes = tree.len();
+    tree.truncate(tree::MAX_CHUNK_NODES);
+    Self {
+      progress,
+      done,
+      paused,
+      tree,
+      total_nodes,
+    }
+  }
+
+  // A finished scan, from its summary and, when they were kept, its folder totals.
+  pub fn finished(scan_id: u64, summary: &ScanSummary, totals: Option<&DirTotals>) -> Self {
+    let progress = ProgressPayload {
+      scan_id,
+      scanned_files: summary.scanned_files,
+      scanned_bytes: summary.scanned_bytes,
+      current_path: summary.path.clone(),
+      top_files: summary.top_files.clone(),
+      memory: MemoryStats::default(),
+      case_conflicts: Vec::new(),
+      sequence: None,
+    };
+    let tree = totals
+      .map(|totals| tree::nodes(&summary.path, totals))
+      .unwrap_or_default();
+    Self::new(progress, true, false, tree)
+  }
+}
+
+// Lets `resync` ask a running scan for its state, which only the scan's own thread can read. The
+// scan answers between files, also while paused.
+#[derive(Default)]
+pub struct ResyncRequests {
+  wanted: AtomicBool,
+  reply: parking_lot::Mutex<Option<ScanResync>>,
+  answered: parking_lot::Condvar,
+}
+
+impl ResyncRequests {
+  // None if the scan didn't answer in time, e.g. because it just ended.
+  pub fn request(&self) -> Option<ScanResync> {
+    let mut reply = self.reply.lock();
+    *reply = None;
+    self.wanted.store(true, Ordering::Relaxed);
+    self
+      .answered
+      .wait_while_for(&mut reply, |reply| reply.is_none(), RESYNC_TIMEOUT);
+    reply.take()
+  }
+
+  fn answer(&self, state: impl FnOnce() -> ScanResync) {
+    if self.wanted.swap(false, Ordering::Relaxed) {
+      let state = state();
+      *self.reply.lock() = Some(state);
+      self.answered.notify_all();
+    }
+  }
+}
+
+// Sends a scan's periodic progress: a full snapshot first and every so often after, and in
+// between only what changed, so long scans don't resend the whole top list five times a second.
+#[derive(Default)]
+struct ProgressStream {
+  sequence: u64,
+  sent: HashSet<u64>,
+  scanned_files: u64,
+  scanned_bytes: u64,
+  case_conflicts: usize,
+}
+
+impl ProgressStream {
+  fn emit(
+    &mut self,
+    app: &AppHandle,
+    results: &ScanResults,
+    memory: &MemoryCounters,
+    scan_id: u64,
+  ) {
+    let mut payload = progress_payload(results, memory, scan_id);
+    let sequence = self.sequence;
+    self.sequence += 1;
+    let sent = mem::replace(
+      &mut self.sent,
+      payload.top_files.iter().map(|entry| entry.id).collect(),
+    );
+    let added_files = payload.scanned_files - self.scanned_files;
+    let added_bytes = payload.scanned_bytes - self.scanned_bytes;
+    self.scanned_files = payload.scanned_files;
+    self.scanned_bytes = payload.scanned_bytes;
+    let known_conflicts = mem::replace(&mut self.case_conflicts, payload.case_conflicts.len());
+
+    if sequence.is_multiple_of(SNAPSHOT_EVERY) {
+      payload.sequence = Some(sequence);
+      let _ = app.emit_to("main", "scan_progress", payload);
+      return;
+    }
+    let delta = ProgressDeltaPayload {
+      scan_id,
+      sequence,
+      added_files,
+      added_bytes,
+      current_path: payload.current_path,
+      evicted: sent
+        .iter()
+        .filter(|id| !self.sent.contains(id))
+        .copied()
+        .collect(),
+      added: payload
+        .top_files
+        .into_iter()
+        .filter(|entry| !sent.contains(&entry.id))
+        .collect(),
+      memory: payload.memory,
+      case_conflicts: payload
+        .case_conflicts
+        .get(known_conflicts..)
+        .unwrap_or_default()
+        .to_vec(),
+    };
+    let _ = app.emit_to("main", "scan_progress_delta", delta);
+  }
+
+  // The full state for a `resync`. It takes the next sequence number, like a snapshot would, and
+  // the deltas after it are relative to it.
+  fn resync(
+    &mut self,
+    results: &ScanResults,
+    memory: &MemoryCounters,
+    scan_id: u64,
+    root: &Path,
+    paused: bool,
+  ) -> ScanResync {
+    let mut payload = progress_payload(results, memory, scan_id);
+    payload.sequence = Some(self.sequence);
+    self.sequence += 1;
+    self.sent = payload.top_files.iter().map(|entry| entry.id).collect();
+    self.scanned_files = payload.scanned_files;
+    self.scanned_bytes = payload.scanned_bytes;
+    self.case_conflicts = payload.case_conflicts.len();
+    let tree = results
+      .dir_totals
+      .as_ref()
+      .map(|(_, totals)| tree::nodes(&root.to_string_lossy(), totals))
+      .unwrap_or_default();
+    ScanResync::new(payload, false, paused, tree)
+  }
+}
+
+// A short summary every tenth of the expected work (or every 10 GB without an estimate) and at
+// the end, for screen readers to announce; `scan_progress` is far too frequent for that.
+#[derive(Clone, Serialize)]
+pub struct MilestonePayload {
+  #[serde(rename = "scanId")]
+  pub scan_id: u64,
+  #[serde(rename = "scannedFiles")]
+  pub scanned_files: u64,
+  #[serde(rename = "scannedBytes")]
+  pub scanned_bytes: u64,
+  // Of the expected bytes; None without an estimate or once the scan has outgrown it.
+  pub percent: Option<u64>,
+  pub done: bool,
+  pub summary: Message,
+}
+
+struct Milestones {
+  expected: Option<u64>,
+  step: u64,
+  next: u64,
+}
+
+impl Milestones {
+  fn new(expected: Option<u64>, scanned_bytes: u64) -> Self {
+    let expected = expected.filter(|bytes| *bytes > 0);
+    let step = expected.map_or(MILESTONE_BYTES, |bytes| (bytes / 10).max(1));
+    Self {
+      expected,
+      step,
+      next: (scanned_bytes / step + 1) * step,
+    }
+  }
+
+  fn check(&mut self, app: &AppHandle, scan_id: u64, results: &ScanResults) {
+    if results.scanned_bytes < self.next {
+      return;
+    }
+    self.next = (results.scanned_bytes / self.step + 1) * self.step;
+    let percent = self
+      .expected
+      .filter(|expected| results.scanned_bytes < *expected)
+      .map(|expected| results.scanned_bytes * 100 / expected);
+    let summary = match percent {
+      Some(percent) => Message::new("milestone.progress").with("percent", percent),
+      None => Message::new("milestone.scanned"),
+    };
+    emit_milestone(app, scan_id, results, percent, false, summary);
+  }
+
+  fn finish(&self, app: &AppHandle, scan_id: u64, results: &ScanResults, cancelled: bool) {
+    let summary = Message::new(if cancelled {
+      "milestone.cancelled"
+    } else {
+      "milestone.done"
+    });
+    emit_milestone(app, scan_id, results, None, true, summary);
+  }
+}
+
+fn emit_milestone(
+  app: &AppHandle,
+  scan_id: u64,
+  results: &ScanResults,
+  percent: Option<u64>,
+  done: bool,
+  summary: Message,
+) {
+  let payload = MilestonePayload {
+    scan_id,
+    scanned_files: results.scanned_files,
+    scanned_bytes: results.scanned_bytes,
+    percent,
+    done,
+    summary: summary
+      .with("files", results.scanned_files)
+      .with("size", messages::size(results.scanned_bytes)),
+  };
+  let _ = app.emit_to("main", "scan_milestone", payload);
+}
+
+// A scan's progress at a directory boundary: the directories still to read plus everything
+// accumulated so far. Journaled so an interrupted scan can continue where it stopped.
+#[derive(Clone, Default, Serialize, Deserialize)]
+pub struct WalkState {
+  #[serde(rename = "scannedFiles")]
+  pub scanned_files: u64,
+  #[serde(rename = "scannedBytes")]
+  pub scanned_bytes: u64,
+  pub top: Vec<HeapEntry>,
+  #[serde(rename = "caseConflicts")]
+  pub case_conflicts: Vec<[String; 2]>,
+  pub queue: Vec<String>,
+  #[serde(rename = "smallFiles", default)]
+  pub small_files: HashMap<String, DirTotal>,
+}
+
+// Counters as they were when a directory was taken off the queue.
+struct DirMark {
+  scanned_files: u64,
+  scanned_bytes: u64,
+  queued_dirs: usize,
+  case_conflicts: usize,
+}
+
+// Size of one folder and everything under it.
+#[derive(Clone, Copy, Default, Serialize, Deserialize)]
+pub struct DirTotal {
+  pub bytes: u64,
+  pub files: u64,
+}
+
+// Files under a scan's minimum size, counted per folder instead of being listed.
+#[derive(Clone, Serialize)]
+pub struct SmallFiles {
+  #[serde(rename = "minFileSize")]
+  pub min_file_size: u64,
+  pub files: u64,
+  pub bytes: u64,
+  // The folders holding the most of them, largest first.
+  pub folders: Vec<SmallFolder>,
+}
+
+#[derive(Clone, Serialize)]
+pub struct SmallFolder {
+  pub path: String,
+  pub files: u64,
+  pub bytes: u64,
+}
+
+// Totals for the folders near the root, keyed by their path relative to it ("" for the root).
+pub type DirTotals = HashMap<String, DirTotal>;
+
+fn add_to_totals(totals: &mut DirTotals, root_len: usize, path: &str, size: u64) {
+  let relative = match path.get(root_len..) {
+    Some(relative) => relative.trim_start_matches(std::path::MAIN_SEPARATOR),
+    None => return,
+  };
+  let separators = relative
+    .match_indices(std::path::MAIN_SEPARATOR)
+    .map(|(index, _)| index)
+    .take(TOTALS_DEPTH);
+  for end in std::iter::once(0).chain(separators) {
+    let total = match totals.get_mut(&relative[..end]) {
+      Some(total) => total,
+      None => totals.entry(relative[..end].to_string()).or_default(),
+    };
+    total.bytes += size;
+    total.files += 1;
+  }
+}
+
+struct ScanResults {
+  top_n: usize,
+  heap: BinaryHeap<Reverse<HeapEntry>>,
+  tracker: MemoryTracker,
+  scanned_files: u64,
+  scanned_bytes: u64,
+  current_path: String,
+  case_conflicts: Vec<[String; 2]>,
+  device: Option<u64>,
+  rules: Arc<RuleSet>,
+  // Reads each directory's entries in name order, so repeated scans report identically.
+  sorted: bool,
+  // Above one, directories are read ahead of the walk on this many threads.
+  threads: usize,
+  dir_cache: Option<DirCache>,
+  // Set when folder totals are kept, with the length of the root path they're relative to.
+  dir_totals: Option<(usize, DirTotals)>,
+  include: Option<Arc<NameFilter>>,
+  // Smaller files are counted, and totalled per folder in `small_files`, but not kept as entries.
+  min_file_size: u64,
+  small_files: HashMap<String, DirTotal>,
+  // How many of the largest files the heap keeps; at least `top_n`.
+  pool: usize,
+  // Pseudo-filesystem mounts under the root, which are never descended into.
+  skipped_mounts: Vec<SkippedMount>,
+  skipped_dirs: HashSet<PathBuf>,
+  // The folder being read, and the entries from earlier folders its files pushed out of the
+  // heap: put back if the scan is cancelled partway through it. At most `pool` of them.
+  reading: PathBuf,
+  displaced: Vec<HeapEntry>,
+}
+
+impl ScanResults {
+  fn new(top_n: usize) -> Self {
+    Self {
+      device: None,
+      rules: Arc::default(),
+      sorted: false,
+      threads: 1,
+      dir_cache: None,
+      dir_totals: None,
+      include: None,
+      min_file_size: 0,
+      small_files: HashMap::new(),
+      pool: top_n,
+      skipped_mounts: Vec::new(),
+      skipped_dirs: HashSet::new(),
+      reading: PathBuf::new(),
+      displaced: Vec::new(),
+      top_n,
+      heap: BinaryHeap::new(),
+      tracker: MemoryTracker::default(),
+      scanned_files: 0,
+      scanned_bytes: 0,
+      current_path: String::new(),
+      case_conflicts: Vec::new(),
+    }
+  }
+
+  fn add_file(&mut self, entry: HeapEntry) {
+    self.scanned_files += 1;
+    self.scanned_bytes += entry.0;
+    if let Some((root_len, totals)) = &mut self.dir_totals {
+      add_to_totals(totals, *root_len, &entry.1, entry.0);
+    }
+    if entry.0 < self.min_file_size {
+      let dir = Path::new(&entry.1)
+        .parent()
+        .map(|dir| dir.to_string_lossy().to_string())
+        .unwrap_or_default();
+      let total = self.small_files.entry(dir).or_default();
+      total.bytes += entry.0;
+      total.files += 1;
+      return;
+    }
+    self.tracker.heap_path_bytes += entry.1.len() as u64;
+    self.heap.push(Reverse(entry));
+    if self.heap.len() > self.pool {
+      if let Some(Reverse(evicted)) = self.heap.pop() {
+        self.tracker.heap_path_bytes -= evicted.1.len() as u64;
+        if Path::new(&evicted.1).parent() != Some(self.reading.as_path()) {
+          self.displaced.push(evicted);
+        }
+      }
+    }
+  }
+
+  fn push_dir(&mut self, dirs: &mut VecDeque<PathBuf>, dir: PathBuf) {
+    if !self.skipped_dirs.is_empty() && self.skipped_dirs.contains(&dir) {
+      return;
+    }
+    self.tracker.queued_dirs += 1;
+    self.tracker.queued_path_bytes += dir.as_os_str().len() as u64;
+    dirs.push_back(dir);
+  }
+
+  fn pop_dir(&mut self, dirs: &mut VecDeque<PathBuf>) -> Option<PathBuf> {
+    let dir = dirs.pop_front()?;
+    self.tracker.queued_dirs -= 1;
+    self.tracker.queued_path_bytes -= dir.as_os_str().len() as u64;
+    Some(dir)
+  }
+
+  fn walk_state(&self, dirs: &VecDeque<PathBuf>) -> WalkState {
+    WalkState {
+      scanned_files: self.scanned_files,
+      scanned_bytes: self.scanned_bytes,
+      top: self.heap.iter().map(|entry| entry.0.clone()).collect(),
+      case_conflicts: self.case_conflicts.clone(),
+      queue: dirs
+        .iter()
+        .map(|dir| dir.to_string_lossy().to_string())
+        .collect(),
+      small_files: self.small_files.clone(),
+    }
+  }
+
+  fn mark(&mut self, dir: &Path, dirs: &VecDeque<PathBuf>) -> DirMark {
+    self.reading = dir.to_path_buf();
+    self.displaced.clear();
+    DirMark {
+      scanned_files: self.scanned_files,
+      scanned_bytes: self.scanned_bytes,
+      queued_dirs: dirs.len(),
+      case_conflicts: self.case_conflicts.len(),
+    }
+  }
+
+  // The state from before `dir` was read, for a scan cancelled partway through it: the files it
+  // contributed are dropped, those they displaced from the top list come back, and it goes back
+  // to the front of the queue, so resuming reads it again from the start.
+  fn walk_state_before(&self, dir: &Path, mark: &DirMark, dirs: &VecDeque<PathBuf>) -> WalkState {
+    let mut state = self.walk_state(dirs);
+    state.scanned_files = mark.scanned_files;
+    state.scanned_bytes = mark.scanned_bytes;
+    state
+      .top
+      .retain(|(_, path, _)| Path::new(path).parent() != Some(dir));
+    state.top.extend(self.displaced.iter().cloned());
+    state.case_conflicts.truncate(mark.case_conflicts);
+    state.small_files.remove(dir.to_string_lossy().as_ref());
+    state.queue.truncate(mark.queued_dirs);
+    state.queue.insert(0, dir.to_string_lossy().to_string());
+    state
+  }
+
+  // Restores the counters and top list; returns the directories still to read.
+  fn restore(&mut self, state: WalkState) -> Vec<PathBuf> {
+    for entry in state.top {
+      self.add_file(entry);
+    }
+    self.scanned_files = state.scanned_files;
+    self.scanned_bytes = state.scanned_bytes;
+    self.case_conflicts = state.case_conflicts;
+    self.small_files = state.small_files;
+    state.queue.into_iter().map(PathBuf::from).collect()
+  }
+
+  fn record_case_conflict(&mut self, existing: &Path, path: &Path) {
+    if self.case_conflicts.len() < MAX_CASE_CONFLICTS {
+      self.case_conflicts.push([
+        existing.to_string_lossy().to_string(),
+        path.to_string_lossy().to_string(),
+      ]);
+    }
+  }
+}
+
+#[derive(Default, Deserialize)]
+pub struct ScanOptions {
+  #[serde(rename = "topN")]
+  pub top_n: Option<usize>,
+  #[serde(default)]
+  pub metadata: HashMap<String, String>,
+  // Traverses directories in sorted order so exports and diffs are reproducible.
+  #[serde(default)]
+  pub deterministic: bool,
+  // Threads reading directories; picked from the storage type when unset.
+  pub threads: Option<usize>,
+  // Reuses what the last scan found in unchanged folders; follows the settings when unset.
+  pub incremental: Option<bool>,
+  // Only files matching these globs are counted, e.g. `["*.mp4,*.mov,*.mkv"]`.
+  #[serde(default)]
+  pub include: Vec<String>,
+  // Files smaller than this are left out of the top files and only totalled per folder.
+  #[serde(rename = "minFileSize")]
+  pub min_file_size: Option<u64>,
+}
+
+// Per-scan settings resolved by the caller, as opposed to the frontend-facing ScanOptions.
+pub struct ScanConfig {
+  pub top_n: usize,
+  pub rules: Arc<RuleSet>,
+  pub deterministic: bool,
+  pub threads: usize,
+  // Set for incremental scans.
+  pub dir_cache: Option<DirCache>,
+  pub journal: Option<ScanJournal>,
+  // Continues from a checkpoint instead of starting at the root.
+  pub resume: Option<WalkState>,
+  // What the scan is likely to find, for `scan_milestone` percentages.
+  pub expected_bytes: Option<u64>,
+  pub include: Option<Arc<NameFilter>>,
+  pub min_file_size: u64,
+  // While set, the walk waits before its next file.
+  pub paused: Arc<AtomicBool>,
+  // Answered between files; see `ResyncRequests`.
+  pub resync: Arc<ResyncRequests>,
+}
+
+#[derive(Clone, Serialize)]
+pub struct ScanSummary {
+  pub path: String,
+  #[serde(rename = "scannedFiles")]
+  pub scanned_files: u64,
+  #[serde(rename = "scannedBytes")]
+  pub scanned_bytes: u64,
+  #[serde(rename = "topFiles")]
+  pub top_files: Vec<FileEntry>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub incremental: Option<IncrementalStats>,
+  #[serde(rename = "smallFiles", skip_serializing_if = "Option::is_none")]
+  pub small_files: Option<SmallFiles>,
+  // Pseudo-filesystems (/proc, /sys, /dev, snap loops, Time Machine) under the root, left out.
+  #[serde(rename = "skippedMounts", skip_serializing_if = "Vec::is_empty")]
+  pub skipped_mounts: Vec<SkippedMount>,
+  // The next largest files after `top_files`, largest first; kept in memory only.
+  #[serde(skip)]
+  pub candidates: Vec<FileEntry>,
 }
 
 pub fn scan_directory(
   app: AppHandle,
   root: PathBuf,
   cancel: Arc<AtomicBool>,
-  top_n: usize,
+  config: ScanConfig,
   scan_id: u64,
-) -> bool {
-  let mut dirs: VecDeque<PathBuf> = VecDeque::new();
-  let mut heap: BinaryHeap<Reverse<HeapEntry>> = BinaryHeap::new();
-  let mut scanned_files = 0u64;
-  let mut scanned_bytes = 0u64;
-  let mut current_path = String::new();
+  memory: Arc<MemoryCounters>,
+  profiler: Option<&mut ScanProfiler>,
+) -> (bool, ScanSummary, Option<DirTotals>) {
+  let mut results = ScanResults::new(config.top_n);
+  results.rules = config.rules;
+  results.sorted = config.deterministic;
+  results.threads = config.threads;
+  results.dir_cache = config.dir_cache;
+  results.include = config.include;
+  results.min_file_size = config.min_file_size;
+  results.pool = config.top_n.max(CANDIDATE_POOL);
+  let mut journal = config.journal;
   let mut last_emit = Instant::now() - EMIT_INTERVAL;
-  let mut cancelled = false;
 
   if let Ok(metadata) = fs::metadata(&root) {
     if metadata.is_file() {
-      let size = metadata.len();
       let path_string = root.to_string_lossy().to_string();
-      scanned_files = 1;
-      scanned_bytes = size;
-      current_path = path_string.clone();
-      push_top(&mut heap, (size, path_string), top_n);
-      emit_progress(
-        &app,
-        scanned_files,
-        scanned_bytes,
-        &current_path,
-        &heap,
-        scan_id,
-        "scan_progress",
-      );
-      emit_progress(
-        &app,
-        scanned_files,
-        scanned_bytes,
-        &current_path,
-        &heap,
-        scan_id,
-        "scan_complete",
-      );
-      return false;
-    }
-  }
-
-  dirs.push_back(root);
-
-  while let Some(dir) = dirs.pop_front() {
-    if cancel.load(Ordering::Relaxed) {
-      cancelled = true;
-      break;
-    }
-
-    let entries = match fs::read_dir(&dir) {
-      Ok(entries) => entries,
-      Err(_) => continue,
-    };
+      results.current_path = path_string.clone();
+      results.add_file((metadata.len(), path_string, None));
+      ProgressStream::default().emit(&app, &results, &memory, scan_id);
+      emit_progress(&app, &results, &memory, scan_id, "scan_complete");
+      Milestones::new(None, 0).finish(&app, scan_id, &results, false);
+      return (false, summary(&root, &results), None);
+    }
+  }
 
-    for entry in entries {
-      if cancel.load(Ordering::Relaxed) {
-        cancelled = true;
-        break;
+  // A resumed scan's totals would miss what was read before it stopped.
+  let start = match config.resume {
+    Some(state) => results.restore(state),
+    None => {
+      results.dir_totals = Some((root.as_os_str().len(), DirTotals::new()));
+      vec![root.clone()]
+    }
+  };
+  let mut milestones = Milestones::new(config.expected_bytes, results.scanned_bytes);
+  let mut progress = ProgressStream::default();
+  let cancelled = walk(
+    &mut results,
+    &root,
+    start,
+    &cancel,
+    profiler,
+    journal.as_mut(),
+    |results| {
+      if last_emit.elapsed() >= EMIT_INTERVAL {
+        progress.emit(&app, results, &memory, scan_id);
+        last_emit = Instant::now();
       }
+      milestones.check(&app, scan_id, results);
+      loop {
+        let paused = config.paused.load(Ordering::Relaxed) && !cancel.load(Ordering::Relaxed);
+        config
+          .resync
+          .answer(|| progress.resync(results, &memory, scan_id, &root, paused));
+        if !paused {
+          break;
+        }
+        thread::sleep(PAUSE_POLL_INTERVAL);
+      }
+    },
+  );
 
-      let entry = match entry {
-        Ok(entry) => entry,
-        Err(_) => continue,
-      };
+  emit_progress(&app, &results, &memory, scan_id, "scan_complete");
+  milestones.finish(&app, scan_id, &results, cancelled);
 
-      let file_type = match entry.file_type() {
-        Ok(file_type) => file_type,
-        Err(_) => continue,
-      };
+  let summary = summary(&root, &results);
+  if let Some(dir_cache) = results.dir_cache.take().filter(|_| !cancelled) {
+    dir_cache.save();
+  }
+  let dir_totals = results
+    .dir_totals
+    .take()
+    .filter(|_| !cancelled)
+    .map(|(_, totals)| totals);
+  (cancelled, summary, dir_totals)
+}
 
-      if file_type.is_symlink() {
-        continue;
-      }
+// Rescans one folder without emitting progress; returns None if cancelled.
+pub fn scan_subtree(root: &Path, cancel: &AtomicBool, top_n: usize) -> Option<ScanSummary> {
+  scan_subtree_with(root, cancel, top_n, 1, false, None)
+}
 
-      let path = entry.path();
-      let path_string = path.to_string_lossy().to_string();
-      current_path = path_string.clone();
+// scan_subtree with a given number of listing threads, traversal order, and incremental cache.
+pub fn scan_subtree_with(
+  root: &Path,
+  cancel: &AtomicBool,
+  top_n: usize,
+  threads: usize,
+  sorted: bool,
+  dir_cache: Option<DirCache>,
+) -> Option<ScanSummary> {
+  let mut results = ScanResults::new(top_n);
+  results.threads = threads;
+  results.sorted = sorted;
+  results.dir_cache = dir_cache;
+  let start = vec![root.to_path_buf()];
+  if walk(&mut results, root, start, cancel, None, None, |_| {}) {
+    return None;
+  }
+  let summary = summary(root, &results);
+  if let Some(dir_cache) = results.dir_cache.take() {
+    dir_cache.save();
+  }
+  Some(summary)
+}
 
-      if file_type.is_dir() {
-        dirs.push_back(path);
-        continue;
-      }
+// Like scan_subtree, but stays on the root's filesystem instead of descending into other mounts.
+pub fn scan_volume(root: &Path, cancel: &AtomicBool, top_n: usize) -> Option<ScanSummary> {
+  let mut results = ScanResults::new(top_n);
+  results.device = fs::metadata(root)
+    .ok()
+    .and_then(|metadata| device_of(&metadata));
+  let start = vec![root.to_path_buf()];
+  if walk(&mut results, root, start, cancel, None, None, |_| {}) {
+    return None;
+  }
+  Some(summary(root, &results))
+}
 
-      if !file_type.is_file() {
-        continue;
-      }
+#[cfg(target_family = "unix")]
+fn device_of(metadata: &fs::Metadata) -> Option<u64> {
+  Some(metadata.dev())
+}
 
-      let metadata = match entry.metadata() {
-        Ok(metadata) => metadata,
-        Err(_) => continue,
-      };
+#[cfg(not(target_family = "unix"))]
+fn device_of(_metadata: &fs::Metadata) -> Option<u64> {
+  None
+}
 
-      let size = metadata.len();
-      scanned_files += 1;
-      scanned_bytes += size;
-      push_top(&mut heap, (size, path_string), top_n);
+impl ScanSummary {
+  // Marks the entries at or under `path` as removed, and drops such candidates; returns how many
+  // entries were marked.
+  pub fn mark_removed(&mut self, path: &Path, at: u64) -> usize {
+    let mut marked = 0;
+    for entry in &mut self.top_files {
+      if entry.removed_at.is_none() && Path::new(&entry.path).starts_with(path) {
+        entry.removed_at = Some(at);
+        marked += 1;
+      }
+    }
+    self
+      .candidates
+      .retain(|entry| !Path::new(&entry.path).starts_with(path));
+    marked
+  }
 
-      if last_emit.elapsed() >= EMIT_INTERVAL {
-        emit_progress(
-          &app,
-          scanned_files,
-          scanned_bytes,
-          &current_path,
-          &heap,
-          scan_id,
-          "scan_progress",
-        );
-        last_emit = Instant::now();
+  // Moves up to `count` of the largest candidates into the top files, skipping any that are gone
+  // too; returns the entries added.
+  pub fn backfill(&mut self, count: usize) -> Vec<FileEntry> {
+    let mut added = Vec::new();
+    while added.len() < count && !self.candidates.is_empty() {
+      let entry = self.candidates.remove(0);
+      if fs::symlink_metadata(&entry.path).is_ok() {
+        added.push(entry);
       }
     }
+    self.top_files.extend(added.iter().cloned());
+    added
   }
 
-  emit_progress(
-    &app,
-    scanned_files,
-    scanned_bytes,
-    &current_path,
-    &heap,
-    scan_id,
-    "scan_complete",
-  );
+  // A file created again where a tombstone is brings its entry back.
+  pub fn mark_present(&mut self, path: &Path, size: u64) {
+    if let Some(entry) = self
+      .top_files
+      .iter_mut()
+      .find(|entry| entry.removed_at.is_some() && Path::new(&entry.path) == path)
+    {
+      entry.removed_at = None;
+      entry.size = size;
+    }
+  }
 
-  cancelled
+  // The `n` largest files still present, from the top files and then the candidates.
+  pub fn largest(&self, n: usize) -> Vec<FileEntry> {
+    let mut files: Vec<FileEntry> = self
+      .top_files
+      .iter()
+      .chain(&self.candidates)
+      .filter(|entry| entry.removed_at.is_none())
+      .cloned()
+      .collect();
+    files.sort_by(|a, b| b.size.cmp(&a.size).then_with(|| a.path.cmp(&b.path)));
+    files.truncate(n);
+    files
+  }
+
+  // Tombstoned entries, most recently removed first.
+  pub fn removals(&self) -> Vec<FileEntry> {
+    let mut removals: Vec<FileEntry> = self
+      .top_files
+      .iter()
+      .filter(|entry| entry.removed_at.is_some())
+      .cloned()
+      .collect();
+    removals.sort_by(|a, b| {
+      b.removed_at
+        .cmp(&a.removed_at)
+        .then_with(|| b.size.cmp(&a.size))
+    });
+    removals
+  }
 }
 
-fn push_top(heap: &mut BinaryHeap<Reverse<HeapEntry>>, entry: HeapEntry, limit: usize) {
-  heap.push(Reverse(entry));
-  if heap.len() > limit {
-    heap.pop();
+fn summary(root: &Path, results: &ScanResults) -> ScanSummary {
+  let mut top_files = top_files(results);
+  let candidates = top_files.split_off(results.top_n.min(top_files.len()));
+  ScanSummary {
+    path: root.to_string_lossy().to_string(),
+    scanned_files: results.scanned_files,
+    scanned_bytes: results.scanned_bytes,
+    top_files,
+    incremental: results.dir_cache.as_ref().map(DirCache::stats),
+    small_files: small_files(results),
+    skipped_mounts: results.skipped_mounts.clone(),
+    candidates,
   }
 }
 
-fn emit_progress(
-  app: &AppHandle,
-  scanned_files: u64,
-  scanned_bytes: u64,
-  current_path: &str,
-  heap: &BinaryHeap<Reverse<HeapEntry>>,
+fn small_files(results: &ScanResults) -> Option<SmallFiles> {
+  if results.min_file_size == 0 {
+    return None;
+  }
+  let mut folders: Vec<SmallFolder> = results
+    .small_files
+    .iter()
+    .map(|(path, total)| SmallFolder {
+      path: path.clone(),
+      files: total.files,
+      bytes: total.bytes,
+    })
+    .collect();
+  folders.sort_by(|a, b| b.bytes.cmp(&a.bytes).then_with(|| a.path.cmp(&b.path)));
+  let files = folders.iter().map(|folder| folder.files).sum();
+  let bytes = folders.iter().map(|folder| folder.bytes).sum();
+  folders.truncate(SMALL_FILE_FOLDERS);
+  Some(SmallFiles {
+    min_file_size: results.min_file_size,
+    files,
+    bytes,
+    folders,
+  })
+}
+
+// Feeds files found elsewhere (e.g. a remote ncdu export) through the normal progress events.
+pub struct StreamedScan {
+  app: AppHandle,
   scan_id: u64,
-  event_name: &str,
-) {
-  let mut top_files: Vec<FileEntry> = heap
+  memory: Arc<MemoryCounters>,
+  resync: Arc<ResyncRequests>,
+  results: ScanResults,
+  last_emit: Instant,
+  milestones: Milestones,
+  progress: ProgressStream,
+}
+
+impl StreamedScan {
+  pub fn new(
+    app: AppHandle,
+    scan_id: u64,
+    memory: Arc<MemoryCounters>,
+    resync: Arc<ResyncRequests>,
+    top_n: usize,
+  ) -> Self {
+    Self {
+      app,
+      scan_id,
+      memory,
+      resync,
+      results: ScanResults {
+        pool: top_n.max(CANDIDATE_POOL),
+        ..ScanResults::new(top_n)
+      },
+      last_emit: Instant::now() - EMIT_INTERVAL,
+      milestones: Milestones::new(None, 0),
+      progress: ProgressStream::default(),
+    }
+  }
+
+  pub fn add_file(&mut self, path: String, size: u64) {
+    self.results.current_path = path.clone();
+    self.results.add_file((size, path, None));
+    if self.last_emit.elapsed() >= EMIT_INTERVAL {
+      self
+        .progress
+        .emit(&self.app, &self.results, &self.memory, self.scan_id);
+      self.last_emit = Instant::now();
+    }
+    self
+      .milestones
+      .check(&self.app, self.scan_id, &self.results);
+    self.resync.answer(|| {
+      self.progress.resync(
+        &self.results,
+        &self.memory,
+        self.scan_id,
+        Path::new(""),
+        false,
+      )
+    });
+  }
+
+  pub fn finish(self, root: &str) -> ScanSummary {
+    emit_progress(
+      &self.app,
+      &self.results,
+      &self.memory,
+      self.scan_id,
+      "scan_complete",
+    );
+    self
+      .milestones
+      .finish(&self.app, self.scan_id, &self.results, false);
+    summary(Path::new(root), &self.results)
+  }
+}
+
+fn walk(
+  results: &mut ScanResults,
+  root: &Path,
+  start: Vec<PathBuf>,
+  cancel: &AtomicBool,
+  mut profiler: Option<&mut ScanProfiler>,
+  mut journal: Option<&mut ScanJournal>,
+  mut on_file: impl FnMut(&ScanResults),
+) -> bool {
+  // Only stat and readdir are used below; this makes sure nothing can download a cloud file.
+  // Listing threads do the same for themselves.
+  let _no_materialize = NoMaterialize::enter();
+  debug_assert!(dataless::materialization_disabled());
+  let mut dirs: VecDeque<PathBuf> = VecDeque::new();
+  let mut cancelled = false;
+
+  let track_case_conflicts = filesystem::filesystem_info(root)
+    .and_then(|info| info.case_sensitive)
+    .unwrap_or(false);
+  let mut sibling_names: HashMap<String, PathBuf> = HashMap::new();
+  let stat_dirs = results.device.is_some();
+  let mut dir_cache = results.dir_cache.take();
+  results.skipped_mounts = volumes::pseudo_mounts_under(root);
+  results.skipped_dirs = results
+    .skipped_mounts
+    .iter()
+    .map(|mount| PathBuf::from(&mount.path))
+    .collect();
+
+  for dir in start {
+    results.push_dir(&mut dirs, dir);
+  }
+
+  let pool = Some(results.threads)
+    .filter(|threads| *threads > 1)
+    .map(|threads| ListingPool::new(threads, results.sorted, stat_dirs, results.include.clone()));
+  thread::scope(|scope| {
+    if let Some(pool) = &pool {
+      for _ in 0..pool.threads() {
+        scope.spawn(|| pool.work(cancel));
+      }
+    }
+    let _stop = pool.as_ref().map(StopOnDrop);
+    // How many folders at the front of `dirs` have been handed to the pool.
+    let mut requested: usize = 0;
+
+    while let Some(dir) = results.pop_dir(&mut dirs) {
+      let mark = results.mark(&dir, &dirs);
+      if cancel.load(Ordering::Relaxed) {
+        cancelled = true;
+        if let Some(journal) = journal.as_deref_mut() {
+          journal.save_cancelled(results.walk_state_before(&dir, &mark, &dirs));
+        }
+        break;
+      }
+
+      if let Some(pool) = &pool {
+        requested = requested.saturating_sub(1);
+        while requested < pool.read_ahead() && requested < dirs.len() {
+          let next = &dirs[requested];
+          if dir_cache
+            .as_ref()
+            .filter(|cache| cache.is_fresh(next))
+            .is_none()
+          {
+            pool.request(next.clone());
+          }
+          requested += 1;
+        }
+      }
+
+      let dir_started = Instant::now();
+      let mut dir_calls = DirCalls {
+        read_dir: 1,
+        ..DirCalls::default()
+      };
+      sibling_names.clear();
+
+      let mut record = None;
+      match dir_cache.as_mut().and_then(|cache| cache.lookup(&dir)) {
+        Some(Ok(cached)) => {
+          for (name, size, logical_size) in &cached.files {
+            let path_string = dir.join(name).to_string_lossy().to_string();
+            results.current_path = path_string.clone();
+            results.add_file((*size, path_string, *logical_size));
+            on_file(results);
+          }
+          for name in &cached.dirs {
+            results.push_dir(&mut dirs, dir.join(name));
+          }
+          if let Some(profiler) = profiler.as_deref_mut() {
+            dir_calls.read_dir = 0;
+            profiler.record_dir(&dir, dir_started, dir_calls);
+          }
+          if let Some(journal) = journal.as_deref_mut().filter(|journal| journal.due()) {
+            journal.save(results.walk_state(&dirs));
+          }
+          continue;
+        }
+        Some(Err(empty)) => record = Some(empty),
+        None => {}
+      }
+
+      let listing = match &pool {
+        Some(pool) => {
+          let listing = pool.take(&dir, cancel);
+          results.tracker.read_ahead_entries = pool.buffered() as u64;
+          listing
+        }
+        None => listing::read(
+          &dir,
+          results.sorted,
+          stat_dirs,
+          results.include.clone(),
+          cancel,
+        ),
+      };
+      let entries = match listing {
+        Some(entries) => entries,
+        None => {
+          if let Some(profiler) = profiler.as_deref_mut() {
+            profiler.record_dir(&dir, dir_started, dir_calls);
+          }
+          continue;
+        }
+      };
+
+      for listed in entries {
+        if cancel.load(Ordering::Relaxed) {
+          cancelled = true;
+          break;
+        }
+
+        let Listed {
+          entry,
+          file_type,
+          metadata,
+        } = listed;
+        dir_calls.entries += 1;
+        // A name that isn't valid UTF-8 couldn't be rebuilt from the cache, so its folder is
+        // always read.
+        let name = record
+          .as_ref()
+          .and_then(|_| entry.file_name().into_string().ok());
+        if name.is_none() {
+          record = None;
+        }
+
+        if track_case_conflicts {
+          let key = entry.file_name().to_string_lossy().to_lowercase();
+          match sibling_names.get(&key) {
+            Some(existing) => results.record_case_conflict(existing, &entry.path()),
+            None => {
+              sibling_names.insert(key, entry.path());
+            }
+          }
+        }
+
+        let file_type = match file_type {
+          Some(file_type) => file_type,
+          None => continue,
+        };
+
+        if file_type.is_symlink() {
+          continue;
+        }
+
+        let path = entry.path();
+        let path_string = path.to_string_lossy().to_string();
+        results.current_path = path_string.clone();
+
+        if file_type.is_dir() {
+          if snapshots::is_snapshot_dir(&entry) {
+            continue;
+          }
+
+          if let Some(device) = results.device {
+            dir_calls.metadata += 1;
+            let entry_device = metadata.as_ref().and_then(device_of);
+            if entry_device.is_some() && entry_device != Some(device) {
+              continue;
+            }
+          }
+
+          if disk_image::is_sparse_bundle(&path) {
+            let excluded = results
+              .include
+              .as_ref()
+              .is_some_and(|include| !include.matches(&entry.file_name().to_string_lossy()));
+            if excluded {
+              continue;
+            }
+            if let Some(image) = disk_image::sparse_bundle_size(&path) {
+              if let (Some(record), Some(name)) = (record.as_mut(), name) {
+                record
+                  .files
+                  .push((name, image.allocated, Some(image.logical)));
+              }
+              results.add_file((image.allocated, path_string, Some(image.logical)));
+            }
+            continue;
+          }
+
+          if let (Some(record), Some(name)) = (record.as_mut(), name) {
+            record.dirs.push(name);
+          }
+          results.push_dir(&mut dirs, path);
+          continue;
+        }
+
+        if !file_type.is_file() {
+          continue;
+        }
+
+        dir_calls.metadata += 1;
+        let metadata = match metadata {
+          Some(metadata) => metadata,
+          None => continue,
+        };
+
+        let (size, logical_size) = if disk_image::is_sparse_image(&path) {
+          (disk_image::allocated_size(&metadata), Some(metadata.len()))
+        } else {
+          (metadata.len(), None)
+        };
+        if let (Some(record), Some(name)) = (record.as_mut(), name) {
+          record.files.push((name, size, logical_size));
+        }
+        results.add_file((size, path_string, logical_size));
+        on_file(results);
+      }
+
+      if let Some(profiler) = profiler.as_deref_mut() {
+        profiler.record_dir(&dir, dir_started, dir_calls);
+      }
+      if cancelled {
+        if let Some(journal) = journal.as_deref_mut() {
+          journal.save_cancelled(results.walk_state_before(&dir, &mark, &dirs));
+        }
+        break;
+      }
+      if let (Some(dir_cache), Some(record)) = (dir_cache.as_mut(), record) {
+        dir_cache.store(&dir, record);
+      }
+      if let Some(journal) = journal.as_deref_mut().filter(|journal| journal.due()) {
+        journal.save(results.walk_state(&dirs));
+      }
+    }
+  });
+
+  results.tracker.read_ahead_entries = 0;
+  results.dir_cache = dir_cache;
+  cancelled
+}
+
+fn top_files(results: &ScanResults) -> Vec<FileEntry> {
+  let mut top_files: Vec<FileEntry> = results
+    .heap
     .iter()
     .map(|entry| {
-      let (size, path) = &entry.0;
+      let (size, path, logical_size) = &entry.0;
       FileEntry {
+        id: entry_id(path),
+        classification: results.rules.classify(path, *size),
         path: path.clone(),
         size: *size,
+        logical_size: *logical_size,
+        removed_at: None,
       }
     })
     .collect();
 
-  top_files.sort_by(|a, b| b.size.cmp(&a.size));
+  top_files.sort_by(|a, b| b.size.cmp(&a.size).then_with(|| a.path.cmp(&b.path)));
+  top_files
+}
 
-  let payload = ProgressPayload {
+fn progress_payload(
+  results: &ScanResults,
+  memory: &MemoryCounters,
+  scan_id: u64,
+) -> ProgressPayload {
+  let stats = results.tracker.stats(results.heap.len());
+  memory.store(&stats);
+  let mut top_files = top_files(results);
+  top_files.truncate(results.top_n);
+
+  ProgressPayload {
     scan_id,
-    scanned_files,
-    scanned_bytes,
-    current_path: current_path.to_string(),
+    scanned_files: results.scanned_files,
+    scanned_bytes: results.scanned_bytes,
+    current_path: results.current_path.clone(),
     top_files,
-  };
+    memory: stats,
+    case_conflicts: results.case_conflicts.clone(),
+    sequence: None,
+  }
+}
 
+fn emit_progress(
+  app: &AppHandle,
+  results: &ScanResults,
+  memory: &MemoryCounters,
+  scan_id: u64,
+  event_name: &str,
+) {
+  let payload = progress_payload(results, memory, scan_id);
   let _ = app.emit_to("main", event_name, payload);
 }
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn top_paths(state: &WalkState) -> Vec<&str> {
+    let mut paths: Vec<&str> = state.top.iter().map(|(_, path, _)| path.as_str()).collect();
+    paths.sort();
+    paths
+  }
+
+  // A folder whose files push earlier ones out of a full top list, then is cancelled partway.
+  #[test]
+  fn cancelling_mid_folder_restores_the_top_list() {
+    let mut results = ScanResults::new(2);
+    let mut dirs = VecDeque::new();
+    let first = PathBuf::from("/root/a");
+    let second = PathBuf::from("/root/b");
+    results.mark(&first, &dirs);
+    results.add_file((10, "/root/a/one".to_string(), None));
+    results.add_file((20, "/root/a/two".to_string(), None));
+    results.add_file((5, "/root/a/three".to_string(), None));
+
+    dirs.push_back(PathBuf::from("/root/c"));
+    let mark = results.mark(&second, &dirs);
+    results.add_file((30, "/root/b/big".to_string(), None));
+    results.add_file((40, "/root/b/bigger".to_string(), None));
+    results.add_file((35, "/root/b/large".to_string(), None));
+
+    let state = results.walk_state_before(&second, &mark, &dirs);
+    assert_eq!(top_paths(&state), ["/root/a/one", "/root/a/two"]);
+    assert_eq!(state.scanned_files, 3);
+    assert_eq!(state.queue, ["/root/b", "/root/c"]);
+  }
+}
@@ -0,0 +1,119 @@
+use serde::Serialize;
+use std::collections::VecDeque;
+use std::fs;
+#[cfg(target_family = "unix")]
+use std::os::unix::fs::MetadataExt;
+use std::path::PathBuf;
+
+const MAX_FINDINGS: usize = 1000;
+
+#[cfg(target_family = "unix")]
+const S_ISUID: u32 = 0o4000;
+#[cfg(target_family = "unix")]
+const S_ISGID: u32 = 0o2000;
+#[cfg(target_family = "unix")]
+const S_ISVTX: u32 = 0o1000;
+#[cfg(target_family = "unix")]
+const S_IWOTH: u32 = 0o0002;
+#[cfg(target_family = "unix")]
+const S_IXUSR_GRP_OTH: u32 = 0o0111;
+
+#[derive(Serialize)]
+pub struct SecurityFinding {
+  pub path: String,
+  pub kind: String,
+  pub mode: String,
+}
+
+#[derive(Serialize)]
+pub struct SecurityReport {
+  #[serde(rename = "rootPath")]
+  pub root_path: String,
+  #[serde(rename = "checkedEntries")]
+  pub checked_entries: u64,
+  pub findings: Vec<SecurityFinding>,
+  pub truncated: bool,
+}
+
+#[cfg(target_family = "unix")]
+fn classify(metadata: &fs::Metadata) -> Vec<&'static str> {
+  let mode = metadata.mode();
+  let mut kinds = Vec::new();
+
+  if metadata.is_dir() {
+    if mode & S_IWOTH != 0 && mode & S_ISVTX == 0 {
+      kinds.push("world_writable_dir");
+    }
+    return kinds;
+  }
+
+  if mode & S_IWOTH != 0 {
+    kinds.push("world_writable_file");
+  }
+  if mode & S_IXUSR_GRP_OTH != 0 {
+    if mode & S_ISUID != 0 {
+      kinds.push("setuid");
+    }
+    if mode & S_ISGID != 0 {
+      kinds.push("setgid");
+    }
+  }
+  kinds
+}
+
+#[cfg(target_family = "unix")]
+pub fn security_report(root: PathBuf) -> SecurityReport {
+  let mut report = SecurityReport {
+    root_path: root.to_string_lossy().to_string(),
+    checked_entries: 0,
+    findings: Vec::new(),
+    truncated: false,
+  };
+  let mut dirs = VecDeque::from([root]);
+
+  while let Some(dir) = dirs.pop_front() {
+    let entries = match fs::read_dir(&dir) {
+      Ok(entries) => entries,
+      Err(_) => continue,
+    };
+
+    for entry in entries.flatten() {
+      let metadata = match entry.metadata() {
+        Ok(metadata) => metadata,
+        Err(_) => continue,
+      };
+      if metadata.file_type().is_symlink() {
+        continue;
+      }
+      report.checked_entries += 1;
+
+      for kind in classify(&metadata) {
+        if report.findings.len() >= MAX_FINDINGS {
+          report.truncated = true;
+          break;
+        }
+        report.findings.push(SecurityFinding {
+          path: entry.path().to_string_lossy().to_string(),
+          kind: kind.to_string(),
+          mode: format!("{:o}", metadata.mode() & 0o7777),
+        });
+      }
+
+      if metadata.is_dir() {
+        dirs.push_back(entry.path());
+      }
+    }
+  }
+
+  report
+}
+
+#[cfg(not(target_family = "unix"))]
+pub fn security_report(root: PathBuf) -> SecurityReport {
+  SecurityReport {
+    root_path: root.to_string_lossy().to_string(),
+    checked_entries: 0,
+    findings: Vec::new(),
+    truncated: false,
+  }
+}
@@ -0,0 +1,174 @@
+use crate::budgets::Budget;
+use crate::cache;
+use crate::conflicts::ConflictPolicy;
+use crate::dircache::IncrementalSettings;
+use crate::hashing::HashingSettings;
+use crate::maintenance::CacheRetention;
+use crate::messages::Message;
+use crate::policies::{self, ArchivePolicy};
+use crate::rules::Rule;
+use crate::volumes::{self, MountedVolume};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::path::Path;
+
+const SETTINGS_FILE: &str = "settings.json";
+
+// Runs `command` (payload on stdin) and/or POSTs the payload to `url`. Without a threshold the
+// hook fires after every completed scan; with one, only when the volume's usage crosses it.
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+pub struct Hook {
+  pub command: Option<String>,
+  pub url: Option<String>,
+  #[serde(rename = "thresholdPercent")]
+  pub threshold_percent: Option<f64>,
+}
+
+// How the app treats one volume, e.g. an external backup drive versus the system disk.
+#[derive(Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct VolumeSettings {
+  #[serde(rename = "skipFullScans")]
+  pub skip_full_scans: bool,
+  #[serde(rename = "neverWatch")]
+  pub never_watch: bool,
+  // "delete" (the default) or "trash".
+  #[serde(rename = "deleteMode")]
+  pub delete_mode: Option<String>,
+  // Emits `low_space` after a scan when free space drops below this share of the volume.
+  #[serde(rename = "lowSpacePercent")]
+  pub low_space_percent: Option<f64>,
+}
+
+#[derive(Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Settings {
+  pub hooks: Vec<Hook>,
+  // e.g. "127.0.0.1:9393"; serves Prometheus metrics at /metrics when set.
+  #[serde(rename = "metricsAddress")]
+  pub metrics_address: Option<String>,
+  #[serde(rename = "disabledAnalyzers")]
+  pub disabled_analyzers: Vec<String>,
+  pub rules: Vec<Rule>,
+  pub budgets: Vec<Budget>,
+  // Limits for background hashing such as duplicate detection.
+  pub hashing: HashingSettings,
+  #[serde(rename = "archivePolicies")]
+  pub archive_policies: Vec<ArchivePolicy>,
+  #[serde(rename = "cacheRetention")]
+  pub cache_retention: CacheRetention,
+  #[serde(rename = "incrementalScans")]
+  pub incremental_scans: IncrementalSettings,
+  // How copies and restores settle a taken name unless told otherwise; "skip" when unset.
+  #[serde(rename = "conflictPolicy")]
+  pub conflict_policy: Option<String>,
+  // Keyed by volume UUID, or by mount point for volumes without one.
+  pub volumes: HashMap<String, VolumeSettings>,
+  // Inspection only: every command that deletes, moves, or rewrites files is refused. Once on,
+  // it can only be turned off by editing settings.json.
+  #[serde(rename = "auditMode")]
+  pub audit_mode: bool,
+  // "owner" (the default), "operator" or "viewer": which destructive commands may be confirmed.
+  // Like audit mode, it can only be raised by editing settings.json.
+  pub role: Option<String>,
+}
+
+impl Settings {
+  pub fn for_volume(&self, volume: Option<&MountedVolume>) -> VolumeSettings {
+    let volume = match volume {
+      Some(volume) => volume,
+      None => return VolumeSettings::default(),
+    };
+    volumes::volume_uuid(volume)
+      .and_then(|uuid| self.volumes.get(&uuid))
+      .or_else(|| {
+        self
+          .volumes
+          .get(volume.mount_point.to_string_lossy().as_ref())
+      })
+      .cloned()
+      .unwrap_or_default()
+  }
+
+  pub fn for_path(&self, path: &Path) -> VolumeSettings {
+    self.for_volume(volumes::volume_for(path).as_ref())
+  }
+
+  pub fn metrics_socket(&self) -> Result<Option<SocketAddr>, Message> {
+    self
+      .metrics_address
+      .as_deref()
+      .map(|address| {
+        address
+          .parse()
+          .map_err(|_| Message::new("metrics.invalid_address"))
+      })
+      .transpose()
+  }
+
+  pub fn validate(&self) -> Result<(), Message> {
+    self.metrics_socket()?;
+    policies::validate(&self.archive_policies)?;
+    ConflictPolicy::parse(self.conflict_policy.as_deref())?;
+    if self
+      .rules
+      .iter()
+      .any(|rule| rule.glob.trim().is_empty() || rule.category.trim().is_empty())
+    {
+      return Err(Message::new("rules.incomplete"));
+    }
+    if self
+      .budgets
+      .iter()
+      .any(|budget| budget.path.trim().is_empty() || budget.max_bytes == 0)
+    {
+      return Err(Message::new("budget.incomplete"));
+    }
+    let retention = &self.cache_retention;
+    if retention.max_age_days == Some(0) || retention.max_history_entries == Some(0) {
+      return Err(Message::new("cache.invalid_retention"));
+    }
+    if self.incremental_scans.full_verification_days == Some(0) {
+      return Err(Message::new("cache.invalid_verification_interval"));
+    }
+    for volume in self.volumes.values() {
+      if !matches!(
+        volume.delete_mode.as_deref(),
+        None | Some("delete") | Some("trash")
+      ) {
+        return Err(Message::new("settings.invalid_delete_mode"));
+      }
+      if let Some(threshold) = volume.low_space_percent {
+        if !(0.0..=100.0).contains(&threshold) {
+          return Err(Message::new("settings.invalid_low_space"));
+        }
+      }
+    }
+    for hook in &self.hooks {
+      if hook.command.is_none() && hook.url.is_none() {
+        return Err(Message::new("hooks.incomplete"));
+      }
+      if let Some(url) = &hook.url {
+        if !url.starts_with("http://") && !url.starts_with("https://") {
+          return Err(Message::new("hooks.invalid_url"));
+        }
+      }
+      if let Some(threshold) = hook.threshold_percent {
+        if !(0.0..=100.0).contains(&threshold) {
+          return Err(Message::new("hooks.invalid_threshold"));
+        }
+      }
+    }
+    Ok(())
+  }
+}
+
+pub fn load(app_dir: &Path) -> Settings {
+  cache::read_json(&app_dir.join(SETTINGS_FILE))
+}
+
+pub fn save(app_dir: &Path, settings: &Settings) -> Result<(), Message> {
+  settings.validate()?;
+  cache::write_json(&app_dir.join(SETTINGS_FILE), settings)
+}
@@ -0,0 +1,125 @@
+#[cfg(target_os = "windows")]
+use crate::volumes;
+use serde::Serialize;
+use std::path::Path;
+#[cfg(target_os = "windows")]
+use std::process::{Command, Stdio};
+
+// WMI reports storage that may grow without limit as the largest u64, which `vssadmin` shows as
+// UNBOUNDED.
+#[cfg(any(target_os = "windows", test))]
+const UNBOUNDED: u64 = u64::MAX;
+
+// Each storage association as `storage <diff volume> <used> <allocated> <max>`, and each shadow
+// copy as `copy <volume>`. WMI, unlike `vssadmin`, reports bytes and isn't localized.
+#[cfg(target_os = "windows")]
+const QUERY: &str = "Get-CimInstance Win32_ShadowStorage | ForEach-Object { \
+  \"storage $($_.DiffVolume.DeviceID) $($_.UsedSpace) $($_.AllocatedSpace) $($_.MaxSpace)\" }; \
+  Get-CimInstance Win32_ShadowCopy | ForEach-Object { \"copy $($_.VolumeName)\" }";
+
+// Space Volume Shadow Copies (restore points and Previous Versions) take on a volume. It never
+// shows up in a scan, and regularly explains tens of gigabytes missing from one.
+#[derive(Serialize)]
+pub struct ShadowStorage {
+  #[serde(rename = "usedBytes")]
+  pub used_bytes: u64,
+  #[serde(rename = "allocatedBytes")]
+  pub allocated_bytes: u64,
+  // None when the storage may grow to fill the volume.
+  #[serde(rename = "maxBytes")]
+  pub max_bytes: Option<u64>,
+  // Shadow copies of the volume itself.
+  pub copies: u64,
+}
+
+// Storage for several volumes can live on one, so it is summed over every association whose
+// diff area is on the volume with GUID `guid`.
+#[cfg(any(target_os = "windows", test))]
+fn parse(output: &str, guid: &str) -> Option<ShadowStorage> {
+  let on_volume = |device: &str| device.to_uppercase().contains(&format!("{{{}}}", guid));
+  let mut storage = None;
+  let mut copies = 0;
+  for line in output.lines() {
+    let mut fields = line.split_whitespace();
+    match (fields.next(), fields.next()) {
+      (Some("storage"), Some(device)) if on_volume(device) => {
+        let mut sizes = fields.map(|field| field.parse::<u64>().unwrap_or(0));
+        let (Some(used), Some(allocated), Some(max)) = (sizes.next(), sizes.next(), sizes.next())
+        else {
+          continue;
+        };
+        let total = storage.get_or_insert(ShadowStorage {
+          used_bytes: 0,
+          allocated_bytes: 0,
+          max_bytes: Some(0),
+          copies: 0,
+        });
+        total.used_bytes += used;
+        total.allocated_bytes += allocated;
+        total.max_bytes = total
+          .max_bytes
+          .zip((max != UNBOUNDED).then_some(max))
+          .map(|(total, max)| total.saturating_add(max));
+      }
+      (Some("copy"), Some(device)) if on_volume(device) => copies += 1,
+      _ => {}
+    }
+  }
+  storage.map(|storage| ShadowStorage { copies, ..storage })
+}
+
+// Reading shadow storage needs an elevated process; without one WMI denies the query and this
+// returns None, as it does for volumes without any.
+#[cfg(target_os = "windows")]
+pub fn shadow_storage(path: &Path) -> Option<ShadowStorage> {
+  let guid = volumes::volume_for(path).and_then(|volume| volumes::volume_uuid(&volume))?;
+  let output = Command::new("powershell")
+    .args(["-NoProfile", "-NonInteractive", "-Command", QUERY])
+    .stdin(Stdio::null())
+    .stderr(Stdio::null())
+    .output()
+    .ok()?;
+  parse(&String::from_utf8_lossy(&output.stdout), &guid)
+}
+
+#[cfg(not(target_os = "windows"))]
+pub fn shadow_storage(_path: &Path) -> Option<ShadowStorage> {
+  None
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  const C: &str = "3F2504E0-4F89-11D3-9A0C-0305E82C3301";
+  const D: &str = "6B29FC40-CA47-1067-B31D-00DD010662DA";
+
+  #[test]
+  fn sums_the_storage_on_the_volume_and_counts_its_copies() {
+    let output = format!(
+      "storage \\\\?\\Volume{{{c}}}\\ 100 200 1000\n\
+       storage \\\\?\\Volume{{{c}}}\\ 5 10 {unbounded}\n\
+       storage \\\\?\\Volume{{{d}}}\\ 7 7 7\n\
+       copy \\\\?\\Volume{{{c}}}\\\n\
+       copy \\\\?\\Volume{{{c}}}\\\n\
+       copy \\\\?\\Volume{{{d}}}\\\n",
+      c = C.to_lowercase(),
+      d = D,
+      unbounded = UNBOUNDED
+    );
+    let storage = parse(&output, C).unwrap();
+    assert_eq!((storage.used_bytes, storage.allocated_bytes), (105, 210));
+    assert_eq!(storage.max_bytes, None);
+    assert_eq!(storage.copies, 2);
+
+    let storage = parse(&output, D).unwrap();
+    assert_eq!(storage.max_bytes, Some(7));
+    assert_eq!(storage.copies, 1);
+  }
+
+  #[test]
+  fn volumes_without_storage_report_none() {
+    assert!(parse("", C).is_none());
+    assert!(parse(&format!("copy \\\\?\\Volume{{{}}}\\", C), C).is_none());
+  }
+}
@@ -0,0 +1,164 @@
+use crate::dataless::NoMaterialize;
+use crate::disk_image;
+use crate::filesystem;
+use crate::messages::Message;
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
+use std::fs;
+#[cfg(target_family = "unix")]
+use std::os::unix::fs::MetadataExt;
+use std::path::{Path, PathBuf};
+
+// How much of a folder's data is its own versus shared through clones or reflinks, so a
+// "delete this folder" estimate holds on filesystems that share blocks between files.
+#[derive(Default, Serialize)]
+pub struct SharingReport {
+  pub path: String,
+  pub files: u64,
+  // Per file, so blocks shared between files are counted once for each of them.
+  #[serde(rename = "allocatedBytes")]
+  pub allocated_bytes: u64,
+  // Used by a single file and nothing else; always freed by deleting the folder.
+  #[serde(rename = "uniqueBytes")]
+  pub unique_bytes: u64,
+  // Shared blocks, each counted once. Freed only if nothing outside the folder uses them.
+  #[serde(rename = "sharedBytes")]
+  pub shared_bytes: u64,
+  // Further references to shared blocks already counted above.
+  #[serde(rename = "sharedWithinBytes")]
+  pub shared_within_bytes: u64,
+  #[serde(rename = "minFreedBytes")]
+  pub min_freed_bytes: u64,
+  #[serde(rename = "maxFreedBytes")]
+  pub max_freed_bytes: u64,
+  // False where the filesystem can't share blocks or doesn't say which are shared; everything
+  // then counts as unique.
+  pub supported: bool,
+}
+
+#[cfg(target_family = "unix")]
+fn inode(metadata: &fs::Metadata) -> Option<(u64, u64)> {
+  Some((metadata.dev(), metadata.ino()))
+}
+
+#[cfg(not(target_family = "unix"))]
+fn inode(_metadata: &fs::Metadata) -> Option<(u64, u64)> {
+  None
+}
+
+// Regular files under `root`, each hard-linked inode once.
+fn files(root: &Path) -> Vec<(PathBuf, fs::Metadata)> {
+  let mut seen: HashSet<(u64, u64)> = HashSet::new();
+  let mut files = Vec::new();
+  let mut pending = vec![root.to_path_buf()];
+  while let Some(path) = pending.pop() {
+    let metadata = match fs::symlink_metadata(&path) {
+      Ok(metadata) => metadata,
+      Err(_) => continue,
+    };
+    if metadata.is_dir() {
+      if let Ok(entries) = fs::read_dir(&path) {
+        pending.extend(entries.flatten().map(|entry| entry.path()));
+      }
+      continue;
+    }
+    if !metadata.is_file() {
+      continue;
+    }
+    if let Some(inode) = inode(&metadata) {
+      if !seen.insert(inode) {
+        continue;
+      }
+    }
+    files.push((path, metadata));
+  }
+  files
+}
+
+// Shared blocks counted once, by physical extent.
+#[cfg(target_os = "linux")]
+fn tally(files: &[(PathBuf, fs::Metadata)], report: &mut SharingReport) {
+  let mut shared: HashMap<(u64, u64), u64> = HashMap::new();
+  for (path, metadata) in files {
+    let extents = match filesystem::extents(path) {
+      Some(extents) => extents,
+      None => {
+        report.unique_bytes += disk_image::allocated_size(metadata);
+        continue;
+      }
+    };
+    for extent in extents {
+      if extent.shared {
+        let length = shared.entry((metadata.dev(), extent.physical)).or_default();
+        *length = (*length).max(extent.length);
+      } else {
+        report.unique_bytes += extent.length;
+      }
+    }
+  }
+  report.shared_bytes = shared.values().sum();
+}
+
+// APFS reports each file's private bytes; the rest is shared, counted once per clone family.
+#[cfg(target_os = "macos")]
+fn tally(files: &[(PathBuf, fs::Metadata)], report: &mut SharingReport) {
+  let mut families: HashMap<(u64, u64), u64> = HashMap::new();
+  for (path, metadata) in files {
+    let allocated = disk_image::allocated_size(metadata);
+    let private = filesystem::private_size(path)
+      .unwrap_or(allocated)
+      .min(allocated);
+    report.unique_bytes += private;
+    let shared = allocated - private;
+    if shared == 0 {
+      continue;
+    }
+    match filesystem::clone_family(path) {
+      Some(family) => {
+        let largest = families.entry(family).or_default();
+        *largest = (*largest).max(shared);
+      }
+      None => report.shared_bytes += shared,
+    }
+  }
+  report.shared_bytes += families.values().sum::<u64>();
+}
+
+#[cfg(not(any(target_os = "macos", target_os = "linux")))]
+fn tally(files: &[(PathBuf, fs::Metadata)], report: &mut SharingReport) {
+  report.unique_bytes = files
+    .iter()
+    .map(|(_, metadata)| disk_image::allocated_size(metadata))
+    .sum();
+}
+
+pub fn analyze(root: &Path) -> Result<SharingReport, Message> {
+  if !root.exists() {
+    return Err(Message::new("path.missing"));
+  }
+  let _no_materialize = NoMaterialize::enter();
+  let files = files(root);
+  let mut report = SharingReport {
+    path: root.to_string_lossy().to_string(),
+    files: files.len() as u64,
+    allocated_bytes: files
+      .iter()
+      .map(|(_, metadata)| disk_image::allocated_size(metadata))
+      .sum(),
+    supported: filesystem::filesystem_info(root)
+      .filter(|info| info.supports_clones)
+      .is_some(),
+    ..SharingReport::default()
+  };
+  if report.supported {
+    tally(&files, &mut report);
+  } else {
+    report.unique_bytes = report.allocated_bytes;
+  }
+  report.shared_within_bytes = report
+    .allocated_bytes
+    .saturating_sub(report.unique_bytes + report.shared_bytes);
+  report.min_freed_bytes = report.unique_bytes;
+  report.max_freed_bytes = report.unique_bytes + report.shared_bytes;
+  Ok(report)
+}
@@ -0,0 +1,196 @@
+use crate::messages::Message;
+use serde::Serialize;
+use std::fs::DirEntry;
+#[cfg(target_os = "linux")]
+use std::os::unix::fs::DirEntryExt;
+#[cfg(target_os = "linux")]
+use std::os::unix::io::AsRawFd;
+#[cfg(target_os = "linux")]
+use std::path::Path;
+#[cfg(target_os = "linux")]
+use std::process::Command;
+
+#[cfg(target_os = "linux")]
+const BTRFS_FIRST_FREE_OBJECTID: u64 = 256;
+#[cfg(target_os = "linux")]
+const BTRFS_IOC_SUBVOL_GETFLAGS: libc::c_ulong = 0x8008_9419;
+#[cfg(target_os = "linux")]
+const BTRFS_SUBVOL_RDONLY: u64 = 1 << 1;
+
+#[derive(Serialize)]
+pub struct SnapshotEntry {
+  pub name: String,
+  pub kind: String,
+  #[serde(rename = "referencedBytes")]
+  pub referenced_bytes: Option<u64>,
+  #[serde(rename = "uniqueBytes")]
+  pub unique_bytes: Option<u64>,
+}
+
+#[cfg(target_os = "linux")]
+fn is_btrfs_snapshot(path: &Path) -> bool {
+  let dir = match std::fs::File::open(path) {
+    Ok(dir) => dir,
+    Err(_) => return false,
+  };
+  let mut flags: u64 = 0;
+  let result = unsafe { libc::ioctl(dir.as_raw_fd(), BTRFS_IOC_SUBVOL_GETFLAGS, &mut flags) };
+  result == 0 && flags & BTRFS_SUBVOL_RDONLY != 0
+}
+
+#[cfg(target_os = "linux")]
+pub fn is_snapshot_dir(entry: &DirEntry) -> bool {
+  if entry.file_name() == ".zfs" {
+    return true;
+  }
+  entry.ino() == BTRFS_FIRST_FREE_OBJECTID && is_btrfs_snapshot(&entry.path())
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn is_snapshot_dir(_entry: &DirEntry) -> bool {
+  false
+}
+
+#[cfg(target_os = "linux")]
+fn command_output(command: &mut Command) -> Option<String> {
+  let output = command.output().ok()?;
+  if !output.status.success() {
+    return None;
+  }
+  Some(String::from_utf8_lossy(&output.stdout).to_string())
+}
+
+#[cfg(target_os = "linux")]
+fn zfs_snapshots() -> Option<Vec<SnapshotEntry>> {
+  let output = command_output(
+    Command::new("zfs")
+      .arg("list")
+      .arg("-Hp")
+      .arg("-t")
+      .arg("filesystem,snapshot")
+      .arg("-o")
+      .arg("name,type,used,referenced"),
+  )?;
+
+  // With -Hp each line is tab separated: name, type, used (unique), referenced.
+  Some(
+    output
+      .lines()
+      .filter_map(|line| {
+        let columns: Vec<&str> = line.split('\t').collect();
+        match columns.as_slice() {
+          [name, kind, used, referenced] => Some(SnapshotEntry {
+            name: name.to_string(),
+            kind: if *kind == "snapshot" {
+              "zfs_snapshot".to_string()
+            } else {
+              "zfs_dataset".to_string()
+            },
+            referenced_bytes: referenced.parse().ok(),
+            unique_bytes: used.parse().ok(),
+          }),
+          _ => None,
+        }
+      })
+      .collect(),
+  )
+}
+
+#[cfg(target_os = "linux")]
+fn btrfs_subvolumes(root: &Path) -> Option<Vec<SnapshotEntry>> {
+  let subvolumes = command_output(Command::new("btrfs").arg("subvolume").arg("list").arg(root))?;
+  let snapshots = command_output(
+    Command::new("btrfs")
+      .arg("subvolume")
+      .arg("list")
+      .arg("-s")
+      .arg(root),
+  )
+  .unwrap_or_default();
+  let qgroups = command_output(
+    Command::new("btrfs")
+      .arg("qgroup")
+      .arg("show")
+      .arg("--raw")
+      .arg(root),
+  )
+  .unwrap_or_default();
+
+  // Subvolume lines look like "ID 257 gen 10 top level 5 path home".
+  let parse_id_path = |line: &str| -> Option<(String, String)> {
+    let mut words = line.split_whitespace();
+    if words.next()? != "ID" {
+      return None;
+    }
+    let id = words.next()?.to_string();
+    let path = line.split_once(" path ")?.1.to_string();
+    Some((id, path))
+  };
+
+  let snapshot_ids: Vec<String> = snapshots
+    .lines()
+    .filter_map(parse_id_path)
+    .map(|(id, _)| id)
+    .collect();
+
+  // Qgroup lines look like "0/257 16384 16384" (referenced, exclusive).
+  let qgroup_sizes = |id: &str| -> (Option<u64>, Option<u64>) {
+    let qgroup_id = format!("0/{}", id);
+    qgroups
+      .lines()
+      .map(|line| line.split_whitespace().collect::<Vec<_>>())
+      .find(|columns| columns.first() == Some(&qgroup_id.as_str()))
+      .map(|columns| {
+        (
+          columns.get(1).and_then(|value| value.parse().ok()),
+          columns.get(2).and_then(|value| value.parse().ok()),
+        )
+      })
+      .unwrap_or((None, None))
+  };
+
+  Some(
+    subvolumes
+      .lines()
+      .filter_map(parse_id_path)
+      .map(|(id, path)| {
+        let (referenced_bytes, unique_bytes) = qgroup_sizes(&id);
+        SnapshotEntry {
+          name: path,
+          kind: if snapshot_ids.contains(&id) {
+            "btrfs_snapshot".to_string()
+          } else {
+            "btrfs_subvolume".to_string()
+          },
+          referenced_bytes,
+          unique_bytes,
+        }
+      })
+      .collect(),
+  )
+}
+
+#[cfg(target_os = "linux")]
+pub fn list_snapshots(root: &Path) -> Result<Vec<SnapshotEntry>, Message> {
+  let mut entries = Vec::new();
+  let mut found = false;
+
+  if let Some(subvolumes) = btrfs_subvolumes(root) {
+    found = true;
+    entries.extend(subvolumes);
+  }
+  if let Some(datasets) = zfs_snapshots() {
+    found = true;
+    entries.extend(datasets);
+  }
+
+  if !found {
+    return Err(Message::new("snapshots.no_tooling"));
+  }
+  Ok(entries)
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn list_snapshots(_root: &std::path::Path) -> Result<Vec<SnapshotEntry>, Message> {
+  Err(Message::new("snapshots.linux_only"))
+}
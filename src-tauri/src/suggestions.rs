@@ -0,0 +1,244 @@
+use crate::analyzers::Registry;
+use crate::checksums::ChecksumDb;
+use crate::hashing::{self, HashThrottle};
+use crate::messages::Message;
+use crate::plan::{self, PlanFailure};
+use crate::scanner::FileEntry;
+use crate::settings::Settings;
+use crate::{system_usage, trash};
+use serde::Serialize;
+use std::cmp::Reverse;
+use std::fs;
+use std::path::Path;
+
+const MAX_SUGGESTION_PATHS: usize = 5000;
+// Files removed between `suggestion_progress` events.
+const PROGRESS_EVERY: usize = 25;
+
+#[derive(Clone, Serialize)]
+pub struct Suggestion {
+  pub id: String,
+  pub category: String,
+  pub title: Message,
+  pub analyzer: String,
+  #[serde(rename = "estimatedBytes")]
+  pub estimated_bytes: u64,
+  pub paths: Vec<String>,
+  // Applied by adding the paths to the cleanup plan, or directly with `execute_suggestion`.
+  pub action: String,
+  // For duplicates, the copy that stays.
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub kept: Option<KeptCopy>,
+}
+
+// What every copy in a duplicate group measured when it was suggested.
+#[derive(Clone, Serialize)]
+pub struct KeptCopy {
+  pub path: String,
+  pub size: u64,
+  pub sha256: String,
+}
+
+impl KeptCopy {
+  // Whether `path`, `size` bytes long, still holds the same contents as the kept copy did.
+  fn matches(&self, path: &Path, size: u64) -> Result<(), Message> {
+    if size != self.size {
+      return Err(Message::new("plan.size_changed"));
+    }
+    if hashing::sha256_file(path)? != self.sha256 {
+      return Err(Message::new("plan.contents_changed"));
+    }
+    Ok(())
+  }
+}
+
+impl Suggestion {
+  pub fn keeping(mut self, kept: KeptCopy) -> Self {
+    self.kept = Some(kept);
+    self
+  }
+}
+
+#[derive(Default)]
+pub struct Candidate {
+  pub bytes: u64,
+  pub paths: Vec<String>,
+}
+
+impl Candidate {
+  pub fn add(&mut self, path: &Path, size: u64) {
+    if self.paths.len() < MAX_SUGGESTION_PATHS {
+      self.bytes += size;
+      self.paths.push(path.to_string_lossy().to_string());
+    }
+  }
+
+  pub fn into_suggestion(self, id: String, category: &str, title: Message) -> Suggestion {
+    Suggestion {
+      id,
+      category: category.to_string(),
+      title,
+      analyzer: String::new(),
+      estimated_bytes: self.bytes,
+      paths: self.paths,
+      action: "add_to_plan".to_string(),
+      kept: None,
+    }
+  }
+}
+
+// Ranked by estimated savings, largest first.
+pub fn suggestions(
+  root: &Path,
+  top_files: &[FileEntry],
+  disabled_analyzers: &[String],
+  hashing: &HashThrottle,
+  checksums: &ChecksumDb,
+) -> Vec<Suggestion> {
+  let mut registry = Registry::default();
+  registry.disable(disabled_analyzers);
+  let mut suggestions = registry.run(root, top_files, hashing, checksums);
+  let system_files = system_usage::system_file_paths();
+  suggestions.retain(|suggestion| {
+    suggestion.estimated_bytes > 0
+      && !suggestion
+        .paths
+        .iter()
+        .any(|path| system_files.contains(path))
+  });
+  suggestions.sort_by_key(|suggestion| Reverse(suggestion.estimated_bytes));
+  suggestions
+}
+
+#[derive(Clone, Serialize)]
+pub struct SuggestionProgress {
+  #[serde(rename = "suggestionId")]
+  pub suggestion_id: String,
+  pub done: usize,
+  pub total: usize,
+  #[serde(rename = "freedBytes")]
+  pub freed_bytes: u64,
+  #[serde(rename = "currentPath")]
+  pub current_path: String,
+}
+
+#[derive(Serialize)]
+pub struct SuggestionReport {
+  #[serde(rename = "suggestionId")]
+  pub suggestion_id: String,
+  pub category: String,
+  #[serde(rename = "estimatedBytes")]
+  pub estimated_bytes: u64,
+  #[serde(rename = "removedFiles")]
+  pub removed_files: u64,
+  #[serde(rename = "removedBytes")]
+  pub removed_bytes: u64,
+  // Given back right away, and still held in the trash until it's emptied.
+  #[serde(rename = "freedBytes")]
+  pub freed_bytes: u64,
+  #[serde(rename = "trashedBytes")]
+  pub trashed_bytes: u64,
+  // Cache subfolders left empty and removed.
+  #[serde(rename = "prunedFolders")]
+  pub pruned_folders: u64,
+  pub failures: Vec<PlanFailure>,
+}
+
+// Removes the folders between `path` and `stop` that are empty once `path` is gone.
+fn prune_empty_parents(path: &Path, stop: &Path) -> u64 {
+  let mut pruned = 0;
+  let mut dir = path.parent();
+  while let Some(current) = dir {
+    if current == stop || !current.starts_with(stop) || fs::remove_dir(current).is_err() {
+      break;
+    }
+    pruned += 1;
+    dir = current.parent();
+  }
+  pruned
+}
+
+// Applies a suggestion's cleanup: its files are removed the way `delete_file` removes them (to
+// the trash where the volume asks for it), and a cache suggestion also prunes the subfolders of
+// the cache it empties. Like the cleanup plan, nothing runs while a file on a volume without a
+// usable trash still needs `confirmed_permanent`. A duplicate group is skipped unless its kept
+// copy is still there unchanged, and each extra copy is only removed while it still matches it.
+// `removed` is called for each file removed.
+pub fn execute(
+  suggestion: &Suggestion,
+  settings: &Settings,
+  cache_dir: &Path,
+  confirmed_permanent: bool,
+  removed: &mut dyn FnMut(&Path),
+  progress: &mut dyn FnMut(&SuggestionProgress),
+) -> Result<SuggestionReport, Message> {
+  if !confirmed_permanent {
+    if let Some(path) = suggestion
+      .paths
+      .iter()
+      .find(|path| trash::trash_unavailable(Path::new(path), settings))
+    {
+      return Err(Message::new(trash::PERMANENT_DELETE_UNCONFIRMED).with("path", path));
+    }
+  }
+  if let Some(kept) = &suggestion.kept {
+    let path = Path::new(&kept.path);
+    plan::regular_file_size(path)
+      .and_then(|size| kept.matches(path, size))
+      .map_err(|_| Message::new("suggestion.kept_changed").with("path", &kept.path))?;
+  }
+  let cache_folder = suggestion
+    .id
+    .strip_prefix("caches:")
+    .filter(|_| suggestion.category == "caches")
+    .map(Path::new);
+
+  let mut report = SuggestionReport {
+    suggestion_id: suggestion.id.clone(),
+    category: suggestion.category.clone(),
+    estimated_bytes: suggestion.estimated_bytes,
+    removed_files: 0,
+    removed_bytes: 0,
+    freed_bytes: 0,
+    trashed_bytes: 0,
+    pruned_folders: 0,
+    failures: Vec::new(),
+  };
+  let total = suggestion.paths.len();
+  for (index, path) in suggestion.paths.iter().enumerate() {
+    let file = Path::new(path);
+    let result = plan::regular_file_size(file)
+      .and_then(|size| match &suggestion.kept {
+        Some(kept) => kept.matches(file, size),
+        None => Ok(()),
+      })
+      .and_then(|_| trash::remove(file, settings, cache_dir, confirmed_permanent));
+    match result {
+      Ok(removal) => {
+        report.removed_files += 1;
+        report.removed_bytes += removal.removed_bytes;
+        report.freed_bytes += removal.freed_bytes;
+        report.trashed_bytes += removal.trashed_bytes;
+        removed(file);
+        if let Some(folder) = cache_folder {
+          report.pruned_folders += prune_empty_parents(file, folder);
+        }
+      }
+      Err(error) => report.failures.push(PlanFailure {
+        path: path.clone(),
+        error,
+      }),
+    }
+    let done = index + 1;
+    if done.is_multiple_of(PROGRESS_EVERY) || done == total {
+      progress(&SuggestionProgress {
+        suggestion_id: suggestion.id.clone(),
+        done,
+        total,
+        freed_bytes: report.freed_bytes,
+        current_path: path.clone(),
+      });
+    }
+  }
+  Ok(report)
+}
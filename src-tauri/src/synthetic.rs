@@ -0,0 +1,429 @@
+use crate::messages::Message;
+use crate::scanner::ScanSummary;
+use std::fs;
+#[cfg(target_family = "unix")]
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
+
+// The shape of a tree to build. Files are sparse, so even large trees are quick to build and
+// take almost no disk space.
+#[derive(Clone, Debug)]
+pub struct TreeSpec {
+  pub depth: usize,
+  pub dirs_per_dir: usize,
+  pub files_per_dir: usize,
+  pub min_file_bytes: u64,
+  pub max_file_bytes: u64,
+  // Spread over the tree; symlinks point at files and are never followed by the scanner.
+  pub symlinks: usize,
+  pub hard_links: usize,
+  // Directories made unreadable (mode 000) to exercise permission errors.
+  pub unreadable_dirs: usize,
+  // Same seed, same sizes.
+  pub seed: u64,
+}
+
+impl Default for TreeSpec {
+  fn default() -> Self {
+    Self {
+      depth: 3,
+      dirs_per_dir: 3,
+      files_per_dir: 10,
+      min_file_bytes: 0,
+      max_file_bytes: 64 * 1024,
+      symlinks: 0,
+      hard_links: 0,
+      unreadable_dirs: 0,
+      seed: 1,
+    }
+  }
+}
+
+// What a scan of `root` should report. Dropping it deletes the tree.
+pub struct SyntheticTree {
+  pub root: PathBuf,
+  // Regular files the scanner can reach, hard links included.
+  pub files: u64,
+  pub bytes: u64,
+  // Sizes of those files, largest first.
+  pub sizes: Vec<u64>,
+  unreadable: Vec<PathBuf>,
+}
+
+impl Drop for SyntheticTree {
+  fn drop(&mut self) {
+    #[cfg(target_family = "unix")]
+    for dir in &self.unreadable {
+      let _ = fs::set_permissions(dir, fs::Permissions::from_mode(0o755));
+    }
+    let _ = fs::remove_dir_all(&self.root);
+  }
+}
+
+// xorshift64*, enough to spread file sizes reproducibly.
+struct Sizes(u64);
+
+impl Sizes {
+  fn next(&mut self, min: u64, max: u64) -> u64 {
+    self.0 ^= self.0 >> 12;
+    self.0 ^= self.0 << 25;
+    self.0 ^= self.0 >> 27;
+    let value = self.0.wrapping_mul(0x2545_f491_4f6c_dd1d);
+    min + value % (max.saturating_sub(min) + 1)
+  }
+}
+
+struct Builder<'a> {
+  spec: &'a TreeSpec,
+  sizes: Sizes,
+  // With their depth below the root.
+  dirs: Vec<(PathBuf, usize)>,
+  // Every regular file and hard link, with its size.
+  files: Vec<(PathBuf, u64)>,
+}
+
+impl Builder<'_> {
+  fn build_dir(&mut self, dir: &Path, level: usize) -> Result<(), Message> {
+    fs::create_dir_all(dir).map_err(|_| Message::new("synthetic.create_folder_failed"))?;
+    self.dirs.push((dir.to_path_buf(), level));
+    for index in 0..self.spec.files_per_dir {
+      let path = dir.join(format!("file-{:04}.bin", index));
+      let size = self
+        .sizes
+        .next(self.spec.min_file_bytes, self.spec.max_file_bytes);
+      fs::File::create(&path)
+        .and_then(|file| file.set_len(size))
+        .map_err(|_| Message::new("synthetic.create_file_failed"))?;
+      self.files.push((path, size));
+    }
+    if level < self.spec.depth {
+      for index in 0..self.spec.dirs_per_dir {
+        self.build_dir(&dir.join(format!("dir-{:03}", index)), level + 1)?;
+      }
+    }
+    Ok(())
+  }
+
+  // Links go in the next folder over, so they are found in a different directory than their
+  // target.
+  fn link(&mut self) -> Result<(), Message> {
+    let originals = self.files.len();
+    if originals == 0 {
+      return Ok(());
+    }
+    for index in 0..self.spec.hard_links {
+      let (target, size) = self.files[index % originals].clone();
+      let dir = &self.dirs[(index + 1) % self.dirs.len()].0;
+      let link = dir.join(format!("hardlink-{:04}.bin", index));
+      fs::hard_link(target, &link)
+        .map_err(|_| Message::new("synthetic.create_hard_link_failed"))?;
+      self.files.push((link, size));
+    }
+    #[cfg(target_family = "unix")]
+    for index in 0..self.spec.symlinks {
+      let target = &self.files[index % originals].0;
+      let dir = &self.dirs[(index + 1) % self.dirs.len()].0;
+      std::os::unix::fs::symlink(target, dir.join(format!("symlink-{:04}", index)))
+        .map_err(|_| Message::new("synthetic.create_symlink_failed"))?;
+    }
+    Ok(())
+  }
+}
+
+// Builds a fresh tree named `name` inside `parent`, replacing any previous one.
+pub fn build(parent: &Path, name: &str, spec: &TreeSpec) -> Result<SyntheticTree, Message> {
+  let root = parent.join(name);
+  if root.exists() {
+    fs::remove_dir_all(&root).map_err(|_| Message::new("synthetic.clear_failed"))?;
+  }
+  let mut builder = Builder {
+    spec,
+    sizes: Sizes(spec.seed.max(1)),
+    dirs: Vec::new(),
+    files: Vec::new(),
+  };
+  builder.build_dir(&root, 0)?;
+  builder.link()?;
+
+  // Only leaf folders are locked, so nothing below them needs accounting for. Their contents
+  // drop out of the expected totals unless permissions are not enforced (e.g. as root).
+  let mut unreadable = Vec::new();
+  let mut hidden = Vec::new();
+  #[cfg(target_family = "unix")]
+  for (dir, _) in builder
+    .dirs
+    .iter()
+    .filter(|(_, level)| *level == spec.depth)
+    .take(spec.unreadable_dirs)
+  {
+    fs::set_permissions(dir, fs::Permissions::from_mode(0o000))
+      .map_err(|_| Message::new("synthetic.lock_failed"))?;
+    unreadable.push(dir.clone());
+    if fs::read_dir(dir).is_err() {
+      hidden.push(dir.clone());
+    }
+  }
+
+  let mut reachable: Vec<u64> = builder
+    .files
+    .iter()
+    .filter(|(path, _)| {
+      !hidden
+        .iter()
+        .any(|dir| path.parent() == Some(dir.as_path()))
+    })
+    .map(|(_, size)| *size)
+    .collect();
+  reachable.sort_unstable_by(|a, b| b.cmp(a));
+  Ok(SyntheticTree {
+    root,
+    files: reachable.len() as u64,
+    bytes: reachable.iter().sum(),
+    sizes: reachable,
+    unreadable,
+  })
+}
+
+impl SyntheticTree {
+  // Differences between a scan of the tree and what it should have found.
+  pub fn mismatches(&self, summary: &ScanSummary) -> Vec<String> {
+    let mut mismatches = Vec::new();
+    if summary.scanned_files != self.files {
+      mismatches.push(format!(
+        "scanned {} files, expected {}",
+        summary.scanned_files, self.files
+      ));
+    }
+    if summary.scanned_bytes != self.bytes {
+      mismatches.push(format!(
+        "scanned {} bytes, expected {}",
+        summary.scanned_bytes, self.bytes
+      ));
+    }
+    // The scanner's bounded heap against a full sort of every size.
+    let top: Vec<u64> = summary.top_files.iter().map(|file| file.size).collect();
+    let expected = &self.sizes[..top.len().min(self.sizes.len())];
+    if top != expected {
+      mismatches.push(format!("top file sizes {:?}, expected {:?}", top, expected));
+    }
+    mismatches
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::dircache::{DirCache, IncrementalSettings};
+  use crate::savings;
+  use crate::scanner;
+  use proptest::prelude::*;
+  use std::sync::atomic::AtomicBool;
+  use std::thread;
+  use std::time::Duration;
+
+  // The default shape with every kind of odd entry.
+  fn odd_spec() -> TreeSpec {
+    TreeSpec {
+      symlinks: 5,
+      hard_links: 5,
+      unreadable_dirs: 1,
+      ..TreeSpec::default()
+    }
+  }
+
+  // Small trees of any shape, so each case builds and scans in a few milliseconds.
+  fn tree_specs() -> impl Strategy<Value = TreeSpec> {
+    (
+      (0..4usize, 0..4usize, 0..12usize),
+      (0..24u32, 0..5usize, 0..5usize, 0..2usize),
+      any::<u64>(),
+    )
+      .prop_map(
+        |(
+          (depth, dirs_per_dir, files_per_dir),
+          (size_bits, symlinks, hard_links, unreadable_dirs),
+          seed,
+        )| {
+          TreeSpec {
+            depth,
+            dirs_per_dir,
+            files_per_dir,
+            min_file_bytes: 0,
+            max_file_bytes: 1 << size_bits,
+            symlinks,
+            hard_links,
+            unreadable_dirs,
+            seed,
+          }
+        },
+      )
+  }
+
+  fn scan(root: &Path, top_n: usize) -> ScanSummary {
+    scanner::scan_subtree(root, &AtomicBool::new(false), top_n).expect("scan was cancelled")
+  }
+
+  fn add_file(folder: &Path, name: &str, size: u64) {
+    fs::File::create(folder.join(name))
+      .and_then(|file| file.set_len(size))
+      .unwrap();
+  }
+
+  // The first folder below the root, or the root of a tree without any.
+  fn first_folder(tree: &SyntheticTree) -> PathBuf {
+    let folder = tree.root.join("dir-000");
+    if folder.is_dir() {
+      folder
+    } else {
+      tree.root.clone()
+    }
+  }
+
+  #[test]
+  fn full_and_repeat_scans_match_the_tree() {
+    let temp = tempfile::tempdir().unwrap();
+    let tree = build(temp.path(), "tree", &odd_spec()).unwrap();
+    for _ in 0..2 {
+      let mismatches = tree.mismatches(&scan(&tree.root, 10));
+      assert!(mismatches.is_empty(), "{:?}", mismatches);
+    }
+  }
+
+  #[test]
+  fn cancelled_scan_returns_nothing() {
+    let temp = tempfile::tempdir().unwrap();
+    let tree = build(temp.path(), "tree", &TreeSpec::default()).unwrap();
+    assert!(scanner::scan_subtree(&tree.root, &AtomicBool::new(true), 10).is_none());
+    // With listing threads, in both traversal orders.
+    for sorted in [false, true] {
+      let cancel = AtomicBool::new(true);
+      assert!(scanner::scan_subtree_with(&tree.root, &cancel, 10, 4, sorted, None).is_none());
+    }
+  }
+
+  // Folders wider than the listing threads may stat ahead of the walk.
+  #[test]
+  fn threaded_scans_of_wide_folders_match_the_tree() {
+    let temp = tempfile::tempdir().unwrap();
+    let spec = TreeSpec {
+      depth: 1,
+      dirs_per_dir: 3,
+      files_per_dir: 5000,
+      ..TreeSpec::default()
+    };
+    let tree = build(temp.path(), "tree", &spec).unwrap();
+    for sorted in [false, true] {
+      let summary =
+        scanner::scan_subtree_with(&tree.root, &AtomicBool::new(false), 10, 2, sorted, None)
+          .expect("scan was cancelled");
+      let mismatches = tree.mismatches(&summary);
+      assert!(mismatches.is_empty(), "{:?}", mismatches);
+    }
+  }
+
+  #[test]
+  fn rescan_counts_an_added_file() {
+    let temp = tempfile::tempdir().unwrap();
+    let tree = build(temp.path(), "tree", &TreeSpec::default()).unwrap();
+    let folder = first_folder(&tree);
+    let before = scan(&folder, 0);
+    add_file(&folder, "added.bin", 4096);
+    let after = scan(&folder, 0);
+    assert_eq!(after.scanned_files, before.scanned_files + 1);
+    assert_eq!(after.scanned_bytes, before.scanned_bytes + 4096);
+  }
+
+  // A first pass that reads everything, a second that reuses it, and a third after a file is
+  // added below a reused folder. Each must agree with a plain scan.
+  #[test]
+  fn dir_cache_scans_agree_with_plain_scans() {
+    let temp = tempfile::tempdir().unwrap();
+    let tree = build(temp.path(), "tree", &TreeSpec::default()).unwrap();
+    let cache_dir = temp.path().join("cache");
+    fs::create_dir_all(&cache_dir).unwrap();
+    let settings = IncrementalSettings {
+      enabled: true,
+      full_verification_days: None,
+    };
+    let pass = || {
+      let dir_cache = DirCache::open(&cache_dir, &settings);
+      let cached = scanner::scan_subtree_with(
+        &tree.root,
+        &AtomicBool::new(false),
+        10,
+        1,
+        false,
+        Some(dir_cache),
+      )
+      .expect("scan was cancelled");
+      let plain = scan(&tree.root, 10);
+      assert_eq!(
+        (cached.scanned_files, cached.scanned_bytes),
+        (plain.scanned_files, plain.scanned_bytes)
+      );
+      cached
+    };
+
+    pass();
+    let second = pass();
+    assert!(second
+      .incremental
+      .as_ref()
+      .is_some_and(|stats| stats.reused_dirs > 0));
+
+    // Folder timestamps can be a few milliseconds coarse; the change must land in a later tick.
+    thread::sleep(Duration::from_millis(20));
+    let nested = first_folder(&tree).join("dir-000");
+    let folder = if nested.is_dir() {
+      nested
+    } else {
+      first_folder(&tree)
+    };
+    add_file(&folder, "cached-added.bin", 8192);
+    let third = pass();
+    assert_eq!(third.scanned_files, second.scanned_files + 1);
+  }
+
+  proptest! {
+    #![proptest_config(ProptestConfig::with_cases(48))]
+
+    // The scanner's bounded heap against a full sort of every size, plus the totals.
+    #[test]
+    fn scans_match_any_tree(spec in tree_specs(), top_n in 0..64usize) {
+      let temp = tempfile::tempdir().unwrap();
+      let tree = build(temp.path(), "tree", &spec).unwrap();
+      let mismatches = tree.mismatches(&scan(&tree.root, top_n));
+      prop_assert!(mismatches.is_empty(), "{:?}", mismatches);
+    }
+
+    // A folder's totals are its own files plus the totals of each subfolder scanned on its own.
+    #[test]
+    fn children_add_up_to_their_parent(spec in tree_specs()) {
+      let temp = tempfile::tempdir().unwrap();
+      let tree = build(temp.path(), "tree", &spec).unwrap();
+      let whole = scan(&tree.root, 0);
+      let mut parts = (0, 0);
+      for entry in fs::read_dir(&tree.root).unwrap().flatten() {
+        let file_type = entry.file_type().unwrap();
+        if file_type.is_dir() {
+          let child = scan(&entry.path(), 0);
+          parts = (parts.0 + child.scanned_files, parts.1 + child.scanned_bytes);
+        } else if file_type.is_file() {
+          parts = (parts.0 + 1, parts.1 + entry.metadata().unwrap().len());
+        }
+      }
+      prop_assert_eq!(parts, (whole.scanned_files, whole.scanned_bytes));
+    }
+
+    // Counting each hard-linked file once can only lower the totals.
+    #[test]
+    fn hard_link_dedupe_never_exceeds_the_totals(spec in tree_specs()) {
+      let temp = tempfile::tempdir().unwrap();
+      let tree = build(temp.path(), "tree", &spec).unwrap();
+      let whole = scan(&tree.root, 0);
+      let simulation = savings::simulate(&tree.root, std::slice::from_ref(&tree.root)).unwrap();
+      prop_assert!(simulation.files <= whole.scanned_files);
+      prop_assert!(simulation.selected_bytes <= whole.scanned_bytes);
+    }
+  }
+}
@@ -0,0 +1,136 @@
+use crate::disk_image;
+#[cfg(target_os = "macos")]
+use crate::volumes;
+use serde::Serialize;
+use std::fs;
+#[cfg(all(target_family = "unix", not(target_os = "macos")))]
+use std::os::unix::fs::MetadataExt;
+use std::path::{Path, PathBuf};
+
+// Space the OS uses for virtual memory and hibernation: large, easily mistaken for clutter, and
+// not the user's to delete.
+#[derive(Serialize)]
+pub struct SystemFile {
+  pub path: String,
+  // "swap" or "hibernation".
+  pub kind: &'static str,
+  pub bytes: u64,
+}
+
+#[derive(Serialize)]
+pub struct SystemUsage {
+  #[serde(rename = "totalBytes")]
+  pub total_bytes: u64,
+  pub files: Vec<SystemFile>,
+}
+
+#[cfg(target_os = "macos")]
+fn candidates() -> Vec<(PathBuf, &'static str)> {
+  // /private/var/vm before Big Sur, the VM volume since.
+  let mut candidates = Vec::new();
+  for dir in ["/System/Volumes/VM", "/private/var/vm"] {
+    let entries = match fs::read_dir(dir) {
+      Ok(entries) => entries,
+      Err(_) => continue,
+    };
+    for entry in entries.flatten() {
+      let name = entry.file_name().to_string_lossy().to_string();
+      if name.starts_with("swapfile") {
+        candidates.push((entry.path(), "swap"));
+      } else if name == "sleepimage" {
+        candidates.push((entry.path(), "hibernation"));
+      }
+    }
+  }
+  candidates
+}
+
+// Swap files in use, from /proc/swaps; Linux hibernates into swap, so there is no separate image.
+#[cfg(target_os = "linux")]
+fn candidates() -> Vec<(PathBuf, &'static str)> {
+  let swaps = fs::read_to_string("/proc/swaps").unwrap_or_default();
+  swaps
+    .lines()
+    .skip(1)
+    .filter_map(|line| {
+      let mut columns = line.split_whitespace();
+      let path = columns.next()?;
+      (columns.next()? == "file").then(|| (PathBuf::from(path.replace("\\040", " ")), "swap"))
+    })
+    .collect()
+}
+
+#[cfg(target_os = "windows")]
+fn candidates() -> Vec<(PathBuf, &'static str)> {
+  let drive = std::env::var("SystemDrive").unwrap_or_else(|_| "C:".to_string());
+  [
+    ("pagefile.sys", "swap"),
+    ("swapfile.sys", "swap"),
+    ("hiberfil.sys", "hibernation"),
+  ]
+  .into_iter()
+  .map(|(name, kind)| (PathBuf::from(format!("{}\\{}", drive, name)), kind))
+  .collect()
+}
+
+#[cfg(not(any(target_os = "macos", target_os = "linux", target_os = "windows")))]
+fn candidates() -> Vec<(PathBuf, &'static str)> {
+  Vec::new()
+}
+
+// The VM volume shares the boot container with the system and data volumes, so its files count
+// towards those.
+#[cfg(target_os = "macos")]
+pub fn same_volume(_file: &Path, root: &Path) -> bool {
+  volumes::volume_for(root).is_some_and(|volume| {
+    volume.mount_point == Path::new("/") || volume.mount_point.starts_with("/System/Volumes")
+  })
+}
+
+#[cfg(all(target_family = "unix", not(target_os = "macos")))]
+pub fn same_volume(file: &Path, root: &Path) -> bool {
+  match (fs::metadata(file), fs::metadata(root)) {
+    (Ok(file), Ok(root)) => file.dev() == root.dev(),
+    _ => false,
+  }
+}
+
+// Same drive letter.
+#[cfg(not(target_family = "unix"))]
+pub fn same_volume(file: &Path, root: &Path) -> bool {
+  let drive = |path: &Path| {
+    path
+      .components()
+      .next()
+      .map(|component| component.as_os_str().to_string_lossy().to_uppercase())
+  };
+  drive(file) == drive(root)
+}
+
+// Every swap and hibernation file, on any volume.
+pub fn system_file_paths() -> Vec<String> {
+  candidates()
+    .into_iter()
+    .map(|(file, _)| file.to_string_lossy().to_string())
+    .collect()
+}
+
+// The swap and hibernation files stored on the volume holding `path`.
+pub fn system_usage(path: &Path) -> SystemUsage {
+  let files: Vec<SystemFile> = candidates()
+    .into_iter()
+    .filter(|(file, _)| same_volume(file, path))
+    .filter_map(|(file, kind)| {
+      let metadata = fs::metadata(&file).ok()?;
+      Some(SystemFile {
+        path: file.to_string_lossy().to_string(),
+        kind,
+        bytes: disk_image::allocated_size(&metadata),
+      })
+    })
+    .collect();
+  SystemUsage {
+    total_bytes: files.iter().map(|file| file.bytes).sum(),
+    files,
+  }
+}
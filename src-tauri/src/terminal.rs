@@ -0,0 +1,60 @@
+use crate::messages::Message;
+use std::path::Path;
+use std::process::{Command, Stdio};
+
+fn spawn(command: &mut Command) -> bool {
+  command
+    .stdin(Stdio::null())
+    .stdout(Stdio::null())
+    .stderr(Stdio::null())
+    .spawn()
+    .is_ok()
+}
+
+#[cfg(target_os = "macos")]
+pub fn open_terminal(dir: &Path) -> Result<(), Message> {
+  let app = if Path::new("/Applications/iTerm.app").exists() {
+    "iTerm"
+  } else {
+    "Terminal"
+  };
+  if spawn(Command::new("open").arg("-a").arg(app).arg(dir)) {
+    Ok(())
+  } else {
+    Err(Message::new("terminal.open_terminal_app_failed"))
+  }
+}
+
+#[cfg(target_os = "windows")]
+pub fn open_terminal(dir: &Path) -> Result<(), Message> {
+  if spawn(Command::new("wt").arg("-d").arg(dir)) {
+    return Ok(());
+  }
+  if spawn(
+    Command::new("cmd")
+      .args(["/C", "start", "cmd", "/K"])
+      .current_dir(dir),
+  ) {
+    Ok(())
+  } else {
+    Err(Message::new("terminal.open_failed"))
+  }
+}
+
+#[cfg(not(any(target_os = "macos", target_os = "windows")))]
+pub fn open_terminal(dir: &Path) -> Result<(), Message> {
+  // Debian-style alternatives first; the others take an explicit working directory flag.
+  let opened = spawn(Command::new("x-terminal-emulator").current_dir(dir))
+    || spawn(
+      Command::new("gnome-terminal")
+        .arg("--working-directory")
+        .arg(dir),
+    )
+    || spawn(Command::new("konsole").arg("--workdir").arg(dir))
+    || spawn(Command::new("xterm").current_dir(dir));
+  if opened {
+    Ok(())
+  } else {
+    Err(Message::new("terminal.not_found"))
+  }
+}
@@ -0,0 +1,278 @@
+use crate::history;
+use crate::messages::Message;
+use serde::Serialize;
+use std::collections::hash_map::RandomState;
+use std::collections::{BTreeSet, HashMap};
+use std::hash::BuildHasher;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+// A token has to be used this soon after the user confirmed.
+const TOKEN_LIFETIME: Duration = Duration::from_secs(60);
+// Unused tokens beyond this are dropped, oldest first.
+const MAX_PENDING_TOKENS: usize = 32;
+
+// Commands an `operator` may confirm: each keeps the data somewhere, in an archive, an offload,
+// a copy or back in place.
+const OPERATOR_COMMANDS: [&str; 6] = [
+  "archive_and_delete",
+  "offload_item",
+  "undo_offload",
+  "export_selection",
+  "compact_vm_image",
+  "restore_item",
+];
+
+// Who uses the app, from the `role` setting. An `owner` may confirm every destructive command,
+// an `operator` only those in `OPERATOR_COMMANDS`, and a `viewer` none.
+#[derive(Clone, Copy, PartialEq, PartialOrd)]
+pub enum Role {
+  Viewer,
+  Operator,
+  Owner,
+}
+
+impl Role {
+  pub fn parse(value: Option<&str>) -> Result<Self, Message> {
+    match value.unwrap_or("owner") {
+      "owner" => Ok(Self::Owner),
+      "operator" => Ok(Self::Operator),
+      "viewer" => Ok(Self::Viewer),
+      _ => Err(Message::new("role.invalid")),
+    }
+  }
+
+  pub fn may_confirm(self, command: &str) -> bool {
+    match self {
+      Self::Owner => true,
+      Self::Operator => OPERATOR_COMMANDS.contains(&command),
+      Self::Viewer => false,
+    }
+  }
+}
+
+#[derive(Serialize)]
+pub struct DestructiveToken {
+  pub token: String,
+  pub summary: String,
+  pub command: String,
+  pub targets: Vec<String>,
+  #[serde(rename = "expiresAt")]
+  pub expires_at: u64,
+}
+
+// What the user confirmed: the command the token may be spent on, and exactly what it may act
+// on. The order of the targets doesn't matter.
+struct Confirmation {
+  summary: String,
+  command: String,
+  targets: BTreeSet<String>,
+  minted: Instant,
+}
+
+// Confirmation tokens minted by `request_destructive_token` and not yet spent. Each destructive
+// command takes one, so nothing is deleted without a confirmation the backend saw.
+#[derive(Default)]
+pub struct DestructiveTokens {
+  counter: AtomicU64,
+  pending: Mutex<HashMap<String, Confirmation>>,
+}
+
+impl DestructiveTokens {
+  // 128 bits from two hashers with the process's random keys; the window can't predict it.
+  fn random_token(&self) -> String {
+    let count = self.counter.fetch_add(1, Ordering::Relaxed);
+    let nanos = SystemTime::now()
+      .duration_since(UNIX_EPOCH)
+      .map(|duration| duration.as_nanos() as u64)
+      .unwrap_or(0);
+    let high = RandomState::new().hash_one((count, nanos));
+    let low = RandomState::new().hash_one((nanos, count));
+    format!("{:016x}{:016x}", high, low)
+  }
+
+  pub fn mint(
+    &self,
+    summary: String,
+    command: String,
+    targets: Vec<String>,
+  ) -> Result<DestructiveToken, Message> {
+    let token = self.random_token();
+    let mut pending = self
+      .pending
+      .lock()
+      .map_err(|_| Message::new("token.lock_poisoned"))?;
+    let now = Instant::now();
+    pending.retain(|_, confirmation| now.duration_since(confirmation.minted) < TOKEN_LIFETIME);
+    while pending.len() >= MAX_PENDING_TOKENS {
+      let oldest = pending
+        .iter()
+        .min_by_key(|(_, confirmation)| confirmation.minted)
+        .map(|(token, _)| token.clone());
+      match oldest {
+        Some(oldest) => pending.remove(&oldest),
+        None => break,
+      };
+    }
+    pending.insert(
+      token.clone(),
+      Confirmation {
+        summary: summary.clone(),
+        command: command.clone(),
+        targets: targets.iter().cloned().collect(),
+        minted: now,
+      },
+    );
+    Ok(DestructiveToken {
+      token,
+      summary,
+      command,
+      targets,
+      expires_at: history::now_secs() + TOKEN_LIFETIME.as_secs(),
+    })
+  }
+
+  // Spends a token on `command` acting on `targets`, returning the summary the user confirmed.
+  // A token works once, even when it is refused for the wrong command or targets.
+  pub fn redeem(&self, token: &str, command: &str, targets: &[String]) -> Result<String, Message> {
+    let mut pending = self
+      .pending
+      .lock()
+      .map_err(|_| Message::new("token.lock_poisoned"))?;
+    let confirmation = pending
+      .remove(token)
+      .ok_or_else(|| Message::new("token.invalid"))?;
+    if confirmation.minted.elapsed() >= TOKEN_LIFETIME {
+      return Err(Message::new("token.expired"));
+    }
+    if confirmation.command != command {
+      return Err(Message::new("token.wrong_command").with("command", command));
+    }
+    if confirmation.targets != targets.iter().cloned().collect::<BTreeSet<_>>() {
+      return Err(Message::new("token.wrong_targets"));
+    }
+    Ok(confirmation.summary)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn targets(paths: &[&str]) -> Vec<String> {
+    paths.iter().map(|path| path.to_string()).collect()
+  }
+
+  fn mint(tokens: &DestructiveTokens, command: &str, paths: &[&str]) -> String {
+    tokens
+      .mint("summary".to_string(), command.to_string(), targets(paths))
+      .unwrap()
+      .token
+  }
+
+  fn refusal(result: Result<String, Message>) -> &'static str {
+    result.expect_err("token was accepted").key
+  }
+
+  #[test]
+  fn a_token_works_once() {
+    let tokens = DestructiveTokens::default();
+    let token = mint(&tokens, "delete_file", &["/a"]);
+    assert_eq!(
+      tokens.redeem(&token, "delete_file", &targets(&["/a"])),
+      Ok("summary".to_string())
+    );
+    assert_eq!(
+      refusal(tokens.redeem(&token, "delete_file", &targets(&["/a"]))),
+      "token.invalid"
+    );
+  }
+
+  #[test]
+  fn a_refused_token_is_spent() {
+    let tokens = DestructiveTokens::default();
+    let token = mint(&tokens, "delete_file", &["/a"]);
+    assert_eq!(
+      refusal(tokens.redeem(&token, "truncate_file", &targets(&["/a"]))),
+      "token.wrong_command"
+    );
+    assert_eq!(
+      refusal(tokens.redeem(&token, "delete_file", &targets(&["/a"]))),
+      "token.invalid"
+    );
+  }
+
+  #[test]
+  fn an_expired_token_is_refused() {
+    let tokens = DestructiveTokens::default();
+    let token = mint(&tokens, "delete_file", &["/a"]);
+    tokens
+      .pending
+      .lock()
+      .unwrap()
+      .get_mut(&token)
+      .unwrap()
+      .minted -= TOKEN_LIFETIME;
+    assert_eq!(
+      refusal(tokens.redeem(&token, "delete_file", &targets(&["/a"]))),
+      "token.expired"
+    );
+  }
+
+  #[test]
+  fn a_token_is_for_its_command_only() {
+    let tokens = DestructiveTokens::default();
+    let token = mint(&tokens, "delete_file", &["/a"]);
+    let refused = tokens
+      .redeem(&token, "execute_plan", &targets(&["/a"]))
+      .unwrap_err();
+    assert_eq!(
+      refused,
+      Message::new("token.wrong_command").with("command", "execute_plan")
+    );
+  }
+
+  #[test]
+  fn targets_must_match_in_any_order() {
+    let tokens = DestructiveTokens::default();
+    for other in [&["/a"][..], &["/a", "/b", "/c"], &["/a", "/c"]] {
+      let token = mint(&tokens, "execute_plan", &["/a", "/b"]);
+      assert_eq!(
+        refusal(tokens.redeem(&token, "execute_plan", &targets(other))),
+        "token.wrong_targets"
+      );
+    }
+    let token = mint(&tokens, "execute_plan", &["/a", "/b"]);
+    assert!(tokens
+      .redeem(&token, "execute_plan", &targets(&["/b", "/a"]))
+      .is_ok());
+  }
+
+  #[test]
+  fn the_oldest_token_is_evicted() {
+    let tokens = DestructiveTokens::default();
+    let oldest = mint(&tokens, "delete_file", &["/oldest"]);
+    // Minted well apart from the rest, so it is unambiguously the oldest.
+    tokens
+      .pending
+      .lock()
+      .unwrap()
+      .get_mut(&oldest)
+      .unwrap()
+      .minted -= Duration::from_secs(1);
+    let rest: Vec<String> = (0..MAX_PENDING_TOKENS)
+      .map(|index| mint(&tokens, "delete_file", &[&format!("/{}", index)]))
+      .collect();
+    assert_eq!(
+      refusal(tokens.redeem(&oldest, "delete_file", &targets(&["/oldest"]))),
+      "token.invalid"
+    );
+    for (index, token) in rest.iter().enumerate() {
+      let path = format!("/{}", index);
+      assert!(tokens
+        .redeem(token, "delete_file", &targets(&[&path]))
+        .is_ok());
+    }
+  }
+}
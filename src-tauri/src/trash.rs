@@ -0,0 +1,632 @@
+use crate::messages::Message;
+use crate::settings::Settings;
+use crate::watcher::OwnActivity;
+use crate::{audit, cache, conflicts, disk_image, filesystem, history, volumes};
+use serde::{Deserialize, Serialize};
+#[cfg(any(target_os = "macos", target_os = "linux"))]
+use std::ffi::CString;
+use std::fs;
+use std::io;
+#[cfg(target_os = "linux")]
+use std::io::Write;
+#[cfg(any(target_os = "macos", target_os = "linux"))]
+use std::os::unix::ffi::OsStrExt;
+#[cfg(target_family = "unix")]
+use std::os::unix::fs::{DirBuilderExt, MetadataExt};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+// The key of the error for a delete that would skip the trash and wasn't confirmed.
+pub const PERMANENT_DELETE_UNCONFIRMED: &str = "trash.permanent_unconfirmed";
+const TRASH_LEDGER_FILE: &str = "trash.json";
+
+static LEDGER_LOCK: Mutex<()> = Mutex::new(());
+
+// What removing a file did to the disk. A trashed file is gone from where it was but keeps its
+// space until the trash is emptied; a file with other hard links frees nothing either way.
+#[derive(Clone, Copy, Serialize)]
+pub struct Removal {
+  #[serde(rename = "removedBytes")]
+  pub removed_bytes: u64,
+  #[serde(rename = "freedBytes")]
+  pub freed_bytes: u64,
+  #[serde(rename = "trashedBytes")]
+  pub trashed_bytes: u64,
+}
+
+// A file this app moved to the trash, so trash growth can be told apart from the user's own.
+#[derive(Serialize, Deserialize)]
+struct TrashedItem {
+  #[serde(rename = "trashPath")]
+  trash_path: String,
+  #[serde(rename = "originalPath")]
+  original_path: String,
+  bytes: u64,
+  #[serde(rename = "trashedAt")]
+  trashed_at: u64,
+}
+
+#[derive(Serialize)]
+pub struct Restored {
+  #[serde(rename = "auditId")]
+  pub audit_id: u64,
+  pub path: String,
+  #[serde(rename = "trashPath")]
+  pub trash_path: String,
+}
+
+#[derive(Serialize)]
+pub struct TrashVolume {
+  #[serde(rename = "mountPoint")]
+  pub mount_point: String,
+  #[serde(rename = "trashedBytes")]
+  pub trashed_bytes: u64,
+  #[serde(rename = "availableBytes")]
+  pub available_bytes: Option<u64>,
+  // What the volume would have free once the app's items are emptied from the trash.
+  #[serde(rename = "availableAfterEmptying")]
+  pub available_after_emptying: Option<u64>,
+}
+
+#[derive(Serialize)]
+pub struct TrashUsage {
+  pub items: u64,
+  #[serde(rename = "trashedBytes")]
+  pub trashed_bytes: u64,
+  pub volumes: Vec<TrashVolume>,
+}
+
+fn wants_trash(path: &Path, settings: &Settings) -> bool {
+  settings.for_path(path).delete_mode.as_deref() == Some("trash")
+}
+
+// FAT and exFAT drives are usually emptied by cameras, TVs, or Windows, none of which know about
+// a hidden trash folder, so trashed files would keep filling the drive. They are deleted instead.
+pub fn trash_unavailable(path: &Path, settings: &Settings) -> bool {
+  wants_trash(path, settings) && filesystem::is_fat(path)
+}
+
+#[cfg(target_family = "unix")]
+fn last_link(metadata: &fs::Metadata) -> bool {
+  metadata.nlink() <= 1
+}
+
+#[cfg(not(target_family = "unix"))]
+fn last_link(_metadata: &fs::Metadata) -> bool {
+  true
+}
+
+// Deletes `path`, or moves it to the trash when its volume's `deleteMode` is "trash". Where the
+// trash is unavailable the file is only deleted once `confirmed_permanent` is set. Trashed files
+// are recorded in `cache_dir` for `usage`, and every attempt in the audit log.
+pub fn remove(
+  path: &Path,
+  settings: &Settings,
+  cache_dir: &Path,
+  confirmed_permanent: bool,
+) -> Result<Removal, Message> {
+  let size = fs::symlink_metadata(path)
+    .map(|metadata| metadata.len())
+    .unwrap_or(0);
+  let action = if wants_trash(path, settings) && !trash_unavailable(path, settings) {
+    "trash"
+  } else {
+    "delete"
+  };
+  match remove_unaudited(path, settings, cache_dir, confirmed_permanent) {
+    Ok((removal, trashed)) => {
+      audit::record(cache_dir, action, path, size, trashed.as_deref(), Ok(()));
+      Ok(removal)
+    }
+    // Nothing was attempted yet; the caller asks the user first.
+    Err(error) if error.key == PERMANENT_DELETE_UNCONFIRMED => Err(error),
+    Err(error) => {
+      audit::record(cache_dir, action, path, size, None, Err(&error));
+      Err(error)
+    }
+  }
+}
+
+fn remove_unaudited(
+  path: &Path,
+  settings: &Settings,
+  cache_dir: &Path,
+  confirmed_permanent: bool,
+) -> Result<(Removal, Option<PathBuf>), Message> {
+  let metadata = fs::symlink_metadata(path).map_err(|_| Message::new("file.not_found"))?;
+  let reclaimable = if last_link(&metadata) {
+    disk_image::allocated_size(&metadata)
+  } else {
+    0
+  };
+  if trash_unavailable(path, settings) {
+    if !confirmed_permanent {
+      return Err(Message::new(PERMANENT_DELETE_UNCONFIRMED));
+    }
+  } else if wants_trash(path, settings) {
+    let volume = volumes::volume_for(path);
+    let trashed = move_to_trash(
+      path,
+      volume.as_ref().map(|volume| volume.mount_point.as_path()),
+    )?;
+    record(cache_dir, path, &trashed, reclaimable);
+    return Ok((
+      Removal {
+        removed_bytes: metadata.len(),
+        freed_bytes: 0,
+        trashed_bytes: reclaimable,
+      },
+      Some(trashed),
+    ));
+  }
+  fs::remove_file(path).map_err(|_| Message::new("file.delete_failed"))?;
+  Ok((
+    Removal {
+      removed_bytes: metadata.len(),
+      freed_bytes: reclaimable,
+      trashed_bytes: 0,
+    },
+    None,
+  ))
+}
+
+// Reads, changes and writes back the ledger under a lock, so concurrent removals and restores
+// don't lose each other's changes. `change` says whether it changed anything; the ledger is
+// returned as it ends up.
+fn update_ledger(
+  cache_dir: &Path,
+  change: impl FnOnce(&mut Vec<TrashedItem>) -> bool,
+) -> Vec<TrashedItem> {
+  let _guard = LEDGER_LOCK.lock();
+  let ledger_file = cache_dir.join(TRASH_LEDGER_FILE);
+  let mut ledger: Vec<TrashedItem> = cache::read_json(&ledger_file);
+  if change(&mut ledger) {
+    let _ = cache::write_json(&ledger_file, &ledger);
+  }
+  ledger
+}
+
+fn record(cache_dir: &Path, original: &Path, trashed: &Path, bytes: u64) {
+  update_ledger(cache_dir, |ledger| {
+    ledger.push(TrashedItem {
+      trash_path: trashed.to_string_lossy().to_string(),
+      original_path: original.to_string_lossy().to_string(),
+      bytes,
+      trashed_at: history::now_secs(),
+    });
+    true
+  });
+}
+
+// Space held in the trash by files this app moved there, per volume. Items that were emptied or
+// put back since are dropped from the ledger.
+pub fn usage(cache_dir: &Path) -> TrashUsage {
+  let ledger = update_ledger(cache_dir, |ledger| {
+    let recorded = ledger.len();
+    ledger.retain(|item| fs::symlink_metadata(&item.trash_path).is_ok());
+    ledger.len() != recorded
+  });
+
+  let mut volumes: Vec<TrashVolume> = Vec::new();
+  for item in &ledger {
+    let path = Path::new(&item.trash_path);
+    let mount_point = volumes::volume_for(path)
+      .map(|volume| volume.mount_point)
+      .unwrap_or_else(|| path.parent().unwrap_or(path).to_path_buf())
+      .to_string_lossy()
+      .to_string();
+    match volumes
+      .iter_mut()
+      .find(|volume| volume.mount_point == mount_point)
+    {
+      Some(volume) => volume.trashed_bytes += item.bytes,
+      None => volumes.push(TrashVolume {
+        mount_point,
+        trashed_bytes: item.bytes,
+        available_bytes: None,
+        available_after_emptying: None,
+      }),
+    }
+  }
+  for volume in &mut volumes {
+    volume.available_bytes = filesystem::available_bytes(Path::new(&volume.mount_point));
+    volume.available_after_emptying = volume
+      .available_bytes
+      .map(|available| available + volume.trashed_bytes);
+  }
+  TrashUsage {
+    items: ledger.len() as u64,
+    trashed_bytes: ledger.iter().map(|item| item.bytes).sum(),
+    volumes,
+  }
+}
+
+// Puts an item this app moved to the trash back where it was, from its audit log entry. The
+// trash path the entry recorded is tried first; when the item isn't there any more, the trash is
+// searched for one of the same size that came from the same place at about the same time.
+pub fn restore(
+  cache_dir: &Path,
+  audit_id: u64,
+  own_activity: &OwnActivity,
+) -> Result<Restored, Message> {
+  let entry =
+    audit::find(cache_dir, audit_id).ok_or_else(|| Message::new("restore.unknown_entry"))?;
+  if entry.action != "trash" || entry.outcome != "ok" {
+    return Err(Message::new("restore.not_trashed"));
+  }
+  let original = PathBuf::from(&entry.path);
+  if fs::symlink_metadata(&original).is_ok() {
+    return Err(Message::new("restore.original_taken"));
+  }
+  let trashed = entry
+    .destination
+    .as_ref()
+    .map(PathBuf::from)
+    .filter(|trashed| {
+      fs::symlink_metadata(trashed).is_ok_and(|metadata| metadata.len() == entry.size)
+    })
+    .or_else(|| find_in_trash(&original, entry.size, entry.at))
+    .ok_or_else(|| Message::new("restore.not_found"))?;
+  if let Some(parent) = original.parent() {
+    fs::create_dir_all(parent).map_err(|_| Message::new("restore.folder_failed"))?;
+  }
+
+  own_activity.record(&original);
+  let restored = move_no_replace(&trashed, &original).map_err(|error| {
+    if error.kind() == io::ErrorKind::AlreadyExists {
+      Message::new("restore.original_taken")
+    } else {
+      Message::new("restore.failed")
+    }
+  });
+  audit::record(
+    cache_dir,
+    "restore",
+    &trashed,
+    entry.size,
+    Some(&original),
+    restored.as_ref().map(|_| ()),
+  );
+  restored?;
+  forget_trash_info(&trashed);
+  let trash_path = trashed.to_string_lossy().to_string();
+  update_ledger(cache_dir, |ledger| {
+    ledger.retain(|item| item.trash_path != trash_path);
+    true
+  });
+  Ok(Restored {
+    audit_id,
+    path: entry.path,
+    trash_path,
+  })
+}
+
+// Moves `from` to `to`, failing with `AlreadyExists` if something is at `to`. The check is part of
+// the move, so a file created there after `restore` looked isn't overwritten.
+#[cfg(target_os = "linux")]
+fn move_no_replace(from: &Path, to: &Path) -> io::Result<()> {
+  let c_from = CString::new(from.as_os_str().as_bytes())?;
+  let c_to = CString::new(to.as_os_str().as_bytes())?;
+  let result = unsafe {
+    libc::renameat2(
+      libc::AT_FDCWD,
+      c_from.as_ptr(),
+      libc::AT_FDCWD,
+      c_to.as_ptr(),
+      libc::RENAME_NOREPLACE,
+    )
+  };
+  if result == 0 {
+    return Ok(());
+  }
+  let error = io::Error::last_os_error();
+  match error.raw_os_error() {
+    // Filesystems and kernels that don't support RENAME_NOREPLACE.
+    Some(libc::EINVAL) | Some(libc::ENOSYS) => link_and_unlink(from, to),
+    _ => Err(error),
+  }
+}
+
+#[cfg(target_os = "macos")]
+fn move_no_replace(from: &Path, to: &Path) -> io::Result<()> {
+  let c_from = CString::new(from.as_os_str().as_bytes())?;
+  let c_to = CString::new(to.as_os_str().as_bytes())?;
+  let result = unsafe { libc::renamex_np(c_from.as_ptr(), c_to.as_ptr(), libc::RENAME_EXCL) };
+  if result == 0 {
+    return Ok(());
+  }
+  let error = io::Error::last_os_error();
+  match error.raw_os_error() {
+    Some(libc::ENOTSUP) => link_and_unlink(from, to),
+    _ => Err(error),
+  }
+}
+
+#[cfg(not(any(target_os = "macos", target_os = "linux")))]
+fn move_no_replace(from: &Path, to: &Path) -> io::Result<()> {
+  link_and_unlink(from, to)
+}
+
+// A hard link fails if `to` exists. Only trashed files are restored, so linking them works.
+fn link_and_unlink(from: &Path, to: &Path) -> io::Result<()> {
+  fs::hard_link(from, to)?;
+  fs::remove_file(from).inspect_err(|_| {
+    let _ = fs::remove_file(to);
+  })
+}
+
+// The trash in the home folder, and the one on the volume `original` was on.
+#[cfg(any(target_os = "macos", target_os = "linux"))]
+fn trash_dirs(original: &Path) -> Vec<PathBuf> {
+  let mut dirs: Vec<PathBuf> = home_trash().into_iter().collect();
+  if let Some(volume) = volumes::volume_for(original) {
+    dirs.push(volume_trash(&volume.mount_point));
+  }
+  dirs
+}
+
+// Finder names a trashed file after the original, or "name 2.ext" when the trash already has one.
+#[cfg(target_os = "macos")]
+fn trashed_name_matches(name: &str, candidate: &str) -> bool {
+  if candidate == name {
+    return true;
+  }
+  let (stem, extension) = match name.rsplit_once('.') {
+    Some((stem, extension)) if !stem.is_empty() => (stem, format!(".{}", extension)),
+    _ => (name, String::new()),
+  };
+  candidate
+    .strip_prefix(stem)
+    .and_then(|rest| rest.strip_prefix(' '))
+    .and_then(|rest| rest.strip_suffix(extension.as_str()))
+    .is_some_and(|counter| counter.parse::<u32>().is_ok())
+}
+
+// The macOS trash keeps no record of where items came from, so this goes by name and size, and
+// picks the one moved there closest to `at`.
+#[cfg(target_os = "macos")]
+fn find_in_trash(original: &Path, size: u64, at: u64) -> Option<PathBuf> {
+  let name = original.file_name()?.to_string_lossy().to_string();
+  trash_dirs(original)
+    .into_iter()
+    .filter_map(|trash| fs::read_dir(trash).ok())
+    .flat_map(|entries| entries.flatten())
+    .filter(|entry| trashed_name_matches(&name, &entry.file_name().to_string_lossy()))
+    .filter_map(|entry| {
+      let metadata = entry.metadata().ok()?;
+      (metadata.len() == size).then(|| ((metadata.ctime() as u64).abs_diff(at), entry.path()))
+    })
+    .min_by_key(|(distance, _)| *distance)
+    .map(|(_, path)| path)
+}
+
+// Matches the `.trashinfo` files that name `original`, written closest to `at`.
+#[cfg(target_os = "linux")]
+fn find_in_trash(original: &Path, size: u64, at: u64) -> Option<PathBuf> {
+  // Trashed paths are recorded canonicalized.
+  let canonical = original
+    .parent()
+    .and_then(|parent| parent.canonicalize().ok())
+    .zip(original.file_name())
+    .map(|(parent, name)| parent.join(name))
+    .unwrap_or_else(|| original.to_path_buf());
+  trash_dirs(original)
+    .into_iter()
+    .filter_map(|trash| {
+      let entries = fs::read_dir(trash.join("info")).ok()?;
+      Some((trash.join("files"), entries))
+    })
+    .flat_map(|(files, entries)| entries.flatten().map(move |entry| (files.clone(), entry)))
+    .filter_map(|(files, entry)| {
+      let file_name = entry.file_name().to_string_lossy().to_string();
+      let name = file_name.strip_suffix(".trashinfo")?;
+      let info = fs::read_to_string(entry.path()).ok()?;
+      let from = PathBuf::from(percent_decode(
+        info.lines().find_map(|line| line.strip_prefix("Path="))?,
+      ));
+      if from != canonical && from != original {
+        return None;
+      }
+      let trashed = files.join(name);
+      if fs::symlink_metadata(&trashed).ok()?.len() != size {
+        return None;
+      }
+      let written = entry
+        .metadata()
+        .ok()?
+        .modified()
+        .ok()?
+        .duration_since(std::time::UNIX_EPOCH)
+        .ok()?
+        .as_secs();
+      Some((written.abs_diff(at), trashed))
+    })
+    .min_by_key(|(distance, _)| *distance)
+    .map(|(_, trashed)| trashed)
+}
+
+#[cfg(not(any(target_os = "macos", target_os = "linux")))]
+fn find_in_trash(_original: &Path, _size: u64, _at: u64) -> Option<PathBuf> {
+  None
+}
+
+// A restored item's `.trashinfo` would otherwise describe a file that is no longer there.
+#[cfg(target_os = "linux")]
+fn forget_trash_info(trashed: &Path) {
+  let (files, name) = match (trashed.parent(), trashed.file_name()) {
+    (Some(files), Some(name)) => (files, name.to_string_lossy()),
+    _ => return,
+  };
+  if let Some(trash) = files.parent() {
+    let _ = fs::remove_file(trash.join("info").join(format!("{}.trashinfo", name)));
+  }
+}
+
+#[cfg(not(target_os = "linux"))]
+fn forget_trash_info(_trashed: &Path) {}
+
+#[cfg(target_family = "unix")]
+fn same_device(a: &Path, b: &Path) -> bool {
+  match (fs::metadata(a), fs::metadata(b)) {
+    (Ok(a), Ok(b)) => a.dev() == b.dev(),
+    _ => false,
+  }
+}
+
+#[cfg(target_family = "unix")]
+fn private_dir(path: &Path) -> Result<(), Message> {
+  fs::DirBuilder::new()
+    .recursive(true)
+    .mode(0o700)
+    .create(path)
+    .map_err(|_| Message::new("trash.create_failed"))
+}
+
+#[cfg(target_os = "macos")]
+fn home_trash() -> Result<PathBuf, Message> {
+  let home = std::env::var("HOME").map_err(|_| Message::new("path.home_unavailable"))?;
+  Ok(PathBuf::from(home).join(".Trash"))
+}
+
+#[cfg(target_os = "macos")]
+fn volume_trash(mount_point: &Path) -> PathBuf {
+  mount_point
+    .join(".Trashes")
+    .join(unsafe { libc::getuid() }.to_string())
+}
+
+#[cfg(target_os = "macos")]
+fn move_to_trash(path: &Path, mount_point: Option<&Path>) -> Result<PathBuf, Message> {
+  let home_trash = home_trash()?;
+  let trash = if same_device(path, &home_trash) {
+    home_trash
+  } else {
+    let mount_point = mount_point.ok_or_else(|| Message::new("volume.not_found"))?;
+    let trash = volume_trash(mount_point);
+    private_dir(&trash)?;
+    trash
+  };
+  let name = conflicts::unique_name(path, |name| trash.join(name).exists())?;
+  let destination = trash.join(name);
+  fs::rename(path, &destination).map_err(|_| Message::new("trash.move_failed"))?;
+  Ok(destination)
+}
+
+// The freedesktop.org trash: files/ holds the file, info/<name>.trashinfo where it came from.
+#[cfg(target_os = "linux")]
+fn home_trash() -> Result<PathBuf, Message> {
+  Ok(
+    std::env::var("XDG_DATA_HOME")
+      .map(PathBuf::from)
+      .or_else(|_| std::env::var("HOME").map(|home| PathBuf::from(home).join(".local/share")))
+      .map_err(|_| Message::new("path.home_unavailable"))?
+      .join("Trash"),
+  )
+}
+
+#[cfg(target_os = "linux")]
+fn volume_trash(mount_point: &Path) -> PathBuf {
+  mount_point.join(format!(".Trash-{}", unsafe { libc::getuid() }))
+}
+
+#[cfg(target_os = "linux")]
+fn move_to_trash(path: &Path, mount_point: Option<&Path>) -> Result<PathBuf, Message> {
+  let home_trash = home_trash()?;
+  let existing = home_trash
+    .ancestors()
+    .find(|dir| dir.exists())
+    .unwrap_or(&home_trash);
+  let trash = if same_device(path, existing) {
+    home_trash
+  } else {
+    let mount_point = mount_point.ok_or_else(|| Message::new("volume.not_found"))?;
+    volume_trash(mount_point)
+  };
+  let (files, info) = (trash.join("files"), trash.join("info"));
+  private_dir(&files)?;
+  private_dir(&info)?;
+
+  let original = path
+    .canonicalize()
+    .map_err(|_| Message::new("file.not_found"))?;
+  let name = conflicts::unique_name(path, |name| {
+    files.join(name).exists() || info.join(format!("{}.trashinfo", name)).exists()
+  })?;
+  let info_path = info.join(format!("{}.trashinfo", name));
+  let mut info_file = fs::OpenOptions::new()
+    .write(true)
+    .create_new(true)
+    .open(&info_path)
+    .map_err(|_| Message::new("trash.move_failed"))?;
+  let written = write!(
+    info_file,
+    "[Trash Info]\nPath={}\nDeletionDate={}\n",
+    percent_encode(&original.to_string_lossy()),
+    deletion_date()
+  );
+  let destination = files.join(&name);
+  if written.is_err() || fs::rename(path, &destination).is_err() {
+    let _ = fs::remove_file(&info_path);
+    return Err(Message::new("trash.move_failed"));
+  }
+  Ok(destination)
+}
+
+#[cfg(target_os = "linux")]
+fn percent_encode(path: &str) -> String {
+  path
+    .bytes()
+    .map(|byte| match byte {
+      b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'/' | b'-' | b'_' | b'.' | b'~' => {
+        (byte as char).to_string()
+      }
+      _ => format!("%{:02X}", byte),
+    })
+    .collect()
+}
+
+#[cfg(target_os = "linux")]
+fn percent_decode(path: &str) -> String {
+  let bytes = path.as_bytes();
+  let mut decoded = Vec::with_capacity(bytes.len());
+  let mut index = 0;
+  while index < bytes.len() {
+    let escaped = (bytes[index] == b'%')
+      .then(|| path.get(index + 1..index + 3))
+      .flatten()
+      .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+    match escaped {
+      Some(byte) => {
+        decoded.push(byte);
+        index += 3;
+      }
+      None => {
+        decoded.push(bytes[index]);
+        index += 1;
+      }
+    }
+  }
+  String::from_utf8_lossy(&decoded).to_string()
+}
+
+// Local time as YYYY-MM-DDThh:mm:ss.
+#[cfg(target_os = "linux")]
+fn deletion_date() -> String {
+  let now = unsafe { libc::time(std::ptr::null_mut()) };
+  let mut local: libc::tm = unsafe { std::mem::zeroed() };
+  unsafe { libc::localtime_r(&now, &mut local) };
+  format!(
+    "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}",
+    local.tm_year + 1900,
+    local.tm_mon + 1,
+    local.tm_mday,
+    local.tm_hour,
+    local.tm_min,
+    local.tm_sec
+  )
+}
+
+#[cfg(not(any(target_os = "macos", target_os = "linux")))]
+fn move_to_trash(_path: &Path, _mount_point: Option<&Path>) -> Result<PathBuf, Message> {
+  Err(Message::new("trash.unsupported"))
+}
@@ -0,0 +1,126 @@
+use crate::diff;
+use crate::messages::Message;
+use crate::scanner::{self, DirTotal, DirTotals};
+use serde::Serialize;
+use std::cmp::Reverse;
+use std::path::Path;
+use tauri::{AppHandle, Manager};
+
+pub const DEFAULT_CHUNK_NODES: usize = 5_000;
+pub const MAX_CHUNK_NODES: usize = 50_000;
+
+#[derive(Clone, Serialize)]
+pub struct TreeNode {
+  // `scanner::entry_id` of the folder's path, as in every other payload.
+  pub id: u64,
+  // None for the scan root.
+  #[serde(rename = "parentId")]
+  pub parent_id: Option<u64>,
+  pub path: String,
+  pub bytes: u64,
+  pub files: u64,
+}
+
+#[derive(Clone, Serialize)]
+struct TreeChunkPayload {
+  #[serde(rename = "scanId")]
+  scan_id: u64,
+  sequence: usize,
+  #[serde(rename = "totalNodes")]
+  total_nodes: usize,
+  nodes: Vec<TreeNode>,
+  done: bool,
+}
+
+#[derive(Serialize)]
+pub struct TreeStream {
+  #[serde(rename = "totalNodes")]
+  pub total_nodes: usize,
+  pub chunks: usize,
+}
+
+fn depth(relative: &str) -> usize {
+  if relative.is_empty() {
+    0
+  } else {
+    relative.matches(std::path::MAIN_SEPARATOR).count() + 1
+  }
+}
+
+fn parent(relative: &str) -> Option<&str> {
+  if relative.is_empty() {
+    return None;
+  }
+  Some(
+    relative
+      .rfind(std::path::MAIN_SEPARATOR)
+      .map_or("", |index| &relative[..index]),
+  )
+}
+
+// The folders of `totals`, a scan's folder totals under `root`, breadth-first: a folder always
+// comes after its parent, and siblings come largest first.
+pub fn nodes(root: &str, totals: &DirTotals) -> Vec<TreeNode> {
+  let mut folders: Vec<(&String, &DirTotal)> = totals.iter().collect();
+  folders.sort_by(|(a, a_total), (b, b_total)| {
+    depth(a)
+      .cmp(&depth(b))
+      .then_with(|| parent(a).cmp(&parent(b)))
+      .then_with(|| Reverse(a_total.bytes).cmp(&Reverse(b_total.bytes)))
+      .then_with(|| a.cmp(b))
+  });
+
+  let full_path = |relative: &str| {
+    if relative.is_empty() {
+      root.to_string()
+    } else {
+      Path::new(root).join(relative).to_string_lossy().to_string()
+    }
+  };
+  folders
+    .into_iter()
+    .map(|(relative, total)| {
+      let path = full_path(relative);
+      TreeNode {
+        id: scanner::entry_id(&path),
+        parent_id: parent(relative).map(|parent| scanner::entry_id(&full_path(parent))),
+        path,
+        bytes: total.bytes,
+        files: total.files,
+      }
+    })
+    .collect()
+}
+
+// Emits the folder tree of a completed scan, in the order of `nodes`, as `scan_tree_chunk` events
+// of at most `chunk_nodes` folders. The last chunk has `done: true`.
+pub fn stream_tree(
+  app: &AppHandle,
+  app_dir: &Path,
+  scan_id: u64,
+  chunk_nodes: usize,
+) -> Result<TreeStream, Message> {
+  let (scan, totals) = diff::scan_totals(app_dir, scan_id)?;
+  let nodes = nodes(&scan.root, &totals);
+  let total_nodes = nodes.len();
+  let chunk_nodes = chunk_nodes.max(1);
+  let chunks = total_nodes.div_ceil(chunk_nodes).max(1);
+  let mut nodes = nodes.into_iter();
+  for sequence in 0..chunks {
+    let _ = app.emit_to(
+      "main",
+      "scan_tree_chunk",
+      TreeChunkPayload {
+        scan_id,
+        sequence,
+        total_nodes,
+        nodes: nodes.by_ref().take(chunk_nodes).collect(),
+        done: sequence + 1 == chunks,
+      },
+    );
+  }
+  Ok(TreeStream {
+    total_nodes,
+    chunks,
+  })
+}
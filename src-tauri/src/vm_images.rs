@@ -0,0 +1,258 @@
+use crate::disk_image;
+use crate::messages::Message;
+use crate::scanner::FileEntry;
+use serde::Serialize;
+use std::cmp::Reverse;
+use std::env;
+use std::fs::{self, File};
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+const QCOW2_MAGIC: &[u8] = b"QFI\xfb";
+const VMDK_MAGIC: &[u8] = b"KDMV";
+const VDI_SIGNATURE: u32 = 0xbeda_107f;
+const VDI_HEADER_BYTES: usize = 376;
+
+// A virtual machine or container disk among a scan's largest files.
+#[derive(Serialize)]
+pub struct VmImage {
+  // The image file, or the bundle folder for Parallels disks.
+  pub path: String,
+  // "utm", "qemu", "parallels", "vmware", "virtualbox", "wsl", or "docker".
+  pub kind: String,
+  #[serde(rename = "allocatedBytes")]
+  pub allocated_bytes: u64,
+  // What the guest can grow the image to, where the format records it.
+  #[serde(rename = "maximumBytes")]
+  pub maximum_bytes: Option<u64>,
+  pub instructions: Message,
+  // Whether `compact_vm_image` can compact it here: the format has an in-place compaction tool,
+  // and it is installed.
+  pub compactable: bool,
+}
+
+#[derive(Serialize)]
+pub struct Compaction {
+  pub path: String,
+  #[serde(rename = "beforeBytes")]
+  pub before_bytes: u64,
+  #[serde(rename = "afterBytes")]
+  pub after_bytes: u64,
+  #[serde(rename = "freedBytes")]
+  pub freed_bytes: u64,
+}
+
+fn extension(path: &Path) -> String {
+  path
+    .extension()
+    .map(|extension| extension.to_string_lossy().to_lowercase())
+    .unwrap_or_default()
+}
+
+fn inside_bundle(path: &Path, bundle_extension: &str) -> Option<PathBuf> {
+  path
+    .ancestors()
+    .skip(1)
+    .find(|ancestor| extension(ancestor) == bundle_extension)
+    .map(Path::to_path_buf)
+}
+
+// The image a file belongs to and its kind; Parallels disks are bundles of `.hds` files.
+fn classify(path: &Path) -> Option<(PathBuf, &'static str)> {
+  let name = path.file_name()?.to_string_lossy().to_lowercase();
+  let lower = path.to_string_lossy().to_lowercase();
+  if let Some(bundle) = inside_bundle(path, "hdd") {
+    return Some((bundle, "parallels"));
+  }
+  let kind = match extension(path).as_str() {
+    _ if name == "docker.raw" => "docker",
+    "vhdx" if lower.contains("docker") => "docker",
+    "vhdx" => "wsl",
+    "qcow2" | "img" if inside_bundle(path, "utm").is_some() => "utm",
+    "qcow2" => "qemu",
+    "vmdk" => "vmware",
+    "vdi" => "virtualbox",
+    _ => return None,
+  };
+  Some((path.to_path_buf(), kind))
+}
+
+fn read_header(path: &Path, bytes: usize) -> Option<Vec<u8>> {
+  let mut header = vec![0; bytes];
+  File::open(path).ok()?.read_exact(&mut header).ok()?;
+  Some(header)
+}
+
+fn u64_at(header: &[u8], offset: usize, big_endian: bool) -> Option<u64> {
+  let bytes: [u8; 8] = header.get(offset..offset + 8)?.try_into().ok()?;
+  Some(if big_endian {
+    u64::from_be_bytes(bytes)
+  } else {
+    u64::from_le_bytes(bytes)
+  })
+}
+
+// The virtual size from the image header, or the file length for raw sparse images. Only the
+// first few hundred bytes are read.
+fn maximum_size(path: &Path, kind: &str) -> Option<u64> {
+  match (kind, extension(path).as_str()) {
+    (_, "qcow2") => {
+      let header = read_header(path, 32)?;
+      header
+        .starts_with(QCOW2_MAGIC)
+        .then(|| u64_at(&header, 24, true))?
+    }
+    (_, "vmdk") => {
+      let header = read_header(path, 20)?;
+      header
+        .starts_with(VMDK_MAGIC)
+        .then(|| u64_at(&header, 12, false).map(|sectors| sectors * 512))?
+    }
+    (_, "vdi") => {
+      let header = read_header(path, VDI_HEADER_BYTES)?;
+      let signature = u32::from_le_bytes(header.get(64..68)?.try_into().ok()?);
+      (signature == VDI_SIGNATURE).then(|| u64_at(&header, 368, false))?
+    }
+    ("docker", "raw") | ("utm", "img") => fs::metadata(path).ok().map(|metadata| metadata.len()),
+    _ => None,
+  }
+}
+
+fn allocated_size(path: &Path) -> u64 {
+  let metadata = match fs::metadata(path) {
+    Ok(metadata) => metadata,
+    Err(_) => return 0,
+  };
+  if !metadata.is_dir() {
+    return disk_image::allocated_size(&metadata);
+  }
+  fs::read_dir(path)
+    .map(|entries| {
+      entries
+        .flatten()
+        .map(|entry| allocated_size(&entry.path()))
+        .sum()
+    })
+    .unwrap_or(0)
+}
+
+// Where each compaction tool is installed when it isn't on the PATH.
+fn tool_locations(tool: &str) -> &'static [&'static str] {
+  match tool {
+    "VBoxManage" => &[
+      "/Applications/VirtualBox.app/Contents/MacOS/VBoxManage",
+      "C:\\Program Files\\Oracle\\VirtualBox\\VBoxManage.exe",
+    ],
+    "vmware-vdiskmanager" => &[
+      "/Applications/VMware Fusion.app/Contents/Library/vmware-vdiskmanager",
+      "C:\\Program Files (x86)\\VMware\\VMware Workstation\\vmware-vdiskmanager.exe",
+    ],
+    "prl_disk_tool" => &["/Applications/Parallels Desktop.app/Contents/MacOS/prl_disk_tool"],
+    _ => &[],
+  }
+}
+
+fn find_tool(tool: &str) -> Option<PathBuf> {
+  let on_path = env::var_os("PATH").and_then(|paths| {
+    env::split_paths(&paths).find_map(|dir| {
+      [tool.to_string(), format!("{}.exe", tool)]
+        .into_iter()
+        .map(|name| dir.join(name))
+        .find(|candidate| candidate.is_file())
+    })
+  });
+  on_path.or_else(|| {
+    tool_locations(tool)
+      .iter()
+      .map(PathBuf::from)
+      .find(|candidate| candidate.is_file())
+  })
+}
+
+// The in-place compaction command for an image, when its format has one. QEMU images can only be
+// rewritten into a copy, and Docker and WSL disks are shrunk from their own apps.
+fn compaction_command(path: &Path, kind: &str) -> Option<Command> {
+  let (tool, args): (&str, Vec<&std::ffi::OsStr>) = match kind {
+    "virtualbox" => (
+      "VBoxManage",
+      vec![
+        "modifymedium".as_ref(),
+        "disk".as_ref(),
+        path.as_os_str(),
+        "--compact".as_ref(),
+      ],
+    ),
+    "vmware" => ("vmware-vdiskmanager", vec!["-k".as_ref(), path.as_os_str()]),
+    "parallels" => (
+      "prl_disk_tool",
+      vec!["compact".as_ref(), "--hdd".as_ref(), path.as_os_str()],
+    ),
+    _ => return None,
+  };
+  let mut command = Command::new(find_tool(tool)?);
+  command.args(args);
+  Some(command)
+}
+
+// The VM and container disks among `files`, largest allocation first.
+pub fn find(files: &[FileEntry]) -> Vec<VmImage> {
+  let mut images: Vec<VmImage> = Vec::new();
+  for file in files {
+    let (path, kind) = match classify(Path::new(&file.path)) {
+      Some(image) => image,
+      None => continue,
+    };
+    let path_string = path.to_string_lossy().to_string();
+    if images.iter().any(|image| image.path == path_string) {
+      continue;
+    }
+    images.push(VmImage {
+      path: path_string,
+      kind: kind.to_string(),
+      allocated_bytes: allocated_size(&path),
+      maximum_bytes: maximum_size(&path, kind),
+      instructions: Message::new(match kind {
+        "utm" | "qemu" => "vm.compact_qemu",
+        "parallels" => "vm.compact_parallels",
+        "vmware" => "vm.compact_vmware",
+        "virtualbox" => "vm.compact_virtualbox",
+        "wsl" => "vm.compact_wsl",
+        _ => "vm.compact_docker",
+      }),
+      compactable: compaction_command(&path, kind).is_some(),
+    });
+  }
+  images.sort_by_key(|image| Reverse(image.allocated_bytes));
+  images
+}
+
+// Runs the image's compaction tool in place. The VM using it has to be shut down; the tools
+// refuse images that are in use.
+pub fn compact(path: &Path) -> Result<Compaction, Message> {
+  let (path, kind) = classify(path).ok_or_else(|| Message::new("vm.not_image"))?;
+  if !path.exists() {
+    return Err(Message::new("file.not_found"));
+  }
+  let mut command =
+    compaction_command(&path, kind).ok_or_else(|| Message::new("vm.not_compactable"))?;
+  let before_bytes = allocated_size(&path);
+  let output = command
+    .output()
+    .map_err(|_| Message::new("vm.compaction_failed"))?;
+  if !output.status.success() {
+    let stderr = String::from_utf8_lossy(&output.stderr).trim().to_string();
+    return Err(if stderr.is_empty() {
+      Message::new("vm.compaction_failed")
+    } else {
+      Message::unexpected(stderr)
+    });
+  }
+  let after_bytes = allocated_size(&path);
+  Ok(Compaction {
+    path: path.to_string_lossy().to_string(),
+    before_bytes,
+    after_bytes,
+    freed_bytes: before_bytes.saturating_sub(after_bytes),
+  })
+}
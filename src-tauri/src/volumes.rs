@@ -0,0 +1,607 @@
+use crate::filesystem;
+use crate::scanner::{self, FileEntry, DEFAULT_TOP_N};
+use crate::settings::Settings;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+#[cfg(target_os = "macos")]
+use std::ffi::{CStr, CString};
+#[cfg(target_os = "linux")]
+use std::fs;
+#[cfg(target_os = "macos")]
+use std::os::unix::ffi::OsStrExt;
+use std::path::{Path, PathBuf};
+#[cfg(any(target_os = "linux", target_os = "windows"))]
+use std::process::{Command, Stdio};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use tauri::Manager;
+
+#[derive(Clone, Serialize)]
+pub struct MountedVolume {
+  #[serde(rename = "mountPoint")]
+  pub mount_point: PathBuf,
+  pub device: String,
+  #[serde(rename = "fsType")]
+  pub fs_type: String,
+}
+
+// /proc/mounts escapes spaces and tabs in paths as octal (`\040`).
+#[cfg(target_os = "linux")]
+fn unescape_mount_path(field: &str) -> String {
+  let bytes = field.as_bytes();
+  let mut out = Vec::with_capacity(bytes.len());
+  let mut index = 0;
+  while index < bytes.len() {
+    if bytes[index] == b'\\' && index + 3 < bytes.len() {
+      let digits = std::str::from_utf8(&bytes[index + 1..index + 4]).ok();
+      if let Some(value) = digits.and_then(|digits| u8::from_str_radix(digits, 8).ok()) {
+        out.push(value);
+        index += 4;
+        continue;
+      }
+    }
+    out.push(bytes[index]);
+    index += 1;
+  }
+  String::from_utf8_lossy(&out).to_string()
+}
+
+// Block-device backed mounts only; a device mounted twice (bind mounts) is listed once.
+#[cfg(target_os = "linux")]
+pub fn mounted_volumes() -> Vec<MountedVolume> {
+  let mounts = match fs::read_to_string("/proc/self/mounts") {
+    Ok(mounts) => mounts,
+    Err(_) => return Vec::new(),
+  };
+
+  let mut volumes: Vec<MountedVolume> = Vec::new();
+  for line in mounts.lines() {
+    let fields: Vec<&str> = line.split_whitespace().collect();
+    if fields.len() < 3 || !fields[0].starts_with("/dev/") {
+      continue;
+    }
+    if volumes.iter().any(|volume| volume.device == fields[0]) {
+      continue;
+    }
+    volumes.push(MountedVolume {
+      mount_point: PathBuf::from(unescape_mount_path(fields[1])),
+      device: fields[0].to_string(),
+      fs_type: fields[2].to_string(),
+    });
+  }
+  volumes
+}
+
+#[cfg(target_os = "macos")]
+pub fn mounted_volumes() -> Vec<MountedVolume> {
+  const MNT_DONTBROWSE: u32 = 0x0010_0000;
+
+  let mut mounts: *mut libc::statfs = std::ptr::null_mut();
+  let count = unsafe { libc::getmntinfo(&mut mounts, libc::MNT_NOWAIT) };
+  if count <= 0 || mounts.is_null() {
+    return Vec::new();
+  }
+
+  let mounts = unsafe { std::slice::from_raw_parts(mounts, count as usize) };
+  mounts
+    .iter()
+    .filter(|mount| mount.f_flags & MNT_DONTBROWSE == 0)
+    .filter_map(|mount| {
+      let text = |chars: &[libc::c_char]| {
+        unsafe { CStr::from_ptr(chars.as_ptr()) }
+          .to_string_lossy()
+          .to_string()
+      };
+      let device = text(&mount.f_mntfromname);
+      let fs_type = text(&mount.f_fstypename);
+      if !device.starts_with("/dev/") || matches!(fs_type.as_str(), "devfs" | "autofs") {
+        return None;
+      }
+      Some(MountedVolume {
+        mount_point: PathBuf::from(text(&mount.f_mntonname)),
+        device,
+        fs_type,
+      })
+    })
+    .collect()
+}
+
+#[cfg(not(any(target_os = "macos", target_os = "linux")))]
+pub fn mounted_volumes() -> Vec<MountedVolume> {
+  Vec::new()
+}
+
+// A mount a scan leaves out because its contents aren't files on a disk.
+#[derive(Clone, Serialize)]
+pub struct SkippedMount {
+  pub path: String,
+  #[serde(rename = "fsType")]
+  pub fs_type: String,
+}
+
+// Kernel and device views (/proc, /sys, /dev, ...) and snap packages, whose squashfs loop mounts
+// would count each package again next to its .snap file.
+#[cfg(target_os = "linux")]
+const PSEUDO_FS_TYPES: &[&str] = &[
+  "proc",
+  "sysfs",
+  "devtmpfs",
+  "devpts",
+  "cgroup",
+  "cgroup2",
+  "securityfs",
+  "debugfs",
+  "tracefs",
+  "pstore",
+  "bpf",
+  "configfs",
+  "fusectl",
+  "mqueue",
+  "hugetlbfs",
+  "autofs",
+  "binfmt_misc",
+  "efivarfs",
+  "selinuxfs",
+  "rpc_pipefs",
+  "nsfs",
+  "squashfs",
+];
+
+// Time Machine's local snapshot and backup destination mounts repeat what is already on disk.
+#[cfg(target_os = "macos")]
+const TIME_MACHINE_MOUNTS: &[&str] = &[
+  "/Volumes/com.apple.TimeMachine.localsnapshots",
+  "/Volumes/.timemachine",
+];
+
+#[cfg(target_os = "linux")]
+fn pseudo_mounts() -> Vec<SkippedMount> {
+  let mounts = match fs::read_to_string("/proc/self/mounts") {
+    Ok(mounts) => mounts,
+    Err(_) => return Vec::new(),
+  };
+  mounts
+    .lines()
+    .filter_map(|line| {
+      let fields: Vec<&str> = line.split_whitespace().collect();
+      if fields.len() < 3 || !PSEUDO_FS_TYPES.contains(&fields[2]) {
+        return None;
+      }
+      Some(SkippedMount {
+        path: unescape_mount_path(fields[1]),
+        fs_type: fields[2].to_string(),
+      })
+    })
+    .collect()
+}
+
+#[cfg(target_os = "macos")]
+fn pseudo_mounts() -> Vec<SkippedMount> {
+  let mut mounts: *mut libc::statfs = std::ptr::null_mut();
+  let count = unsafe { libc::getmntinfo(&mut mounts, libc::MNT_NOWAIT) };
+  if count <= 0 || mounts.is_null() {
+    return Vec::new();
+  }
+
+  let mounts = unsafe { std::slice::from_raw_parts(mounts, count as usize) };
+  mounts
+    .iter()
+    .filter_map(|mount| {
+      let text = |chars: &[libc::c_char]| {
+        unsafe { CStr::from_ptr(chars.as_ptr()) }
+          .to_string_lossy()
+          .to_string()
+      };
+      let path = text(&mount.f_mntonname);
+      let fs_type = text(&mount.f_fstypename);
+      let time_machine = TIME_MACHINE_MOUNTS
+        .iter()
+        .any(|prefix| Path::new(&path).starts_with(prefix));
+      if !time_machine && !matches!(fs_type.as_str(), "devfs" | "autofs" | "fdesc") {
+        return None;
+      }
+      Some(SkippedMount { path, fs_type })
+    })
+    .collect()
+}
+
+#[cfg(not(any(target_os = "macos", target_os = "linux")))]
+fn pseudo_mounts() -> Vec<SkippedMount> {
+  Vec::new()
+}
+
+// The pseudo-filesystem mounts below `root` (not `root` itself, which is scanned if asked for),
+// with paths as seen through `root`; a mount inside another is left out.
+pub fn pseudo_mounts_under(root: &Path) -> Vec<SkippedMount> {
+  let resolved = root.canonicalize().unwrap_or_else(|_| root.to_path_buf());
+  let mut mounts: Vec<SkippedMount> = pseudo_mounts()
+    .into_iter()
+    .filter_map(|mount| {
+      let relative = Path::new(&mount.path).strip_prefix(&resolved).ok()?;
+      if relative.as_os_str().is_empty() {
+        return None;
+      }
+      Some(SkippedMount {
+        path: root.join(relative).to_string_lossy().to_string(),
+        fs_type: mount.fs_type,
+      })
+    })
+    .collect();
+  mounts.sort_by(|a, b| a.path.cmp(&b.path));
+  mounts.dedup_by(|mount, outer| Path::new(&mount.path).starts_with(&outer.path));
+  mounts
+}
+
+// The volume holding `path`: the mounted volume with the longest matching mount point.
+pub fn volume_for(path: &Path) -> Option<MountedVolume> {
+  let path = path.canonicalize().unwrap_or_else(|_| path.to_path_buf());
+  mounted_volumes()
+    .into_iter()
+    .filter(|volume| path.starts_with(&volume.mount_point))
+    .max_by_key(|volume| volume.mount_point.components().count())
+}
+
+// Where a path lives independent of where its volume is mounted, so cached data and history
+// follow a drive from `/Volumes/Drive` to `/Volumes/Drive 1`.
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+pub struct VolumeLocation {
+  pub uuid: String,
+  // Relative to the mount point; empty for the mount point itself.
+  #[serde(rename = "relativePath")]
+  pub relative_path: String,
+}
+
+pub fn locate(path: &Path) -> Option<VolumeLocation> {
+  let path = path.canonicalize().ok()?;
+  let volume = volume_for(&path)?;
+  let uuid = volume_uuid(&volume)?;
+  let relative = path.strip_prefix(&volume.mount_point).ok()?;
+  Some(VolumeLocation {
+    uuid,
+    relative_path: relative.to_string_lossy().to_string(),
+  })
+}
+
+#[derive(Serialize)]
+pub struct VolumeInfo {
+  #[serde(flatten)]
+  pub volume: MountedVolume,
+  pub uuid: Option<String>,
+  #[serde(rename = "totalBytes")]
+  pub total_bytes: Option<u64>,
+  #[serde(rename = "availableBytes")]
+  pub available_bytes: Option<u64>,
+}
+
+pub fn list_volumes() -> Vec<VolumeInfo> {
+  mounted_volumes()
+    .into_iter()
+    .map(|volume| {
+      let space = filesystem::volume_space(&volume.mount_point);
+      VolumeInfo {
+        uuid: volume_uuid(&volume),
+        total_bytes: space.map(|(total, _)| total),
+        available_bytes: space.map(|(_, available)| available),
+        volume,
+      }
+    })
+    .collect()
+}
+
+// The filesystem UUID from /dev/disk/by-uuid, whose links point at the block devices, falling
+// back to blkid (which may need root for devices udev has not probed).
+#[cfg(target_os = "linux")]
+pub fn volume_uuid(volume: &MountedVolume) -> Option<String> {
+  let device = fs::canonicalize(&volume.device).ok()?;
+  let by_uuid = fs::read_dir("/dev/disk/by-uuid").ok().and_then(|links| {
+    links
+      .flatten()
+      .find(|link| fs::canonicalize(link.path()).ok().as_ref() == Some(&device))
+      .map(|link| link.file_name().to_string_lossy().to_string())
+  });
+  by_uuid.or_else(|| {
+    let output = Command::new("blkid")
+      .arg("-s")
+      .arg("UUID")
+      .arg("-o")
+      .arg("value")
+      .arg(&device)
+      .stderr(Stdio::null())
+      .output()
+      .ok()?;
+    let uuid = String::from_utf8_lossy(&output.stdout).trim().to_string();
+    (output.status.success() && !uuid.is_empty()).then_some(uuid)
+  })
+}
+
+#[cfg(target_os = "macos")]
+mod disk_arbitration {
+  use std::ffi::{c_void, CStr, CString};
+  use std::os::raw::c_char;
+
+  type CFTypeRef = *const c_void;
+  const K_CF_STRING_ENCODING_UTF8: u32 = 0x0800_0100;
+
+  #[link(name = "CoreFoundation", kind = "framework")]
+  extern "C" {
+    fn CFDictionaryGetValue(dictionary: CFTypeRef, key: CFTypeRef) -> CFTypeRef;
+    fn CFUUIDCreateString(allocator: CFTypeRef, uuid: CFTypeRef) -> CFTypeRef;
+    fn CFStringGetCString(string: CFTypeRef, buffer: *mut c_char, size: isize, encoding: u32)
+      -> u8;
+    fn CFRelease(value: CFTypeRef);
+  }
+
+  #[link(name = "DiskArbitration", kind = "framework")]
+  extern "C" {
+    static kDADiskDescriptionVolumeUUIDKey: CFTypeRef;
+    fn DASessionCreate(allocator: CFTypeRef) -> CFTypeRef;
+    fn DADiskCreateFromBSDName(
+      allocator: CFTypeRef,
+      session: CFTypeRef,
+      name: *const c_char,
+    ) -> CFTypeRef;
+    fn DADiskCopyDescription(disk: CFTypeRef) -> CFTypeRef;
+  }
+
+  // The volume UUID DiskArbitration reports for a BSD device such as `disk3s1`.
+  pub fn volume_uuid(bsd_name: &str) -> Option<String> {
+    let name = CString::new(bsd_name).ok()?;
+    unsafe {
+      let session = DASessionCreate(std::ptr::null());
+      if session.is_null() {
+        return None;
+      }
+      let disk = DADiskCreateFromBSDName(std::ptr::null(), session, name.as_ptr());
+      let description = if disk.is_null() {
+        std::ptr::null()
+      } else {
+        DADiskCopyDescription(disk)
+      };
+      let mut uuid = None;
+      if !description.is_null() {
+        let value = CFDictionaryGetValue(description, kDADiskDescriptionVolumeUUIDKey);
+        if !value.is_null() {
+          let string = CFUUIDCreateString(std::ptr::null(), value);
+          if !string.is_null() {
+            let mut buffer = [0 as c_char; 64];
+            if CFStringGetCString(
+              string,
+              buffer.as_mut_ptr(),
+              buffer.len() as isize,
+              K_CF_STRING_ENCODING_UTF8,
+            ) != 0
+            {
+              uuid = Some(
+                CStr::from_ptr(buffer.as_ptr())
+                  .to_string_lossy()
+                  .to_string(),
+              );
+            }
+            CFRelease(string);
+          }
+        }
+        CFRelease(description);
+      }
+      if !disk.is_null() {
+        CFRelease(disk);
+      }
+      CFRelease(session);
+      uuid
+    }
+  }
+}
+
+// DiskArbitration first; the volume's own UUID attribute covers volumes it does not know.
+#[cfg(target_os = "macos")]
+pub fn volume_uuid(volume: &MountedVolume) -> Option<String> {
+  volume
+    .device
+    .strip_prefix("/dev/")
+    .and_then(disk_arbitration::volume_uuid)
+    .or_else(|| volume_uuid_attribute(volume))
+}
+
+#[cfg(target_os = "macos")]
+fn volume_uuid_attribute(volume: &MountedVolume) -> Option<String> {
+  const ATTR_BIT_MAP_COUNT: u16 = 5;
+  const ATTR_VOL_UUID: u32 = 0x00040000;
+  const ATTR_VOL_INFO: u32 = 0x80000000;
+
+  let c_path = CString::new(volume.mount_point.as_os_str().as_bytes()).ok()?;
+  let mut attrlist: libc::attrlist = unsafe { std::mem::zeroed() };
+  attrlist.bitmapcount = ATTR_BIT_MAP_COUNT;
+  attrlist.volattr = ATTR_VOL_INFO | ATTR_VOL_UUID;
+
+  let mut buffer = [0u8; 20];
+  let result = unsafe {
+    libc::getattrlist(
+      c_path.as_ptr(),
+      &mut attrlist as *mut _ as *mut libc::c_void,
+      buffer.as_mut_ptr() as *mut _,
+      buffer.len(),
+      0,
+    )
+  };
+  if result != 0 {
+    return None;
+  }
+  let uuid = &buffer[4..20];
+  if uuid.iter().all(|byte| *byte == 0) {
+    return None;
+  }
+  let hex: String = uuid.iter().map(|byte| format!("{:02X}", byte)).collect();
+  Some(format!(
+    "{}-{}-{}-{}-{}",
+    &hex[0..8],
+    &hex[8..12],
+    &hex[12..16],
+    &hex[16..20],
+    &hex[20..32]
+  ))
+}
+
+// The volume GUID from `mountvol`, e.g. `\\?\Volume{GUID}\` for `C:\`.
+#[cfg(target_os = "windows")]
+pub fn volume_uuid(volume: &MountedVolume) -> Option<String> {
+  let output = Command::new("mountvol")
+    .arg(&volume.mount_point)
+    .arg("/L")
+    .stderr(Stdio::null())
+    .output()
+    .ok()?;
+  let text = String::from_utf8_lossy(&output.stdout);
+  let start = text.find('{')? + 1;
+  let end = start + text[start..].find('}')?;
+  Some(text[start..end].to_uppercase())
+}
+
+#[cfg(not(any(target_os = "macos", target_os = "linux", target_os = "windows")))]
+pub fn volume_uuid(_volume: &MountedVolume) -> Option<String> {
+  None
+}
+
+#[derive(Clone, Serialize)]
+struct LowSpacePayload {
+  #[serde(rename = "mountPoint")]
+  mount_point: String,
+  uuid: Option<String>,
+  #[serde(rename = "totalBytes")]
+  total_bytes: u64,
+  #[serde(rename = "availableBytes")]
+  available_bytes: u64,
+  #[serde(rename = "thresholdPercent")]
+  threshold_percent: f64,
+}
+
+// Emits `low_space` if the volume holding `path` has less free space than its threshold.
+pub fn check_low_space(app: &tauri::AppHandle, path: &Path, settings: &Settings) {
+  let volume = match volume_for(path) {
+    Some(volume) => volume,
+    None => return,
+  };
+  let threshold = match settings.for_volume(Some(&volume)).low_space_percent {
+    Some(threshold) => threshold,
+    None => return,
+  };
+  let (total, available) = match filesystem::volume_space(&volume.mount_point) {
+    Some(space) => space,
+    None => return,
+  };
+  if total == 0 || available as f64 / total as f64 * 100.0 >= threshold {
+    return;
+  }
+  let _ = app.emit_to(
+    "main",
+    "low_space",
+    LowSpacePayload {
+      mount_point: volume.mount_point.to_string_lossy().to_string(),
+      uuid: volume_uuid(&volume),
+      total_bytes: total,
+      available_bytes: available,
+      threshold_percent: threshold,
+    },
+  );
+}
+
+#[derive(Default, Deserialize)]
+pub struct VolumeScanOptions {
+  #[serde(default)]
+  pub parallel: bool,
+  #[serde(rename = "topN")]
+  pub top_n: Option<usize>,
+}
+
+#[derive(Clone, Serialize)]
+pub struct VolumeReport {
+  #[serde(rename = "scanId")]
+  pub scan_id: u64,
+  #[serde(flatten)]
+  pub volume: MountedVolume,
+  pub uuid: Option<String>,
+  #[serde(rename = "totalBytes")]
+  pub total_bytes: Option<u64>,
+  #[serde(rename = "availableBytes")]
+  pub available_bytes: Option<u64>,
+  #[serde(rename = "scannedFiles")]
+  pub scanned_files: u64,
+  #[serde(rename = "scannedBytes")]
+  pub scanned_bytes: u64,
+  #[serde(rename = "topFiles")]
+  pub top_files: Vec<FileEntry>,
+}
+
+#[derive(Clone, Serialize)]
+pub struct AllVolumesReport {
+  #[serde(rename = "scanId")]
+  pub scan_id: u64,
+  pub cancelled: bool,
+  // Keyed by mount point.
+  pub volumes: BTreeMap<String, VolumeReport>,
+}
+
+fn scan_one(
+  app: &tauri::AppHandle,
+  scan_id: u64,
+  volume: MountedVolume,
+  cancel: &AtomicBool,
+  top_n: usize,
+) -> Option<VolumeReport> {
+  let summary = scanner::scan_volume(&volume.mount_point, cancel, top_n)?;
+  let space = filesystem::volume_space(&volume.mount_point);
+  let report = VolumeReport {
+    scan_id,
+    uuid: volume_uuid(&volume),
+    total_bytes: space.map(|(total, _)| total),
+    available_bytes: space.map(|(_, available)| available),
+    scanned_files: summary.scanned_files,
+    scanned_bytes: summary.scanned_bytes,
+    top_files: summary.top_files,
+    volume,
+  };
+  let _ = app.emit_to("main", "volume_scan_complete", report.clone());
+  Some(report)
+}
+
+// Blocks until every volume is scanned (or the scan is cancelled), then emits the combined report.
+pub fn scan_all(
+  app: &tauri::AppHandle,
+  scan_id: u64,
+  volumes: Vec<MountedVolume>,
+  cancel: &AtomicBool,
+  options: &VolumeScanOptions,
+) -> AllVolumesReport {
+  let top_n = options.top_n.unwrap_or(DEFAULT_TOP_N);
+  let reports: Vec<VolumeReport> = if options.parallel {
+    thread::scope(|scope| {
+      let workers: Vec<_> = volumes
+        .into_iter()
+        .map(|volume| scope.spawn(move || scan_one(app, scan_id, volume, cancel, top_n)))
+        .collect();
+      workers
+        .into_iter()
+        .filter_map(|worker| worker.join().ok().flatten())
+        .collect()
+    })
+  } else {
+    volumes
+      .into_iter()
+      .map_while(|volume| scan_one(app, scan_id, volume, cancel, top_n))
+      .collect()
+  };
+
+  let report = AllVolumesReport {
+    scan_id,
+    cancelled: cancel.load(Ordering::Relaxed),
+    volumes: reports
+      .into_iter()
+      .map(|report| {
+        (
+          report.volume.mount_point.to_string_lossy().to_string(),
+          report,
+        )
+      })
+      .collect(),
+  };
+  let _ = app.emit_to("main", "all_volumes_complete", report.clone());
+  report
+}
@@ -0,0 +1,212 @@
+use crate::dataless::NoMaterialize;
+use crate::filesystem;
+use crate::growth::GrowthTracker;
+use crate::messages::Message;
+use crate::scanner;
+use crate::watcher::{self, OwnActivity, WatchCommand};
+use notify::{RecommendedWatcher, RecursiveMode, Watcher};
+use serde::Serialize;
+use std::path::{Path, PathBuf};
+use std::sync::{
+  atomic::{AtomicBool, Ordering},
+  mpsc, Arc, Mutex,
+};
+use std::thread;
+use std::time::Duration;
+use tauri::Manager;
+
+const SESSION_POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+struct SessionRoot {
+  path: PathBuf,
+  case_insensitive: bool,
+}
+
+#[derive(Clone, Serialize)]
+pub struct WatchSessionInfo {
+  #[serde(rename = "sessionId")]
+  pub session_id: u64,
+  roots: Vec<String>,
+}
+
+#[derive(Clone, Serialize)]
+struct SessionChangePayload {
+  #[serde(rename = "sessionId")]
+  session_id: u64,
+  #[serde(rename = "rootId")]
+  root_id: usize,
+  root: String,
+  id: u64,
+  path: String,
+  #[serde(rename = "pathKey", skip_serializing_if = "Option::is_none")]
+  path_key: Option<String>,
+  kind: String,
+  size: Option<u64>,
+}
+
+#[derive(Clone, Serialize)]
+struct SessionStoppedPayload {
+  #[serde(rename = "sessionId")]
+  session_id: u64,
+  reason: Message,
+}
+
+// One notify watcher shared by every root; dropping the handle stops the session.
+pub struct WatchSessionHandle {
+  session_id: u64,
+  roots: Vec<PathBuf>,
+  stop: Arc<AtomicBool>,
+  control: mpsc::Sender<WatchCommand>,
+  growth: Arc<Mutex<GrowthTracker>>,
+}
+
+impl WatchSessionHandle {
+  pub fn send(&self, command: WatchCommand) -> bool {
+    self.control.send(command).is_ok()
+  }
+
+  pub fn info(&self) -> WatchSessionInfo {
+    WatchSessionInfo {
+      session_id: self.session_id,
+      roots: self
+        .roots
+        .iter()
+        .map(|root| root.to_string_lossy().to_string())
+        .collect(),
+    }
+  }
+
+  pub fn growth(&self) -> Arc<Mutex<GrowthTracker>> {
+    self.growth.clone()
+  }
+}
+
+impl Drop for WatchSessionHandle {
+  fn drop(&mut self) {
+    self.stop.store(true, Ordering::Relaxed);
+  }
+}
+
+// Nested roots are allowed; an event belongs to the deepest root containing it.
+fn root_for(roots: &[SessionRoot], path: &Path) -> Option<usize> {
+  roots
+    .iter()
+    .enumerate()
+    .filter(|(_, root)| path.starts_with(&root.path))
+    .max_by_key(|(_, root)| root.path.components().count())
+    .map(|(index, _)| index)
+}
+
+pub fn start_watch_session(
+  app: tauri::AppHandle,
+  session_id: u64,
+  roots: Vec<PathBuf>,
+  ignore_patterns: Vec<String>,
+) -> Result<WatchSessionHandle, Message> {
+  if roots.is_empty() {
+    return Err(Message::new("watch.no_roots"));
+  }
+
+  let (tx, rx) = mpsc::sync_channel(1024);
+  let mut watcher: RecommendedWatcher = notify::recommended_watcher(move |res| {
+    let _ = tx.try_send(res);
+  })
+  .map_err(|_| Message::new("watch.start_failed"))?;
+
+  for root in &roots {
+    if !root.is_dir() {
+      return Err(Message::new("path.not_folder").with("path", root.display()));
+    }
+    watcher
+      .watch(root, RecursiveMode::Recursive)
+      .map_err(|_| Message::new("watch.path_failed").with("path", root.display()))?;
+  }
+
+  let session_roots: Vec<SessionRoot> = roots
+    .iter()
+    .map(|root| SessionRoot {
+      path: root.clone(),
+      case_insensitive: filesystem::filesystem_info(root).and_then(|info| info.case_sensitive)
+        == Some(false),
+    })
+    .collect();
+  let stop = Arc::new(AtomicBool::new(false));
+  let (control_tx, control) = mpsc::channel();
+  let growth = Arc::new(Mutex::new(GrowthTracker::default()));
+  let handle = WatchSessionHandle {
+    session_id,
+    roots,
+    stop: stop.clone(),
+    control: control_tx,
+    growth: growth.clone(),
+  };
+
+  thread::spawn(move || {
+    let _watcher = watcher;
+    let _no_materialize = NoMaterialize::enter();
+    let mut ignore_patterns = ignore_patterns;
+    let reason = loop {
+      if stop.load(Ordering::Relaxed) {
+        break "watch.session_stopped";
+      }
+      while let Ok(command) = control.try_recv() {
+        if let WatchCommand::Ignore(patterns) = command {
+          ignore_patterns = patterns;
+        }
+      }
+
+      let event = match rx.recv_timeout(SESSION_POLL_INTERVAL) {
+        Ok(Ok(event)) => event,
+        Ok(Err(_)) | Err(mpsc::RecvTimeoutError::Timeout) => continue,
+        Err(mpsc::RecvTimeoutError::Disconnected) => break "watch.disconnected",
+      };
+      let (kind, dir_kind) = match watcher::event_kinds(&event.kind) {
+        Some(kinds) => kinds,
+        None => continue,
+      };
+
+      let own_activity = app.state::<OwnActivity>();
+      for path in event.paths {
+        if watcher::is_ignored(&path, &ignore_patterns) || own_activity.contains(&path) {
+          continue;
+        }
+        let root_id = match root_for(&session_roots, &path) {
+          Some(root_id) => root_id,
+          None => continue,
+        };
+        let (event_kind, size) = match watcher::classify_change(&path, kind, dir_kind) {
+          Some(change) => change,
+          None => continue,
+        };
+        if let Ok(mut growth) = growth.lock() {
+          growth.record(&path, event_kind, size);
+        }
+        let root = &session_roots[root_id];
+        let path_string = path.to_string_lossy().to_string();
+
+        let payload = SessionChangePayload {
+          session_id,
+          root_id,
+          root: root.path.to_string_lossy().to_string(),
+          id: scanner::entry_id(&path_string),
+          path_key: root.case_insensitive.then(|| path_string.to_lowercase()),
+          path: path_string,
+          kind: event_kind.to_string(),
+          size,
+        };
+        let _ = app.emit_to("main", "watch_session_change", payload);
+      }
+    };
+
+    let _ = app.emit_to(
+      "main",
+      "watch_session_stopped",
+      SessionStoppedPayload {
+        session_id,
+        reason: Message::new(reason),
+      },
+    );
+  });
+
+  Ok(handle)
+}
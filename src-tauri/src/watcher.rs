@@ -0,0 +1,703 @@
+use crate::dataless::{self, NoMaterialize};
+use crate::filesystem;
+use crate::messages::Message;
+use crate::scanner::{self, ScanSummary, DEFAULT_TOP_N};
+use crate::SharedScanState;
+use crate::{retire_path, spawn_worker};
+use notify::event::{ModifyKind, RemoveKind};
+use notify::{EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
+use std::path::{Component, Path, PathBuf};
+use std::sync::{
+  atomic::{AtomicBool, AtomicU64, Ordering},
+  mpsc, Arc, Mutex,
+};
+use std::time::{Duration, Instant};
+use std::{fs, thread};
+use tauri::Manager;
+
+const WATCH_POLL_INTERVAL: Duration = Duration::from_millis(250);
+const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(5);
+const RESTART_BACKOFF_MIN: Duration = Duration::from_secs(1);
+const RESTART_BACKOFF_MAX: Duration = Duration::from_secs(60);
+const BURST_WINDOW: Duration = Duration::from_secs(60);
+const BURST_EVENT_THRESHOLD: u64 = 10_000;
+const BURST_BYTE_THRESHOLD: u64 = 1024 * 1024 * 1024;
+const RESCAN_QUIET_PERIOD: Duration = Duration::from_secs(5);
+const OWN_ACTIVITY_TTL: Duration = Duration::from_secs(10);
+pub const DEFAULT_IGNORE_PATTERNS: &[&str] = &[
+  "*.swp",
+  "*.swx",
+  "*.part",
+  "*.crdownload",
+  "*.download",
+  "*.tmp",
+  "*~",
+  ".#*",
+  ".DS_Store",
+];
+
+pub enum WatchCommand {
+  Focus(PathBuf),
+  Ignore(Vec<String>),
+}
+
+// Paths the app itself just deleted or rewrote, so the watcher does not report them back.
+#[derive(Default)]
+pub struct OwnActivity {
+  recent: Mutex<HashMap<PathBuf, Instant>>,
+}
+
+impl OwnActivity {
+  pub fn record(&self, path: &Path) {
+    if let Ok(mut recent) = self.recent.lock() {
+      recent.retain(|_, at| at.elapsed() < OWN_ACTIVITY_TTL);
+      recent.insert(path.to_path_buf(), Instant::now());
+    }
+  }
+
+  pub fn contains(&self, path: &Path) -> bool {
+    match self.recent.lock() {
+      Ok(recent) => recent
+        .get(path)
+        .map(|at| at.elapsed() < OWN_ACTIVITY_TTL)
+        .unwrap_or(false),
+      Err(_) => false,
+    }
+  }
+}
+
+#[derive(Default)]
+struct WatchCounters {
+  running: AtomicBool,
+  events: AtomicU64,
+  dropped: AtomicU64,
+  restarts: AtomicU64,
+}
+
+#[derive(Clone, Default, Serialize)]
+pub struct WatchStatus {
+  active: bool,
+  #[serde(rename = "scanId")]
+  scan_id: Option<u64>,
+  root: Option<String>,
+  #[serde(rename = "eventsProcessed")]
+  events_processed: u64,
+  #[serde(rename = "droppedEvents")]
+  dropped_events: u64,
+  restarts: u64,
+}
+
+// Dropping the handle, when its scan is cancelled or another one starts, stops the watcher.
+pub struct WatchHandle {
+  pub scan_id: u64,
+  root: PathBuf,
+  control: mpsc::Sender<WatchCommand>,
+  counters: Arc<WatchCounters>,
+  stop: Arc<AtomicBool>,
+}
+
+impl Drop for WatchHandle {
+  fn drop(&mut self) {
+    self.stop.store(true, Ordering::Relaxed);
+  }
+}
+
+impl WatchHandle {
+  pub fn send(&self, command: WatchCommand) -> bool {
+    self.control.send(command).is_ok()
+  }
+
+  pub fn status(&self) -> WatchStatus {
+    status_for(self.scan_id, &self.root, &self.counters)
+  }
+}
+
+fn status_for(scan_id: u64, root: &Path, counters: &WatchCounters) -> WatchStatus {
+  WatchStatus {
+    active: counters.running.load(Ordering::Relaxed),
+    scan_id: Some(scan_id),
+    root: Some(root.to_string_lossy().to_string()),
+    events_processed: counters.events.load(Ordering::Relaxed),
+    dropped_events: counters.dropped.load(Ordering::Relaxed),
+    restarts: counters.restarts.load(Ordering::Relaxed),
+  }
+}
+
+#[derive(Clone, Serialize)]
+struct WatchStoppedPayload {
+  #[serde(rename = "scanId")]
+  scan_id: u64,
+  root: String,
+  reason: Message,
+  restarting: bool,
+}
+
+#[derive(Clone, Serialize)]
+struct SummaryUpdatedPayload {
+  #[serde(rename = "scanId")]
+  scan_id: u64,
+  subtrees: Vec<ScanSummary>,
+}
+
+#[derive(Clone, Serialize)]
+struct FsChangePayload {
+  #[serde(rename = "scanId")]
+  scan_id: u64,
+  id: u64,
+  path: String,
+  #[serde(rename = "pathKey", skip_serializing_if = "Option::is_none")]
+  path_key: Option<String>,
+  kind: String,
+  size: Option<u64>,
+}
+
+// Counts changes per window; once a burst crosses a threshold, the touched top-level
+// folders are rescanned after the watcher has been quiet for a moment.
+#[derive(Default)]
+struct BurstTracker {
+  started: Option<Instant>,
+  last_event: Option<Instant>,
+  events: u64,
+  bytes: u64,
+  subtrees: HashSet<PathBuf>,
+  pending: bool,
+}
+
+impl BurstTracker {
+  fn record(&mut self, root: &Path, path: &Path, bytes: u64) {
+    let now = Instant::now();
+    let expired = self
+      .started
+      .map(|started| now.duration_since(started) >= BURST_WINDOW)
+      .unwrap_or(true);
+    if expired && !self.pending {
+      self.started = Some(now);
+      self.events = 0;
+      self.bytes = 0;
+      self.subtrees.clear();
+    }
+
+    self.events += 1;
+    self.bytes = self.bytes.saturating_add(bytes);
+    self.last_event = Some(now);
+    let subtree = match path
+      .strip_prefix(root)
+      .ok()
+      .and_then(|rest| rest.components().next())
+    {
+      Some(Component::Normal(name)) if path != root.join(name) => root.join(name),
+      _ => root.to_path_buf(),
+    };
+    self.subtrees.insert(subtree);
+
+    if self.events >= BURST_EVENT_THRESHOLD || self.bytes >= BURST_BYTE_THRESHOLD {
+      self.pending = true;
+    }
+  }
+
+  fn take_ready(&mut self, root: &Path) -> Option<Vec<PathBuf>> {
+    let quiet = self
+      .last_event
+      .map(|last| last.elapsed() >= RESCAN_QUIET_PERIOD)
+      .unwrap_or(false);
+    if !self.pending || !quiet {
+      return None;
+    }
+
+    self.pending = false;
+    self.started = None;
+    let subtrees: Vec<PathBuf> = self.subtrees.drain().collect();
+    if subtrees.iter().any(|subtree| subtree == root) {
+      return Some(vec![root.to_path_buf()]);
+    }
+    Some(subtrees)
+  }
+}
+
+pub fn default_ignore_patterns() -> Vec<String> {
+  DEFAULT_IGNORE_PATTERNS
+    .iter()
+    .map(|pattern| pattern.to_string())
+    .collect()
+}
+
+fn wildcard_match(pattern: &[u8], name: &[u8]) -> bool {
+  let (mut p, mut n) = (0, 0);
+  let mut star: Option<(usize, usize)> = None;
+
+  while n < name.len() {
+    if p < pattern.len() && (pattern[p] == b'?' || pattern[p] == name[n]) {
+      p += 1;
+      n += 1;
+    } else if p < pattern.len() && pattern[p] == b'*' {
+      star = Some((p, n));
+      p += 1;
+    } else if let Some((star_p, star_n)) = star {
+      p = star_p + 1;
+      n = star_n + 1;
+      star = Some((star_p, star_n + 1));
+    } else {
+      return false;
+    }
+  }
+
+  pattern[p..].iter().all(|&byte| byte == b'*')
+}
+
+pub fn is_ignored(path: &Path, patterns: &[String]) -> bool {
+  let name = match path.file_name() {
+    Some(name) => name.to_string_lossy(),
+    None => return false,
+  };
+
+  patterns
+    .iter()
+    .any(|pattern| wildcard_match(pattern.as_bytes(), name.as_bytes()))
+}
+
+fn path_is_file(path: &Path) -> bool {
+  match fs::symlink_metadata(path) {
+    Ok(metadata) => metadata.is_file() && !metadata.file_type().is_symlink(),
+    Err(_) => false,
+  }
+}
+
+fn path_is_dir(path: &Path) -> bool {
+  match fs::symlink_metadata(path) {
+    Ok(metadata) => metadata.is_dir(),
+    Err(_) => false,
+  }
+}
+
+// Maps a notify event to our change kind, plus the kind to use when the path is a folder.
+pub fn event_kinds(kind: &EventKind) -> Option<(&'static str, Option<&'static str>)> {
+  match kind {
+    EventKind::Create(_) => Some(("create", Some("dir_created"))),
+    EventKind::Modify(ModifyKind::Name(_)) => Some(("modify", Some("dir_created"))),
+    EventKind::Modify(_) => Some(("modify", None)),
+    EventKind::Remove(RemoveKind::Folder) => Some(("dir_removed", None)),
+    EventKind::Remove(_) => Some(("remove", None)),
+    _ => None,
+  }
+}
+
+// Callers must hold a NoMaterialize guard on the current thread.
+pub fn classify_change(
+  path: &Path,
+  kind: &'static str,
+  dir_kind: Option<&'static str>,
+) -> Option<(&'static str, Option<u64>)> {
+  debug_assert!(dataless::materialization_disabled());
+  if kind == "remove" || kind == "dir_removed" {
+    Some((kind, None))
+  } else if !path.exists() {
+    Some(("remove", None))
+  } else if path_is_file(path) {
+    Some((kind, fs::metadata(path).ok().map(|metadata| metadata.len())))
+  } else {
+    dir_kind
+      .filter(|_| path_is_dir(path))
+      .map(|dir_kind| (dir_kind, None))
+  }
+}
+
+// Sets up a watcher without touching the filesystem, so the coordinator can hand out the handle
+// and leave `WatchWorker::spawn` to the caller.
+pub fn prepare_fs_watcher(
+  app: tauri::AppHandle,
+  root: PathBuf,
+  scan_id: u64,
+  cancel_flag: Arc<AtomicBool>,
+  ignore_patterns: Vec<String>,
+) -> (WatchHandle, WatchWorker) {
+  let (control_tx, control) = mpsc::channel();
+  let counters = Arc::new(WatchCounters::default());
+  let stop = Arc::new(AtomicBool::new(false));
+  let handle = WatchHandle {
+    scan_id,
+    root: root.clone(),
+    control: control_tx,
+    counters: counters.clone(),
+    stop: stop.clone(),
+  };
+
+  let worker = WatchWorker {
+    app,
+    root: root.clone(),
+    scan_id,
+    stop,
+    cancel_flag,
+    counters,
+    control,
+    ignore_patterns,
+    case_insensitive: false,
+    focus: root,
+    burst: BurstTracker::default(),
+  };
+  (handle, worker)
+}
+
+pub struct WatchWorker {
+  app: tauri::AppHandle,
+  root: PathBuf,
+  scan_id: u64,
+  // Set once the coordinator drops the handle.
+  stop: Arc<AtomicBool>,
+  cancel_flag: Arc<AtomicBool>,
+  counters: Arc<WatchCounters>,
+  control: mpsc::Receiver<WatchCommand>,
+  ignore_patterns: Vec<String>,
+  case_insensitive: bool,
+  focus: PathBuf,
+  burst: BurstTracker,
+}
+
+impl WatchWorker {
+  pub fn spawn(self) {
+    spawn_worker(self.app.clone(), self.scan_id, move || {
+      self.run_with_restarts()
+    });
+  }
+
+  fn stopped(&self) -> bool {
+    self.cancel_flag.load(Ordering::Relaxed) || self.stop.load(Ordering::Relaxed)
+  }
+
+  fn run_with_restarts(mut self) {
+    let _no_materialize = NoMaterialize::enter();
+    self.case_insensitive =
+      filesystem::filesystem_info(&self.root).and_then(|info| info.case_sensitive) == Some(false);
+    let mut backoff = RESTART_BACKOFF_MIN;
+
+    loop {
+      let started = Instant::now();
+      let failure = self.run();
+      self.counters.running.store(false, Ordering::Relaxed);
+
+      let restarting = failure.is_some() && !self.stopped();
+      let _ = self.app.emit_to(
+        "main",
+        "watch_stopped",
+        WatchStoppedPayload {
+          scan_id: self.scan_id,
+          root: self.root.to_string_lossy().to_string(),
+          reason: failure
+            .clone()
+            .unwrap_or_else(|| Message::new("watch.stopped")),
+          restarting,
+        },
+      );
+      if !restarting {
+        return;
+      }
+
+      if started.elapsed() >= RESTART_BACKOFF_MAX {
+        backoff = RESTART_BACKOFF_MIN;
+      }
+      if !self.wait_for_root(backoff) {
+        return;
+      }
+      backoff = (backoff * 2).min(RESTART_BACKOFF_MAX);
+      self.counters.restarts.fetch_add(1, Ordering::Relaxed);
+    }
+  }
+
+  fn spawn_rescan(&self, subtrees: Vec<PathBuf>) {
+    let app = self.app.clone();
+    let scan_id = self.scan_id;
+    let stop = self.stop.clone();
+    let cancel_flag = self.cancel_flag.clone();
+
+    spawn_worker(app.clone(), scan_id, move || {
+      let mut summaries = Vec::new();
+      for subtree in subtrees {
+        if !path_is_dir(&subtree) {
+          summaries.push(ScanSummary {
+            path: subtree.to_string_lossy().to_string(),
+            scanned_files: 0,
+            scanned_bytes: 0,
+            top_files: Vec::new(),
+            incremental: None,
+            small_files: None,
+            skipped_mounts: Vec::new(),
+            candidates: Vec::new(),
+          });
+          continue;
+        }
+        match scanner::scan_subtree(&subtree, &cancel_flag, DEFAULT_TOP_N) {
+          Some(summary) => summaries.push(summary),
+          None => return,
+        }
+      }
+
+      if !stop.load(Ordering::Relaxed) {
+        let _ = app.emit_to(
+          "main",
+          "scan_summary_updated",
+          SummaryUpdatedPayload {
+            scan_id,
+            subtrees: summaries,
+          },
+        );
+      }
+    });
+  }
+
+  // Entries of the retained result that disappear become tombstones instead of vanishing.
+  fn update_retained(&self, path: &Path, kind: &str, size: Option<u64>) {
+    if kind == "remove" || kind == "dir_removed" {
+      retire_path(&self.app, Some(self.scan_id), path);
+      return;
+    }
+    let size = match (kind, size) {
+      ("create", Some(size)) => size,
+      _ => return,
+    };
+    let state = self.app.state::<SharedScanState>();
+    let mut state = state.lock();
+    if let Some(summary) = state.scan_summaries.get_mut(&self.scan_id) {
+      summary.mark_present(path, size);
+    }
+  }
+
+  fn wait_for_root(&self, backoff: Duration) -> bool {
+    let mut waited = Duration::ZERO;
+    loop {
+      if self.stopped() {
+        return false;
+      }
+      if waited >= backoff && path_is_dir(&self.root) {
+        return true;
+      }
+      thread::sleep(WATCH_POLL_INTERVAL);
+      waited += WATCH_POLL_INTERVAL;
+    }
+  }
+
+  fn run(&mut self) -> Option<Message> {
+    let (tx, rx) = mpsc::sync_channel(1024);
+    let callback_counters = self.counters.clone();
+    let mut watcher: RecommendedWatcher = match notify::recommended_watcher(move |res| {
+      if tx.try_send(res).is_err() {
+        callback_counters.dropped.fetch_add(1, Ordering::Relaxed);
+      }
+    }) {
+      Ok(watcher) => watcher,
+      Err(_) => return Some(Message::new("watch.start_failed")),
+    };
+
+    let mut watched = Vec::new();
+    let focus = self.focus.clone();
+    if apply_focus(&mut watcher, &self.root, &focus, &mut watched).is_err() {
+      return Some(Message::new("watch.root_failed"));
+    }
+
+    self.counters.running.store(true, Ordering::Relaxed);
+    let mut last_heartbeat = Instant::now();
+
+    loop {
+      if self.stopped() {
+        return None;
+      }
+
+      if last_heartbeat.elapsed() >= HEARTBEAT_INTERVAL {
+        if !path_is_dir(&self.root) {
+          return Some(Message::new("scan.root_unavailable"));
+        }
+        let _ = self.app.emit_to(
+          "main",
+          "watch_heartbeat",
+          status_for(self.scan_id, &self.root, &self.counters),
+        );
+        last_heartbeat = Instant::now();
+      }
+
+      while let Ok(command) = self.control.try_recv() {
+        match command {
+          WatchCommand::Focus(focus) => {
+            if apply_focus(&mut watcher, &self.root, &focus, &mut watched).is_ok() {
+              self.focus = focus;
+            } else {
+              self.focus = self.root.clone();
+              let _ = apply_focus(&mut watcher, &self.root, &self.root, &mut watched);
+            }
+          }
+          WatchCommand::Ignore(patterns) => self.ignore_patterns = patterns,
+        }
+      }
+
+      if let Some(subtrees) = self.burst.take_ready(&self.root) {
+        self.spawn_rescan(subtrees);
+      }
+
+      let result = match rx.recv_timeout(WATCH_POLL_INTERVAL) {
+        Ok(result) => result,
+        Err(mpsc::RecvTimeoutError::Timeout) => continue,
+        Err(mpsc::RecvTimeoutError::Disconnected) => {
+          return Some(Message::new("watch.disconnected"))
+        }
+      };
+
+      let event = match result {
+        Ok(event) => event,
+        Err(_) if !path_is_dir(&self.root) => return Some(Message::new("scan.root_unavailable")),
+        Err(_) => continue,
+      };
+      self.counters.events.fetch_add(1, Ordering::Relaxed);
+
+      let (kind, dir_kind) = match event_kinds(&event.kind) {
+        Some(kinds) => kinds,
+        None => continue,
+      };
+
+      let own_activity = self.app.state::<OwnActivity>();
+      for path in event.paths {
+        if is_ignored(&path, &self.ignore_patterns) || own_activity.contains(&path) {
+          continue;
+        }
+
+        let (event_kind, size) = match classify_change(&path, kind, dir_kind) {
+          Some(change) => change,
+          None => continue,
+        };
+        let path_string = path.to_string_lossy().to_string();
+
+        self.burst.record(&self.root, &path, size.unwrap_or(0));
+        self.update_retained(&path, event_kind, size);
+
+        let payload = FsChangePayload {
+          scan_id: self.scan_id,
+          id: scanner::entry_id(&path_string),
+          path_key: self.case_insensitive.then(|| path_string.to_lowercase()),
+          path: path_string,
+          kind: event_kind.to_string(),
+          size,
+        };
+
+        let _ = self.app.emit_to("main", "scan_fs_change", payload);
+      }
+
+      if !path_is_dir(&self.root) {
+        return Some(Message::new("scan.root_unavailable"));
+      }
+    }
+  }
+}
+
+fn apply_focus(
+  watcher: &mut RecommendedWatcher,
+  root: &Path,
+  focus: &Path,
+  watched: &mut Vec<PathBuf>,
+) -> notify::Result<()> {
+  for path in watched.drain(..) {
+    let _ = watcher.unwatch(&path);
+  }
+
+  let focus = if focus.starts_with(root) && focus.is_dir() {
+    focus
+  } else {
+    root
+  };
+
+  watcher.watch(focus, RecursiveMode::Recursive)?;
+  watched.push(focus.to_path_buf());
+
+  let mut ancestor = focus.parent().filter(|_| focus != root);
+  while let Some(dir) = ancestor {
+    if !dir.starts_with(root) {
+      break;
+    }
+    if watcher.watch(dir, RecursiveMode::NonRecursive).is_ok() {
+      watched.push(dir.to_path_buf());
+    }
+    if dir == root {
+      break;
+    }
+    ancestor = dir.parent();
+  }
+
+  Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn ignore_patterns_match_names_only() {
+    let patterns = default_ignore_patterns();
+    assert!(is_ignored(Path::new("/a/report.docx.tmp"), &patterns));
+    assert!(is_ignored(Path::new("/a/.#notes"), &patterns));
+    assert!(is_ignored(Path::new("/a/notes~"), &patterns));
+    assert!(!is_ignored(Path::new("/a.tmp/notes"), &patterns));
+    assert!(!is_ignored(Path::new("/a/report.docx"), &patterns));
+    assert!(wildcard_match(b"a?c*", b"abcdef"));
+    assert!(!wildcard_match(b"a?c", b"abcd"));
+  }
+
+  #[test]
+  fn bursts_rescan_the_folders_they_touched_once_quiet() {
+    let root = Path::new("/root");
+    let mut burst = BurstTracker::default();
+    for index in 0..BURST_EVENT_THRESHOLD {
+      let folder = if index % 2 == 0 { "a" } else { "b" };
+      burst.record(root, &root.join(folder).join("file"), 0);
+    }
+    assert!(burst.take_ready(root).is_none());
+
+    burst.last_event = Some(Instant::now() - RESCAN_QUIET_PERIOD);
+    let mut subtrees = burst.take_ready(root).unwrap();
+    subtrees.sort();
+    assert_eq!(subtrees, vec![root.join("a"), root.join("b")]);
+    assert!(burst.take_ready(root).is_none());
+
+    // A change directly in the root rescans all of it.
+    burst.record(root, &root.join("file"), BURST_BYTE_THRESHOLD);
+    burst.last_event = Some(Instant::now() - RESCAN_QUIET_PERIOD);
+    assert_eq!(burst.take_ready(root), Some(vec![root.to_path_buf()]));
+  }
+
+  // Runs a real watcher over a temp folder and maps its events the way the worker does.
+  #[test]
+  fn watcher_reports_created_changed_and_removed_files() {
+    let _no_materialize = NoMaterialize::enter();
+    let temp = tempfile::tempdir().unwrap();
+    let root = temp.path().canonicalize().unwrap();
+    fs::create_dir(root.join("nested")).unwrap();
+    let (tx, rx) = mpsc::channel();
+    let mut watcher = notify::recommended_watcher(move |res| {
+      let _ = tx.send(res);
+    })
+    .unwrap();
+    let mut watched = Vec::new();
+    apply_focus(&mut watcher, &root, &root, &mut watched).unwrap();
+
+    let path = root.join("nested").join("file.bin");
+    let mut seen = HashSet::new();
+    let mut wait_for = |expected: &'static str| {
+      let deadline = Instant::now() + Duration::from_secs(5);
+      while !seen.contains(expected) {
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        let event = rx.recv_timeout(remaining).expect("no watch event").unwrap();
+        let Some((kind, dir_kind)) = event_kinds(&event.kind) else {
+          continue;
+        };
+        for changed in event.paths.iter().filter(|changed| **changed == path) {
+          if let Some((kind, _)) = classify_change(changed, kind, dir_kind) {
+            seen.insert(kind);
+          }
+        }
+      }
+    };
+
+    fs::write(&path, b"first").unwrap();
+    wait_for("create");
+    fs::write(&path, b"second write").unwrap();
+    wait_for("modify");
+    fs::remove_file(&path).unwrap();
+    wait_for("remove");
+  }
+}
@@ -8,12 +8,26 @@ import {
   onMount,
 } from "solid-js";
 import { listen } from "@tauri-apps/api/event";
-import { open } from "@tauri-apps/api/dialog";
+import { confirm, open } from "@tauri-apps/api/dialog";
 import { homeDir } from "@tauri-apps/api/path";
 import { invoke } from "@tauri-apps/api/tauri";
 import TopFiles from "./components/TopFiles";
-import { scanState, setScanState, type ScanProgress } from "./stores/scan";
+import {
+  scanState,
+  setScanState,
+  type FileEntry,
+  type ScanProgress,
+  type ScanProgressDelta,
+} from "./stores/scan";
 import { formatBytes, formatDuration } from "./utils/format";
+import { formatMessage, type Message } from "./utils/messages";
+
+type InterruptedScan = {
+  rootPath: string;
+  scannedFiles: number;
+  queuedDirs: number;
+  cancelled: boolean;
+};
 
 type ScanHistoryEntry = {
   id: number;
@@ -28,6 +42,7 @@ type DiskOverview = {
   rootPath: string;
   mountPoint: string;
   volumeName: string;
+  volumeUuid?: string | null;
   totalBytes: number;
   availableBytes: number;
   usedBytes: number;
@@ -36,9 +51,31 @@ type DiskOverview = {
 
 type FsChangePayload = {
   scanId: number;
+  id: number;
   path: string;
-  kind: "create" | "modify" | "remove";
+  kind: "create" | "modify" | "remove" | "dir_created" | "dir_removed";
   size?: number | null;
+  pathKey?: string;
+};
+
+type BackfillPayload = {
+  scanId: number;
+  added: FileEntry[];
+};
+
+type ScanFailedPayload = {
+  scanId: number;
+  error: Message;
+};
+
+type SummaryUpdatedPayload = {
+  scanId: number;
+  subtrees: {
+    path: string;
+    scannedFiles: number;
+    scannedBytes: number;
+    topFiles: FileEntry[];
+  }[];
 };
 
 type LogEntry = {
@@ -73,15 +110,19 @@ export default function App() {
   const [error, setError] = createSignal<string | null>(null);
 
   let unlistenProgress: (() => void) | undefined;
+  let unlistenProgressDelta: (() => void) | undefined;
   let unlistenComplete: (() => void) | undefined;
   let unlistenFsChange: (() => void) | undefined;
+  let unlistenSummaryUpdated: (() => void) | undefined;
+  let unlistenBackfilled: (() => void) | undefined;
+  let unlistenFailed: (() => void) | undefined;
   let diskRequestId = 0;
   let diskRefreshTimeout: number | undefined;
   let logId = 0;
   let lastDiskRefreshAt = 0;
 
   const shouldHandleEvent = (
-    payload: ScanProgress,
+    payload: Pick<ScanProgress, "scanId">,
     eventType: "progress" | "complete"
   ) => {
     const completedId = completedScanId();
@@ -159,6 +200,25 @@ export default function App() {
       }
     );
 
+    unlistenProgressDelta = await listen<ScanProgressDelta>(
+      "scan_progress_delta",
+      (event) => {
+        if (!shouldHandleEvent(event.payload, "progress")) {
+          return;
+        }
+        const evicted = new Set(event.payload.evicted);
+        setScanState({
+          scannedFiles: scanState.scannedFiles + event.payload.addedFiles,
+          scannedBytes: scanState.scannedBytes + event.payload.addedBytes,
+          currentPath: event.payload.currentPath,
+          topFiles: scanState.topFiles
+            .filter((file) => !evicted.has(file.id))
+            .concat(event.payload.added)
+            .sort((a, b) => b.size - a.size),
+        });
+      }
+    );
+
     unlistenComplete = await listen<ScanProgress>(
       "scan_complete",
       (event) => {
@@ -214,29 +274,39 @@ export default function App() {
 
         setScanState("topFiles", (files) => {
           let next = files.slice();
-          const { path, kind, size } = event.payload;
-
-          if (kind === "remove") {
-            const prefix = path.endsWith("/") ? path : `${path}/`;
-            const filtered = next.filter(
-              (file) => file.path !== path && !file.path.startsWith(prefix)
-            );
+          const { id, path, kind, size, pathKey } = event.payload;
+          const keyFor = (value: string) =>
+            pathKey ? value.toLowerCase() : value;
+          const key = pathKey ?? path;
+
+          if (kind === "remove" || kind === "dir_removed") {
+            const prefix = key.endsWith("/") ? key : `${key}/`;
+            const filtered = next.filter((file) => {
+              const fileKey = keyFor(file.path);
+              return fileKey !== key && !fileKey.startsWith(prefix);
+            });
             return filtered.length === next.length ? files : filtered;
           }
 
+          if (kind === "dir_created") {
+            return files;
+          }
+
           if (!Number.isFinite(size) || (size as number) <= 0) {
             return files;
           }
 
-          const existingIndex = next.findIndex((file) => file.path === path);
+          const existingIndex = next.findIndex(
+            (file) => keyFor(file.path) === key
+          );
           if (existingIndex >= 0) {
-            next[existingIndex] = { path, size: size as number };
+            next[existingIndex] = { id, path, size: size as number };
           } else {
             const smallest = next[next.length - 1]?.size ?? 0;
             if (next.length >= TOP_FILES_LIMIT && (size as number) <= smallest) {
               return files;
             }
-            next.push({ path, size: size as number });
+            next.push({ id, path, size: size as number });
           }
 
           next.sort((a, b) => b.size - a.size);
@@ -247,7 +317,10 @@ export default function App() {
         });
 
         const shouldRefresh =
-          event.payload.kind === "create" || event.payload.kind === "remove";
+          event.payload.kind === "create" ||
+          event.payload.kind === "remove" ||
+          event.payload.kind === "dir_created" ||
+          event.payload.kind === "dir_removed";
         if (shouldRefresh && diskPath()) {
           if (diskRefreshTimeout) {
             clearTimeout(diskRefreshTimeout);
@@ -261,12 +334,92 @@ export default function App() {
         }
       }
     );
+
+    unlistenSummaryUpdated = await listen<SummaryUpdatedPayload>(
+      "scan_summary_updated",
+      (event) => {
+        const activeId = scanId();
+        if (!activeId || event.payload.scanId !== activeId) {
+          return;
+        }
+        if (scanState.inProgress) {
+          return;
+        }
+
+        // Replace everything under each rescanned folder with its fresh results
+        setScanState("topFiles", (files) => {
+          const prefixes = event.payload.subtrees.map((subtree) =>
+            subtree.path.endsWith("/") ? subtree.path : `${subtree.path}/`
+          );
+          const next = files
+            .filter((file) => !prefixes.some((prefix) => file.path.startsWith(prefix)))
+            .concat(event.payload.subtrees.flatMap((subtree) => subtree.topFiles));
+          next.sort((a, b) => b.size - a.size);
+          return next.slice(0, TOP_FILES_LIMIT);
+        });
+        addLog(
+          `Rescanned ${event.payload.subtrees.length} folder(s) after a burst of changes`
+        );
+      }
+    );
+
+    // Deleted top files are replaced by the next largest ones the scan kept
+    unlistenBackfilled = await listen<BackfillPayload>(
+      "scan_top_files_backfilled",
+      (event) => {
+        if (event.payload.scanId !== scanId()) {
+          return;
+        }
+        setScanState("topFiles", (files) => {
+          const known = new Set(files.map((file) => file.id));
+          const next = files.concat(
+            event.payload.added.filter((file) => !known.has(file.id))
+          );
+          next.sort((a, b) => b.size - a.size);
+          return next;
+        });
+      }
+    );
+
+    // A scan or watcher thread that crashed ends the scan instead of leaving it spinning
+    unlistenFailed = await listen<ScanFailedPayload>("scan_failed", (event) => {
+      if (event.payload.scanId !== scanId()) {
+        return;
+      }
+      setScanState("inProgress", false);
+      setScanStartedAt(null);
+      setError(formatMessage(event.payload.error));
+    });
+
+    // A scan that was still running when the app quit can pick up from its last checkpoint
+    try {
+      const interrupted = await invoke<InterruptedScan[]>("list_interrupted_scans");
+      const last = interrupted.find((scan) => !scan.cancelled);
+      if (last) {
+        const resume = await confirm(
+          `The scan of ${last.rootPath} was interrupted after ${last.scannedFiles.toLocaleString()} files. Resume it?`,
+          { title: "Resume scan", type: "info" }
+        );
+        if (resume) {
+          setFolder(last.rootPath);
+          await startScan(true);
+        } else {
+          await invoke("discard_interrupted_scan", { rootPath: last.rootPath });
+        }
+      }
+    } catch (err) {
+      console.error(err);
+    }
   });
 
   onCleanup(() => {
     unlistenProgress?.();
+    unlistenProgressDelta?.();
     unlistenComplete?.();
     unlistenFsChange?.();
+    unlistenSummaryUpdated?.();
+    unlistenBackfilled?.();
+    unlistenFailed?.();
     if (diskRefreshTimeout) {
       clearTimeout(diskRefreshTimeout);
     }
@@ -285,7 +438,7 @@ export default function App() {
     }
   };
 
-  const startScan = async () => {
+  const startScan = async (resume = false) => {
     if (!folder()) {
       setError("Choose a folder to scan.");
       return;
@@ -310,7 +463,7 @@ export default function App() {
     setCompletedScanId(null);
 
     try {
-      const id = await invoke<number>("start_scan", {
+      const id = await invoke<number>(resume ? "resume_interrupted_scan" : "start_scan", {
         rootPath: folder(),
       });
       setScanId(id);
@@ -321,7 +474,7 @@ export default function App() {
       setScanElapsedMs(0);
       setScanId(previousScanId ?? null);
       setIgnoredScanId(null);
-      setError(String(err));
+      setError(formatMessage(err));
     }
   };
 
@@ -388,7 +541,7 @@ export default function App() {
         return;
       }
       setDiskInfo(null);
-      setDiskError(String(err));
+      setDiskError(formatMessage(err));
     } finally {
       if (requestId === diskRequestId) {
         setDiskLoading(false);
@@ -595,7 +748,7 @@ export default function App() {
         <div class="controls-row">
           <button
             class="button primary"
-            onClick={startScan}
+            onClick={() => startScan()}
             disabled={!folder() || scanState.inProgress}
           >
             Start Scan
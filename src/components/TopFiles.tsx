@@ -6,6 +6,13 @@ import { open as openPath } from "@tauri-apps/api/shell";
 import { invoke } from "@tauri-apps/api/tauri";
 import type { FileEntry } from "../stores/scan";
 import { formatBytes } from "../utils/format";
+import { formatMessage } from "../utils/messages";
+
+type DeleteCheck = {
+  path: string;
+  openBy: { pid: number; name: string }[];
+  trashUnavailable: boolean;
+};
 
 type Props = {
   files: FileEntry[];
@@ -200,18 +207,33 @@ export default function TopFiles(props: Props) {
     const name = getFileName(file.path);
     const sensitive = isSensitivePath(file.path);
 
-    // First confirmation
-    const firstConfirm = await confirm(
-      sensitive
-        ? `⚠️ "${name}" appears to be a security-sensitive file.\n\nPath: ${file.path}\n\nAre you sure you want to delete it?`
-        : `Delete "${name}"? This cannot be undone.`,
-      {
-        title: sensitive ? "Delete sensitive file" : "Delete file",
-        type: "warning",
+    // Warn when another process still has the file open: its space is not freed until it closes
+    let inUseWarning = "";
+    let trashUnavailable = false;
+    try {
+      const check = await invoke<DeleteCheck>("check_delete", { path: file.path });
+      if (check.openBy.length > 0) {
+        const holders = check.openBy.map((process) => `${process.name} (${process.pid})`).join(", ");
+        inUseWarning = `\n\nIn use by ${holders}. The space will not be freed until it closes the file.`;
       }
-    );
+      trashUnavailable = check.trashUnavailable;
+    } catch (err) {
+      console.error(err);
+    }
 
-    if (!firstConfirm) {
+    // First confirmation, asked by the backend, which only then hands out the token
+    let token: string;
+    try {
+      ({ token } = await invoke<{ token: string }>("request_destructive_token", {
+        summary:
+          (sensitive
+            ? `⚠️ "${name}" appears to be a security-sensitive file.\n\nAre you sure you want to delete it?`
+            : `Delete "${name}"? This cannot be undone.`) + inUseWarning,
+        command: "delete_file",
+        targets: [file.path],
+      }));
+    } catch (err) {
+      setStatus(formatMessage(err), "warning");
       return;
     }
 
@@ -229,17 +251,34 @@ export default function TopFiles(props: Props) {
       }
     }
 
+    // FAT and exFAT drives have no usable trash, so the file would be deleted permanently
+    if (trashUnavailable) {
+      const permanentConfirm = await confirm(
+        `"${name}" is on a drive without a usable trash and will be deleted permanently.`,
+        {
+          title: "Delete permanently",
+          type: "warning",
+        }
+      );
+      if (!permanentConfirm) {
+        return;
+      }
+    }
+
     lastDeleteTime = Date.now();
 
     try {
-      await invoke("delete_file", { path: file.path });
+      await invoke("delete_file", {
+        path: file.path,
+        confirmPermanent: trashUnavailable,
+        token,
+      });
       hidePath(file.path);
       setStatus(sensitive ? "Sensitive file deleted." : "File deleted.", "warning");
       props.onStorageRefresh?.("delete", undefined, true);
     } catch (err) {
       console.error(err);
-      const message = err instanceof Error ? err.message : String(err);
-      setStatus(`Delete failed: ${message}`, "error");
+      setStatus(`Delete failed: ${formatMessage(err)}`, "error");
     }
   };
 
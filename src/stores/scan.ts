@@ -1,6 +1,7 @@
 import { createStore } from "solid-js/store";
 
 export type FileEntry = {
+  id: number;
   path: string;
   size: number;
 };
@@ -13,6 +14,17 @@ export type ScanProgress = {
   topFiles: FileEntry[];
 };
 
+// Sent between `scan_progress` snapshots with only what changed.
+export type ScanProgressDelta = {
+  scanId: number;
+  sequence: number;
+  addedFiles: number;
+  addedBytes: number;
+  currentPath: string;
+  added: FileEntry[];
+  evicted: number[];
+};
+
 export const [scanState, setScanState] = createStore({
   inProgress: false,
   scannedFiles: 0,
@@ -0,0 +1,34 @@
+// A message from the backend: a stable identifier (see `src-tauri/src/messages.rs`), its
+// parameters, and the English text.
+export type Message = {
+  key: string;
+  params: Record<string, string>;
+  text: string;
+};
+
+type Translations = Record<string, string>;
+
+// Wordings keyed by message identifier, with `{name}` placeholders; English comes from the
+// backend, so only other languages need a table.
+const translations: Record<string, Translations> = {};
+
+export function isMessage(value: unknown): value is Message {
+  return (
+    typeof value === "object" &&
+    value !== null &&
+    typeof (value as Message).key === "string" &&
+    typeof (value as Message).text === "string"
+  );
+}
+
+export function formatMessage(value: unknown, locale = navigator.language): string {
+  if (!isMessage(value)) {
+    return value instanceof Error ? value.message : String(value);
+  }
+  const template =
+    translations[locale]?.[value.key] ?? translations[locale.split("-")[0]]?.[value.key];
+  if (!template) {
+    return value.text;
+  }
+  return template.replace(/\{(\w+)\}/g, (match, name) => value.params[name] ?? match);
+}